@@ -0,0 +1,68 @@
+//! バッファをパースしたときに保持されるヒープ領域の大きさを測るベンチマーク。
+//!
+//! ```sh
+//! cargo run --release --example cst_memory [FILE]
+//! ```
+//!
+//! ファイルを与えなかった場合は、節や段落を多数含む文書を生成して測る。
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Instant,
+};
+
+use maquette_satysfi_language_server::Buffer;
+
+/// 確保中のバイト数を数えるアロケータ。
+struct Counting;
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        ALLOCATED.fetch_sub(layout.size(), Ordering::Relaxed);
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+/// 節と段落を多数含む文書を生成する。
+fn generate() -> String {
+    let mut text = String::from("@require: stdjabook\n\nlet-inline ctx \\strong it = read-inline ctx it\nin\n");
+    text.push_str("document (| title = {Bench}; author = {}; show-title = true; show-toc = false; |) '<\n");
+    for i in 0..300 {
+        text.push_str(&format!(
+            "  +section{{Section {}}}<\n    +p{{ Some \\strong{{text}} and ${{x^{{{}}} + y}} here. }}\n  >\n",
+            i, i
+        ));
+    }
+    text.push_str(">\n");
+    text
+}
+
+fn main() {
+    let text = match std::env::args().nth(1) {
+        Some(path) => std::fs::read_to_string(path).expect("failed to read the file"),
+        None => generate(),
+    };
+    let text_len = text.len();
+
+    let before = ALLOCATED.load(Ordering::Relaxed);
+    let start = Instant::now();
+    let buf = Buffer::new(text);
+    let elapsed = start.elapsed();
+    let held = ALLOCATED.load(Ordering::Relaxed) - before;
+
+    println!("text:     {} bytes", text_len);
+    println!("parse:    {:?}", elapsed);
+    println!("held:     {} bytes ({:.1}x text)", held, held as f64 / text_len as f64);
+    println!("estimate: {} bytes", buf.buf_cst.memory_estimate());
+}
@@ -0,0 +1,81 @@
+//! ドキュメントから見えるすべてのコマンドを返す `satysfi/allCommands` リクエスト。
+//!
+//! エディタのプラグインが、補完とは別にコマンドの一覧や挿入用のウィザードを作るために用いる。
+
+use lsp_types::{request::Request, Range, TextDocumentIdentifier, Url};
+use serde::{Deserialize, Serialize};
+
+use crate::{config::Config, package_doc::load_catalog_documentation, Buffer, CmdKind, Environment};
+
+/// ドキュメントから見えるすべてのコマンドを返すカスタムリクエスト。
+pub enum AllCommands {}
+
+impl Request for AllCommands {
+    type Params = AllCommandsParams;
+    type Result = Vec<CommandInfo>;
+    const METHOD: &'static str = "satysfi/allCommands";
+}
+
+/// `satysfi/allCommands` のパラメータ。
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AllCommandsParams {
+    /// 対象のドキュメント。
+    pub text_document: TextDocumentIdentifier,
+}
+
+/// コマンドの情報。
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CommandInfo {
+    /// コマンド名。
+    pub name: String,
+    /// コマンドの種類。
+    pub kind: CmdKind,
+    /// コンテキストとオプション引数を除いた引数の数。
+    pub arity: usize,
+    /// オプション引数の数。
+    pub optional_arity: usize,
+    /// 補完候補のカタログに書かれたドキュメント。
+    pub documentation: Option<String>,
+    /// 定義されているファイルの URI.
+    pub uri: Url,
+    /// 定義されている場所。
+    pub range: Range,
+}
+
+/// allCommands リクエストへの response を返す。
+/// バッファ自身の定義と、読み込んだパッケージから公開されている定義を返す。
+pub fn get_all_commands_response(buf: &Buffer, uri: &Url, config: &Config) -> Vec<CommandInfo> {
+    let docs = load_catalog_documentation(config);
+    let envs = std::iter::once((uri, &buf.env))
+        .chain(buf.packages.iter().map(|pkg| (&pkg.uri, &pkg.env)));
+
+    let mut commands = vec![];
+    for (uri, env) in envs {
+        for (name, kind, (arity, optional_arity), range) in env_commands(env) {
+            commands.push(CommandInfo {
+                documentation: docs.get(name).cloned(),
+                name: name.to_owned(),
+                kind,
+                arity,
+                optional_arity,
+                uri: uri.clone(),
+                range,
+            });
+        }
+    }
+    commands
+}
+
+/// Environment に含まれるコマンドを、名前、種類、引数とオプション引数の数、定義の場所の組で列挙する。
+fn env_commands(env: &Environment) -> Vec<(&str, CmdKind, (usize, usize), Range)> {
+    [CmdKind::Inline, CmdKind::Block, CmdKind::Math]
+        .iter()
+        .flat_map(|&kind| env.commands(kind))
+        .map(|c| (c.name, c.kind, (c.arity, c.optional_arity), c.def_range.into()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,35 @@
+//! test module for allCommands.
+
+use super::*;
+
+#[test]
+fn test_all_commands() {
+    let buf = Buffer::new(
+        r#"let-inline ctx \emph inner = inner
+let-block ctx +section ?:label title inner = '<>
+let-math \abs x = x
+"#
+        .to_owned(),
+    );
+    let uri = Url::parse("file:///test.satyh").unwrap();
+    let commands = get_all_commands_response(&buf, &uri, &Config::default());
+
+    let summary = commands
+        .iter()
+        .map(|c| (c.name.as_str(), c.kind, c.arity))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        summary,
+        vec![
+            ("\\emph", CmdKind::Inline, 1),
+            ("+section", CmdKind::Block, 2),
+            ("\\abs", CmdKind::Math, 1),
+        ]
+    );
+    assert_eq!(commands[1].optional_arity, 1);
+    assert!(commands.iter().all(|c| c.uri == uri));
+
+    let json = serde_json::to_value(&commands[0]).unwrap();
+    assert_eq!(json["kind"], "inline");
+    assert_eq!(json["arity"], 1);
+}
@@ -0,0 +1,80 @@
+//! コマンドの使用箇所で、与えられた引数が定義のどの引数にあたるかを求める関数群。
+//!
+//! オプション引数 (`?:(...)` や `?*`) と、オプションでない引数はそれぞれ前から順に対応づける。
+//! 使用箇所では両者が入り混じってもよいため、何番目の子であるかではなく、
+//! それぞれの種類の中で何番目であるかを数える。
+//! signature help と、引数に関する diagnostics はどちらもこの数え方に従う。
+
+use crate::{parser::Rule, position::Position, BufferCst, Cst};
+
+/// 定義の引数のうち、どれにあたるか。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArgumentSlot {
+    /// 前から数えて何番目のオプション引数か。
+    Optional(usize),
+    /// コンテキストとオプション引数を除いて、前から数えて何番目の引数か。
+    Mandatory(usize),
+}
+
+/// コマンドの使用箇所 (`inline_cmd` など) で与えられている引数を、対応する定義の引数とともに順に返す。
+pub fn command_arguments(usage: &Cst) -> Vec<(ArgumentSlot, &Cst)> {
+    let (mut optional, mut mandatory) = (0, 0);
+    let mut arguments = vec![];
+    for arg in usage.inner.iter().skip(1) {
+        let slot = match arg.rule {
+            Rule::cmd_expr_option | Rule::math_cmd_expr_option => {
+                optional += 1;
+                ArgumentSlot::Optional(optional - 1)
+            }
+            Rule::cmd_expr_arg | Rule::math_cmd_expr_arg | Rule::cmd_text_arg => {
+                mandatory += 1;
+                ArgumentSlot::Mandatory(mandatory - 1)
+            }
+            _ => continue,
+        };
+        arguments.push((slot, arg));
+    }
+    arguments
+}
+
+/// 使用箇所の pos にある引数、または pos から書き始める引数がどれにあたるかを返す。
+/// pos がコマンド名より後ろになければ None を返す。
+///
+/// pos が与えられた引数の中 (開き括弧の直後から閉じ括弧の直前まで) にあればその引数を、
+/// そうでなければ pos より前にある引数の次の引数を返す。
+/// 直前が `?:` であれば、次のオプション引数を書き始めているものとみなす。
+pub fn argument_slot_at(buf_cst: &BufferCst, usage: &Cst, pos: &Position) -> Option<ArgumentSlot> {
+    let name = usage.inner.first()?;
+    if *pos < Position::from(name.range.end.clone()) {
+        return None;
+    }
+    let arguments = command_arguments(usage);
+    let inside = arguments.iter().find(|(_, arg)| {
+        let start = Position::from(arg.range.start.clone());
+        let end = Position::from(arg.range.end.clone());
+        start < *pos && *pos < end
+    });
+    if let Some((slot, _)) = inside {
+        return Some(*slot);
+    }
+
+    let preceding = arguments
+        .iter()
+        .filter(|(_, arg)| Position::from(arg.range.end.clone()) <= *pos);
+    let (mut optional, mut mandatory) = (0, 0);
+    for (slot, _) in preceding {
+        match slot {
+            ArgumentSlot::Optional(i) => optional = i + 1,
+            ArgumentSlot::Mandatory(i) => mandatory = i + 1,
+        }
+    }
+    let before = &buf_cst.buffer[..buf_cst.line_index().offset(*pos)];
+    if before.trim_end().ends_with("?:") {
+        Some(ArgumentSlot::Optional(optional))
+    } else {
+        Some(ArgumentSlot::Mandatory(mandatory))
+    }
+}
+
+#[cfg(test)]
+mod tests;
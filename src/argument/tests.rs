@@ -0,0 +1,62 @@
+//! test module for argument.
+
+use super::*;
+use crate::test_utils::TestDocument;
+
+/// 文書で最初に使われているコマンドの使用箇所で、マーカーの位置の引数を求める。
+fn slots(source: &str, rule: Rule) -> Vec<Option<ArgumentSlot>> {
+    let doc = TestDocument::new(source);
+    let buf = doc.buffer();
+    let cst = buf.buf_cst.cst().expect("document must parse");
+    let usage = cst.pickup(rule)[0];
+    doc.positions()
+        .into_iter()
+        .map(|(_, pos)| argument_slot_at(&buf.buf_cst, usage, &pos))
+        .collect()
+}
+
+#[test]
+fn test_command_arguments() {
+    let buf = crate::Buffer::new("'<\n  +p(1)?:(2)(3)?*{a}<>\n>\n".to_owned());
+    let cst = buf.buf_cst.cst().unwrap();
+    let usage = cst.pickup(Rule::block_cmd)[0];
+    let slots = command_arguments(usage).into_iter().map(|(slot, _)| slot).collect::<Vec<_>>();
+    assert_eq!(
+        slots,
+        vec![
+            ArgumentSlot::Mandatory(0),
+            ArgumentSlot::Optional(0),
+            ArgumentSlot::Mandatory(1),
+            ArgumentSlot::Optional(1),
+            ArgumentSlot::Mandatory(2),
+            ArgumentSlot::Mandatory(3),
+        ]
+    );
+}
+
+#[test]
+fn test_argument_slot_at() {
+    use ArgumentSlot::*;
+    // オプション引数を飛ばしても、オプションでない引数は前から数える。
+    assert_eq!(
+        slots("'<\n  +^1p(^2x)^3 ?:(^4y) ?*{^5a}^6\n>\n", Rule::block_cmd),
+        vec![
+            None,
+            Some(Mandatory(0)),
+            Some(Mandatory(1)),
+            Some(Optional(0)),
+            Some(Mandatory(1)),
+            Some(Mandatory(2)),
+        ]
+    );
+    // `?:` の直後はオプション引数を書き始めている。
+    assert_eq!(
+        slots("'<\n  +p?:(1) ?:^1(2){a}\n>\n", Rule::block_cmd),
+        vec![Some(Optional(1))]
+    );
+    // 入れ子になったコマンドでは、外側の引数として数える。
+    assert_eq!(
+        slots("'<\n  +p?*(1){ \\q?:(2){^1b} }\n>\n", Rule::block_cmd),
+        vec![Some(Mandatory(1))]
+    );
+}
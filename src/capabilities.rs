@@ -0,0 +1,374 @@
+//! クライアントが対応している機能に合わせて response を調整する層。
+//!
+//! initialize で受け取った ClientCapabilities を保持し、各機能の response をクライアントに送る前に通す。
+//! 機能ごとの関数はクライアントの対応を気にせずに response を作り、調整はここにまとめる。
+//! LSP の仕様に従い、クライアントが明示していない機能には対応していないものとみなす。
+
+use lsp_types::{
+    ClientCapabilities, CodeActionKind, CodeActionOrCommand, CodeActionResponse, CompletionResponse,
+    CompletionTextEdit, Diagnostic, DocumentSymbol, DocumentSymbolResponse, Documentation, Hover,
+    HoverContents, InsertTextFormat, Location, MarkupContent, MarkupKind, SignatureHelp,
+    SymbolInformation, TextDocumentClientCapabilities, Url,
+};
+
+/// クライアントが対応している機能。
+#[derive(Debug, Clone, Default)]
+pub struct ClientSupport {
+    /// initialize で受け取ったクライアントの capabilities.
+    capabilities: ClientCapabilities,
+    /// `textDocument/diagnostic` による pull model の diagnostics に対応しているか。
+    pull_diagnostics: bool,
+    /// `workspace/diagnostic/refresh` リクエストに対応しているか。
+    diagnostic_refresh: bool,
+}
+
+impl ClientSupport {
+    /// クライアントの capabilities から作る。
+    pub fn new(capabilities: ClientCapabilities) -> Self {
+        Self {
+            capabilities,
+            pull_diagnostics: false,
+            diagnostic_refresh: false,
+        }
+    }
+
+    /// initialize リクエストで受け取った JSON の capabilities から作る。
+    /// lsp-types の ClientCapabilities は `textDocument.diagnostic` と `workspace.diagnostics` を持たないため、
+    /// JSON から直接読む。
+    pub fn from_json(capabilities: serde_json::Value) -> Result<Self, serde_json::Error> {
+        let pull_diagnostics = capabilities.pointer("/textDocument/diagnostic").is_some();
+        let diagnostic_refresh = capabilities
+            .pointer("/workspace/diagnostics/refreshSupport")
+            .and_then(serde_json::Value::as_bool)
+            .unwrap_or(false);
+        Ok(Self {
+            capabilities: serde_json::from_value(capabilities)?,
+            pull_diagnostics,
+            diagnostic_refresh,
+        })
+    }
+
+    /// textDocument に関する capabilities.
+    fn text_document(&self) -> Option<&TextDocumentClientCapabilities> {
+        self.capabilities.text_document.as_ref()
+    }
+
+    /// 定義ジャンプで LocationLink を受け取れるか。
+    pub fn link_support(&self) -> bool {
+        self.text_document()
+            .and_then(|doc| doc.definition.as_ref())
+            .and_then(|def| def.link_support)
+            .unwrap_or(false)
+    }
+
+    /// 補完候補の挿入文字列に snippet を使えるか。
+    pub fn snippet_support(&self) -> bool {
+        self.text_document()
+            .and_then(|doc| doc.completion.as_ref())
+            .and_then(|completion| completion.completion_item.as_ref())
+            .and_then(|item| item.snippet_support)
+            .unwrap_or(false)
+    }
+
+    /// 補完候補の説明に Markdown を使えるか。
+    pub fn completion_markdown_support(&self) -> bool {
+        self.text_document()
+            .and_then(|doc| doc.completion.as_ref())
+            .and_then(|completion| completion.completion_item.as_ref())
+            .and_then(|item| item.documentation_format.as_ref())
+            .is_some_and(|formats| formats.contains(&MarkupKind::Markdown))
+    }
+
+    /// hover の内容に Markdown を使えるか。
+    pub fn hover_markdown_support(&self) -> bool {
+        self.text_document()
+            .and_then(|doc| doc.hover.as_ref())
+            .and_then(|hover| hover.content_format.as_ref())
+            .is_some_and(|formats| formats.contains(&MarkupKind::Markdown))
+    }
+
+    /// シグネチャと引数の説明に Markdown を使えるか。
+    pub fn signature_help_markdown_support(&self) -> bool {
+        self.text_document()
+            .and_then(|doc| doc.signature_help.as_ref())
+            .and_then(|help| help.signature_information.as_ref())
+            .and_then(|info| info.documentation_format.as_ref())
+            .is_some_and(|formats| formats.contains(&MarkupKind::Markdown))
+    }
+
+    /// code action を CodeAction のリテラルとして受け取れる場合に、対応している kind の一覧。
+    /// リテラルに対応していなければ None を返す。
+    pub fn code_action_kinds(&self) -> Option<&[String]> {
+        self.text_document()
+            .and_then(|doc| doc.code_action.as_ref())
+            .and_then(|action| action.code_action_literal_support.as_ref())
+            .map(|literal| literal.code_action_kind.value_set.as_slice())
+    }
+
+    /// documentSymbol で入れ子になった DocumentSymbol を受け取れるか。
+    pub fn hierarchical_document_symbol_support(&self) -> bool {
+        self.text_document()
+            .and_then(|doc| doc.document_symbol.as_ref())
+            .and_then(|symbol| symbol.hierarchical_document_symbol_support)
+            .unwrap_or(false)
+    }
+
+    /// diagnostics の relatedInformation を受け取れるか。
+    pub fn related_information_support(&self) -> bool {
+        self.text_document()
+            .and_then(|doc| doc.publish_diagnostics.as_ref())
+            .and_then(|diagnostics| diagnostics.related_information)
+            .unwrap_or(false)
+    }
+
+    /// diagnostics を `textDocument/diagnostic` で取りに来るか。
+    /// 対応していれば、開かれているドキュメントの diagnostics は publish しない。
+    pub fn pull_diagnostic_support(&self) -> bool {
+        self.pull_diagnostics
+    }
+
+    /// pull model の diagnostics を取り直すよう、`workspace/diagnostic/refresh` で依頼できるか。
+    pub fn diagnostic_refresh_support(&self) -> bool {
+        self.pull_diagnostics && self.diagnostic_refresh
+    }
+
+    /// `workspace/didChangeWatchedFiles` の監視対象を、サーバから動的に登録できるか。
+    pub fn watched_files_registration_support(&self) -> bool {
+        self.capabilities
+            .workspace
+            .as_ref()
+            .and_then(|workspace| workspace.did_change_watched_files.as_ref())
+            .and_then(|watched| watched.dynamic_registration)
+            .unwrap_or(false)
+    }
+
+    /// サーバから作った work done progress を受け取れるか。
+    pub fn work_done_progress_support(&self) -> bool {
+        self.capabilities
+            .window
+            .as_ref()
+            .and_then(|window| window.work_done_progress)
+            .unwrap_or(false)
+    }
+
+    /// snippet に対応していなければ、snippet の補完候補を同じ内容のただの文字列にする。
+    /// Markdown に対応していなければ、説明をただの文字列として送る。
+    pub fn adapt_completion(&self, response: CompletionResponse) -> CompletionResponse {
+        let snippet_support = self.snippet_support();
+        let markdown_support = self.completion_markdown_support();
+        let adapt_items = |items: Vec<lsp_types::CompletionItem>| {
+            items
+                .into_iter()
+                .map(|mut item| {
+                    if !snippet_support && item.insert_text_format == Some(InsertTextFormat::Snippet) {
+                        item.insert_text = item.insert_text.as_deref().map(snippet_to_plain_text);
+                        match &mut item.text_edit {
+                            Some(CompletionTextEdit::Edit(edit)) => {
+                                edit.new_text = snippet_to_plain_text(&edit.new_text)
+                            }
+                            Some(CompletionTextEdit::InsertAndReplace(edit)) => {
+                                edit.new_text = snippet_to_plain_text(&edit.new_text)
+                            }
+                            None => (),
+                        }
+                        item.insert_text_format = Some(InsertTextFormat::PlainText);
+                    }
+                    if !markdown_support {
+                        if let Some(Documentation::MarkupContent(content)) = &mut item.documentation {
+                            to_plain_text(content);
+                        }
+                    }
+                    item
+                })
+                .collect()
+        };
+        match response {
+            CompletionResponse::Array(items) => CompletionResponse::Array(adapt_items(items)),
+            CompletionResponse::List(mut list) => {
+                list.items = adapt_items(list.items);
+                CompletionResponse::List(list)
+            }
+        }
+    }
+
+    /// Markdown に対応していなければ、hover の内容をただの文字列として送る。
+    pub fn adapt_hover(&self, mut hover: Hover) -> Hover {
+        if !self.hover_markdown_support() {
+            if let HoverContents::Markup(content) = &mut hover.contents {
+                to_plain_text(content);
+            }
+        }
+        hover
+    }
+
+    /// Markdown に対応していなければ、シグネチャと引数の説明をただの文字列として送る。
+    pub fn adapt_signature_help(&self, mut help: SignatureHelp) -> SignatureHelp {
+        if self.signature_help_markdown_support() {
+            return help;
+        }
+        for signature in &mut help.signatures {
+            let parameters = signature.parameters.iter_mut().flatten();
+            let docs = std::iter::once(&mut signature.documentation)
+                .chain(parameters.map(|parameter| &mut parameter.documentation));
+            for doc in docs {
+                if let Some(Documentation::MarkupContent(content)) = doc {
+                    to_plain_text(content);
+                }
+            }
+        }
+        help
+    }
+
+    /// CodeAction のリテラルに対応していなければ、Command 以外を取り除く。
+    /// 対応していれば、各 CodeAction の kind を、クライアントの知っている最も詳しい kind に置き換える。
+    /// `refactor.extract` を知らないクライアントには `refactor` として送り、どれも知らなければ kind を付けない。
+    pub fn adapt_code_actions(&self, response: CodeActionResponse) -> CodeActionResponse {
+        let kinds = match self.code_action_kinds() {
+            Some(kinds) => kinds,
+            None => {
+                return response
+                    .into_iter()
+                    .filter(|action| matches!(action, CodeActionOrCommand::Command(_)))
+                    .collect()
+            }
+        };
+        response
+            .into_iter()
+            .map(|action| match action {
+                CodeActionOrCommand::CodeAction(mut action) => {
+                    action.kind = action.kind.and_then(|kind| supported_kind(kind.as_str(), kinds));
+                    CodeActionOrCommand::CodeAction(action)
+                }
+                command => command,
+            })
+            .collect()
+    }
+
+    /// 入れ子の DocumentSymbol に対応していなければ、`uri` のファイルの SymbolInformation の列にする。
+    pub fn adapt_document_symbols(
+        &self,
+        uri: &Url,
+        response: DocumentSymbolResponse,
+    ) -> DocumentSymbolResponse {
+        match response {
+            DocumentSymbolResponse::Nested(symbols) if !self.hierarchical_document_symbol_support() => {
+                let mut flat = vec![];
+                flatten_symbols(uri, symbols, None, &mut flat);
+                DocumentSymbolResponse::Flat(flat)
+            }
+            response => response,
+        }
+    }
+
+    /// relatedInformation に対応していなければ、diagnostics から取り除く。
+    pub fn adapt_diagnostics(&self, mut diagnostics: Vec<Diagnostic>) -> Vec<Diagnostic> {
+        if !self.related_information_support() {
+            for diagnostic in &mut diagnostics {
+                diagnostic.related_information = None;
+            }
+        }
+        diagnostics
+    }
+}
+
+/// `kind` そのものか、`.` で区切った上位の kind のうち、`kinds` に含まれる最も詳しいもの。
+fn supported_kind(kind: &str, kinds: &[String]) -> Option<CodeActionKind> {
+    let mut kind = kind;
+    loop {
+        if kinds.iter().any(|k| k == kind) {
+            return Some(CodeActionKind::from(kind.to_owned()));
+        }
+        kind = &kind[..kind.rfind('.')?];
+    }
+}
+
+/// Markdown の内容を、記法を取り除いたただの文字列にする。
+/// コードブロックは囲みの行だけを取り除いて中身をそのまま残し、
+/// それ以外の行からは強調の `**` とインラインコードの `` ` `` を取り除く。
+fn to_plain_text(content: &mut MarkupContent) {
+    if content.kind == MarkupKind::Markdown {
+        content.value = markdown_to_plain_text(&content.value);
+    }
+    content.kind = MarkupKind::PlainText;
+}
+
+/// [`to_plain_text`] で用いる、Markdown の文字列からの変換。
+fn markdown_to_plain_text(markdown: &str) -> String {
+    let mut in_code_block = false;
+    let mut lines = vec![];
+    for line in markdown.lines() {
+        if line.trim_start().starts_with("```") {
+            in_code_block = !in_code_block;
+        } else if in_code_block {
+            lines.push(line.to_owned());
+        } else {
+            lines.push(line.replace("**", "").replace('`', ""));
+        }
+    }
+    let mut text = lines.join("\n");
+    if markdown.ends_with('\n') {
+        text.push('\n');
+    }
+    text
+}
+
+/// 入れ子の DocumentSymbol を、親の名前を container_name とする SymbolInformation の列にする。
+fn flatten_symbols(
+    uri: &Url,
+    symbols: Vec<DocumentSymbol>,
+    container: Option<&str>,
+    flat: &mut Vec<SymbolInformation>,
+) {
+    for symbol in symbols {
+        #[allow(deprecated)]
+        flat.push(SymbolInformation {
+            name: symbol.name.clone(),
+            kind: symbol.kind,
+            tags: symbol.tags.clone(),
+            deprecated: None,
+            location: Location::new(uri.clone(), symbol.range),
+            container_name: container.map(str::to_owned),
+        });
+        if let Some(children) = symbol.children {
+            flatten_symbols(uri, children, Some(&symbol.name), flat);
+        }
+    }
+}
+
+/// snippet の文字列を、挿入される既定の文字列にする。
+/// `$1` や `${1}` のタブストップは取り除き、`${1:text}` のプレースホルダは `text` に、
+/// `${1|a,b|}` の選択肢は最初の選択肢にする。`\` によるエスケープも解く。
+pub fn snippet_to_plain_text(snippet: &str) -> String {
+    let mut output = String::new();
+    let mut chars = snippet.chars().peekable();
+    // 閉じていないプレースホルダの `}` を数える。
+    let mut depth = 0;
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => output.extend(chars.next()),
+            '$' if chars.peek().is_some_and(char::is_ascii_digit) => {
+                while chars.next_if(char::is_ascii_digit).is_some() {}
+            }
+            '$' if chars.peek() == Some(&'{') => {
+                chars.next();
+                while chars.next_if(char::is_ascii_digit).is_some() {}
+                match chars.next() {
+                    Some(':') => depth += 1,
+                    Some('|') => {
+                        let choices: String = chars.by_ref().take_while(|&c| c != '|').collect();
+                        output.push_str(choices.split(',').next().unwrap_or_default());
+                        chars.next_if_eq(&'}');
+                    }
+                    // `${1}` はタブストップなので何も挿入しない。
+                    _ => (),
+                }
+            }
+            '}' if depth > 0 => depth -= 1,
+            c => output.push(c),
+        }
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests;
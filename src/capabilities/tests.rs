@@ -0,0 +1,282 @@
+//! test module for capabilities.
+
+use lsp_types::{
+    CodeAction, CodeActionClientCapabilities,
+    CodeActionKindLiteralSupport, CodeActionLiteralSupport, Command, CompletionClientCapabilities,
+    CompletionItem, CompletionItemCapability, CompletionList, HoverClientCapabilities,
+    ParameterInformation, ParameterLabel, Position, Range, SignatureHelpClientCapabilities,
+    SignatureInformation, SignatureInformationSettings, SymbolKind,
+};
+
+use super::*;
+
+fn client(text_document: TextDocumentClientCapabilities) -> ClientSupport {
+    ClientSupport::new(ClientCapabilities {
+        text_document: Some(text_document),
+        ..Default::default()
+    })
+}
+
+#[test]
+fn test_snippet_to_plain_text() {
+    assert_eq!(snippet_to_plain_text("+p{$0}"), "+p{}");
+    assert_eq!(snippet_to_plain_text("title = {${1:Title}};$2"), "title = {Title};");
+    assert_eq!(snippet_to_plain_text("${1:a ${2:b}} c${3}"), "a b c");
+    assert_eq!(snippet_to_plain_text("${1|left,right|} \\$1 \\}"), "left $1 }");
+}
+
+#[test]
+fn test_default_is_unsupported() {
+    let support = ClientSupport::default();
+    assert!(!support.link_support());
+    assert!(!support.snippet_support());
+    assert!(!support.hover_markdown_support());
+    assert!(!support.signature_help_markdown_support());
+    assert!(support.code_action_kinds().is_none());
+    assert!(!support.hierarchical_document_symbol_support());
+    assert!(!support.pull_diagnostic_support());
+    assert!(!support.watched_files_registration_support());
+}
+
+#[test]
+fn test_watched_files_registration_support() {
+    let support = |watched: serde_json::Value| {
+        let capabilities = serde_json::json!({"workspace": {"didChangeWatchedFiles": watched}});
+        ClientSupport::from_json(capabilities).unwrap().watched_files_registration_support()
+    };
+    assert!(support(serde_json::json!({"dynamicRegistration": true})));
+    assert!(!support(serde_json::json!({"dynamicRegistration": false})));
+    assert!(!support(serde_json::json!({})));
+}
+
+#[test]
+fn test_pull_diagnostic_support() {
+    let capabilities = serde_json::json!({"textDocument": {"diagnostic": {}, "hover": {"contentFormat": ["markdown"]}}});
+    let support = ClientSupport::from_json(capabilities).unwrap();
+    assert!(support.pull_diagnostic_support());
+    assert!(support.hover_markdown_support());
+
+    assert!(!support.diagnostic_refresh_support());
+
+    let support = ClientSupport::from_json(serde_json::json!({"textDocument": {}})).unwrap();
+    assert!(!support.pull_diagnostic_support());
+}
+
+#[test]
+fn test_diagnostic_refresh_support() {
+    let capabilities = serde_json::json!({
+        "textDocument": {"diagnostic": {}},
+        "workspace": {"diagnostics": {"refreshSupport": true}},
+    });
+    assert!(ClientSupport::from_json(capabilities).unwrap().diagnostic_refresh_support());
+    // pull model を使わないクライアントには依頼しない。
+    let capabilities = serde_json::json!({"workspace": {"diagnostics": {"refreshSupport": true}}});
+    assert!(!ClientSupport::from_json(capabilities).unwrap().diagnostic_refresh_support());
+}
+
+#[test]
+fn test_adapt_completion() {
+    let item = CompletionItem {
+        label: "section".to_owned(),
+        insert_text: Some("+section{${1:Title}}<$0>".to_owned()),
+        insert_text_format: Some(InsertTextFormat::Snippet),
+        ..Default::default()
+    };
+    let response = CompletionResponse::List(CompletionList {
+        is_incomplete: false,
+        items: vec![item],
+    });
+
+    let plain = match ClientSupport::default().adapt_completion(response.clone()) {
+        CompletionResponse::List(list) => list.items.into_iter().next().unwrap(),
+        _ => unreachable!(),
+    };
+    assert_eq!(plain.insert_text.as_deref(), Some("+section{Title}<>"));
+    assert_eq!(plain.insert_text_format, Some(InsertTextFormat::PlainText));
+
+    let support = client(TextDocumentClientCapabilities {
+        completion: Some(CompletionClientCapabilities {
+            completion_item: Some(CompletionItemCapability {
+                snippet_support: Some(true),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }),
+        ..Default::default()
+    });
+    let snippet = match support.adapt_completion(response) {
+        CompletionResponse::List(list) => list.items.into_iter().next().unwrap(),
+        _ => unreachable!(),
+    };
+    assert_eq!(snippet.insert_text_format, Some(InsertTextFormat::Snippet));
+}
+
+#[test]
+fn test_adapt_hover() {
+    let hover = Hover {
+        contents: HoverContents::Markup(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value: "`\\emph`".to_owned(),
+        }),
+        range: None,
+    };
+    let content = |hover: Hover| match hover.contents {
+        HoverContents::Markup(content) => content,
+        _ => unreachable!(),
+    };
+    let kind = |hover: Hover| content(hover).kind;
+    let plain = content(ClientSupport::default().adapt_hover(hover.clone()));
+    assert_eq!(plain.kind, MarkupKind::PlainText);
+    assert_eq!(plain.value, "\\emph");
+    let support = client(TextDocumentClientCapabilities {
+        hover: Some(HoverClientCapabilities {
+            dynamic_registration: None,
+            content_format: Some(vec![MarkupKind::Markdown, MarkupKind::PlainText]),
+        }),
+        ..Default::default()
+    });
+    assert_eq!(kind(support.adapt_hover(hover)), MarkupKind::Markdown);
+}
+
+#[test]
+fn test_markdown_to_plain_text() {
+    // コードブロックの中身はそのまま残す。
+    let markdown = "```satysfi\nlet s = `a` ^ `**b**`\n```\n\nlength: **≈ 72pt** for `1inch`\n";
+    assert_eq!(markdown_to_plain_text(markdown), "let s = `a` ^ `**b**`\n\nlength: ≈ 72pt for 1inch\n");
+}
+
+#[test]
+fn test_adapt_signature_help() {
+    let markdown = |value: &str| {
+        Some(Documentation::MarkupContent(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value: value.to_owned(),
+        }))
+    };
+    let help = SignatureHelp {
+        signatures: vec![SignatureInformation {
+            label: "\\emph {...}".to_owned(),
+            documentation: markdown("emphasize `it`"),
+            parameters: Some(vec![ParameterInformation {
+                label: ParameterLabel::LabelOffsets([6, 11]),
+                documentation: markdown("**text**"),
+            }]),
+            active_parameter: None,
+        }],
+        active_signature: None,
+        active_parameter: None,
+    };
+    let plain = ClientSupport::default().adapt_signature_help(help.clone());
+    let signature = &plain.signatures[0];
+    assert_eq!(
+        signature.documentation,
+        Some(Documentation::MarkupContent(MarkupContent {
+            kind: MarkupKind::PlainText,
+            value: "emphasize it".to_owned(),
+        }))
+    );
+    match &signature.parameters.as_ref().unwrap()[0].documentation {
+        Some(Documentation::MarkupContent(content)) => {
+            assert_eq!((&content.kind, content.value.as_str()), (&MarkupKind::PlainText, "text"))
+        }
+        doc => panic!("unexpected documentation: {:?}", doc),
+    }
+
+    let support = client(TextDocumentClientCapabilities {
+        signature_help: Some(SignatureHelpClientCapabilities {
+            signature_information: Some(SignatureInformationSettings {
+                documentation_format: Some(vec![MarkupKind::Markdown]),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }),
+        ..Default::default()
+    });
+    assert_eq!(support.adapt_signature_help(help.clone()), help);
+}
+
+#[test]
+fn test_adapt_code_actions() {
+    let action = |kind: CodeActionKind| {
+        CodeActionOrCommand::CodeAction(CodeAction {
+            title: kind.as_str().to_owned(),
+            kind: Some(kind),
+            ..Default::default()
+        })
+    };
+    let command = CodeActionOrCommand::Command(Command {
+        title: "lint workspace".to_owned(),
+        command: "satysfi.lintWorkspace".to_owned(),
+        arguments: None,
+    });
+    let response = vec![
+        action(CodeActionKind::QUICKFIX),
+        action(CodeActionKind::REFACTOR_EXTRACT),
+        action(CodeActionKind::REFACTOR_INLINE),
+        command.clone(),
+    ];
+    let kinds = |response: CodeActionResponse| {
+        response
+            .into_iter()
+            .map(|action| match action {
+                CodeActionOrCommand::CodeAction(action) => action.kind.map(|kind| kind.as_str().to_owned()),
+                CodeActionOrCommand::Command(command) => Some(command.command),
+            })
+            .collect::<Vec<_>>()
+    };
+
+    // リテラルに対応していなければ、Command だけを送る。
+    assert_eq!(ClientSupport::default().adapt_code_actions(response.clone()), vec![command]);
+
+    let support = |value_set: &[&str]| {
+        client(TextDocumentClientCapabilities {
+            code_action: Some(CodeActionClientCapabilities {
+                code_action_literal_support: Some(CodeActionLiteralSupport {
+                    code_action_kind: CodeActionKindLiteralSupport {
+                        value_set: value_set.iter().map(|kind| kind.to_string()).collect(),
+                    },
+                }),
+                ..Default::default()
+            }),
+            ..Default::default()
+        })
+    };
+    assert_eq!(
+        kinds(support(&["quickfix", "refactor", "refactor.inline"]).adapt_code_actions(response.clone())),
+        vec![
+            Some("quickfix".to_owned()),
+            Some("refactor".to_owned()),
+            Some("refactor.inline".to_owned()),
+            Some("satysfi.lintWorkspace".to_owned()),
+        ]
+    );
+    // 知らない kind は付けずに送る。
+    assert_eq!(kinds(support(&["quickfix"]).adapt_code_actions(response))[1], None);
+}
+
+#[test]
+fn test_flat_document_symbols() {
+    let range = Range::new(Position::new(0, 0), Position::new(0, 5));
+    #[allow(deprecated)]
+    let symbol = |name: &str, children| DocumentSymbol {
+        name: name.to_owned(),
+        detail: None,
+        kind: SymbolKind::Function,
+        tags: None,
+        deprecated: None,
+        range,
+        selection_range: range,
+        children,
+    };
+    let uri = Url::parse("file:///main.saty").unwrap();
+    let nested = DocumentSymbolResponse::Nested(vec![symbol("M", Some(vec![symbol("f", None)]))]);
+    match ClientSupport::default().adapt_document_symbols(&uri, nested) {
+        DocumentSymbolResponse::Flat(flat) => {
+            assert_eq!(flat.len(), 2);
+            assert_eq!(flat[1].name, "f");
+            assert_eq!(flat[1].container_name.as_deref(), Some("M"));
+            assert_eq!(flat[1].location.uri, uri);
+        }
+        res => panic!("unexpected response: {:?}", res),
+    }
+}
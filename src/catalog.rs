@@ -0,0 +1,98 @@
+//! completion.toml と、設定の resources で追加した補完候補のカタログ。
+
+use std::{collections::HashMap, fmt};
+
+use anyhow::Result;
+
+use crate::completion::MyCompletionItem;
+
+/// デフォルトで用意される補完候補。
+const COMPLETION_RESOURCES: &str = include_str!("resource/completion.toml");
+
+/// 補完候補のカタログ。設定を読み込むたびに一度だけ作り、補完、hover、diagnostics で共有する。
+#[derive(Clone)]
+pub struct Catalog {
+    sections: HashMap<String, Section>,
+}
+
+/// カタログの一つのセクション。項目を [`item_key`] で引けるようにし、ファイルに書かれた順序も保つ。
+#[derive(Clone, Default)]
+struct Section {
+    /// 項目のキー。最初に現れた順に並べる。
+    keys: Vec<String>,
+    /// キーから引く項目。
+    items: HashMap<String, MyCompletionItem>,
+}
+
+impl Default for Catalog {
+    /// completion.toml だけからなるカタログ。
+    fn default() -> Self {
+        Catalog::build(&[], None).expect("completion.toml should be valid")
+    }
+}
+
+impl fmt::Debug for Catalog {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // 項目の中身まで出すとログが読めなくなるため、セクションごとの項目数だけを示す。
+        f.debug_map()
+            .entries(self.sections.iter().map(|(name, section)| (name, section.keys.len())))
+            .finish()
+    }
+}
+
+impl Catalog {
+    /// completion.toml に `resource_texts` の内容を加えてカタログを作る。
+    /// 同じセクションに同じキーの項目があれば、後に読み込んだものを最初の項目の位置に置く。
+    /// ドキュメントは `locale` に合う言語のものを選ぶ。
+    pub fn build(resource_texts: &[String], locale: Option<&str>) -> Result<Self> {
+        let mut sections: HashMap<String, Section> = HashMap::new();
+        let texts = std::iter::once(COMPLETION_RESOURCES).chain(resource_texts.iter().map(String::as_str));
+        for text in texts {
+            let resources: HashMap<String, Vec<MyCompletionItem>> = toml::from_str(text)?;
+            for (name, items) in resources {
+                let section = sections.entry(name).or_default();
+                for mut item in items {
+                    item.documentation = item.localized_documentation.take().map(|doc| doc.select(locale));
+                    let key = item_key(item.class.as_deref(), &item.label);
+                    if !section.items.contains_key(&key) {
+                        section.keys.push(key.clone());
+                    }
+                    section.items.insert(key, item);
+                }
+            }
+        }
+        Ok(Catalog { sections })
+    }
+
+    /// セクション `section` の項目を書かれた順に返す。セクションがなければ空とする。
+    pub(crate) fn section(&self, section: &str) -> Vec<&MyCompletionItem> {
+        match self.sections.get(section) {
+            Some(section) => section.keys.iter().map(|key| &section.items[key]).collect(),
+            None => vec![],
+        }
+    }
+
+    /// セクション `section` で、文書クラス `class` に属するラベル `label` の項目を返す。
+    pub(crate) fn get(&self, section: &str, class: Option<&str>, label: &str) -> Option<&MyCompletionItem> {
+        self.sections.get(section)?.items.get(&item_key(class, label))
+    }
+
+    /// すべての項目を、そのセクションの名前とともに返す。
+    pub(crate) fn items(&self) -> impl Iterator<Item = (&str, &MyCompletionItem)> {
+        self.sections
+            .iter()
+            .flat_map(|(name, section)| section.items.values().map(move |item| (name.as_str(), item)))
+    }
+}
+
+/// カタログの項目を引くためのキー。文書クラスに属する項目では、クラスごとに同じラベルがありうるため、
+/// クラスの名前を前に付ける。
+fn item_key(class: Option<&str>, label: &str) -> String {
+    match class {
+        Some(class) => format!("{} {}", class, label),
+        None => label.to_owned(),
+    }
+}
+
+#[cfg(test)]
+mod tests;
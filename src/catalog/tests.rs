@@ -0,0 +1,35 @@
+//! test module for catalog.
+
+use super::*;
+
+#[test]
+fn test_class_items_are_keyed_by_class() {
+    let catalog = Catalog::default();
+    let book = catalog.get("classes", Some("stdjabook"), "+section").unwrap();
+    assert_eq!(book.class.as_deref(), Some("stdjabook"));
+    assert!(catalog.get("classes", None, "+section").is_none());
+    assert!(catalog.get("primitive", None, "let-block").is_some());
+}
+
+#[test]
+fn test_resources_override_builtin_items() {
+    let extra = r#"
+[[units]]
+label = "pt"
+detail = "overridden"
+
+[[units]]
+label = "zw"
+"#;
+    let catalog = Catalog::build(&[extra.to_owned()], None).unwrap();
+    let labels = catalog.section("units").iter().map(|item| item.label.as_str()).collect::<Vec<_>>();
+    assert_eq!(labels, vec!["pt", "mm", "cm", "inch", "em", "ex", "zw"]);
+    let pt = catalog.get("units", None, "pt").unwrap();
+    assert_eq!(pt.detail.as_deref(), Some("overridden"));
+    assert!(catalog.section("no-such-section").is_empty());
+}
+
+#[test]
+fn test_invalid_resources() {
+    assert!(Catalog::build(&["units = 1".to_owned()], None).is_err());
+}
@@ -0,0 +1,294 @@
+//! code action に関する関数群。
+
+use itertools::Itertools;
+use log::warn;
+use lsp_types::{
+    CodeAction, CodeActionKind, CodeActionOrCommand, CodeActionParams, CodeActionResponse,
+    Diagnostic, NumberOrString, Url,
+};
+use pest::Parser;
+
+use crate::{
+    diagnostic::{
+        LongerFenceData, UndefinedCommandData, UnusedDefinitionData, UNBALANCED_BACKTICK,
+        UNDEFINED_COMMAND, UNUSED_DEFINITION,
+    },
+    edit::EditBuilder,
+    parser::{Rule, SatysfiParser},
+    position::{Position, Range},
+    Buffer, CmdKind, Cst,
+};
+
+/// 切り出したインラインコマンドに付ける名前。
+const EXTRACTED_COMMAND_NAME: &str = "\\new-cmd";
+
+/// codeAction リクエストへの response を返す。
+pub fn get_code_action_response(
+    buf: Option<&Buffer>,
+    params: CodeActionParams,
+) -> Option<CodeActionResponse> {
+    let uri = params.text_document.uri;
+    let range = Range::from(params.range);
+    let mut actions = vec![];
+
+    if let Some(action) = buf.and_then(|buf| extract_inline_command_action(&uri, buf, range)) {
+        actions.push(CodeActionOrCommand::CodeAction(action));
+    }
+    if let Some(action) = buf.and_then(|buf| inline_command_action(&uri, buf, range.start)) {
+        actions.push(CodeActionOrCommand::CodeAction(action));
+    }
+
+    for diagnostic in params.context.diagnostics {
+        if diagnostic.code == Some(NumberOrString::String(UNUSED_DEFINITION.to_owned())) {
+            if let Some(action) = remove_definition_action(&uri, &diagnostic) {
+                actions.push(CodeActionOrCommand::CodeAction(action));
+            }
+            continue;
+        }
+        if diagnostic.code == Some(NumberOrString::String(UNBALANCED_BACKTICK.to_owned())) {
+            if let Some(action) = longer_fence_action(&uri, &diagnostic) {
+                actions.push(CodeActionOrCommand::CodeAction(action));
+            }
+            continue;
+        }
+        if diagnostic.code != Some(NumberOrString::String(UNDEFINED_COMMAND.to_owned())) {
+            continue;
+        }
+        let data: UndefinedCommandData = match &diagnostic.data {
+            Some(data) => serde_json::from_value(data.clone()).unwrap_or_default(),
+            None => continue,
+        };
+        let respelling = data.respelling;
+        if let Some(respelling) = &respelling {
+            if let Some(action) = respell_command_action(&uri, &diagnostic, respelling) {
+                actions.push(CodeActionOrCommand::CodeAction(action));
+            }
+        }
+        // 書き方だけが異なるコマンドがなければ、最も近い候補を優先的な修正とする。
+        let suggestions = data
+            .suggestions
+            .into_iter()
+            .filter(|suggestion| Some(suggestion) != respelling.as_ref());
+        for (i, suggestion) in suggestions.enumerate() {
+            let mut edit = EditBuilder::new();
+            if let Err(err) = edit.replace(&uri, diagnostic.range, suggestion.clone()) {
+                warn!("skipped code action: {}", err);
+                continue;
+            }
+            let action = CodeAction {
+                title: format!("Replace with `{}`", suggestion),
+                kind: Some(CodeActionKind::QUICKFIX),
+                diagnostics: Some(vec![diagnostic.clone()]),
+                edit: Some(edit.build()),
+                is_preferred: Some(i == 0 && respelling.is_none()),
+                ..Default::default()
+            };
+            actions.push(CodeActionOrCommand::CodeAction(action));
+        }
+    }
+
+    Some(actions)
+}
+
+/// コマンド名を、大文字小文字や区切り方だけが異なる定義済みの綴りに書き換える code action を作る。
+fn respell_command_action(uri: &Url, diagnostic: &Diagnostic, respelling: &str) -> Option<CodeAction> {
+    let mut edit = EditBuilder::new();
+    edit.replace(uri, diagnostic.range, respelling.to_owned())
+        .map_err(|err| warn!("skipped code action: {}", err))
+        .ok()?;
+    Some(CodeAction {
+        title: format!("Use the defined spelling `{}`", respelling),
+        kind: Some(CodeActionKind::QUICKFIX),
+        diagnostics: Some(vec![diagnostic.clone()]),
+        edit: Some(edit.build()),
+        is_preferred: Some(true),
+        ..Default::default()
+    })
+}
+
+/// 使われていない定義を取り除く code action を作る。
+fn remove_definition_action(uri: &Url, diagnostic: &Diagnostic) -> Option<CodeAction> {
+    let data: UnusedDefinitionData = serde_json::from_value(diagnostic.data.clone()?).ok()?;
+    let mut edit = EditBuilder::new();
+    edit.delete(uri, data.removal)
+        .map_err(|err| warn!("skipped code action: {}", err))
+        .ok()?;
+    Some(CodeAction {
+        title: "Remove unused definition".to_owned(),
+        kind: Some(CodeActionKind::QUICKFIX),
+        diagnostics: Some(vec![diagnostic.clone()]),
+        edit: Some(edit.build()),
+        is_preferred: Some(true),
+        ..Default::default()
+    })
+}
+
+/// 文字列リテラルのバッククォートを長くする code action を作る。
+fn longer_fence_action(uri: &Url, diagnostic: &Diagnostic) -> Option<CodeAction> {
+    let data: LongerFenceData = serde_json::from_value(diagnostic.data.clone()?).ok()?;
+    let mut edit = EditBuilder::new();
+    for text_edit in data.edits {
+        edit.replace(uri, text_edit.range, text_edit.new_text)
+            .map_err(|err| warn!("skipped code action: {}", err))
+            .ok()?;
+    }
+    Some(CodeAction {
+        title: "Use a longer backtick fence".to_owned(),
+        kind: Some(CodeActionKind::QUICKFIX),
+        diagnostics: Some(vec![diagnostic.clone()]),
+        edit: Some(edit.build()),
+        ..Default::default()
+    })
+}
+
+/// 選択された水平モードのテキストをインラインコマンドとしてプリアンブルに切り出す code action を作る。
+/// テキスト中の `#x;` で埋め込まれた変数のうち、トップレベルで定義されていないものはコマンドの引数にする。
+fn extract_inline_command_action(uri: &Url, buf: &Buffer, range: Range) -> Option<CodeAction> {
+    let cst = buf.buf_cst.cst.as_ref()?;
+    let index = buf.buf_cst.line_index();
+    let text = &buf.buf_cst.buffer;
+
+    // 前後の空白は切り出さずに残す。
+    let (start, end) = (index.offset(range.start), index.offset(range.end));
+    let selected = text.get(start..end)?;
+    let start = start + (selected.len() - selected.trim_start().len());
+    let end = end - (selected.len() - selected.trim_end().len());
+    if start >= end {
+        return None;
+    }
+    let (start_pos, end_pos) = (index.position(start), index.position(end));
+    let selected = &text[start..end];
+
+    // 選択範囲は一つの水平モードのテキストに収まり、それだけでテキストとして読めなければならない。
+    let in_text = cst.dig(&start_pos).into_iter().any(|c| {
+        c.rule == Rule::horizontal_single && Range::from(c.range.clone()).end >= end_pos
+    });
+    if !in_text {
+        return None;
+    }
+    let pair = SatysfiParser::parse(Rule::horizontal_single, selected).ok()?.next()?;
+    if pair.as_span().end() != selected.len() {
+        return None;
+    }
+    let fragment = Cst::new(pair, selected);
+    let params = fragment
+        .pickup(Rule::horizontal_text_embedding)
+        .into_iter()
+        .filter_map(|embedding| embedding.inner.first())
+        .filter(|name| name.rule == Rule::var_ptn)
+        .map(|name| name.as_str(selected))
+        .filter(|name| buf.env.variables().iter().all(|var| &var.name != name))
+        .unique()
+        .collect_vec();
+
+    let name = (1..)
+        .map(|i| match i {
+            1 => EXTRACTED_COMMAND_NAME.to_owned(),
+            i => format!("{}{}", EXTRACTED_COMMAND_NAME, i),
+        })
+        .find(|name| buf.env.lookup(CmdKind::Inline, name).is_none())?;
+    let definition = format!(
+        "let-inline {}{} = {{{}}}",
+        name,
+        params.iter().map(|p| format!(" {}", p)).join(""),
+        selected
+    );
+    let call = if params.is_empty() {
+        format!("{};", name)
+    } else {
+        format!("{}{};", name, params.iter().map(|p| format!("({})", p)).join(""))
+    };
+
+    let program = cst.inner.first()?;
+    let mut edit = EditBuilder::new();
+    let added = match program.inner.iter().find(|c| c.rule == Rule::preamble) {
+        Some(preamble) => {
+            // プリアンブルの中から切り出す場合は、使う文より前に定義する。
+            let stmt = preamble.inner.iter().find(|stmt| stmt.range.includes(&start_pos));
+            match stmt {
+                Some(stmt) => {
+                    let start = Range::from(stmt.range.clone()).start;
+                    edit.insert(uri, start, format!("{}\n", definition))
+                }
+                None => {
+                    let end = Range::from(preamble.inner.last()?.range.clone()).end;
+                    edit.insert(uri, end, format!("\n{}", definition))
+                }
+            }
+        }
+        None => {
+            // プリアンブルがなければ、本文の式の前に `in` とともに加える。
+            let body = program.inner.iter().rev().find(|c| c.rule == Rule::expr)?;
+            let start = Range::from(body.range.clone()).start;
+            edit.insert(uri, start, format!("{}\nin\n", definition))
+        }
+    };
+    added
+        .and_then(|edit| edit.replace(uri, Range { start: start_pos, end: end_pos }, call))
+        .map_err(|err| warn!("skipped code action: {}", err))
+        .ok()?;
+
+    Some(CodeAction {
+        title: "Extract inline command".to_owned(),
+        kind: Some(CodeActionKind::REFACTOR_EXTRACT),
+        edit: Some(edit.build()),
+        ..Default::default()
+    })
+}
+
+/// `pos` にある引数のないインラインコマンドの使用箇所を、その定義の本体のテキストで置き換える code action を作る。
+/// 対象とするのは、プリアンブルで `let-inline \cmd = { ... }` の形に定義されたコマンドに限る。
+fn inline_command_action(uri: &Url, buf: &Buffer, pos: Position) -> Option<CodeAction> {
+    let cst = buf.buf_cst.cst.as_ref()?;
+    let text = &buf.buf_cst.buffer;
+
+    let usage = cst
+        .dig(&pos)
+        .into_iter()
+        .find(|c| c.rule == Rule::inline_cmd)
+        .filter(|cmd| cmd.inner.len() == 1)?;
+    let name = usage.inner[0].as_str(text);
+
+    let preamble = cst.inner.first()?.inner.iter().find(|c| c.rule == Rule::preamble)?;
+    let body = preamble
+        .inner
+        .iter()
+        .filter_map(|stmt| stmt.inner.first())
+        .filter(|stmt| stmt.rule == Rule::let_inline_stmt)
+        .find_map(|stmt| match &stmt.inner[..] {
+            [cmd_name, expr] if cmd_name.as_str(text) == name => literal_text_body(expr),
+            _ => None,
+        })?;
+    // 本体の前後の空白は horizontal_mode の範囲に含まれないため、使用箇所のまわりの空白はそのまま残る。
+    let body_text = body
+        .inner
+        .iter()
+        .find(|c| c.rule == Rule::horizontal_mode)
+        .map_or("", |mode| mode.as_str(text));
+
+    let mut edit = EditBuilder::new();
+    edit.replace(uri, Range::from(usage.range.clone()), body_text)
+        .map_err(|err| warn!("skipped code action: {}", err))
+        .ok()?;
+    Some(CodeAction {
+        title: "Inline command here".to_owned(),
+        kind: Some(CodeActionKind::REFACTOR_INLINE),
+        edit: Some(edit.build()),
+        ..Default::default()
+    })
+}
+
+/// 式が水平モードのテキスト `{ ... }` だけからなるとき、その horizontal_text を返す。
+fn literal_text_body(expr: &Cst) -> Option<&Cst> {
+    let mut node = expr;
+    while node.rule != Rule::horizontal_text {
+        match &node.inner[..] {
+            [child] => node = child,
+            _ => return None,
+        }
+    }
+    Some(node)
+}
+
+#[cfg(test)]
+mod tests;
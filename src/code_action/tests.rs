@@ -0,0 +1,152 @@
+//! test module for code_action.
+
+use lsp_types::{CodeActionContext, Position, Range, TextDocumentIdentifier, TextEdit};
+
+use super::*;
+use crate::test_utils::TestDocument;
+
+fn uri() -> Url {
+    Url::parse("file:///main.saty").unwrap()
+}
+
+fn range(sl: u32, sc: u32, el: u32, ec: u32) -> Range {
+    Range {
+        start: Position { line: sl, character: sc },
+        end: Position { line: el, character: ec },
+    }
+}
+
+fn action_edits(text: &str, selection: Range, title: &str) -> Option<Vec<TextEdit>> {
+    let buf = Buffer::new(text.to_owned());
+    assert!(buf.error.is_empty(), "parse failed: {:?}", buf.error);
+    let params = CodeActionParams {
+        text_document: TextDocumentIdentifier { uri: uri() },
+        range: selection,
+        context: CodeActionContext::default(),
+        work_done_progress_params: Default::default(),
+        partial_result_params: Default::default(),
+    };
+    get_code_action_response(Some(&buf), params)?
+        .into_iter()
+        .find_map(|action| match action {
+            CodeActionOrCommand::CodeAction(action) if action.title == title => {
+                action.edit?.changes?.remove(&uri())
+            }
+            _ => None,
+        })
+}
+
+fn extract_edits(text: &str, selection: Range) -> Option<Vec<TextEdit>> {
+    action_edits(text, selection, "Extract inline command")
+}
+
+fn inline_edits(text: &str, pos: Position) -> Option<Vec<TextEdit>> {
+    action_edits(text, Range { start: pos, end: pos }, "Inline command here")
+}
+
+#[test]
+fn test_extract_inline_command() {
+    let text = "let-inline ctx \\new-cmd = ctx\nin\n'<\n  +p{ Hello, \\emph{world}! }\n>\n";
+    let edits = extract_edits(text, range(3, 6, 3, 26)).unwrap();
+    assert_eq!(
+        edits,
+        vec![
+            TextEdit {
+                range: range(0, 29, 0, 29),
+                new_text: "\nlet-inline \\new-cmd2 = {Hello, \\emph{world}!}".to_owned(),
+            },
+            TextEdit {
+                range: range(3, 6, 3, 26),
+                new_text: "\\new-cmd2;".to_owned(),
+            },
+        ]
+    );
+}
+
+#[test]
+fn test_extract_with_embedded_variables() {
+    let text = "let-inline ctx \\greet name = read-inline ctx { Hi, #name; and #name;. }\nin\n'<>\n";
+    let edits = extract_edits(text, range(0, 47, 0, 69)).unwrap();
+    assert_eq!(edits[0].range, range(0, 0, 0, 0));
+    assert_eq!(edits[0].new_text, "let-inline \\new-cmd name = {Hi, #name; and #name;.}\n");
+    assert_eq!(edits[1].range, range(0, 47, 0, 69));
+    assert_eq!(edits[1].new_text, "\\new-cmd(name);");
+}
+
+#[test]
+fn test_extract_without_preamble() {
+    let text = "'<\n  +p{ abc }\n>\n";
+    let edits = extract_edits(text, range(1, 6, 1, 9)).unwrap();
+    assert_eq!(edits[0].range, range(0, 0, 0, 0));
+    assert_eq!(edits[0].new_text, "let-inline \\new-cmd = {abc}\nin\n");
+}
+
+#[test]
+fn test_no_extract_outside_text() {
+    let text = "let x = 1\nin\n'<\n  +p{ a \\emph{b} c }\n>\n";
+    // プログラムモードの選択や、コマンドの途中で切れる選択は切り出さない。
+    assert!(extract_edits(text, range(0, 4, 0, 9)).is_none());
+    assert!(extract_edits(text, range(3, 8, 3, 14)).is_none());
+    assert!(extract_edits(text, range(3, 6, 3, 6)).is_none());
+}
+
+#[test]
+fn test_inline_command() {
+    let doc = TestDocument::new("let-inline \\foo = { a \\emph{b} }\nin\n'<\n  +p{ x \\f^1oo; y }\n>\n");
+    let edits = inline_edits(doc.text(), doc.position(1).into()).unwrap();
+    assert_eq!(
+        edits,
+        vec![TextEdit {
+            range: range(3, 8, 3, 13),
+            new_text: "a \\emph{b}".to_owned(),
+        }]
+    );
+}
+
+#[test]
+fn test_no_inline_non_literal_command() {
+    let doc = TestDocument::new("let-inline ctx \\foo = read-inline ctx {a}\nlet-inline \\bar x = {#x;}\nin\n'<\n  +p{ \\^1foo; \\^2bar(`y`); }\n>\n");
+    // コンテキストを受け取るコマンドや、引数をとるコマンドは展開しない。
+    assert!(inline_edits(doc.text(), doc.position(1).into()).is_none());
+    assert!(inline_edits(doc.text(), doc.position(2).into()).is_none());
+}
+
+#[test]
+fn test_respell_command() {
+    let text = "let-inline ctx \\line-break = inline-skip 0pt\nin\n{ \\lineBreak; }\n";
+    let buf = Buffer::new(text.to_owned());
+    let diagnostics = crate::diagnostic::get_diagnostics(&buf, &uri(), &Default::default())
+        .into_iter()
+        .filter(|d| d.code == Some(NumberOrString::String(UNDEFINED_COMMAND.to_owned())))
+        .collect_vec();
+    assert_eq!(diagnostics.len(), 1);
+    let params = CodeActionParams {
+        text_document: TextDocumentIdentifier { uri: uri() },
+        range: diagnostics[0].range,
+        context: CodeActionContext {
+            diagnostics,
+            ..Default::default()
+        },
+        work_done_progress_params: Default::default(),
+        partial_result_params: Default::default(),
+    };
+    let actions = get_code_action_response(Some(&buf), params)
+        .unwrap()
+        .into_iter()
+        .filter_map(|action| match action {
+            CodeActionOrCommand::CodeAction(action) if action.diagnostics.is_some() => Some(action),
+            _ => None,
+        })
+        .collect_vec();
+    assert_eq!(actions.len(), 1);
+    assert_eq!(actions[0].title, "Use the defined spelling `\\line-break`");
+    assert_eq!(actions[0].is_preferred, Some(true));
+    let edits = actions[0].edit.clone().unwrap().changes.unwrap().remove(&uri()).unwrap();
+    assert_eq!(
+        edits,
+        vec![TextEdit {
+            range: range(2, 2, 2, 12),
+            new_text: "\\line-break".to_owned(),
+        }]
+    );
+}
@@ -2,63 +2,334 @@
 
 use std::collections::HashMap;
 
-use anyhow::anyhow;
-use anyhow::Result;
 use itertools::Itertools;
-use log::{debug, warn};
+use log::debug;
+use pest::Parser;
 use lsp_types::{
-    CompletionItem, CompletionList, CompletionParams, CompletionResponse, Documentation,
-    InsertTextFormat, MarkupContent, Position,
+    CompletionItem, CompletionItemKind, CompletionList, CompletionParams, CompletionResponse,
+    CompletionTextEdit, Documentation, InsertTextFormat, MarkupContent, TextEdit, Url,
 };
 use serde::Deserialize;
 
-use crate::{parser::Mode, Buffer, Environment};
-
-/// デフォルトで用意される補完候補。
-const COMPLETION_RESOUCES: &str = include_str!("resource/completion.toml");
+use crate::{
+    config::{Config, LanguageVersion},
+    label::{display_maths, in_reference_argument, DisplayMath},
+    parser::{recovery::RecoveryKind, Mode, Rule, SatysfiParser},
+    partial::parse_around,
+    position::{LineIndex, Position, Range},
+    resolve::{PackageKind, Stage},
+    scope::{local_bindings, BindingKind, LocalBinding},
+    stage::stage_at,
+    typing::{
+        application_at, arrow_types, expected_argument_type, literal_type, normalize_type,
+        pipeline_operand,
+    },
+    workspace::{describe_usage_examples, WorkspaceIndex},
+    Buffer, BufferCst, CmdKind, Cst, Environment, MAX_BUFFER_SIZE,
+};
 
 /// 補完候補を返す。
 pub fn get_completion_response(
     buf: &Buffer,
     params: CompletionParams,
+    config: &Config,
+    index: &WorkspaceIndex,
 ) -> Option<CompletionResponse> {
-    let pos = params.text_document_position.position;
+    let uri = &params.text_document_position.text_document.uri;
+    let pos = Position::from(params.text_document_position.position);
     let trigger_char = &params.context.and_then(|ctx| ctx.trigger_character);
 
-    let completion_list = get_completion_list(buf, &pos, trigger_char);
+    let mut completion_list = get_completion_list(buf, &pos, trigger_char, config);
+    add_usage_examples(&mut completion_list.items, uri, index, config);
     Some(CompletionResponse::List(completion_list))
 }
 
+/// 説明のないコマンドの補完候補に、ワークスペースの他のファイルでの使用例を説明として付ける。
+fn add_usage_examples(
+    items: &mut [CompletionItem],
+    uri: &Url,
+    index: &WorkspaceIndex,
+    config: &Config,
+) {
+    let limit = config.usage_examples();
+    if limit == 0 {
+        return;
+    }
+    for item in items {
+        let is_command = item.label.starts_with(['\\', '+']);
+        if !is_command || item.documentation.is_some() {
+            continue;
+        }
+        let examples = index.usage_examples(&item.label, uri, limit);
+        item.documentation = describe_usage_examples(&examples).map(|value| {
+            Documentation::MarkupContent(MarkupContent {
+                kind: lsp_types::MarkupKind::Markdown,
+                value,
+            })
+        });
+    }
+}
+
 /// completion_resources を取得する。
-fn get_completion_list(buf: &Buffer, pos: &Position, trigger: &Option<String>) -> CompletionList {
+fn get_completion_list(
+    buf: &Buffer,
+    pos: &Position,
+    trigger: &Option<String>,
+    config: &Config,
+) -> CompletionList {
     let mut cmplist = CompletionList::default();
 
-    if buf.buf_cst.cst.is_none() {
+    // 空のドキュメントやヘッダしかないドキュメントでは、文書の雛形を候補とする。
+    if is_header_only(&buf.buf_cst.buffer) {
+        cmplist.items = load_template_completion_items(&buf.buf_cst.buffer, config);
+        return cmplist;
+    }
+
+    // `+cmd{...}<` の直後では、ブロックテキストの本体と閉じ括弧を挿入する snippet を候補とする。
+    // 閉じていない `<` があるとバッファ全体のパースに失敗するため、Cst の有無によらず調べる。
+    if let Some(item) = closing_block_cmd_completion_item(&buf.buf_cst.buffer, pos, config) {
+        cmplist.items = vec![item];
         return cmplist;
     }
 
-    let mode = buf.buf_cst.cst.as_ref().unwrap().mode(pos);
+    // 既にあるコマンド名の途中で補完を求められた場合は、その名前全体を置き換える。
+    // 位置がずれないよう、今の版のパースに成功している場合に限る。
+    let name_at_cursor = buf
+        .buf_cst
+        .cst
+        .as_ref()
+        .and_then(|cst| command_name_at(&buf.buf_cst, cst, pos));
+    // パースに失敗していても、カーソルの直前に入力途中のコマンド名があれば、
+    // その先頭の文字から挿入し直すよう補完候補を置き換える。
+    let typed_name = match name_at_cursor {
+        Some(_) => None,
+        None => typed_command_range(&buf.buf_cst, pos),
+    };
+    // 補完を明示的に求められた場合も、コマンド名の先頭の文字で起動したものとして扱う。
+    let trigger = &trigger.clone().or_else(|| {
+        name_at_cursor
+            .as_ref()
+            .or(typed_name.as_ref())
+            .map(|(_, sigil)| sigil.to_string())
+    });
+
+    // `document (| ... |)` の中では、文書クラスのレコードのフィールドを候補とする。
+    if trigger.is_none() {
+        if let Some(items) = document_field_items(buf, pos, config) {
+            cmplist.items = items;
+            return cmplist;
+        }
+    }
+
+    // パースに失敗している間は、最後にパースに成功した版の文法構造と環境を用いる。
+    let parsed = match buf.latest_parsed() {
+        Some(parsed) => parsed,
+        None => return cmplist,
+    };
+    // 古い版ではカーソル位置がずれているおそれがあるため、
+    // モードはカーソルを含む部分だけを今の版からパースし直して決める。
+    let mut partial_mode = None;
+    if buf.buf_cst.cst.is_none() {
+        debug!("parse failed; using the last parsed version {:?}", parsed.version);
+        partial_mode =
+            parse_around(&buf.buf_cst.buffer, pos).map(|partial| partial.mode_region(pos).mode);
+    }
+    let current = &buf.buf_cst;
+    let buf = parsed;
+
+    let cst = buf.buf_cst.cst.as_ref().unwrap();
+    let mode = partial_mode.unwrap_or_else(|| cst.mode(pos));
     debug!("current mode: {:?}", mode);
-    let env = &buf.env;
 
-    match load_completion_resources(mode, env, pos, trigger) {
-        Ok(res) => {
-            cmplist.items = res;
+    // `\eqref` などの引数の中では、別行立て数式のラベルを候補とする。
+    if mode == Mode::Literal && in_reference_argument(cst, &buf.buf_cst.buffer, pos, config) {
+        cmplist.items = display_maths(buf)
+            .iter()
+            .filter_map(label_completion_item)
+            .collect();
+        return cmplist;
+    }
+
+    // バッファ自身の定義に加え、読み込んだパッケージから公開されている定義も候補とする。
+    let envs = std::iter::once(&buf.env)
+        .chain(buf.packages.iter().map(|pkg| &pkg.env))
+        .collect_vec();
+
+    // 型注釈の中では、定義された型とプリミティブの型を候補とする。
+    if mode == Mode::Program && in_type_annotation(buf, cst, pos) {
+        let mut items = envs
+            .iter()
+            .flat_map(|env| env.types())
+            .map(|ty| {
+                definition_completion_item(&ty.name, CompletionItemKind::Struct, SortGroup::Definition)
+            })
+            .collect_vec();
+        items.extend(load_section_completion_items("types", config));
+        cmplist.items = items;
+        return cmplist;
+    }
+
+    // 数値のリテラルの直後では、長さの単位を候補とする。
+    if mode == Mode::Program {
+        if let Some(range) = length_unit_range(cst, pos) {
+            cmplist.items = load_section_completion_items("units", config)
+                .into_iter()
+                .map(|item| CompletionItem {
+                    text_edit: Some(CompletionTextEdit::Edit(TextEdit {
+                        range: range.into(),
+                        new_text: item.label.clone(),
+                    })),
+                    ..item
+                })
+                .collect();
+            return cmplist;
         }
-        Err(err) => warn!("failed to load completion resources: {}", err),
+    }
+    let locals = local_bindings(&buf.buf_cst, pos);
+
+    // `match x with` の直後では、x の型のコンストラクタを腕に展開する snippet を候補とする。
+    if mode == Mode::Program {
+        if let Some(item) = match_arms_completion_item(buf, &envs, &locals, pos) {
+            cmplist.items = vec![item];
+            return cmplist;
+        }
+    }
+
+    let stage = stage_at(&buf.buf_cst, pos);
+
+    // `ctx |>` の直後では、コンテキストを変える関数を候補とする。
+    if mode == Mode::Program && trigger.is_none() {
+        if let Some(items) = context_pipeline_items(buf, &envs, &locals, stage, pos, config) {
+            cmplist.items = items;
+            return cmplist;
+        }
+    }
+
+    cmplist.items = load_completion_resources(mode, &envs, &locals, stage, trigger, config);
+    // 文書クラスが定義するブロックコマンドを、読み込んだクラスに合わせて候補に加える。
+    if mode == Mode::Vertical && trigger.as_deref() == Some("+") {
+        if let Some(class) = document_class(buf, config) {
+            // パッケージを読み込めていれば、そこで定義されたコマンドを優先する。
+            let defined = cmplist.items.iter().map(|item| item.label.clone()).collect_vec();
+            let items = load_class_completion_items(&class, config)
+                .into_iter()
+                .filter(|item| !defined.contains(&item.label));
+            cmplist.items.extend(items);
+        }
+    }
+    // 式や文の始まりでは、構文の snippet を候補に加える。
+    // 入力中でパースに失敗していることが多いため、今の版のテキストから判断する。
+    if mode == Mode::Program && trigger.is_none() {
+        let offset = current.line_index().offset(*pos);
+        if at_expression_start(&current.buffer[..offset]) {
+            cmplist.items.extend(load_section_completion_items("syntax", config));
+        }
+    }
+    if mode == Mode::Program && trigger.is_none() {
+        rank_by_expected_type(&mut cmplist.items, buf, &envs, &locals, pos);
+    }
+    if let Some((range, sigil)) = name_at_cursor {
+        replace_command_name(&mut cmplist.items, range, sigil);
+    } else if let Some((range, sigil)) = typed_name {
+        anchor_at_sigil(&mut cmplist.items, range, sigil);
     }
 
     cmplist
 }
 
+/// 直後に式が始まる字句。
+const EXPRESSION_PRECEDERS: &[&str] = &["=", "->", "(", "[", ";", ",", "in", "then", "else", "begin", "struct"];
+
+/// 入力中の名前を除いたとき、カーソルの直前が式や文の始まりであるか。
+/// 行頭か、直前の字句が `=` や `in` などである場合に式や文の始まりとみなす。
+fn at_expression_start(before: &str) -> bool {
+    let is_ident = |c: char| c.is_alphanumeric() || c == '-' || c == '_';
+    let before = before.trim_end_matches(is_ident);
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    if before[line_start..].trim().is_empty() {
+        return true;
+    }
+    let before = before.trim_end();
+    let last = match before.chars().next_back() {
+        Some(c) => c,
+        None => return true,
+    };
+    let is_operator = |c: char| "=<>!:|&+-*/^~@$%.?".contains(c);
+    let token = if is_ident(last) {
+        &before[before.trim_end_matches(is_ident).len()..]
+    } else if is_operator(last) {
+        &before[before.trim_end_matches(is_operator).len()..]
+    } else {
+        &before[before.len() - last.len_utf8()..]
+    };
+    EXPRESSION_PRECEDERS.contains(&token)
+}
+
+/// カーソルがコマンド名の先頭の文字より後ろにあれば、その名前の範囲と先頭の文字 (`\` または `+`) を返す。
+fn command_name_at(buf_cst: &BufferCst, cst: &Cst, pos: &Position) -> Option<(Range, char)> {
+    let name = cst.dig(pos).into_iter().find(|c| {
+        matches!(c.rule, Rule::inline_cmd_name | Rule::block_cmd_name | Rule::math_cmd_name)
+    })?;
+    let range: Range = name.range.clone().into();
+    if *pos <= range.start {
+        return None;
+    }
+    let sigil = buf_cst.as_str(name).chars().next()?;
+    Some((range, sigil))
+}
+
+/// カーソルの直前に `\` または `+` で始まる入力途中のコマンド名があれば、
+/// 先頭の文字からカーソルまでの範囲と先頭の文字を返す。
+/// 書きかけでパースに失敗していることが多いため、Cst ではなくテキストから探す。
+fn typed_command_range(buf_cst: &BufferCst, pos: &Position) -> Option<(Range, char)> {
+    let index = buf_cst.line_index();
+    let before = &buf_cst.buffer[..index.offset(*pos)];
+    let head = before.trim_end_matches(|c: char| c.is_alphanumeric() || c == '-' || c == '.');
+    let sigil = head.chars().next_back().filter(|c| matches!(c, '\\' | '+'))?;
+    let start = index.position(head.len() - sigil.len_utf8());
+    Some((Range { start, end: *pos }, sigil))
+}
+
+/// コマンドの補完候補を、入力途中のコマンド名 `range` を先頭の文字ごと置き換えるものにする。
+/// クライアントが先頭の文字を入力中の語に含めるかどうかによらず、同じ位置に挿入される。
+/// snippet はそのまま残し、先頭の文字を付けて挿入する。
+fn anchor_at_sigil(items: &mut [CompletionItem], range: Range, sigil: char) {
+    for item in items.iter_mut().filter(|item| item.label.starts_with(sigil)) {
+        let new_text = match item.insert_text.take() {
+            // completion.toml の snippet などは先頭の文字を除いて書かれている。
+            Some(text) if !text.starts_with(sigil) => format!("{}{}", sigil, text),
+            Some(text) => text,
+            None => item.label.clone(),
+        };
+        item.text_edit = Some(CompletionTextEdit::Edit(TextEdit { range: range.into(), new_text }));
+        item.filter_text = Some(item.label.clone());
+    }
+}
+
+/// コマンドの補完候補を、既にあるコマンド名 `range` を置き換えるものにする。
+/// 引数は既に書かれているので、snippet は使わずに名前だけを挿入する。
+fn replace_command_name(items: &mut [CompletionItem], range: Range, sigil: char) {
+    for item in items.iter_mut().filter(|item| item.label.starts_with(sigil)) {
+        item.text_edit = Some(CompletionTextEdit::Edit(TextEdit {
+            range: range.into(),
+            new_text: item.label.clone(),
+        }));
+        item.filter_text = Some(item.label.clone());
+        item.insert_text = None;
+        item.insert_text_format = None;
+    }
+}
+
 /// completion_resources を取得する。
 fn load_completion_resources(
     mode: Mode,
-    env: &Environment,
-    _pos: &Position,
+    envs: &[&Environment],
+    locals: &[LocalBinding],
+    stage: Stage,
     trigger: &Option<String>,
-) -> Result<Vec<CompletionItem>> {
-    let items = match mode {
+    config: &Config,
+) -> Vec<CompletionItem> {
+    match mode {
         Mode::Program => {
             if let Some(tr) = trigger {
                 match tr.as_str() {
@@ -68,14 +339,36 @@ fn load_completion_resources(
                     _ => vec![], // unreachable だが致命的ではないのでpanicしない
                 }
             } else {
-                let mut vars = env.variables
+                // 局所的な束縛を優先し、それに隠されたトップレベルの変数は出さない。
+                let mut vars = locals.iter().map(local_completion_item).collect_vec();
+                let globals = envs
+                    .iter()
+                    .flat_map(|env| env.variables())
+                    .filter(|s| locals.iter().all(|l| l.name != s.name))
+                    .collect_vec();
+                vars.extend(globals.iter().map(|s| {
+                    // パッケージのモジュールの中で定義されたものは M.x の形で見える。
+                    let kind = if s.name.contains('.') {
+                        CompletionItemKind::Field
+                    } else {
+                        CompletionItemKind::Variable
+                    };
+                    definition_completion_item(&s.name, kind, SortGroup::Definition)
+                }));
+                let modules = globals
                     .iter()
-                    .map(|s| {
-                        CompletionItem::new_simple(s.name.clone(), s.name.clone())
+                    .filter_map(|s| s.name.rsplit_once('.').map(|(module, _)| module))
+                    .unique()
+                    .map(|module| {
+                        definition_completion_item(
+                            module,
+                            CompletionItemKind::Module,
+                            SortGroup::Module,
+                        )
                     })
-                .collect_vec();
-                let primitives = load_primitive_completion_items()?;
-                vars.extend(primitives);
+                    .collect_vec();
+                vars.extend(modules);
+                vars.extend(load_primitive_completion_items(stage, config));
                 vars
             }
         }
@@ -83,13 +376,10 @@ fn load_completion_resources(
         Mode::Math => {
             let show_cand = { trigger == &Some("\\".to_owned()) };
             if show_cand {
-                env.math_cmds
+                envs
                     .iter()
-                    .map(|s| {
-                        let mut item = CompletionItem::new_simple(s.name.clone(), s.name.clone());
-                        item.insert_text = Some(s.name.clone()[1..].to_owned());
-                        item
-                    })
+                    .flat_map(|env| env.commands(CmdKind::Math))
+                    .map(|c| command_completion_item(c.name))
                     .collect()
             } else {
                 vec![]
@@ -99,13 +389,10 @@ fn load_completion_resources(
         Mode::Horizontal => {
             let show_cand = { trigger == &Some("\\".to_owned()) };
             if show_cand {
-                env.inline_cmds
+                envs
                     .iter()
-                    .map(|s| {
-                        let mut item = CompletionItem::new_simple(s.name.clone(), s.name.clone());
-                        item.insert_text = Some(s.name.clone()[1..].to_owned());
-                        item
-                    })
+                    .flat_map(|env| env.commands(CmdKind::Inline))
+                    .map(|c| command_completion_item(c.name))
                     .collect()
             } else {
                 vec![]
@@ -115,13 +402,10 @@ fn load_completion_resources(
         Mode::Vertical => {
             let show_cand = { trigger == &Some("+".to_owned()) };
             if show_cand {
-                env.block_cmds
+                envs
                     .iter()
-                    .map(|s| {
-                        let mut item = CompletionItem::new_simple(s.name.clone(), s.name.clone());
-                        item.insert_text = Some(s.name.clone()[1..].to_owned());
-                        item
-                    })
+                    .flat_map(|env| env.commands(CmdKind::Block))
+                    .map(|c| command_completion_item(c.name))
                     .collect()
             } else {
                 vec![]
@@ -129,59 +413,677 @@ fn load_completion_resources(
         }
 
         _ => vec![],
+    }
+}
+
+/// 補完候補を並べる順序のグループ。エディタ上では上に書いたものほど先に表示される。
+#[derive(Debug, Clone, Copy)]
+enum SortGroup {
+    /// 関数適用の引数に期待される型を持つもの。
+    Expected,
+    /// カーソル位置から見える局所的な束縛。
+    Local,
+    /// バッファやパッケージで定義された変数やコマンド。
+    Definition,
+    /// パッケージのモジュール名。
+    Module,
+    /// completion.toml などで用意されたプリミティブ。
+    Primitive,
+}
+
+impl SortGroup {
+    /// グループと label から sort_text を作る。
+    fn sort_text(self, label: &str) -> String {
+        format!("{}_{}", self as u8, label)
+    }
+}
+
+/// 局所的な束縛を補完候補にする。
+fn local_completion_item(local: &LocalBinding) -> CompletionItem {
+    let (kind, detail) = match local.kind {
+        BindingKind::Variable => (CompletionItemKind::Variable, "local variable"),
+        BindingKind::Function => (CompletionItemKind::Function, "local function"),
+        BindingKind::Parameter => (CompletionItemKind::Variable, "parameter"),
+    };
+    CompletionItem {
+        detail: Some(detail.to_owned()),
+        ..definition_completion_item(&local.name, kind, SortGroup::Local)
+    }
+}
+
+/// 定義された名前を補完候補にする。
+fn definition_completion_item(
+    name: &str,
+    kind: CompletionItemKind,
+    group: SortGroup,
+) -> CompletionItem {
+    CompletionItem {
+        label: name.to_owned(),
+        kind: Some(kind),
+        detail: Some(name.to_owned()),
+        sort_text: Some(group.sort_text(name)),
+        ..Default::default()
+    }
+}
+
+/// ラベルの付いた別行立て数式を補完候補にする。文書中に現れる順に並べる。
+fn label_completion_item(math: &DisplayMath) -> Option<CompletionItem> {
+    let label = math.label.as_ref()?;
+    Some(CompletionItem {
+        label: label.clone(),
+        kind: Some(CompletionItemKind::Reference),
+        detail: Some(format!("({}) {}", math.number, math.command)),
+        sort_text: Some(format!("{:05}", math.number)),
+        ..Default::default()
+    })
+}
+
+/// `\cmd` や `+cmd` のようなコマンドを補完候補にする。
+/// 入力済みの sigil を含めて置き換える範囲は、[`anchor_at_sigil`] で後から決める。
+fn command_completion_item(name: &str) -> CompletionItem {
+    definition_completion_item(name, CompletionItemKind::Function, SortGroup::Definition)
+}
+
+/// プログラムモードのときに返すことのできる補完候補のうち、`stage` で使えるものを取得する。
+fn load_primitive_completion_items(stage: Stage, config: &Config) -> Vec<CompletionItem> {
+    config
+        .catalog
+        .section("primitive")
+        .into_iter()
+        .filter(|item| item.exists_in(config.language_version))
+        .filter(|item| item.stage.is_none_or(|s| s == stage))
+        .cloned()
+        .map(CompletionItem::from)
+        .collect()
+}
+
+/// 名前が name であるプリミティブのうち、設定された SATySFi の版にあるものの項目を返す。
+/// hover でも補完と同じ説明を見せるために用いる。
+pub(crate) fn find_primitive<'a>(name: &str, config: &'a Config) -> Option<&'a MyCompletionItem> {
+    config
+        .catalog
+        .get("primitive", None, name)
+        .filter(|item| item.exists_in(config.language_version))
+}
+
+/// 文書クラス `class` が定義するブロックコマンド `name` を、completion.toml などから探す。
+pub(crate) fn find_class_command<'a>(class: &str, name: &str, config: &'a Config) -> Option<&'a MyCompletionItem> {
+    config.catalog.get("classes", Some(class), name)
+}
+
+/// completion.toml の与えられたセクションの補完候補を取得する。
+fn load_section_completion_items(section: &str, config: &Config) -> Vec<CompletionItem> {
+    config
+        .catalog
+        .section(section)
+        .into_iter()
+        .cloned()
+        .map(CompletionItem::from)
+        .collect()
+}
+
+/// 引数に期待される型のリテラルの補完候補。
+const EXPECTED_LITERALS: &[(&str, &[&str])] = &[
+    ("length", &["0pt", "1cm", "1mm", "1inch"]),
+    ("bool", &["true", "false"]),
+];
+
+/// カーソルが関数適用の引数の位置にあり、その引数に期待される型が分かれば、
+/// その型を持つ変数を先頭に並べ、その型のリテラルを候補に加える。
+/// 関数の型は let の型注釈か、カタログに書かれたプリミティブの型から求める。
+fn rank_by_expected_type(
+    items: &mut Vec<CompletionItem>,
+    buf: &Buffer,
+    envs: &[&Environment],
+    locals: &[LocalBinding],
+    pos: &Position,
+) {
+    let index = buf.buf_cst.line_index();
+    let (func, arg) = match application_at(&buf.buf_cst.buffer[..index.offset(*pos)]) {
+        Some(application) => application,
+        None => return,
+    };
+    let type_of = |name: &str| variable_type(name, envs, locals);
+    let signature = type_of(func).or_else(|| {
+        items
+            .iter()
+            .find(|item| item.label == func)
+            .and_then(|item| item.detail.clone())
+            .filter(|detail| detail.contains("->"))
+    });
+    let expected = match signature.as_deref().and_then(|sig| expected_argument_type(sig, arg)) {
+        Some(ty) => normalize_type(ty),
+        None => return,
     };
-    Ok(items)
+
+    for item in items.iter_mut() {
+        if type_of(&item.label).is_some_and(|ty| normalize_type(&ty) == expected) {
+            item.sort_text = Some(SortGroup::Expected.sort_text(&item.label));
+        }
+    }
+    let literals = EXPECTED_LITERALS
+        .iter()
+        .filter(|(ty, _)| *ty == expected)
+        .flat_map(|(_, literals)| literals.iter());
+    items.extend(literals.map(|literal| CompletionItem {
+        detail: Some(expected.clone()),
+        ..definition_completion_item(literal, CompletionItemKind::Value, SortGroup::Expected)
+    }));
 }
 
-/// プログラムモードのときに返すことのできる補完候補を取得する。
-fn load_primitive_completion_items() -> Result<Vec<CompletionItem>> {
-    let resources: HashMap<String, Vec<MyCompletionItem>> = toml::from_str(COMPLETION_RESOUCES)?;
-    let items = resources
+/// 変数の型注釈か、値がリテラルであればその型を返す。
+/// 局所的な束縛があれば、それがトップレベルの変数を隠す。
+fn variable_type(name: &str, envs: &[&Environment], locals: &[LocalBinding]) -> Option<String> {
+    match locals.iter().find(|l| l.name == name) {
+        Some(local) => local.annotation.clone(),
+        None => envs.iter().flat_map(|env| env.variables()).rfind(|v| v.name() == name).and_then(|v| {
+            v.annotation()
+                .or_else(|| v.value().and_then(literal_type))
+                .map(str::to_owned)
+        }),
+    }
+}
+
+/// 型がコンテキストを受け取ってコンテキストを返す関数の型であるか。
+fn is_context_transformer(ty: &str) -> bool {
+    let types = arrow_types(ty);
+    types.len() >= 2 && types[types.len() - 2..].iter().all(|t| normalize_type(t) == "context")
+}
+
+/// パイプライン `x |>` の関数の位置で左辺がコンテキストであれば、
+/// コンテキストを変える関数（`set-font-size` など）を補完候補として返す。
+/// 左辺の型は、変数の型注釈とプリミティブの型から分かる単純な場合に限って求める。
+fn context_pipeline_items(
+    buf: &Buffer,
+    envs: &[&Environment],
+    locals: &[LocalBinding],
+    stage: Stage,
+    pos: &Position,
+    config: &Config,
+) -> Option<Vec<CompletionItem>> {
+    let index = buf.buf_cst.line_index();
+    let (head, args) = pipeline_operand(&buf.buf_cst.buffer[..index.offset(*pos)])?;
+    let primitives = load_primitive_completion_items(stage, config);
+    let primitive_type = |name: &str| {
+        primitives
+            .iter()
+            .find(|item| item.label == name)
+            .and_then(|item| item.detail.clone())
+            .filter(|detail| detail.contains("->"))
+    };
+
+    // すべての引数を与えた結果の型が、左辺の値の型である。
+    let head_type = variable_type(head, envs, locals).or_else(|| primitive_type(head))?;
+    let types = arrow_types(&head_type);
+    if types.len() != args + 1 || normalize_type(types[args]) != "context" {
+        return None;
+    }
+
+    let mut items = locals
+        .iter()
+        .filter(|local| local.annotation.as_deref().is_some_and(is_context_transformer))
+        .map(|local| CompletionItem {
+            detail: local.annotation.clone(),
+            ..local_completion_item(local)
+        })
+        .collect_vec();
+    let globals = envs
+        .iter()
+        .flat_map(|env| env.variables())
+        .filter(|v| locals.iter().all(|l| l.name != v.name))
+        .filter(|v| v.annotation().is_some_and(is_context_transformer))
+        .map(|v| CompletionItem {
+            detail: v.annotation().map(str::to_owned),
+            ..definition_completion_item(&v.name, CompletionItemKind::Function, SortGroup::Definition)
+        });
+    items.extend(globals);
+    let transformers = primitives
         .into_iter()
-        .filter(|(key, _)| key == "primitive")
-        .map(|(_, val)| val)
-        .next()
-        .ok_or_else(|| anyhow!("No field 'primitive' found in completion.toml."))?;
-    let items = items.into_iter().map(CompletionItem::from).collect();
-    Ok(items)
+        .filter(|item| item.detail.as_deref().is_some_and(is_context_transformer));
+    items.extend(transformers);
+    Some(items)
+}
+
+/// 与えられた位置が型注釈の中にあるか。
+/// 書きかけで取り除かれた文の中では、`let x :` や `val x :` のように `:` の後ろにあるかをテキストから判断する。
+fn in_type_annotation(buf: &Buffer, cst: &Cst, pos: &Position) -> bool {
+    if cst.dig(pos).iter().any(|c| c.rule == Rule::type_expr) {
+        return true;
+    }
+    let index = buf.buf_cst.line_index();
+    if follows_of(&buf.buf_cst.buffer[..index.offset(*pos)]) {
+        return true;
+    }
+    buf.buf_cst
+        .recoveries()
+        .iter()
+        .filter(|r| r.kind == RecoveryKind::IncompleteStatement)
+        .filter(|r| r.range.start <= *pos && *pos <= r.range.end)
+        .any(|r| {
+            let fragment = &buf.buf_cst.buffer[index.offset(r.range.start)..index.offset(*pos)];
+            is_annotating(fragment)
+        })
+}
+
+/// カーソルの直前が、入力中の語を除いてコンストラクタの引数の型を書く `of` か。
+fn follows_of(text: &str) -> bool {
+    let is_var_char = |c: char| c.is_ascii_alphanumeric() || c == '-';
+    let rest = text.trim_end_matches(is_var_char);
+    if rest.len() != text.len() && !rest.ends_with(char::is_whitespace) {
+        return false;
+    }
+    rest.trim_end()
+        .strip_suffix("of")
+        .is_some_and(|head| head.ends_with(char::is_whitespace))
+}
+
+/// 書きかけの文の末尾が、`let` や `val`、`direct` の型注釈の中にあるか。
+fn is_annotating(fragment: &str) -> bool {
+    // 最後に始まった宣言の行から末尾までを見る。
+    let start = fragment
+        .match_indices('\n')
+        .map(|(i, _)| i + 1)
+        .chain(std::iter::once(0))
+        .filter(|&i| {
+            let line = fragment[i..].trim_start();
+            ["let", "val", "direct"]
+                .iter()
+                .any(|kw| line.strip_prefix(kw).is_some_and(|rest| rest.starts_with(char::is_whitespace)))
+        })
+        .max();
+    match start.and_then(|i| fragment[i..].split_once(':')) {
+        Some((head, ty)) => !head.contains('=') && !ty.contains('='),
+        None => false,
+    }
+}
+
+/// カーソルの直前が、垂直モードにあるブロックコマンドのまだ閉じていない `<` であれば、
+/// 本体の行と閉じ括弧 `>` を挿入する snippet を返す。
+///
+/// カーソル位置に `>` を補ったテキストをパースし、補った `>` で閉じる引数を持つブロックコマンドを探す。
+/// `>` だけではパースできず `>;` ならばパースできる文脈では、`;` も挿入する。
+fn closing_block_cmd_completion_item(
+    text: &str,
+    pos: &Position,
+    config: &Config,
+) -> Option<CompletionItem> {
+    if text.len() > MAX_BUFFER_SIZE {
+        return None;
+    }
+    let offset = LineIndex::new(text).offset(*pos);
+    let head = text[..offset].strip_suffix('<')?;
+    // `'<` はブロックテキストのリテラルであり、コマンドの引数ではない。
+    if head.ends_with('\'') {
+        return None;
+    }
+
+    let (cst, closing, patched) = [">", ">;"].iter().find_map(|&closing| {
+        let patched = format!("{}{}{}", &text[..offset], closing, &text[offset..]);
+        let rule = config.language_version.program_rule();
+        let mut pairs = SatysfiParser::parse(rule, &patched).ok()?;
+        Some((Cst::new(pairs.next().unwrap(), &patched), closing, patched))
+    })?;
+    if cst.mode(pos) != Mode::Vertical {
+        return None;
+    }
+    let block_cmd = cst.dig(pos).into_iter().find(|c| c.rule == Rule::block_cmd)?;
+    let opens_here = block_cmd.inner.iter().any(|arg| {
+        arg.rule == Rule::cmd_text_arg && arg.range.start.byte as usize == head.len()
+    });
+    if !opens_here {
+        return None;
+    }
+    let name = block_cmd.inner.first()?;
+    let name = &patched[name.range.start.byte as usize..name.range.end.byte as usize];
+
+    let line = &head[head.rfind('\n').map_or(0, |i| i + 1)..];
+    let indent = &line[..line.len() - line.trim_start().len()];
+    Some(CompletionItem {
+        label: format!("{}<...>", name),
+        kind: Some(CompletionItemKind::Snippet),
+        detail: Some(format!("close the block text of {}", name)),
+        insert_text: Some(format!("\n{}  $0\n{}{}", indent, indent, closing)),
+        insert_text_format: Some(InsertTextFormat::Snippet),
+        sort_text: Some(SortGroup::Local.sort_text(name)),
+        ..Default::default()
+    })
+}
+
+/// カーソルが数値のリテラルの直後にあれば、単位に置き換える範囲を返す。
+/// `12p` のように単位を入力している途中であれば、入力済みの単位を範囲に含める。
+fn length_unit_range(cst: &Cst, pos: &Position) -> Option<Range> {
+    let literal = cst.dig(pos).into_iter().find(|c| {
+        matches!(c.rule, Rule::int_const | Rule::float_const | Rule::length_const)
+    })?;
+    let end: Position = literal.range.end.clone().into();
+    if &end != pos {
+        return None;
+    }
+    match literal.rule {
+        Rule::length_const => {
+            let unit = literal.inner.iter().find(|c| c.rule == Rule::length_unit)?;
+            Some(unit.range.clone().into())
+        }
+        // 16 進数のリテラルには単位を付けられない。
+        Rule::int_const if literal.inner.iter().any(|c| c.rule == Rule::int_hex_const) => None,
+        _ => Some(Range { start: *pos, end: *pos }),
+    }
+}
+
+/// カーソルが `match x with` の直後にあり、x の型がコンストラクタの分かっているヴァリアント型であれば、
+/// すべてのコンストラクタを腕として並べる snippet を返す。
+/// x の型は、x を束縛する let に書かれた型注釈から求める。
+fn match_arms_completion_item(
+    buf: &Buffer,
+    envs: &[&Environment],
+    locals: &[LocalBinding],
+    pos: &Position,
+) -> Option<CompletionItem> {
+    let index = buf.buf_cst.line_index();
+    let scrutinee = match_scrutinee(&buf.buf_cst.buffer[..index.offset(*pos)])?;
+    // 局所的な束縛があれば、それがトップレベルの変数を隠す。
+    let annotation = match locals.iter().find(|l| l.name == scrutinee) {
+        Some(local) => local.annotation.clone(),
+        None => envs
+            .iter()
+            .flat_map(|env| env.variables())
+            .rfind(|v| v.name == scrutinee)
+            .and_then(|v| v.annotation.clone()),
+    }?;
+    // `int list` のような型適用では、最後の名前が型である。
+    let type_name = annotation.split_whitespace().last()?;
+    let ty = envs
+        .iter()
+        .flat_map(|env| env.types())
+        .rfind(|ty| ty.name == type_name && !ty.constructors.is_empty())?;
+
+    let mut tabstop = 0;
+    let mut next_tabstop = || {
+        tabstop += 1;
+        tabstop
+    };
+    let arms = ty
+        .constructors
+        .iter()
+        .map(|c| {
+            let ptn = if c.has_argument {
+                format!("{}(${{{}:_}})", c.name, next_tabstop())
+            } else {
+                c.name.clone()
+            };
+            format!("| {} -> ${}", ptn, next_tabstop())
+        })
+        .join("\n");
+    Some(CompletionItem {
+        label: format!("match arms of {}", ty.name),
+        kind: Some(CompletionItemKind::Snippet),
+        detail: Some(ty.constructors.iter().map(|c| &c.name).join(" | ")),
+        insert_text: Some(arms),
+        insert_text_format: Some(InsertTextFormat::Snippet),
+        sort_text: Some(SortGroup::Local.sort_text(&ty.name)),
+        ..Default::default()
+    })
+}
+
+/// テキストが `match x with` で終わっていれば、x を返す。
+fn match_scrutinee(text: &str) -> Option<&str> {
+    let rest = text.trim_end().strip_suffix("with")?;
+    let expr = rest.trim_end();
+    if expr.len() == rest.len() {
+        return None;
+    }
+    let is_var_char = |c: char| c.is_ascii_alphanumeric() || c == '-';
+    let start = expr
+        .char_indices()
+        .rev()
+        .find(|(_, c)| !is_var_char(*c))
+        .map_or(0, |(i, c)| i + c.len_utf8());
+    let var = &expr[start..];
+    let head = expr[..start].trim_end().strip_suffix("match")?;
+    let keyword_boundary = !head.ends_with(is_var_char);
+    let is_var = var.starts_with(|c: char| c.is_ascii_lowercase());
+    (keyword_boundary && is_var).then_some(var)
+}
+
+/// `@require:` で読み込んだパッケージのうち、文書クラスであるものの名前を返す。
+/// `document` を定義しているパッケージか、completion.toml にコマンドの書かれたクラスを文書クラスとみなす。
+pub(crate) fn document_class(buf: &Buffer, config: &Config) -> Option<String> {
+    let known = config.catalog.section("classes");
+    buf.buf_cst
+        .headers()
+        .into_iter()
+        .filter(|(kind, _)| *kind == PackageKind::Require)
+        .map(|(_, name)| name)
+        .find(|name| {
+            let defines_document = buf.packages.iter().any(|pkg| {
+                &pkg.name == name && pkg.env.variables().iter().any(|var| var.name == "document")
+            });
+            defines_document || known.iter().any(|item| item.class.as_ref() == Some(name))
+        })
+}
+
+/// 文書クラス `class` の `document` に渡すレコードのフィールドを、completion.toml から取得する。
+pub(crate) fn document_fields<'a>(class: &str, config: &'a Config) -> Vec<&'a MyCompletionItem> {
+    config
+        .catalog
+        .section("fields")
+        .into_iter()
+        .filter(|item| item.class.as_deref() == Some(class))
+        .collect()
+}
+
+/// カーソルが `document (| ... |)` のレコードの中でフィールド名を書く位置にあれば、そのレコードの中身を返す。
+/// 書きかけのレコードではパースに失敗するため、テキストから判断する。
+/// 中身は `(|` の直後から、対応する `|)` の直前（なければテキストの末尾）までとする。
+fn document_record_at(text: &str, offset: usize) -> Option<&str> {
+    let (before, after) = text.split_at(offset);
+    let open = before.rfind("(|")?;
+    let inner_before = &before[open + 2..];
+    // フィールドの値の中では候補を出さない。
+    let unit = inner_before.rsplit(';').next().unwrap_or_default();
+    if inner_before.contains("|)") || unit.contains('=') {
+        return None;
+    }
+    let head = before[..open].trim_end().strip_suffix("document")?;
+    if head.ends_with(|c: char| c.is_ascii_alphanumeric() || c == '-') {
+        return None;
+    }
+    let end = after.find("|)").map_or(text.len(), |i| offset + i);
+    Some(&text[open + 2..end])
+}
+
+/// レコードの中身に書かれたフィールド名を列挙する。
+fn record_field_names(record: &str) -> Vec<&str> {
+    record
+        .split(';')
+        .filter_map(|unit| unit.split_once('=').map(|(name, _)| name.trim()))
+        .filter(|name| name.starts_with(|c: char| c.is_ascii_lowercase()))
+        .collect()
+}
+
+/// `document (| ... |)` のレコードの中で、文書クラスのフィールドのうちまだ書かれていないものを候補として返す。
+fn document_field_items(buf: &Buffer, pos: &Position, config: &Config) -> Option<Vec<CompletionItem>> {
+    let text = &buf.buf_cst.buffer;
+    let record = document_record_at(text, buf.buf_cst.line_index().offset(*pos))?;
+    let class = document_class(buf.latest_parsed().unwrap_or(buf), config)?;
+    let written = record_field_names(record);
+    let items = document_fields(&class, config)
+        .into_iter()
+        .filter(|field| !written.contains(&field.label.as_str()))
+        .cloned()
+        .map(CompletionItem::from)
+        .collect();
+    Some(items)
+}
+
+/// 文書クラス `class` が定義するブロックコマンドの補完候補を取得する。
+fn load_class_completion_items(class: &str, config: &Config) -> Vec<CompletionItem> {
+    config
+        .catalog
+        .section("classes")
+        .into_iter()
+        .filter(|item| item.class.as_deref() == Some(class))
+        .map(|item| CompletionItem {
+            kind: Some(CompletionItemKind::Function),
+            ..CompletionItem::from(item.clone())
+        })
+        .collect()
+}
+
+/// 空白行とコメント、ヘッダしか含まないか。
+fn is_header_only(text: &str) -> bool {
+    text.lines().all(|line| {
+        let line = line.trim();
+        line.is_empty() || line.starts_with('@') || line.starts_with('%')
+    })
+}
+
+/// 文書の雛形の補完候補を取得する。
+/// 雛形が必要とするパッケージが `text` でまだ読み込まれていなければ、挿入する文字列の先頭にヘッダを加える。
+/// 空の文書では補完そのものの挿入位置も文書の先頭になるため、ヘッダを別の編集にすると範囲が重なってしまう。
+fn load_template_completion_items(text: &str, config: &Config) -> Vec<CompletionItem> {
+    config
+        .catalog
+        .section("templates")
+        .into_iter()
+        .cloned()
+        .map(|mut template| {
+            let require = template.require.take();
+            let mut item = CompletionItem::from(template);
+            if let Some(package) = require {
+                let header = format!("@require: {}", package);
+                if !text.lines().any(|line| line.trim() == header) {
+                    let body = item.insert_text.take().unwrap_or_else(|| item.label.clone());
+                    item.insert_text = Some(format!("{}\n{}", header, body));
+                }
+            }
+            item
+        })
+        .collect()
 }
 
 /// TOML ファイルに記述する completion items.
-#[derive(Debug, Deserialize)]
-struct MyCompletionItem {
+#[derive(Debug, Clone, Deserialize)]
+pub(crate) struct MyCompletionItem {
     /// The label of this completion item. By default also the text that is inserted when selecting
     /// this completion.
-    label: String,
+    pub(crate) label: String,
     /// A human-readable string with additional information about this item, like type or symbol
     /// information.
-    detail: Option<String>,
-    /// A human-readable string that represents a doc-comment.
-    documentation: Option<String>,
+    pub(crate) detail: Option<String>,
+    /// A human-readable string that represents a doc-comment, selected from
+    /// `localized_documentation` according to the client's locale.
+    #[serde(skip)]
+    pub(crate) documentation: Option<String>,
+    /// The doc-comment written in the TOML file: a string, or a table of strings keyed by language.
+    #[serde(rename = "documentation")]
+    pub(crate) localized_documentation: Option<LocalizedText>,
     /// A string that should be inserted a document when selecting this completion. When falsy the
     /// label is used.
     insert_text: Option<String>,
     /// The format of the insert text. The format applies to both the insertText property and the
     /// newText property of a provided textEdit.
     insert_text_format: Option<String>,
+    /// The kind of this completion item: "function", "keyword", "snippet" and so on. When omitted,
+    /// snippets are "snippet" and the others are "function".
+    kind: Option<String>,
+    /// The package which a document template requires. Used only in the "templates" section.
+    require: Option<String>,
+    /// The document class which defines a block command or a field of the document record. Used
+    /// only in the "classes" and "fields" sections.
+    pub(crate) class: Option<String>,
+    /// The expected argument structure of a block command, like `+section{title}<contents>`. Used
+    /// only in the "classes" section.
+    pub(crate) structure: Option<String>,
+    /// A short usage example of a block command. Used only in the "classes" section.
+    pub(crate) example: Option<String>,
+    /// The only stage at which a primitive can be used. When omitted, it can be used at any stage.
+    stage: Option<Stage>,
+    /// The only SATySFi version in which a primitive exists. When omitted, it exists in any version.
+    language_version: Option<LanguageVersion>,
+}
+
+impl MyCompletionItem {
+    /// SATySFi の版 `version` にある項目か。
+    fn exists_in(&self, version: LanguageVersion) -> bool {
+        self.language_version.is_none_or(|v| v == version)
+    }
+}
+
+/// 言語ごとに書き分けられる文字列。
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub(crate) enum LocalizedText {
+    /// 一つの言語だけで書かれたもの。
+    Plain(String),
+    /// `ja`, `en` などの言語をキーとして書き分けたもの。
+    ByLanguage(HashMap<String, String>),
+}
+
+impl LocalizedText {
+    /// ロケール (`ja-JP` など) に合う文字列を選ぶ。
+    /// ロケールそのもの、その言語の部分、英語の順に探し、どれもなければ言語の名前順で最初のものを選ぶ。
+    pub(crate) fn select(self, locale: Option<&str>) -> String {
+        let mut texts = match self {
+            LocalizedText::Plain(text) => return text,
+            LocalizedText::ByLanguage(texts) => texts,
+        };
+        let language = locale.map(|locale| locale.split(['-', '_']).next().unwrap_or(locale));
+        let preferred = [locale, language, Some("en")];
+        if let Some(key) = preferred.iter().flatten().find(|key| texts.contains_key(**key)) {
+            return texts.remove(*key).unwrap();
+        }
+        texts
+            .into_iter()
+            .min_by(|(a, _), (b, _)| a.cmp(b))
+            .map(|(_, text)| text)
+            .unwrap_or_default()
+    }
 }
 
 impl From<MyCompletionItem> for CompletionItem {
     fn from(my_item: MyCompletionItem) -> Self {
-        let mut item = CompletionItem::default();
-        item.label = my_item.label;
-        item.detail = my_item.detail;
-        item.insert_text = my_item.insert_text;
-        item.insert_text_format = if my_item.insert_text_format == Some("snippet".to_owned()) {
+        let insert_text_format = if my_item.insert_text_format == Some("snippet".to_owned()) {
             Some(InsertTextFormat::Snippet)
         } else {
             None
         };
-        item.documentation = my_item.documentation.map(|s| {
+        let kind = match my_item.kind.as_deref() {
+            Some("keyword") => CompletionItemKind::Keyword,
+            Some("variable") => CompletionItemKind::Variable,
+            Some("module") => CompletionItemKind::Module,
+            Some("field") => CompletionItemKind::Field,
+            Some("snippet") => CompletionItemKind::Snippet,
+            Some("function") => CompletionItemKind::Function,
+            Some("type") => CompletionItemKind::Struct,
+            Some("unit") => CompletionItemKind::Unit,
+            _ if insert_text_format.is_some() => CompletionItemKind::Snippet,
+            _ => CompletionItemKind::Function,
+        };
+        let filter_text = my_item
+            .label
+            .strip_prefix(|c| c == '\\' || c == '+')
+            .map(str::to_owned);
+        let sort_text = Some(SortGroup::Primitive.sort_text(&my_item.label));
+        let documentation = my_item.documentation.map(|s| {
             Documentation::MarkupContent(MarkupContent {
                 kind: lsp_types::MarkupKind::Markdown,
                 value: s,
             })
         });
-        item
+        CompletionItem {
+            label: my_item.label,
+            detail: my_item.detail,
+            insert_text: my_item.insert_text,
+            insert_text_format,
+            documentation,
+            kind: Some(kind),
+            filter_text,
+            sort_text,
+            ..Default::default()
+        }
     }
 }
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,642 @@
+//! test module for completion.
+
+use super::*;
+use crate::test_utils::TestDocument;
+
+fn primitive(label: &str) -> CompletionItem {
+    load_primitive_completion_items(Stage::One, &Config::default())
+        .into_iter()
+        .find(|item| item.label == label)
+        .unwrap()
+}
+
+#[test]
+fn test_primitive_kind() {
+    assert_eq!(primitive("let-inline").kind, Some(CompletionItemKind::Snippet));
+    assert_eq!(primitive("inline-fil").kind, Some(CompletionItemKind::Variable));
+    assert_eq!(primitive("arabic").kind, Some(CompletionItemKind::Function));
+}
+
+#[test]
+fn test_primitives_by_stage() {
+    let doc = TestDocument::new("@stage: 0\nlet x = ^1y in &(f ^2z)\n");
+    let buf = doc.buffer();
+    let labels = |marker| {
+        get_completion_list(&buf, &doc.position(marker), &None, &Config::default())
+            .items
+            .into_iter()
+            .map(|item| item.label)
+            .collect_vec()
+    };
+    let stage0 = labels(1);
+    assert!(stage0.iter().any(|l| l == "lift-int"));
+    assert!(stage0.iter().any(|l| l == "arabic"));
+    assert!(!stage0.iter().any(|l| l == "read-inline"));
+    let stage1 = labels(2);
+    assert!(!stage1.iter().any(|l| l == "lift-int"));
+    assert!(stage1.iter().any(|l| l == "read-inline"));
+}
+
+#[test]
+fn test_primitives_by_language_version() {
+    let labels = |config: &Config| {
+        let doc = TestDocument::new("let x = y^1\n");
+        get_completion_list(&doc.buffer(), &doc.position(1), &None, config)
+            .items
+            .into_iter()
+            .map(|item| item.label)
+            .collect_vec()
+    };
+    assert!(!labels(&Config::default()).iter().any(|l| l == "use package"));
+    let config = Config { language_version: LanguageVersion::Next, ..Default::default() };
+    let next = labels(&config);
+    assert!(next.iter().any(|l| l == "use package"));
+    assert!(next.iter().any(|l| l == "arabic"));
+}
+
+#[test]
+fn test_localized_documentation() {
+    let documentation = |locale: Option<&str>| {
+        let mut config = Config { locale: locale.map(str::to_owned), ..Default::default() };
+        config.build_catalog().unwrap();
+        load_primitive_completion_items(Stage::One, &config)
+            .into_iter()
+            .find(|item| item.label == "let-block")
+            .and_then(|item| item.documentation)
+    };
+    let text = |value: &str| {
+        Some(Documentation::MarkupContent(MarkupContent {
+            kind: lsp_types::MarkupKind::Markdown,
+            value: value.to_owned(),
+        }))
+    };
+    assert_eq!(documentation(Some("ja")), text("ブロックコマンドを新たに定義する。\n"));
+    assert_eq!(documentation(Some("ja-JP")), text("ブロックコマンドを新たに定義する。\n"));
+    assert_eq!(documentation(Some("fr")), text("Declare new block-cmd.\n"));
+    assert_eq!(documentation(None), text("Declare new block-cmd.\n"));
+}
+
+#[test]
+fn test_localized_text_fallback() {
+    let texts = |pairs: &[(&str, &str)]| {
+        let texts = pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        LocalizedText::ByLanguage(texts)
+    };
+    assert_eq!(LocalizedText::Plain("a".to_owned()).select(Some("ja")), "a");
+    assert_eq!(texts(&[("ja", "あ"), ("fr", "b")]).select(Some("de")), "b");
+    assert_eq!(texts(&[("en-GB", "c"), ("en", "d")]).select(Some("en-GB")), "c");
+}
+
+#[test]
+fn test_command_item() {
+    let item = command_completion_item("\\emph");
+    assert_eq!(item.kind, Some(CompletionItemKind::Function));
+    assert_eq!(item.insert_text, None);
+}
+
+#[test]
+fn test_anchor_at_sigil() {
+    let parsed = Buffer::new("let-math \\alpha = ${a}\nin\n'<\n  +p{ ${a} }\n>\n".to_owned());
+    let doc = TestDocument::new("let-math \\alpha = ${a}\nin\n'<\n  +p{ ${\\al^1} }\n  +q(\n>\n");
+    let mut buf = doc.buffer();
+    buf.inherit_last_parsed(parsed);
+    let edit = |marker, trigger: &str, label: &str| {
+        let trigger = Some(trigger.to_owned());
+        let item = get_completion_list(&buf, &doc.position(marker), &trigger, &Config::default())
+            .items
+            .into_iter()
+            .find(|item| item.label == label)?;
+        assert_eq!(item.filter_text.as_deref(), Some(label));
+        match item.text_edit? {
+            CompletionTextEdit::Edit(edit) => Some((Range::from(edit.range), edit.new_text)),
+            _ => None,
+        }
+    };
+
+    // 数式コマンドは、入力済みの `\` から名前全体を挿入し直す。
+    let (range, new_text) = edit(1, "\\", "\\alpha").unwrap();
+    assert_eq!(range.start, Position { line: 3, character: 8 });
+    assert_eq!(range.end, doc.position(1));
+    assert_eq!(new_text, "\\alpha");
+
+    // `+` の直後の文書クラスのコマンドでは、snippet に `+` を付けて挿入する。
+    let header = "@require: stdjabook\n";
+    let parsed = Buffer::new(format!("{}document (||) '<\n  +p{{}}\n>\n", header));
+    let doc = TestDocument::new(&format!("{}document (||) '<\n  +p{{}}\n  +^1\n>\n", header));
+    let mut buf = doc.buffer();
+    buf.inherit_last_parsed(parsed);
+    let item = get_completion_list(&buf, &doc.position(1), &Some("+".to_owned()), &Config::default())
+        .items
+        .into_iter()
+        .find(|item| item.label == "+chapter")
+        .unwrap();
+    match item.text_edit {
+        Some(CompletionTextEdit::Edit(edit)) => {
+            assert_eq!(Position::from(edit.range.start), Position { line: 3, character: 2 });
+            assert_eq!(edit.new_text, "+chapter{${1:Chapter}}<\n  $0\n>");
+        }
+        _ => panic!("no text edit for +chapter"),
+    }
+    assert_eq!(item.insert_text, None);
+    assert_eq!(item.insert_text_format, Some(InsertTextFormat::Snippet));
+}
+
+#[test]
+fn test_sort_order() {
+    let local = SortGroup::Local.sort_text("z");
+    let definition = SortGroup::Definition.sort_text("a");
+    let primitive = primitive("abort-with-message").sort_text.unwrap();
+    assert!(local < definition);
+    assert!(definition < primitive);
+}
+
+#[test]
+fn test_templates_in_empty_document() {
+    assert!(is_header_only(""));
+    assert!(is_header_only("  \n\t\n"));
+    let items = load_template_completion_items("", &Config::default());
+    let book = items.iter().find(|item| item.label == "stdjabook document").unwrap();
+    assert_eq!(book.kind, Some(CompletionItemKind::Snippet));
+    // ヘッダは補完の挿入位置と重なる別の編集ではなく、挿入する文字列に含める。
+    assert!(book.insert_text.as_deref().unwrap().starts_with("@require: stdjabook\ndocument"));
+    assert!(book.additional_text_edits.is_none());
+    let report = items.iter().find(|item| item.label == "stdjareport document").unwrap();
+    assert!(report.insert_text.as_deref().unwrap().starts_with("@require: stdjareport\n"));
+}
+
+#[test]
+fn test_templates_in_header_only_document() {
+    assert!(is_header_only("@require: stdjabook\n% comment\n\n"));
+    let doc = TestDocument::new("@require: stdjabook\n^1\n");
+    let items = get_completion_list(&doc.buffer(), &doc.position(1), &None, &Config::default()).items;
+    // すでに読み込んでいるパッケージのヘッダは加えない。
+    let book = items.iter().find(|item| item.label == "stdjabook document").unwrap();
+    assert!(book.insert_text.as_deref().unwrap().starts_with("document"));
+    let report = items.iter().find(|item| item.label == "stdjareport document").unwrap();
+    assert!(report.insert_text.as_deref().unwrap().starts_with("@require: stdjareport\n"));
+}
+
+#[test]
+fn test_no_templates_with_body() {
+    assert!(!is_header_only("@require: stdjabook\n\ndocument (||) '<>\n"));
+}
+
+#[test]
+fn test_labels_in_reference_command() {
+    let doc = TestDocument::new("'<\n  +math?:(`eq:b`)(${y});\n  +math?:(`eq:a`)(${x});\n  +p{ \\eqref(`e^1q`); }\n>\n");
+    let buf = doc.buffer();
+    let items = get_completion_list(&buf, &doc.position(1), &None, &Config::default()).items;
+    let labels = items.iter().map(|item| item.label.as_str()).collect_vec();
+    assert_eq!(labels, vec!["eq:b", "eq:a"]);
+    assert_eq!(items[1].detail.as_deref(), Some("(2) +math"));
+    assert!(items[0].sort_text < items[1].sort_text);
+}
+
+#[test]
+fn test_types_in_let_annotation() {
+    let doc = TestDocument::new("type point = int * int\nlet origin : poi^1nt = (0^2, 0)\nin\n'<>\n");
+    let buf = doc.buffer();
+    let items = get_completion_list(&buf, &doc.position(1), &None, &Config::default()).items;
+    let point = items.iter().find(|item| item.label == "point").unwrap();
+    assert_eq!(point.kind, Some(CompletionItemKind::Struct));
+    assert!(items.iter().any(|item| item.label == "inline-text"));
+    assert!(items.iter().all(|item| item.label != "let-inline"));
+
+    // 型注釈の外ではプリミティブの型は候補にならない。
+    let items = get_completion_list(&buf, &doc.position(2), &None, &Config::default()).items;
+    assert!(items.iter().all(|item| item.label != "inline-text"));
+}
+
+#[test]
+fn test_types_in_incomplete_annotation() {
+    let doc = TestDocument::new("let x = 1\nlet y : ^1\nin\n'<>\n");
+    let buf = doc.buffer();
+    let items = get_completion_list(&buf, &doc.position(1), &None, &Config::default()).items;
+    assert!(items.iter().any(|item| item.label == "length"));
+
+    assert!(is_annotating("module M : sig\n  val f : int -> "));
+    assert!(!is_annotating("let f x = x : "));
+    assert!(!is_annotating("let-inline ctx \\cmd :"));
+}
+
+#[test]
+fn test_types_after_of() {
+    let doc = TestDocument::new("type shape = | Circle of ^1\nlet x = 1\nin\n'<>\n");
+    let buf = doc.buffer();
+    let items = get_completion_list(&buf, &doc.position(1), &None, &Config::default()).items;
+    assert!(items.iter().any(|item| item.label == "length"));
+    assert!(items.iter().all(|item| item.label != "let-inline"));
+
+    assert!(follows_of("type t = A of le"));
+    assert!(!follows_of("let proof = 1"));
+    assert!(!follows_of("let x = f of-"));
+}
+
+#[test]
+fn test_rank_by_expected_type() {
+    let doc = TestDocument::new("let w = 3pt\nlet n : int = 1\nlet g : length -> int -> unit = h\nlet c = set-font-size ^1\nin\n'<>\n");
+    let buf = doc.buffer();
+    let sort_text = |items: &[CompletionItem], label: &str| {
+        items.iter().find(|item| item.label == label).unwrap().sort_text.clone().unwrap()
+    };
+
+    // プリミティブの型から、第 1 引数に length が期待される。
+    let items = get_completion_list(&buf, &doc.position(1), &None, &Config::default()).items;
+    assert_eq!(sort_text(&items, "w"), SortGroup::Expected.sort_text("w"));
+    assert_eq!(sort_text(&items, "n"), SortGroup::Definition.sort_text("n"));
+    assert_eq!(sort_text(&items, "1cm"), SortGroup::Expected.sort_text("1cm"));
+
+    // 型注釈から、第 2 引数に int が期待される。
+    let doc = TestDocument::new(&doc.text().replace("set-font-size ", "g w ^1"));
+    let items = get_completion_list(&doc.buffer(), &doc.position(1), &None, &Config::default()).items;
+    assert_eq!(sort_text(&items, "n"), SortGroup::Expected.sort_text("n"));
+    assert_eq!(sort_text(&items, "w"), SortGroup::Definition.sort_text("w"));
+    assert!(items.iter().all(|item| item.label != "1cm"));
+}
+
+#[test]
+fn test_match_arms() {
+    let doc = TestDocument::new("type shape = | Circle of length | Square of length | Empty\nlet s : shape = Empty\nlet f x =\n  match s with\n^1\nin\n'<>\n");
+    let buf = doc.buffer();
+    let items = get_completion_list(&buf, &doc.position(1), &None, &Config::default()).items;
+    assert_eq!(items.len(), 1);
+    assert_eq!(items[0].label, "match arms of shape");
+    assert_eq!(
+        items[0].insert_text.as_deref(),
+        Some("| Circle(${1:_}) -> $2\n| Square(${3:_}) -> $4\n| Empty -> $5")
+    );
+
+    // 局所的な束縛の型注釈も使う。型の分からない変数では snippet を出さない。
+    let doc = TestDocument::new("type t = A | B\nin\nlet z : t = A in\nmatch z with^1\n| A -> 1\n");
+    let buf = doc.buffer();
+    assert!(buf.error.is_empty());
+    let items = get_completion_list(&buf, &doc.position(1), &None, &Config::default()).items;
+    assert!(items.iter().any(|item| item.label == "match arms of t"));
+    assert_eq!(match_scrutinee("let f x =\n  match y with "), Some("y"));
+    assert_eq!(match_scrutinee("rematch y with"), None);
+    assert_eq!(match_scrutinee("match f y with"), None);
+}
+
+#[test]
+fn test_usage_examples_in_documentation() {
+    let uri = Url::parse("file:///main.saty").unwrap();
+    let mut index = WorkspaceIndex::default();
+    let other = Url::parse("file:///other.saty").unwrap();
+    index.update(other, &Buffer::new("'<\n  +p{ \\mine{x} }\n>\n".to_owned()));
+
+    let mut items = vec![command_completion_item("\\mine"), command_completion_item("\\unused")];
+    add_usage_examples(&mut items, &uri, &index, &Config::default());
+    match &items[0].documentation {
+        Some(Documentation::MarkupContent(content)) => {
+            assert!(content.value.contains("\\mine{x}  % other.saty:2"))
+        }
+        doc => panic!("unexpected documentation: {:?}", doc),
+    }
+    assert!(items[1].documentation.is_none());
+
+    let config = Config::from_toml("usage-examples = 0").unwrap();
+    let mut items = vec![command_completion_item("\\mine")];
+    add_usage_examples(&mut items, &uri, &index, &config);
+    assert!(items[0].documentation.is_none());
+}
+
+#[test]
+fn test_close_block_cmd() {
+    let item = |source: &str| {
+        let doc = TestDocument::new(source);
+        get_completion_list(&doc.buffer(), &doc.position(1), &Some("<".to_owned()), &Config::default())
+            .items
+            .into_iter()
+            .find(|item| item.kind == Some(CompletionItemKind::Snippet))
+    };
+    let closing = item("'<\n  +section{Title}<^1\n>\n").unwrap();
+    assert_eq!(closing.label, "+section<...>");
+    assert_eq!(closing.insert_text.as_deref(), Some("\n    $0\n  >"));
+    assert_eq!(closing.insert_text_format, Some(InsertTextFormat::Snippet));
+
+    // 閉じ括弧がすでにある場合や、ブロックテキストのリテラルでは出さない。
+    assert!(item("'<\n  +section{Title}<^1>\n>\n").is_none());
+    assert!(item("let x = '<^1\nin\n'<>\n").is_none());
+}
+
+#[test]
+fn test_complete_with_last_parsed() {
+    let parsed = Buffer::new("let-inline ctx \\foo = {}\nin\n'<\n  +p{ \\f; }\n>\n".to_owned());
+    let doc = TestDocument::new("let-inline ctx \\foo = {}\nin\n'<\n  +p{ \\f^1 }\n  +q(\n>\n");
+    let mut buf = doc.buffer();
+    let pos = doc.position(1);
+    let trigger = Some("\\".to_owned());
+    assert!(buf.buf_cst.cst.is_none());
+    assert!(get_completion_list(&buf, &pos, &trigger, &Config::default()).items.is_empty());
+
+    buf.inherit_last_parsed(parsed);
+    let items = get_completion_list(&buf, &pos, &trigger, &Config::default()).items;
+    let labels = items.iter().map(|item| item.label.as_str()).collect_vec();
+    assert_eq!(labels, vec!["\\foo"]);
+}
+
+#[test]
+fn test_mode_of_shifted_paragraph() {
+    // 古い版では 5 行目が存在しないため、モードは今の版の段落だけをパースして決める。
+    let parsed = Buffer::new("let-inline ctx \\foo = {}\nin\n'<\n  +p{ \\f; }\n>\n".to_owned());
+    let doc = TestDocument::new("let-inline ctx \\foo = {}\nin\n'<\n  +p{ x }\n  +q(\n  +p{ \\f^1 }\n>\n");
+    let mut buf = doc.buffer();
+    assert!(buf.buf_cst.cst.is_none());
+    buf.inherit_last_parsed(parsed);
+    let items = get_completion_list(&buf, &doc.position(1), &Some("\\".to_owned()), &Config::default()).items;
+    let labels = items.iter().map(|item| item.label.as_str()).collect_vec();
+    assert_eq!(labels, vec!["\\foo"]);
+}
+
+#[test]
+fn test_inline_cmds_in_math_text_arg() {
+    let doc = TestDocument::new("let-inline ctx \\foo = {}\nin\n'<\n  +p{ ${ \\text!{ a \\f^1 ^2} } }\n>\n");
+    let buf = doc.buffer();
+    let trigger = Some("\\".to_owned());
+    for marker in [1, 2] {
+        let items = get_completion_list(&buf, &doc.position(marker), &trigger, &Config::default()).items;
+        let labels = items.iter().map(|item| item.label.as_str()).collect_vec();
+        assert_eq!(labels, vec!["\\foo"]);
+    }
+}
+
+#[test]
+fn test_length_units_after_number() {
+    let labels_at = |source: &str| {
+        let doc = TestDocument::new(source);
+        get_completion_list(&doc.buffer(), &doc.position(1), &None, &Config::default()).items
+    };
+
+    let items = labels_at("let x = 12^1\n");
+    let labels = items.iter().map(|item| item.label.as_str()).collect_vec();
+    assert_eq!(labels, vec!["pt", "mm", "cm", "inch", "em", "ex"]);
+    assert_eq!(items[0].kind, Some(CompletionItemKind::Unit));
+
+    // 入力途中の単位は置き換える。
+    let items = labels_at("let x = 1.5p^1\n");
+    match &items[0].text_edit {
+        Some(CompletionTextEdit::Edit(edit)) => {
+            assert_eq!(Position::from(edit.range.start), Position { line: 0, character: 11 });
+            assert_eq!(edit.new_text, "pt");
+        }
+        edit => panic!("unexpected text edit: {:?}", edit),
+    }
+
+    assert!(labels_at("let x = 0x1F^1\n").iter().all(|item| item.label != "pt"));
+    assert!(labels_at("let x = 12 ^1\n").iter().all(|item| item.label != "pt"));
+}
+
+#[test]
+fn test_block_cmds_of_document_class() {
+    let labels = |header: &str| {
+        let doc = TestDocument::new(&format!("{}document (||) '<\n  ^1+p{{}}\n>\n", header));
+        let trigger = Some("+".to_owned());
+        get_completion_list(&doc.buffer(), &doc.position(1), &trigger, &Config::default())
+            .items
+            .into_iter()
+            .map(|item| item.label)
+            .collect_vec()
+    };
+    let report = labels("@require: stdjareport\n\n");
+    assert!(report.contains(&"+section".to_owned()));
+    assert!(!report.contains(&"+chapter".to_owned()));
+    let book = labels("@require: stdjabook\n\n");
+    assert!(book.contains(&"+chapter".to_owned()));
+    assert!(labels("").is_empty());
+}
+
+/// 補完のスナップショットテストに用いる文書と、記録した補完候補を置くディレクトリ。
+/// `NAME.saty` に対する補完候補を `NAME.snap` に記録する。
+const SNAPSHOT_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/src/completion/snapshots");
+
+/// スナップショットテストの文書に書く、補完を求めるカーソル位置の目印。一つの文書に複数書ける。
+const CURSOR_MARKER: &str = "<|>";
+
+/// この環境変数を設定してテストを走らせると、記録した補完候補を現在の結果で書き換える。
+const UPDATE_SNAPSHOTS_ENV: &str = "UPDATE_SNAPSHOTS";
+
+/// 目印を取り除いた文書と、目印のあった位置を返す。
+fn strip_cursor_markers(text: &str) -> (String, Vec<Position>) {
+    let mut stripped = String::new();
+    let mut positions = vec![];
+    for (i, chunk) in text.split(CURSOR_MARKER).enumerate() {
+        if i > 0 {
+            let line = stripped.matches('\n').count() as u32;
+            let line_start = stripped.rfind('\n').map_or(0, |i| i + 1);
+            let character = stripped[line_start..].chars().count() as u32;
+            positions.push(Position { line, character });
+        }
+        stripped.push_str(chunk);
+    }
+    (stripped, positions)
+}
+
+/// 文書の各カーソル位置での補完候補を、一行に一つずつ並べた文字列にする。
+/// カーソルの直前が `\` か `+` であれば、それを補完のきっかけの文字とする。
+fn render_completion_snapshot(text: &str) -> String {
+    let (text, positions) = strip_cursor_markers(text);
+    let buf = Buffer::new(text.clone());
+    let lines = text.lines().collect_vec();
+    let mut snapshot = String::new();
+    for pos in positions {
+        let line = lines.get(pos.line as usize).copied().unwrap_or("");
+        let trigger = line
+            .chars()
+            .nth((pos.character as usize).wrapping_sub(1))
+            .filter(|c| matches!(c, '\\' | '+'))
+            .map(String::from);
+        snapshot.push_str(&format!(
+            "== {}:{} trigger={}\n",
+            pos.line + 1,
+            pos.character + 1,
+            trigger.as_deref().unwrap_or("-")
+        ));
+        let items = get_completion_list(&buf, &pos, &trigger, &Config::default())
+            .items
+            .into_iter()
+            .sorted_by(|a, b| (&a.sort_text, &a.label).cmp(&(&b.sort_text, &b.label)));
+        for item in items {
+            snapshot.push_str(&format!("{}\t{:?}\n", item.label, item.kind));
+        }
+    }
+    snapshot
+}
+
+/// 記録と現在の結果で異なる行を、記録にしかないものは `-`、現在の結果にしかないものは `+` を付けて示す。
+fn describe_snapshot_diff(expected: &str, actual: &str) -> String {
+    let removed = expected.lines().filter(|line| !actual.lines().any(|l| l == *line));
+    let added = actual.lines().filter(|line| !expected.lines().any(|l| l == *line));
+    removed
+        .map(|line| format!("- {}", line))
+        .chain(added.map(|line| format!("+ {}", line)))
+        .join("\n")
+}
+
+#[test]
+fn test_completion_snapshots() {
+    let update = std::env::var_os(UPDATE_SNAPSHOTS_ENV).is_some();
+    let corpus = std::fs::read_dir(SNAPSHOT_DIR)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "saty"))
+        .sorted()
+        .collect_vec();
+    assert!(!corpus.is_empty());
+
+    let mut mismatches = vec![];
+    for path in corpus {
+        let actual = render_completion_snapshot(&std::fs::read_to_string(&path).unwrap());
+        let snap = path.with_extension("snap");
+        if update {
+            std::fs::write(&snap, &actual).unwrap();
+            continue;
+        }
+        let expected = std::fs::read_to_string(&snap).unwrap_or_default();
+        if expected != actual {
+            let diff = describe_snapshot_diff(&expected, &actual);
+            mismatches.push(format!("{}:\n{}", snap.display(), diff));
+        }
+    }
+    assert!(
+        mismatches.is_empty(),
+        "completion snapshots differ (rerun with {}=1 to accept):\n{}",
+        UPDATE_SNAPSHOTS_ENV,
+        mismatches.join("\n")
+    );
+}
+
+#[test]
+fn test_strip_cursor_markers() {
+    let (text, positions) = strip_cursor_markers("a<|>b\nc<|>");
+    assert_eq!(text, "ab\nc");
+    assert_eq!(
+        positions,
+        vec![Position { line: 0, character: 1 }, Position { line: 1, character: 1 }]
+    );
+}
+
+#[test]
+fn test_context_functions_after_pipeline() {
+    let labels = |doc: &TestDocument, marker| {
+        get_completion_list(&doc.buffer(), &doc.position(marker), &None, &Config::default())
+            .items
+            .into_iter()
+            .map(|item| item.label)
+            .collect_vec()
+    };
+    let doc = TestDocument::new("let big : context -> context = set-font-size 20pt\nlet-inline ctx \\x it = read-inline (ctx |> ^1set-font-size 12pt |> ^2set-leading 1pt) it\nin\n'<>\n");
+    let after_ctx = labels(&doc, 1);
+    assert!(after_ctx.contains(&"set-font-size".to_owned()));
+    assert!(after_ctx.contains(&"set-paragraph-margin".to_owned()));
+    assert!(after_ctx.contains(&"big".to_owned()));
+    assert!(!after_ctx.contains(&"read-inline".to_owned()));
+    assert_eq!(labels(&doc, 2), after_ctx);
+
+    // 左辺がコンテキストでなければ、通常の候補を示す。
+    let doc = TestDocument::new("let-inline ctx \\x it = read-inline (it |> ^1f) it\nin\n'<>\n");
+    assert!(labels(&doc, 1).contains(&"read-inline".to_owned()));
+}
+
+#[test]
+fn test_replace_existing_command_name() {
+    let doc = TestDocument::new("let-inline ctx \\emphasize x = x\nlet-block ctx +para x = '<>\nin\n'<\n  +p^3ara{ ^4\\emp^1hasize^2{a} }\n>\n");
+    let buf = doc.buffer();
+    let edit = |marker, trigger: Option<&str>, label: &str| {
+        let trigger = trigger.map(str::to_owned);
+        let item = get_completion_list(&buf, &doc.position(marker), &trigger, &Config::default())
+            .items
+            .into_iter()
+            .find(|item| item.label == label)?;
+        assert_eq!(item.filter_text.as_deref(), Some(label));
+        match item.text_edit? {
+            CompletionTextEdit::Edit(edit) => Some((Range::from(edit.range), edit.new_text)),
+            _ => None,
+        }
+    };
+    let range = |line, start, end| Range {
+        start: Position { line, character: start },
+        end: Position { line, character: end },
+    };
+
+    // 明示的に補完を求めても、名前の途中であれば名前全体を置き換える。
+    let expected = Some((range(4, 9, 19), "\\emphasize".to_owned()));
+    assert_eq!(edit(1, None, "\\emphasize"), expected);
+    assert_eq!(edit(2, Some("\\"), "\\emphasize"), expected);
+    assert_eq!(edit(3, None, "+para"), Some((range(4, 2, 7), "+para".to_owned())));
+    // 名前の前では置き換えない。
+    assert_eq!(edit(4, None, "\\emphasize"), None);
+}
+
+#[test]
+fn test_document_record_fields() {
+    let labels = |buf: &Buffer, pos| {
+        get_completion_list(buf, &pos, &None, &Config::default())
+            .items
+            .into_iter()
+            .map(|item| item.label)
+            .collect_vec()
+    };
+    let doc = TestDocument::new("@require: stdjabook\n\ndocument (|\n  title = {T^2};\n  ^1\n|) '<>\n");
+    let buf = doc.buffer();
+    assert_eq!(labels(&buf, doc.position(1)), vec!["author", "show-title", "show-toc"]);
+    // フィールドの値の中では候補としない。
+    assert!(!labels(&buf, doc.position(2)).contains(&"author".to_owned()));
+
+    // 書きかけのフィールドがあっても、最後にパースに成功した版から文書クラスを求める。
+    let doc = TestDocument::new("@require: stdjabook\n\ndocument (|\n  title = {T};\n  au^1\n|) '<>\n");
+    let mut incomplete = doc.buffer();
+    assert!(incomplete.buf_cst.cst.is_none());
+    incomplete.inherit_last_parsed(buf);
+    assert_eq!(labels(&incomplete, doc.position(1)), vec!["author", "show-title", "show-toc"]);
+}
+
+#[test]
+fn test_document_record_at() {
+    let text = "document (| title = {T}; |) '<>";
+    assert_eq!(document_record_at(text, 12), Some(" title = {T}; "));
+    assert_eq!(document_record_at(text, 25), Some(" title = {T}; "));
+    assert_eq!(document_record_at(text, 20), None);
+    assert_eq!(document_record_at("my-document (| |)", 14), None);
+    assert_eq!(record_field_names(" title = {T}; show-toc = true; au"), vec!["title", "show-toc"]);
+}
+
+#[test]
+fn test_syntax_snippets() {
+    let doc = TestDocument::new("let x = 1^3\n^2let y = ^1x\nin\n'<>\n");
+    let buf = doc.buffer();
+    let labels_at = |marker| {
+        get_completion_list(&buf, &doc.position(marker), &None, &Config::default())
+            .items
+            .into_iter()
+            .filter(|item| item.kind == Some(CompletionItemKind::Snippet))
+            .map(|item| item.label)
+            .collect_vec()
+    };
+    // `=` の直後と行頭では構文の snippet を候補とする。
+    assert!(labels_at(1).contains(&"match ... with".to_owned()));
+    assert!(labels_at(2).contains(&"let ... in".to_owned()));
+    // 式の途中では候補としない。
+    assert!(!labels_at(3).contains(&"let ... in".to_owned()));
+
+    let item = load_section_completion_items("syntax", &Config::default())
+        .into_iter()
+        .find(|item| item.label == "module = struct ... end")
+        .unwrap();
+    assert_eq!(item.insert_text_format, Some(InsertTextFormat::Snippet));
+    assert_eq!(item.insert_text.as_deref(), Some("module ${1:Name} = struct\n  $0\nend"));
+}
+
+#[test]
+fn test_at_expression_start() {
+    assert!(at_expression_start(""));
+    assert!(at_expression_start("let f x =\n  ma"));
+    assert!(at_expression_start("let f x = "));
+    assert!(at_expression_start("let f = fun x -> i"));
+    assert!(at_expression_start("let x = 1 in m"));
+    assert!(at_expression_start("let x = (f"));
+    assert!(at_expression_start("if b then 1 else "));
+    assert!(!at_expression_start("let f x = g "));
+    assert!(!at_expression_start("let b = x <= "));
+    assert!(!at_expression_start("let y = List.ma"));
+    assert!(!at_expression_start("let inner-"));
+}
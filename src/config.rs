@@ -0,0 +1,272 @@
+//! ワークスペースの設定ファイル `satysfi-ls.toml` に関する関数群。
+
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use anyhow::{Context, Result};
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use crate::{catalog::Catalog, lint::InvisibleKind, parser::Rule, CmdKind, MAX_BUFFER_SIZE};
+
+/// 設定ファイルの名前。
+pub const CONFIG_FILE_NAME: &str = "satysfi-ls.toml";
+
+/// 編集のたびにパースするバッファの最大バイト数のデフォルト値。
+pub const DEFAULT_MAX_PARSE_SIZE: usize = 1 << 20;
+
+/// hover や補完で示すコマンドの使用例の最大数のデフォルト値。
+pub const DEFAULT_USAGE_EXAMPLES: usize = 3;
+
+/// パース結果を使い回すために保持する文字列の数のデフォルト値。
+pub const DEFAULT_PARSE_CACHE_SIZE: usize = 16;
+
+/// Language server の設定。
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct Config {
+    /// `@require:` で読み込むパッケージを探す追加のディレクトリ。
+    pub search_paths: Vec<PathBuf>,
+    /// lint の設定。
+    pub lint: LintConfig,
+    /// フォーマッタの設定。
+    pub format: FormatConfig,
+    /// 追加で読み込む補完候補のファイル。completion.toml と同じ形式で書く。
+    pub resources: Vec<PathBuf>,
+    /// ワークスペースの索引化に用いるスレッド数。省略した場合は 4。
+    pub index_threads: Option<usize>,
+    /// 編集のたびにパースするバッファの最大バイト数。
+    /// これを超えるバッファは補完などで必要になるまでパースしない。
+    /// 省略した場合は [`DEFAULT_MAX_PARSE_SIZE`]。
+    pub max_parse_size: Option<usize>,
+    /// hover や補完で示す、ワークスペース内でのコマンドの使用例の最大数。
+    /// 0 にすると使用例を示さない。省略した場合は [`DEFAULT_USAGE_EXAMPLES`]。
+    pub usage_examples: Option<usize>,
+    /// パース結果を使い回すために、最近パースした文字列をいくつ保持するか。
+    /// 0 にすると保持しない。省略した場合は [`DEFAULT_PARSE_CACHE_SIZE`]。
+    pub parse_cache_size: Option<usize>,
+    /// ワークスペースの索引化で読み飛ばすパス。
+    /// `.gitignore` と同じ書式で、ワークスペースのルートからの相対パスとして書く。
+    pub ignore: Vec<String>,
+    /// 同期のずれを調べるために、ドキュメントごとに記録する直近の版の数。
+    /// 0（デフォルト）のときは記録しない。記録は `satysfi/dumpHistory` で取り出せる。
+    pub history_size: usize,
+    /// 対象とする SATySFi の版。文法と補完候補のプリミティブを切り替える。
+    pub language_version: LanguageVersion,
+    /// `@import:` で読み込めるファイルを置けるディレクトリ。
+    /// 空（デフォルト）のときは、ワークスペースのルートと SATySFi のライブラリのルートとする。
+    pub allowed_roots: Vec<PathBuf>,
+    /// `\ref`, `\eqref`, `\ref-page` のほかに、引数でラベルを参照するインラインコマンド。
+    /// これらの引数の文字列リテラルの中では、別行立て数式のラベルを補完する。
+    pub reference_commands: Vec<String>,
+    /// let-inline などのほかに、コマンドを定義する構文。
+    /// パッケージが独自の定義の構文を持つ場合に、その定義を補完や定義ジャンプの対象にするために用いる。
+    pub definition_patterns: Vec<DefinitionPattern>,
+    /// コマンドライン引数 `--package-path` で指定されたディレクトリ。
+    /// `search_paths` よりも優先して `@require:` のパッケージを探す。
+    #[serde(skip_deserializing)]
+    pub package_paths: Vec<PathBuf>,
+    /// ワークスペースのルート。
+    #[serde(skip_deserializing)]
+    pub workspace_root: Option<PathBuf>,
+    /// クライアントが initialize リクエストで伝えたロケール (`ja`, `en-US` など)。
+    /// 補完候補のカタログのドキュメントを、この言語のものから選ぶ。
+    #[serde(skip_deserializing)]
+    pub locale: Option<String>,
+    /// 補完と定義ジャンプだけを開かれているバッファに対して提供し、
+    /// ワークスペースの索引化やパッケージの読み込み、semantic tokens、diagnostics を行わないか。
+    /// クライアントが initialize リクエストの `initializationOptions` で `minimalMode` を指定する。
+    #[serde(skip_deserializing)]
+    pub minimal_mode: bool,
+    /// resources から読み込んだ内容。
+    #[serde(skip)]
+    pub(crate) resource_texts: Vec<String>,
+    /// resources と locale から作った補完候補のカタログ。
+    /// これらを変えたときは [`Config::build_catalog`] で作り直す。
+    #[serde(skip)]
+    pub(crate) catalog: Arc<Catalog>,
+}
+
+/// 対象とする SATySFi の版。
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[non_exhaustive]
+pub enum LanguageVersion {
+    /// 0.0 系。`@require:` や `@import:` でパッケージを読み込む。
+    #[default]
+    #[serde(rename = "0.0")]
+    Stable,
+    /// 開発中の 0.1 系。`use` でパッケージやモジュールを読み込む。
+    #[serde(rename = "0.1")]
+    Next,
+}
+
+impl LanguageVersion {
+    /// この版の文法でプログラム全体を読むときの開始規則。
+    pub fn program_rule(self) -> Rule {
+        match self {
+            LanguageVersion::Stable => Rule::program,
+            LanguageVersion::Next => Rule::program_next,
+        }
+    }
+}
+
+/// コマンドを定義する構文を、文法規則と、定義される名前にあたる子の位置で表したもの。
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct DefinitionPattern {
+    /// 定義の構文にあたる文法規則。設定ファイルには `satysfi.pest` での名前で書き、
+    /// 存在しない名前は設定の読み込みの際にエラーとする。
+    #[serde(with = "rule_by_name")]
+    pub rule: Rule,
+    /// 規則の子のうち、定義されるコマンド名にあたるものの位置（0 始まり）。
+    pub child: usize,
+    /// 定義されるコマンドの種類。
+    pub kind: CmdKind,
+}
+
+/// 文法規則を `satysfi.pest` での名前で読み書きする。
+mod rule_by_name {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    use crate::parser::{rule_name, Rule};
+
+    /// 文法規則をその名前として書き出す。
+    pub fn serialize<S: Serializer>(rule: &Rule, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(rule_name::rule_name(*rule))
+    }
+
+    /// 名前から文法規則を読み込む。存在しない名前はエラーとする。
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Rule, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        rule_name::rule_by_name(&name)
+            .ok_or_else(|| D::Error::custom(format!("unknown grammar rule `{}`", name)))
+    }
+}
+
+/// lint の設定。
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct LintConfig {
+    /// 行末の空白を報告するか。
+    pub trailing_space: bool,
+    /// タブ文字を報告するか。
+    pub tab: bool,
+    /// 全角スペースを報告するか。
+    pub full_width_space: bool,
+    /// 文字列リテラル中の対になっていないバッククォートやコマンドらしき文字列を報告するか。
+    pub literal: bool,
+}
+
+impl Default for LintConfig {
+    fn default() -> Self {
+        Self {
+            trailing_space: true,
+            tab: true,
+            full_width_space: true,
+            literal: true,
+        }
+    }
+}
+
+impl LintConfig {
+    /// 与えられた種類の目に見えない文字を報告するか。
+    pub fn reports(&self, kind: InvisibleKind) -> bool {
+        match kind {
+            InvisibleKind::TrailingSpace => self.trailing_space,
+            InvisibleKind::Tab => self.tab,
+            InvisibleKind::FullWidthSpace => self.full_width_space,
+        }
+    }
+}
+
+/// フォーマッタの設定。
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct FormatConfig {
+    /// インデント幅。
+    pub indent_width: usize,
+}
+
+impl Default for FormatConfig {
+    fn default() -> Self {
+        Self { indent_width: 4 }
+    }
+}
+
+impl Config {
+    /// ワークスペースのルートにある設定ファイルを読み込む。
+    /// 設定ファイルが存在しない場合はデフォルトの設定を返す。
+    pub fn load(root: &Path) -> Result<Self> {
+        let path = root.join(CONFIG_FILE_NAME);
+        if !path.is_file() {
+            return Ok(Config::default());
+        }
+        let text = std::fs::read_to_string(&path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        let mut config = Self::from_toml(&text)?;
+        config.resolve_paths(root);
+        config.build_catalog()?;
+        Ok(config)
+    }
+
+    /// 編集のたびにパースするバッファの最大バイト数を返す。
+    /// [`MAX_BUFFER_SIZE`] より大きな値を指定しても、それを超えるバッファはパースしない。
+    pub fn max_parse_size(&self) -> usize {
+        self.max_parse_size
+            .unwrap_or(DEFAULT_MAX_PARSE_SIZE)
+            .min(MAX_BUFFER_SIZE)
+    }
+
+    /// hover や補完で示すコマンドの使用例の最大数を返す。
+    pub fn usage_examples(&self) -> usize {
+        self.usage_examples.unwrap_or(DEFAULT_USAGE_EXAMPLES)
+    }
+
+    /// パース結果を使い回すために保持する文字列の数を返す。
+    pub fn parse_cache_size(&self) -> usize {
+        self.parse_cache_size.unwrap_or(DEFAULT_PARSE_CACHE_SIZE)
+    }
+
+    /// TOML 形式の文字列から設定を読み込む。パスの解決は行わない。
+    pub fn from_toml(text: &str) -> Result<Self> {
+        let config = toml::from_str(text)?;
+        Ok(config)
+    }
+
+    /// resources と locale から補完候補のカタログを作り直す。
+    /// resources の内容が completion.toml の形式でなければエラーを返し、カタログは変えない。
+    pub fn build_catalog(&mut self) -> Result<()> {
+        let catalog = Catalog::build(&self.resource_texts, self.locale.as_deref())
+            .context("failed to load the completion resources")?;
+        self.catalog = Arc::new(catalog);
+        Ok(())
+    }
+
+    /// 相対パスをワークスペースのルートからのパスとして解釈し、追加の補完候補を読み込む。
+    fn resolve_paths(&mut self, root: &Path) {
+        let paths = self
+            .search_paths
+            .iter_mut()
+            .chain(&mut self.resources)
+            .chain(&mut self.allowed_roots);
+        for path in paths {
+            if path.is_relative() {
+                *path = root.join(&path);
+            }
+        }
+        self.resource_texts = self
+            .resources
+            .iter()
+            .filter_map(|path| {
+                std::fs::read_to_string(path)
+                    .map_err(|e| warn!("failed to read {}: {}", path.display(), e))
+                    .ok()
+            })
+            .collect();
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,83 @@
+//! test module for config.
+
+use super::*;
+
+#[test]
+fn test_from_toml() {
+    let config = Config::from_toml(
+        r#"
+search-paths = ["lib"]
+
+[lint]
+tab = false
+
+[format]
+indent-width = 2
+"#,
+    )
+    .unwrap();
+    assert_eq!(config.search_paths, vec![PathBuf::from("lib")]);
+    assert!(!config.lint.tab);
+    assert!(config.lint.trailing_space);
+    assert_eq!(config.format.indent_width, 2);
+    assert!(config.resources.is_empty());
+}
+
+#[test]
+fn test_empty_toml_is_default() {
+    let config = Config::from_toml("").unwrap();
+    assert!(config.search_paths.is_empty());
+    assert!(config.lint.reports(InvisibleKind::FullWidthSpace));
+    assert_eq!(config.format.indent_width, 4);
+}
+
+#[test]
+fn test_max_parse_size() {
+    assert_eq!(Config::default().max_parse_size(), DEFAULT_MAX_PARSE_SIZE);
+    let config = Config::from_toml("max-parse-size = 1024").unwrap();
+    assert_eq!(config.max_parse_size(), 1024);
+    // Cst の位置が 32 bit に収まらないほど大きなバッファはパースしない。
+    let config = Config { max_parse_size: Some(usize::MAX), ..Config::default() };
+    assert_eq!(config.max_parse_size(), crate::MAX_BUFFER_SIZE);
+}
+
+#[test]
+fn test_definition_patterns() {
+    let config = Config::from_toml(
+        r#"
+[[definition-patterns]]
+rule = "sig_direct_stmt"
+child = 0
+kind = "block"
+"#,
+    )
+    .unwrap();
+    assert_eq!(
+        config.definition_patterns,
+        vec![DefinitionPattern {
+            rule: Rule::sig_direct_stmt,
+            child: 0,
+            kind: CmdKind::Block,
+        }]
+    );
+
+    let err = Config::from_toml(
+        r#"
+[[definition-patterns]]
+rule = "sig_direct"
+child = 0
+kind = "block"
+"#,
+    )
+    .unwrap_err();
+    assert!(err.to_string().contains("unknown grammar rule `sig_direct`"), "{}", err);
+}
+
+#[test]
+fn test_language_version() {
+    assert_eq!(Config::default().language_version, LanguageVersion::Stable);
+    let config = Config::from_toml(r#"language-version = "0.1""#).unwrap();
+    assert_eq!(config.language_version, LanguageVersion::Next);
+    assert_eq!(config.language_version.program_rule(), Rule::program_next);
+    assert!(Config::from_toml(r#"language-version = "0.2""#).is_err());
+}
@@ -1,15 +1,24 @@
-use itertools::Itertools;
-use lsp_types::{GotoDefinitionParams, GotoDefinitionResponse, Location, Position};
+//! 定義ジャンプに関する関数群。
+
+use lsp_types::{
+    GotoDefinitionParams, GotoDefinitionResponse, Location, LocationLink, Url,
+};
 
 use crate::parser::Rule;
-use crate::{Buffer, Cst};
+use crate::{
+    position::{Position, Range},
+    scope::local_bindings,
+    Buffer, CmdKind, Cst, Environment,
+};
 
 /// definition リクエストへの response を返す。
+/// `link_support` が true なら、定義する文全体を含む LocationLink を返す。
 pub fn get_definition_response(
     buf: &Buffer,
     params: GotoDefinitionParams,
+    link_support: bool,
 ) -> Option<GotoDefinitionResponse> {
-    let pos = params.text_document_position_params.position;
+    let pos = Position::from(params.text_document_position_params.position);
     let uri = params.text_document_position_params.text_document.uri;
 
     let buf_cst = &buf.buf_cst;
@@ -17,35 +26,110 @@ pub fn get_definition_response(
     let keyword = find_keyword(cst, &pos)?;
     let name = buf_cst.as_str(keyword);
 
-    let range = match keyword.rule {
-        Rule::math_cmd_name => {
-            // 同じ名前のコマンドの定義があった場合は最後を取る。
-            buf.env.math_cmds.iter().filter(|cmd| cmd.name == name).last()?.def_range
+    // 変数は局所的な束縛を最優先する。
+    if keyword.rule == Rule::var {
+        let local = local_bindings(buf_cst, &pos).into_iter().find(|b| b.name == name);
+        if let Some(local) = local {
+            let target = Definition {
+                name_range: local.def_range,
+                stmt_range: local.stmt_range,
+            };
+            return Some(definition_response(keyword, uri, target, link_support));
+        }
+    }
+
+    // バッファ自身の定義を優先し、なければ読み込んだパッケージから探す。
+    let envs = std::iter::once((&uri, &buf.env))
+        .chain(buf.packages.iter().map(|pkg| (&pkg.uri, &pkg.env)));
+    for (uri, env) in envs {
+        if let Some(target) = find_definition(env, keyword.rule, name) {
+            return Some(definition_response(keyword, uri.clone(), target, link_support));
+        }
+    }
+    None
+}
+
+/// 定義の場所。
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Definition {
+    /// 定義された名前の範囲。
+    pub(crate) name_range: Range,
+    /// 定義する文全体の範囲。
+    pub(crate) stmt_range: Range,
+}
+
+/// 定義の場所から definition リクエストへの response を作る。
+fn definition_response(
+    keyword: &Cst,
+    uri: Url,
+    target: Definition,
+    link_support: bool,
+) -> GotoDefinitionResponse {
+    if !link_support {
+        return GotoDefinitionResponse::Scalar(Location {
+            uri,
+            range: target.name_range.into(),
+        });
+    }
+    GotoDefinitionResponse::Link(vec![LocationLink {
+        origin_selection_range: Some(Range::from(keyword.range.clone()).into()),
+        target_uri: uri,
+        target_range: target.stmt_range.into(),
+        target_selection_range: target.name_range.into(),
+    }])
+}
+
+/// environment から与えられた名前の定義を探す。
+pub(crate) fn find_definition(env: &Environment, rule: Rule, name: &str) -> Option<Definition> {
+    // 同じ名前の定義があった場合は最後を取る。
+    let (name_range, stmt_range) = match CmdKind::from_rule(rule) {
+        Some(kind) => {
+            let cmd = env.lookup(kind, name)?;
+            (cmd.def_range, cmd.stmt_range)
         },
-        Rule::inline_cmd_name => {
-            buf.env.inline_cmds.iter().filter(|cmd| cmd.name == name).last()?.def_range
+        None if rule == Rule::var => {
+            let var = env.variable(name)?;
+            (var.def_range(), var.stmt_range())
         },
-        Rule::block_cmd_name => {
-            buf.env.block_cmds.iter().filter(|cmd| cmd.name == name).last()?.def_range
+        None if rule == Rule::type_name => {
+            let ty = env.type_def(name)?;
+            (ty.def_range(), ty.stmt_range())
         },
-        Rule::var => {
-            buf.env.variables.iter().filter(|var| var.name == name).last()?.def_range
+        None if rule == Rule::variant_name => {
+            let (ty, constructor) = env.constructor(name)?;
+            (constructor.def_range(), ty.stmt_range())
         },
-        _ => unreachable!()
+        None => unreachable!()
     };
-    Some(GotoDefinitionResponse::Scalar(Location { uri, range }))
+    Some(Definition { name_range, stmt_range })
 }
 
 /// 与えられたキーワードを見つける。
-/// 今の所、キーワードはコマンドのみ。
+/// キーワードはコマンド名、変数名、型名、コンストラクタ名。
+/// 型注釈などの中の変数名やモジュール付きの名前は、それを含む型名をキーワードとする。
 fn find_keyword<'a>(cst: &'a Cst, pos: &Position) -> Option<&'a Cst> {
-    let keywords = cst.dig(&pos);
+    let keywords = cst.dig(pos);
 
-    for cst in keywords {
-        if let Rule::math_cmd_name | Rule::inline_cmd_name | Rule::block_cmd_name | Rule::var = cst.rule {
+    for (i, cst) in keywords.iter().enumerate() {
+        if let Rule::var | Rule::modvar = cst.rule {
+            let type_name = keywords[i + 1..].iter().take(2).find(|c| c.rule == Rule::type_name);
+            if let Some(type_name) = type_name {
+                return Some(type_name);
+            }
+        }
+        if let Rule::math_cmd_name
+        | Rule::inline_cmd_name
+        | Rule::block_cmd_name
+        | Rule::var
+        | Rule::type_name
+        | Rule::variant_name = cst.rule
+        {
             return Some(cst);
         }
     }
 
     None
 }
+
+#[cfg(test)]
+mod tests;
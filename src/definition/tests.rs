@@ -0,0 +1,89 @@
+//! test module for goto definition.
+
+use lsp_types::{Position, Range, TextDocumentIdentifier, TextDocumentPositionParams};
+
+use super::*;
+use crate::test_utils::TestDocument;
+
+fn definition(text: &str, line: u32, character: u32, link_support: bool) -> GotoDefinitionResponse {
+    let buf = Buffer::new(text.to_owned());
+    let params = GotoDefinitionParams {
+        text_document_position_params: TextDocumentPositionParams {
+            text_document: TextDocumentIdentifier {
+                uri: Url::parse("file:///tmp/test.saty").unwrap(),
+            },
+            position: Position { line, character },
+        },
+        work_done_progress_params: Default::default(),
+        partial_result_params: Default::default(),
+    };
+    get_definition_response(&buf, params, link_support).unwrap()
+}
+
+fn range(sl: u32, sc: u32, el: u32, ec: u32) -> Range {
+    Range {
+        start: Position { line: sl, character: sc },
+        end: Position { line: el, character: ec },
+    }
+}
+
+const DOCUMENT: &str = "let-inline ctx \\foo x =\n  read-inline ctx x\nin\n'<\n  +p{ \\foo{a} }\n>\n";
+
+#[test]
+fn test_location_link() {
+    let links = match definition(DOCUMENT, 4, 8, true) {
+        GotoDefinitionResponse::Link(links) => links,
+        resp => panic!("unexpected response: {:?}", resp),
+    };
+    assert_eq!(links[0].origin_selection_range, Some(range(4, 6, 4, 10)));
+    assert_eq!(links[0].target_selection_range, range(0, 15, 0, 19));
+    assert_eq!(links[0].target_range, range(0, 0, 1, 19));
+}
+
+#[test]
+fn test_location_without_link_support() {
+    match definition(DOCUMENT, 4, 8, false) {
+        GotoDefinitionResponse::Scalar(location) => {
+            assert_eq!(location.range, range(0, 15, 0, 19))
+        }
+        resp => panic!("unexpected response: {:?}", resp),
+    }
+}
+
+#[test]
+fn test_types_and_constructors() {
+    let doc = TestDocument::new(
+        "type shape = | Circle of length | Square\ntype point = (| x : length; y : length |)\nlet s : sh^1ape = Cir^2cle(1pt)\nlet f p = match p with | Sq^3uare -> 0pt | _ -> 1pt\nlet o : po^4int = (| x = 0pt; y = 0pt |)\nin\n'<>\n",
+    );
+    let target = |marker| {
+        let pos = doc.position(marker);
+        match definition(doc.text(), pos.line, pos.character, true) {
+            GotoDefinitionResponse::Link(links) => {
+                (links[0].target_selection_range, links[0].target_range)
+            }
+            resp => panic!("unexpected response: {:?}", resp),
+        }
+    };
+    let shape = range(0, 0, 1, 0);
+    assert_eq!(target(1), (range(0, 5, 0, 10), shape));
+    assert_eq!(target(2), (range(0, 15, 0, 21), shape));
+    assert_eq!(target(3), (range(0, 34, 0, 40), shape));
+    assert_eq!(target(4).0, range(1, 5, 1, 10));
+}
+
+#[test]
+fn test_local_binding_link() {
+    let doc = TestDocument::new("let f p =\n  let y = p in\n  y^1 + ^2p\nin\n'<>\n");
+    let target = |marker| {
+        let pos = doc.position(marker);
+        match definition(doc.text(), pos.line, pos.character, true) {
+            GotoDefinitionResponse::Link(links) => {
+                (links[0].target_selection_range, links[0].target_range)
+            }
+            resp => panic!("unexpected response: {:?}", resp),
+        }
+    };
+    // let ... in の変数は let 文全体を、引数は関数の定義全体を target_range とする。
+    assert_eq!(target(1), (range(1, 6, 1, 7), range(1, 2, 1, 11)));
+    assert_eq!(target(2), (range(0, 6, 0, 7), range(0, 0, 2, 7)));
+}
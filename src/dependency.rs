@@ -0,0 +1,244 @@
+//! `@require:` や `@import:` によるファイル間の依存関係を表すグラフ。
+
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use log::warn;
+use lsp_types::Url;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::Config,
+    resolve::{path_to_uri, resolve_package, uri_to_path, PackageKind},
+    Buffer, Environment,
+};
+
+/// あるファイルからパッケージへの依存。
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Dependency {
+    /// 読み込み方法。
+    pub kind: PackageKind,
+    /// ヘッダに書かれたパッケージ名。
+    pub name: String,
+    /// 読み込まれるパッケージファイルの URI.
+    pub uri: Url,
+}
+
+impl Dependency {
+    /// 依存を表すヘッダの文字列。
+    pub fn header(&self) -> String {
+        let kind = match self.kind {
+            PackageKind::Require => "require",
+            PackageKind::Import => "import",
+        };
+        format!("@{}: {}", kind, self.name)
+    }
+}
+
+/// 依存関係グラフの頂点となるファイル。
+#[derive(Debug, Clone)]
+pub struct DependencyNode {
+    /// ファイルの URI.
+    pub uri: Url,
+    /// ファイルで定義されたもののうち、外から見えるもの。
+    /// 起点のファイルについては、公開されていないものも含む。
+    pub env: Environment,
+    /// ファイルが直接読み込むパッケージ。解決できなかったものは含まない。
+    pub dependencies: Vec<Dependency>,
+}
+
+/// あるファイルから推移的に読み込まれるファイルの依存関係グラフ。
+#[derive(Debug, Clone)]
+pub struct DependencyGraph {
+    /// 起点のファイルの URI.
+    root: Url,
+    /// URI に対応するファイル。
+    nodes: HashMap<Url, DependencyNode>,
+}
+
+impl DependencyGraph {
+    /// `uri` にあるバッファを起点として、読み込まれるパッケージを再帰的に解決する。
+    /// 同じファイルは一度しか読み込まないため、循環していても停止する。
+    pub fn build(buf: &Buffer, uri: &Url, config: &Config) -> Self {
+        let mut nodes = HashMap::new();
+        let root = DependencyNode {
+            uri: uri.clone(),
+            env: buf.env.clone(),
+            dependencies: resolve_dependencies(buf, uri, config),
+        };
+        let mut queue: VecDeque<Url> = root.dependencies.iter().map(|dep| dep.uri.clone()).collect();
+        nodes.insert(uri.clone(), root);
+
+        while let Some(uri) = queue.pop_front() {
+            if nodes.contains_key(&uri) {
+                continue;
+            }
+            let buf = match load_buffer(&uri) {
+                Some(buf) => buf,
+                None => continue,
+            };
+            let node = DependencyNode {
+                uri: uri.clone(),
+                env: buf.env.exported(),
+                dependencies: resolve_dependencies(&buf, &uri, config),
+            };
+            queue.extend(node.dependencies.iter().map(|dep| dep.uri.clone()));
+            nodes.insert(uri, node);
+        }
+
+        Self { root: uri.clone(), nodes }
+    }
+
+    /// 起点のファイルの URI.
+    pub fn root(&self) -> &Url {
+        &self.root
+    }
+
+    /// 与えられた URI のファイルを返す。
+    pub fn get(&self, uri: &Url) -> Option<&DependencyNode> {
+        self.nodes.get(uri)
+    }
+
+    /// 起点のファイルから近い順に、グラフに含まれるファイルを列挙する。起点のファイル自身を含む。
+    pub fn nodes_by_distance(&self) -> Vec<&DependencyNode> {
+        let mut visited = HashSet::new();
+        let mut queue = VecDeque::new();
+        let mut order = vec![];
+        queue.push_back(&self.root);
+        while let Some(uri) = queue.pop_front() {
+            if !visited.insert(uri) {
+                continue;
+            }
+            if let Some(node) = self.nodes.get(uri) {
+                order.push(node);
+                queue.extend(node.dependencies.iter().map(|dep| &dep.uri));
+            }
+        }
+        order
+    }
+
+    /// 起点のファイルから `target` に至る最短の読み込みの連鎖を返す。
+    /// `target` が起点のファイル自身なら空の連鎖を、到達できなければ None を返す。
+    pub fn import_chain(&self, target: &Url) -> Option<Vec<&Dependency>> {
+        // 幅優先探索で、各ファイルに最初に到達した依存とその読み込み元を記録する。
+        let mut reached_by: HashMap<&Url, Option<(&Url, &Dependency)>> = HashMap::new();
+        let mut queue = VecDeque::new();
+        reached_by.insert(&self.root, None);
+        queue.push_back(&self.root);
+        while let Some(uri) = queue.pop_front() {
+            if uri == target {
+                break;
+            }
+            let node = match self.nodes.get(uri) {
+                Some(node) => node,
+                None => continue,
+            };
+            for dep in &node.dependencies {
+                if !reached_by.contains_key(&dep.uri) {
+                    reached_by.insert(&dep.uri, Some((uri, dep)));
+                    queue.push_back(&dep.uri);
+                }
+            }
+        }
+
+        let mut chain = vec![];
+        let mut current = target;
+        while let Some((importer, dep)) = reached_by.get(current)? {
+            chain.push(*dep);
+            current = importer;
+        }
+        chain.reverse();
+        Some(chain)
+    }
+}
+
+/// JSON に書き出すための依存関係グラフ。
+#[derive(Debug, Deserialize, Serialize)]
+pub struct DependencyGraphJson {
+    /// 起点のファイルの URI.
+    pub root: Url,
+    /// 起点のファイルから近い順に並べたファイル。
+    pub nodes: Vec<DependencyNodeJson>,
+}
+
+/// JSON に書き出すための、依存関係グラフの頂点。
+#[derive(Debug, Deserialize, Serialize)]
+pub struct DependencyNodeJson {
+    /// ファイルの URI.
+    pub uri: Url,
+    /// ファイルが直接読み込むパッケージ。
+    pub dependencies: Vec<Dependency>,
+}
+
+impl DependencyGraph {
+    /// グラフを JSON に書き出すための構造体に変換する。
+    pub fn to_json(&self) -> DependencyGraphJson {
+        DependencyGraphJson {
+            root: self.root.clone(),
+            nodes: self
+                .nodes_by_distance()
+                .into_iter()
+                .map(|node| DependencyNodeJson {
+                    uri: node.uri.clone(),
+                    dependencies: node.dependencies.clone(),
+                })
+                .collect(),
+        }
+    }
+
+    /// グラフを Graphviz の DOT 形式で書き出す。
+    /// 頂点はファイル名で、辺は読み込みのヘッダで示す。起点のファイルは二重枠で囲む。
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph dependencies {\n");
+        for node in self.nodes_by_distance() {
+            let name = node.uri.path_segments().and_then(|mut s| s.next_back()).unwrap_or_default();
+            let shape = if node.uri == self.root { "doubleoctagon" } else { "box" };
+            dot.push_str(&format!(
+                "    \"{}\" [label=\"{}\", shape={}];\n",
+                dot_escape(node.uri.as_str()),
+                dot_escape(name),
+                shape
+            ));
+            for dep in &node.dependencies {
+                dot.push_str(&format!(
+                    "    \"{}\" -> \"{}\" [label=\"{}\"];\n",
+                    dot_escape(node.uri.as_str()),
+                    dot_escape(dep.uri.as_str()),
+                    dot_escape(&dep.header())
+                ));
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+/// DOT の文字列リテラルの中に置けるよう、`"` と `\` をエスケープする。
+fn dot_escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// バッファのヘッダに書かれたパッケージを解決する。
+fn resolve_dependencies(buf: &Buffer, uri: &Url, config: &Config) -> Vec<Dependency> {
+    let stage = buf.buf_cst.stage();
+    buf.buf_cst
+        .headers()
+        .into_iter()
+        .filter_map(|(kind, name)| {
+            let path = resolve_package(uri, kind, &name, stage, config)?;
+            let uri = path_to_uri(&path)?;
+            Some(Dependency { kind, name, uri })
+        })
+        .collect()
+}
+
+/// URI の示すファイルを読み込んでパースする。
+fn load_buffer(uri: &Url) -> Option<Buffer> {
+    let path = uri_to_path(uri)?;
+    let text = std::fs::read_to_string(&path)
+        .map_err(|e| warn!("failed to read {}: {}", path.display(), e))
+        .ok()?;
+    Some(Buffer::new(text))
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,61 @@
+//! test module for the dependency graph.
+
+use std::fs;
+
+use super::*;
+use crate::test_utils::temp_dir;
+
+#[test]
+fn test_import_chain() {
+    let dir = temp_dir("dependency");
+    fs::write(dir.join("macros.satyh"), "@import: inner\n\nlet-inline ctx \\outer = {}\n").unwrap();
+    // 循環していても停止する。
+    fs::write(dir.join("inner.satyh"), "@import: macros\n\nlet-inline ctx \\inner = {}\n").unwrap();
+    let uri = Url::from_file_path(dir.join("main.saty")).unwrap();
+    let macros = Url::from_file_path(dir.join("macros.satyh")).unwrap();
+    let inner = Url::from_file_path(dir.join("inner.satyh")).unwrap();
+
+    let buf = Buffer::new("@import: macros\n@import: missing\n\n'<>\n".to_owned());
+    let graph = DependencyGraph::build(&buf, &uri, &Config::default());
+
+    let order = graph.nodes_by_distance().into_iter().map(|node| &node.uri).collect::<Vec<_>>();
+    assert_eq!(order, vec![&uri, &macros, &inner]);
+    assert_eq!(graph.get(&uri).unwrap().dependencies.len(), 1);
+    assert_eq!(graph.get(&inner).unwrap().env.inline_cmds[0].name, "\\inner");
+
+    let chain = graph.import_chain(&inner).unwrap();
+    let headers = chain.iter().map(|dep| dep.header()).collect::<Vec<_>>();
+    assert_eq!(headers, vec!["@import: macros", "@import: inner"]);
+    assert!(graph.import_chain(&uri).unwrap().is_empty());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_dot_and_json() {
+    let dir = temp_dir("dependency-dot");
+    fs::write(dir.join("macros.satyh"), "let-inline ctx \\outer = {}\n").unwrap();
+    let uri = Url::from_file_path(dir.join("main.saty")).unwrap();
+    let macros = Url::from_file_path(dir.join("macros.satyh")).unwrap();
+
+    let buf = Buffer::new("@import: macros\n\n'<>\n".to_owned());
+    let graph = DependencyGraph::build(&buf, &uri, &Config::default());
+
+    let dot = graph.to_dot();
+    assert!(dot.starts_with("digraph dependencies {\n"));
+    assert!(dot.contains(&format!("\"{}\" [label=\"main.saty\", shape=doubleoctagon];", uri)));
+    assert!(dot.contains(&format!("\"{}\" -> \"{}\" [label=\"@import: macros\"];", uri, macros)));
+    assert!(dot.ends_with("}\n"));
+
+    let json = serde_json::to_value(graph.to_json()).unwrap();
+    assert_eq!(json["root"], uri.as_str());
+    assert_eq!(json["nodes"][0]["dependencies"][0]["kind"], "import");
+    assert_eq!(json["nodes"][1]["uri"], macros.as_str());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_dot_escape() {
+    assert_eq!(dot_escape(r#"a"b\c"#), r#"a\"b\\c"#);
+}
@@ -0,0 +1,1094 @@
+//! diagnostics に関する関数群。
+
+use itertools::Itertools;
+use lsp_types::{
+    Diagnostic, DiagnosticRelatedInformation, DiagnosticSeverity, DiagnosticTag, Location,
+    NumberOrString, TextEdit, Url,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    argument::{command_arguments, ArgumentSlot},
+    completion::{document_class, document_fields},
+    config::Config,
+    fuzzy::{cosmetic_match, similar_names},
+    label::display_maths,
+    lint::{find_command_like, has_unbalanced_backticks, longest_backtick_run},
+    parser::{expected::describe_expected, Rule},
+    position::Range,
+    resolve::{import_outside_roots, is_file_uri, PackageKind},
+    stage::stage_errors,
+    Buffer, BufferCst, CmdKind, CommandDef, Cst, Environment, ParamKind,
+};
+
+/// diagnostics の発信元として表示する名前。
+pub const DIAGNOSTIC_SOURCE: &str = "satysfi-ls";
+
+/// 未定義のコマンドを表す diagnostic のコード。
+pub const UNDEFINED_COMMAND: &str = "undefined-command";
+
+/// 重複したコマンド定義を表す diagnostic のコード。
+pub const DUPLICATE_DEFINITION: &str = "duplicate-definition";
+
+/// 使われていない定義を表す diagnostic のコード。
+pub const UNUSED_DEFINITION: &str = "unused-definition";
+
+/// 文字列リテラル中の対になっていないバッククォートを表す diagnostic のコード。
+pub const UNBALANCED_BACKTICK: &str = "unbalanced-backtick";
+
+/// 文字列リテラル中のコマンドらしき文字列を表す diagnostic のコード。
+pub const COMMAND_IN_LITERAL: &str = "command-in-literal";
+
+/// 宣言されていないオプション引数の適用を表す diagnostic のコード。
+pub const UNEXPECTED_OPTION: &str = "unexpected-option";
+
+/// 期待されるのと異なる種類のテキスト引数を表す diagnostic のコード。
+pub const MODE_MISMATCH: &str = "mode-mismatch";
+
+/// ステージの異なるパッケージの読み込みを表す diagnostic のコード。
+pub const STAGE_MISMATCH: &str = "stage-mismatch";
+
+/// `&` や `~`、`lift-*` をそのステージで使えないことを表す diagnostic のコード。
+pub const INVALID_STAGE: &str = "invalid-stage";
+
+/// コンストラクタを網羅していない match 式を表す diagnostic のコード。
+pub const NON_EXHAUSTIVE_MATCH: &str = "non-exhaustive-match";
+
+/// 保存されていないバッファにある `@import:` を表す diagnostic のコード。
+pub const IMPORT_IN_UNSAVED_BUFFER: &str = "import-in-unsaved-buffer";
+
+/// 読み込みを許されたディレクトリの外にあるファイルを指す `@import:` を表す diagnostic のコード。
+pub const IMPORT_OUTSIDE_ROOTS: &str = "import-outside-allowed-roots";
+
+/// signature で公開されていないモジュール内の定義を表す diagnostic のコード。
+pub const UNEXPORTED_DEFINITION: &str = "unexported-definition";
+
+/// struct に実装のない signature の宣言を表す diagnostic のコード。
+pub const MISSING_IMPLEMENTATION: &str = "missing-implementation";
+
+/// 構文エラーを表す diagnostic のコード。
+pub const SYNTAX_ERROR: &str = "syntax-error";
+
+/// 別の別行立て数式と同じ名前のラベルを表す diagnostic のコード。
+pub const DUPLICATE_LABEL: &str = "duplicate-label";
+
+/// 文書クラスの `document` に渡すレコードに足りないフィールドを表す diagnostic のコード。
+pub const MISSING_RECORD_FIELD: &str = "missing-record-field";
+
+/// 提案する似た名前のコマンドの最大数。
+const MAX_SUGGESTIONS: usize = 3;
+
+/// 未定義コマンドの diagnostic に添付するデータ。
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct UndefinedCommandData {
+    /// 似た名前の定義済みコマンド。
+    pub suggestions: Vec<String>,
+    /// 大文字小文字や区切り方だけが異なる定義済みコマンド。
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub respelling: Option<String>,
+}
+
+/// 使われていない定義の diagnostic に添付するデータ。
+#[derive(Debug, Deserialize, Serialize)]
+pub struct UnusedDefinitionData {
+    /// 定義を取り除くときに削除する範囲。
+    pub removal: Range,
+}
+
+/// 対になっていないバッククォートの diagnostic に添付するデータ。
+#[derive(Debug, Deserialize, Serialize)]
+pub struct LongerFenceData {
+    /// リテラルの開始と終了のバッククォートを長くする編集。
+    pub edits: Vec<TextEdit>,
+}
+
+/// diagnostic を組み立てるもの。source は常に [`DIAGNOSTIC_SOURCE`] とする。
+#[derive(Debug)]
+pub(crate) struct DiagnosticBuilder(Diagnostic);
+
+impl DiagnosticBuilder {
+    /// 与えられた範囲と重大度、コード、メッセージの diagnostic を組み立て始める。
+    pub(crate) fn new(
+        range: Range,
+        severity: DiagnosticSeverity,
+        code: &str,
+        message: impl Into<String>,
+    ) -> Self {
+        Self(Diagnostic {
+            range: range.into(),
+            severity: Some(severity),
+            code: Some(NumberOrString::String(code.to_owned())),
+            source: Some(DIAGNOSTIC_SOURCE.to_owned()),
+            message: message.into(),
+            ..Default::default()
+        })
+    }
+
+    /// 定義箇所など、diagnostic に関連する別の場所を加える。
+    pub(crate) fn related(mut self, location: Location, message: impl Into<String>) -> Self {
+        let info = DiagnosticRelatedInformation { location, message: message.into() };
+        self.0.related_information.get_or_insert_with(Vec::new).push(info);
+        self
+    }
+
+    /// タグを加える。
+    pub(crate) fn tag(mut self, tag: DiagnosticTag) -> Self {
+        self.0.tags.get_or_insert_with(Vec::new).push(tag);
+        self
+    }
+
+    /// code action などに渡すデータを添付する。
+    pub(crate) fn data(mut self, data: impl Serialize) -> Self {
+        self.0.data = serde_json::to_value(data).ok();
+        self
+    }
+
+    /// 組み立てた diagnostic を返す。
+    pub(crate) fn build(self) -> Diagnostic {
+        self.0
+    }
+}
+
+/// バッファに対する diagnostics を返す。
+pub fn get_diagnostics(buf: &Buffer, uri: &Url, config: &Config) -> Vec<Diagnostic> {
+    let mut diagnostics = syntax_errors(buf);
+    diagnostics.extend(undefined_commands(buf, config));
+    diagnostics.extend(unexpected_options(buf, uri));
+    diagnostics.extend(mode_mismatches(buf, uri));
+    diagnostics.extend(duplicate_definitions(buf, uri));
+    diagnostics.extend(duplicate_labels(buf, uri));
+    diagnostics.extend(imports_in_unsaved_buffer(buf, uri));
+    diagnostics.extend(imports_outside_roots(buf, uri, config));
+    diagnostics.extend(unused_definitions(buf));
+    diagnostics.extend(stage_mismatches(buf));
+    diagnostics.extend(invalid_stages(buf));
+    diagnostics.extend(non_exhaustive_matches(buf, uri));
+    diagnostics.extend(signature_mismatches(buf));
+    diagnostics.extend(missing_document_fields(buf, config));
+    if config.lint.literal {
+        diagnostics.extend(literal_issues(buf));
+    }
+    diagnostics
+}
+
+/// コマンドの定義で宣言されたよりも多くのオプション引数を与えていれば報告する。
+/// 定義の見つからないコマンドは undefined_commands で報告するため、ここでは扱わない。
+fn unexpected_options(buf: &Buffer, uri: &Url) -> Vec<Diagnostic> {
+    let cst = match &buf.buf_cst.cst {
+        Some(cst) => cst,
+        None => return vec![],
+    };
+    let envs = visible_envs(buf, uri);
+
+    let mut diagnostics = vec![];
+    for rule in [Rule::inline_cmd, Rule::block_cmd, Rule::math_cmd] {
+        for usage in cst.pickup(rule) {
+            let name = match usage.inner.first() {
+                Some(cst) => buf.buf_cst.as_str(cst),
+                None => continue,
+            };
+            let (def, location) = match lookup_command(&envs, rule, name) {
+                Some(found) => found,
+                None => continue,
+            };
+            let declared = def.optional_arity;
+            let options = command_arguments(usage).into_iter().filter_map(|(slot, arg)| match slot {
+                ArgumentSlot::Optional(i) if i >= declared => Some(arg),
+                _ => None,
+            });
+            for option in options {
+                let message = if declared == 0 {
+                    format!("`{}` takes no optional argument", name)
+                } else {
+                    format!("`{}` takes at most {} optional argument(s)", name, declared)
+                };
+                diagnostics.push(
+                    DiagnosticBuilder::new(
+                        option.range.clone().into(),
+                        DiagnosticSeverity::Error,
+                        UNEXPECTED_OPTION,
+                        message,
+                    )
+                    .related(location.clone(), "command defined here")
+                    .build(),
+                );
+            }
+        }
+    }
+    diagnostics
+}
+
+/// インラインテキストを期待する引数に `<...>` を、ブロックテキストを期待する引数に `{...}` を
+/// 与えていれば報告する。引数の種類が分からないものや `(...)` で与えた引数は扱わない。
+fn mode_mismatches(buf: &Buffer, uri: &Url) -> Vec<Diagnostic> {
+    let cst = match &buf.buf_cst.cst {
+        Some(cst) => cst,
+        None => return vec![],
+    };
+    let envs = visible_envs(buf, uri);
+
+    let mut diagnostics = vec![];
+    for rule in [Rule::inline_cmd, Rule::block_cmd] {
+        for usage in cst.pickup(rule) {
+            let name = match usage.inner.first() {
+                Some(cst) => buf.buf_cst.as_str(cst),
+                None => continue,
+            };
+            let (def, location) = match lookup_command(&envs, rule, name) {
+                Some(found) => found,
+                None => continue,
+            };
+            let args = command_arguments(usage).into_iter().filter_map(|(slot, arg)| match slot {
+                ArgumentSlot::Mandatory(i) => Some((arg, def.param_kinds.get(i)?)),
+                _ => None,
+            });
+            for (arg, kind) in args {
+                if arg.rule != Rule::cmd_text_arg {
+                    continue;
+                }
+                let given = if buf.buf_cst.as_str(arg).starts_with('{') {
+                    ParamKind::InlineText
+                } else {
+                    ParamKind::BlockText
+                };
+                let message = match (kind, given) {
+                    (ParamKind::InlineText, ParamKind::BlockText) => format!(
+                        "`{}` expects inline text `{{...}}` here, but block text `<...>` is given",
+                        name
+                    ),
+                    (ParamKind::BlockText, ParamKind::InlineText) => format!(
+                        "`{}` expects block text `<...>` here, but inline text `{{...}}` is given",
+                        name
+                    ),
+                    _ => continue,
+                };
+                diagnostics.push(
+                    DiagnosticBuilder::new(
+                        arg.range.clone().into(),
+                        DiagnosticSeverity::Error,
+                        MODE_MISMATCH,
+                        message,
+                    )
+                    .related(location.clone(), "command defined here")
+                    .build(),
+                );
+            }
+        }
+    }
+    diagnostics
+}
+
+/// バッファ自身と読み込んだパッケージの environment を、定義の探す順に URI と組にして返す。
+pub(crate) fn visible_envs<'a>(buf: &'a Buffer, uri: &'a Url) -> Vec<(&'a Url, &'a Environment)> {
+    std::iter::once((uri, &buf.env))
+        .chain(buf.packages.iter().map(|pkg| (&pkg.uri, &pkg.env)))
+        .collect()
+}
+
+/// コマンドの使用箇所の構文規則と名前から、その定義と定義の場所を探す。
+/// 同じ名前のコマンドの定義があった場合は最後を取る。
+pub(crate) fn lookup_command<'a>(
+    envs: &[(&Url, &'a Environment)],
+    rule: Rule,
+    name: &str,
+) -> Option<(CommandDef<'a>, Location)> {
+    let kind = CmdKind::from_rule(rule)?;
+    envs.iter().find_map(|(uri, env)| {
+        let def = env.lookup(kind, name)?;
+        let location = Location { uri: (*uri).clone(), range: def.def_range.into() };
+        Some((def, location))
+    })
+}
+
+/// バッファと異なるステージのパッケージを読み込んでいれば、そのヘッダを報告する。
+fn stage_mismatches(buf: &Buffer) -> Vec<Diagnostic> {
+    let cst = match &buf.buf_cst.cst {
+        Some(cst) => cst,
+        None => return vec![],
+    };
+    let stage = buf.buf_cst.stage();
+    let headers = cst.pickup(Rule::header);
+
+    let mut diagnostics = vec![];
+    for pkg in &buf.packages {
+        let pkg_stage = match pkg.stage {
+            Some(pkg_stage) if pkg_stage != stage => pkg_stage,
+            _ => continue,
+        };
+        let header = headers.iter().find(|header| {
+            header
+                .inner
+                .iter()
+                .any(|c| c.rule == Rule::pkgname && buf.buf_cst.as_str(c).trim_end() == pkg.name)
+        });
+        let range = match header {
+            Some(header) => header.range.clone().into(),
+            None => continue,
+        };
+        let message = format!(
+            "stage-{} file cannot load stage-{} package `{}`",
+            stage.as_str(),
+            pkg_stage.as_str(),
+            pkg.name
+        );
+        let package = Location { uri: pkg.uri.clone(), range: Default::default() };
+        diagnostics.push(
+            DiagnosticBuilder::new(range, DiagnosticSeverity::Error, STAGE_MISMATCH, message)
+                .related(package, format!("package file for stage {}", pkg_stage.as_str()))
+                .build(),
+        );
+    }
+    diagnostics
+}
+
+/// ファイルを指さない URI のバッファにある `@import:` を報告する。
+/// 読み込むパッケージはバッファのあるディレクトリからの相対パスで探すため、保存するまで解決できない。
+fn imports_in_unsaved_buffer(buf: &Buffer, uri: &Url) -> Vec<Diagnostic> {
+    let cst = match &buf.buf_cst.cst {
+        Some(cst) if !is_file_uri(uri) => cst,
+        _ => return vec![],
+    };
+    cst.pickup(Rule::header)
+        .into_iter()
+        .filter(|header| {
+            header
+                .inner
+                .iter()
+                .any(|c| c.rule == Rule::header_kind && buf.buf_cst.as_str(c) == "import")
+        })
+        .filter_map(|header| header.inner.iter().find(|c| c.rule == Rule::pkgname))
+        .map(|name| {
+            let message = format!(
+                "cannot resolve `@import: {}` in an unsaved buffer; save the file to resolve relative imports",
+                buf.buf_cst.as_str(name).trim_end()
+            );
+            DiagnosticBuilder::new(
+                name.range.clone().into(),
+                DiagnosticSeverity::Warning,
+                IMPORT_IN_UNSAVED_BUFFER,
+                message,
+            )
+            .build()
+        })
+        .collect()
+}
+
+/// 読み込みを許されたディレクトリの外にあるファイルを指す `@import:` を報告する。
+/// そのようなパッケージは読み込まない。
+fn imports_outside_roots(buf: &Buffer, uri: &Url, config: &Config) -> Vec<Diagnostic> {
+    let cst = match &buf.buf_cst.cst {
+        Some(cst) if is_file_uri(uri) => cst,
+        _ => return vec![],
+    };
+    let stage = buf.buf_cst.stage();
+    cst.pickup(Rule::header)
+        .into_iter()
+        .filter(|header| {
+            header
+                .inner
+                .iter()
+                .any(|c| c.rule == Rule::header_kind && buf.buf_cst.as_str(c) == "import")
+        })
+        .filter_map(|header| header.inner.iter().find(|c| c.rule == Rule::pkgname))
+        .filter_map(|name| {
+            let text = buf.buf_cst.as_str(name).trim_end();
+            let path = import_outside_roots(uri, text, stage, config)?;
+            let message = format!(
+                "`@import: {}` resolves to {}, which is outside the allowed roots; add its directory to `allowed-roots` to load it",
+                text,
+                path.display()
+            );
+            let diagnostic = DiagnosticBuilder::new(
+                name.range.clone().into(),
+                DiagnosticSeverity::Error,
+                IMPORT_OUTSIDE_ROOTS,
+                message,
+            )
+            .build();
+            Some(diagnostic)
+        })
+        .collect()
+}
+
+/// 式のステージで使えない `&` や `~`、`lift-*` を報告する。
+fn invalid_stages(buf: &Buffer) -> Vec<Diagnostic> {
+    stage_errors(&buf.buf_cst)
+        .into_iter()
+        .map(|error| {
+            DiagnosticBuilder::new(
+                error.range.into(),
+                DiagnosticSeverity::Error,
+                INVALID_STAGE,
+                error.message,
+            )
+            .build()
+        })
+        .collect()
+}
+
+/// バッファで定義されたヴァリアント型に対する match 式のうち、
+/// すべてのコンストラクタを網羅しておらず、ワイルドカードの腕もないものを報告する。
+/// 型は腕のパターンに現れるコンストラクタから求める。
+fn non_exhaustive_matches(buf: &Buffer, uri: &Url) -> Vec<Diagnostic> {
+    let cst = match &buf.buf_cst.cst {
+        Some(cst) => cst,
+        None => return vec![],
+    };
+
+    let mut diagnostics = vec![];
+    for match_expr in cst.pickup(Rule::match_expr) {
+        let (scrutinee, arms) = match match_expr.inner.split_first() {
+            Some((scrutinee, arms)) => (scrutinee, arms),
+            None => continue,
+        };
+        // when で条件の付いた腕は、どのコンストラクタも網羅しない。
+        let arm_ptns = arms
+            .iter()
+            .filter(|arm| arm.inner.len() == 2)
+            .filter_map(|arm| arm.inner.first());
+        let mut covered = vec![];
+        let mut has_wildcard = false;
+        for ptn in arm_ptns {
+            match covered_constructor(&buf.buf_cst, ptn) {
+                Coverage::All => has_wildcard = true,
+                Coverage::Constructor(name) => covered.push(name),
+                Coverage::Partial => (),
+            }
+        }
+        if has_wildcard {
+            continue;
+        }
+
+        let used = arms
+            .iter()
+            .flat_map(|arm| arm.inner.first())
+            .flat_map(|ptn| ptn.pickup(Rule::pat_variant))
+            .filter_map(|variant| variant.inner.first())
+            .map(|name| buf.buf_cst.as_str(name))
+            .collect_vec();
+        let ty = buf.env.types().iter().rfind(|ty| {
+            ty.constructors.iter().any(|c| used.contains(&c.name.as_str()))
+        });
+        let ty = match ty {
+            Some(ty) => ty,
+            None => continue,
+        };
+        let missing = ty
+            .constructors
+            .iter()
+            .filter(|c| !covered.contains(&c.name.as_str()))
+            .map(|c| format!("`{}`", c.name))
+            .collect_vec();
+        if missing.is_empty() {
+            continue;
+        }
+        let range = Range {
+            start: match_expr.range.start.clone().into(),
+            end: scrutinee.range.end.clone().into(),
+        };
+        let message = format!("non-exhaustive match over `{}`: missing {}", ty.name, missing.join(", "));
+        let definition = Location { uri: uri.clone(), range: ty.def_range.into() };
+        diagnostics.push(
+            DiagnosticBuilder::new(range, DiagnosticSeverity::Warning, NON_EXHAUSTIVE_MATCH, message)
+                .related(definition, "type defined here")
+                .build(),
+        );
+    }
+    diagnostics
+}
+
+/// match の腕のパターンが網羅する値。
+enum Coverage<'a> {
+    /// すべての値。
+    All,
+    /// 与えられたコンストラクタによるすべての値。
+    Constructor(&'a str),
+    /// 一部の値。
+    Partial,
+}
+
+/// match_ptn が網羅する値を求める。
+fn covered_constructor<'a>(buf_cst: &'a BufferCst, match_ptn: &Cst) -> Coverage<'a> {
+    match &match_ptn.inner[..] {
+        // `A x` や `A(_)` のように、引数のパターンがすべての値に合致するもの。
+        [variant] if variant.rule == Rule::pat_variant => match &variant.inner[..] {
+            [name] => Coverage::Constructor(buf_cst.as_str(name)),
+            [name, arg] if is_irrefutable(arg) => Coverage::Constructor(buf_cst.as_str(name)),
+            _ => Coverage::Partial,
+        },
+        // `(A x)` や `A x as y` のように、パターンを括弧や as で包んだもの。
+        [ptn] | [ptn, Cst { rule: Rule::var, .. }] => match &ptn.inner[..] {
+            [inner] if inner.rule == Rule::match_ptn => covered_constructor(buf_cst, inner),
+            _ if is_irrefutable(ptn) => Coverage::All,
+            _ => Coverage::Partial,
+        },
+        _ => Coverage::Partial,
+    }
+}
+
+/// パターンがすべての値に合致するか。
+fn is_irrefutable(cst: &Cst) -> bool {
+    match (cst.rule, &cst.inner[..]) {
+        // `_`
+        (Rule::pattern, []) => true,
+        (Rule::pattern, [inner]) => {
+            matches!(inner.rule, Rule::var | Rule::match_ptn | Rule::pat_tuple) && is_irrefutable(inner)
+        }
+        (Rule::var, _) => true,
+        (Rule::pat_tuple, ptns) => ptns.iter().all(is_irrefutable),
+        // `x as y` は x と同じ値に合致する。`x :: xs` には合致しない値がある。
+        (Rule::match_ptn, [ptn]) | (Rule::match_ptn, [ptn, Cst { rule: Rule::var, .. }]) => {
+            is_irrefutable(ptn)
+        }
+        _ => false,
+    }
+}
+
+/// 文字列リテラル中の、見つけにくい書き間違いを報告する。
+fn literal_issues(buf: &Buffer) -> Vec<Diagnostic> {
+    let cst = match &buf.buf_cst.cst {
+        Some(cst) => cst,
+        None => return vec![],
+    };
+    let index = buf.buf_cst.line_index();
+    let information = |range: Range, code: &str, message: String| {
+        DiagnosticBuilder::new(range, DiagnosticSeverity::Information, code, message)
+    };
+
+    let mut diagnostics = vec![];
+    for interior in cst.pickup(Rule::string_interior) {
+        let text = buf.buf_cst.as_str(interior);
+        let start = interior.range.start.byte as usize;
+        let end = interior.range.end.byte as usize;
+
+        if has_unbalanced_backticks(text) {
+            // 開始と終了のバッククォートの長さは等しい。
+            let fence_len = buf.buf_cst.buffer[..start].len()
+                - buf.buf_cst.buffer[..start].trim_end_matches('`').len();
+            let fence = "`".repeat(longest_backtick_run(text).max(fence_len) + 1);
+            let edits = vec![
+                TextEdit {
+                    range: Range {
+                        start: index.position(start - fence_len),
+                        end: index.position(start),
+                    }
+                    .into(),
+                    new_text: fence.clone(),
+                },
+                TextEdit {
+                    range: Range {
+                        start: index.position(end),
+                        end: index.position(end + fence_len),
+                    }
+                    .into(),
+                    new_text: fence,
+                },
+            ];
+            diagnostics.push(
+                information(
+                    interior.range.clone().into(),
+                    UNBALANCED_BACKTICK,
+                    "string literal contains an unbalanced backtick".to_owned(),
+                )
+                .data(LongerFenceData { edits })
+                .build(),
+            );
+        }
+
+        for command in find_command_like(text) {
+            let range = Range {
+                start: index.position(start + command.offset),
+                end: index.position(start + command.offset + command.name.len()),
+            };
+            diagnostics.push(
+                information(
+                    range,
+                    COMMAND_IN_LITERAL,
+                    format!("`{}` is not interpreted as a command in a string literal", command.name),
+                )
+                .build(),
+            );
+        }
+    }
+    diagnostics
+}
+
+/// 文書ファイルのプリアンブルで定義されているのに、どこからも参照されていないものを報告する。
+/// パッケージファイルの定義は他のファイルから使われうるため報告しない。
+fn unused_definitions(buf: &Buffer) -> Vec<Diagnostic> {
+    let cst = match &buf.buf_cst.cst {
+        Some(cst) => cst,
+        None => return vec![],
+    };
+    let document = [Rule::program_saty, Rule::program_saty_next]
+        .iter()
+        .find_map(|&rule| cst.pickup(rule).first().copied());
+    let preamble = match document {
+        Some(program) => match program.inner.iter().find(|c| c.rule == Rule::preamble) {
+            Some(preamble) => preamble,
+            None => return vec![],
+        },
+        None => return vec![],
+    };
+
+    let occurrences = buf.buf_cst.name_occurrences();
+
+    let mut diagnostics = vec![];
+    for (i, stmt) in preamble.inner.iter().enumerate() {
+        let (kind, name_cst) = match defined_name(stmt) {
+            Some(def) => def,
+            None => continue,
+        };
+        let name = buf.buf_cst.as_str(name_cst);
+        if occurrences.get(name).copied().unwrap_or_default() > 1 {
+            continue;
+        }
+        // 後続の文の直前までを消すことで、空行が残らないようにする。
+        let removal = Range {
+            start: stmt.range.start.clone().into(),
+            end: match preamble.inner.get(i + 1) {
+                Some(next) => next.range.start.clone().into(),
+                None => stmt.range.end.clone().into(),
+            },
+        };
+        diagnostics.push(
+            DiagnosticBuilder::new(
+                name_cst.range.clone().into(),
+                DiagnosticSeverity::Hint,
+                UNUSED_DEFINITION,
+                format!("unused {} `{}`", kind, name),
+            )
+            .tag(DiagnosticTag::Unnecessary)
+            .data(UnusedDefinitionData { removal })
+            .build(),
+        );
+    }
+    diagnostics
+}
+
+/// パッケージファイルのモジュールについて、signature と struct の定義を突き合わせる。
+/// struct で定義されているのに signature にないものは警告し、
+/// signature で宣言されているのに struct で定義されていないものはエラーとする。
+fn signature_mismatches(buf: &Buffer) -> Vec<Diagnostic> {
+    let cst = match &buf.buf_cst.cst {
+        Some(cst) => cst,
+        None => return vec![],
+    };
+    let package = [Rule::program_satyh, Rule::program_satyh_next]
+        .iter()
+        .find_map(|&rule| cst.pickup(rule).first().copied());
+    let program = match package {
+        Some(program) => program,
+        None => return vec![],
+    };
+
+    let mut diagnostics = vec![];
+    for module in program.pickup(Rule::module_stmt) {
+        let module_name = match module.inner.first() {
+            Some(name) if name.rule == Rule::module_name => buf.buf_cst.as_str(name),
+            _ => continue,
+        };
+        let sig = module.inner.iter().find(|c| c.rule == Rule::sig_stmt);
+        let body = module.inner.iter().find(|c| c.rule == Rule::struct_stmt);
+        let (sig, body) = match (sig, body) {
+            (Some(sig), Some(body)) => (sig, body),
+            _ => continue,
+        };
+        let declared = signature_entries(sig);
+        let defined = struct_definitions(body);
+
+        for (is_type, name_cst) in &defined {
+            let name = buf.buf_cst.as_str(name_cst);
+            if declared.iter().any(|(t, d)| t == is_type && buf.buf_cst.as_str(d) == name) {
+                continue;
+            }
+            let kind = if *is_type { "type" } else { "value" };
+            diagnostics.push(
+                DiagnosticBuilder::new(
+                    name_cst.range.clone().into(),
+                    DiagnosticSeverity::Warning,
+                    UNEXPORTED_DEFINITION,
+                    format!(
+                        "{} `{}` is defined in module `{}` but not exported by its signature",
+                        kind, name, module_name
+                    ),
+                )
+                .build(),
+            );
+        }
+        for (is_type, name_cst) in &declared {
+            let name = buf.buf_cst.as_str(name_cst);
+            if defined.iter().any(|(t, d)| t == is_type && buf.buf_cst.as_str(d) == name) {
+                continue;
+            }
+            let kind = if *is_type { "type" } else { "value" };
+            diagnostics.push(
+                DiagnosticBuilder::new(
+                    name_cst.range.clone().into(),
+                    DiagnosticSeverity::Error,
+                    MISSING_IMPLEMENTATION,
+                    format!(
+                        "{} `{}` is declared in the signature of module `{}` but not defined",
+                        kind, name, module_name
+                    ),
+                )
+                .build(),
+            );
+        }
+    }
+    diagnostics
+}
+
+/// signature で宣言された名前を、型かどうかとともに列挙する。演算子の宣言は扱わない。
+fn signature_entries(sig: &Cst) -> Vec<(bool, &Cst)> {
+    let entries = match sig.inner.iter().find(|c| c.rule == Rule::sig_inner) {
+        Some(inner) => &inner.inner[..],
+        None => return vec![],
+    };
+    entries
+        .iter()
+        .filter_map(|entry| match entry.rule {
+            Rule::sig_type_stmt => {
+                let name = entry.inner.iter().find(|c| c.rule == Rule::var)?;
+                Some((true, name))
+            }
+            Rule::sig_val_stmt | Rule::sig_direct_stmt => {
+                let name = entry.inner.first()?;
+                let is_name = matches!(
+                    name.rule,
+                    Rule::var | Rule::inline_cmd_name | Rule::block_cmd_name
+                );
+                is_name.then_some((false, name))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// struct の直下で定義された名前を、型かどうかとともに列挙する。入れ子のモジュールは扱わない。
+fn struct_definitions(body: &Cst) -> Vec<(bool, &Cst)> {
+    let mut names = vec![];
+    for stmt in body.inner.iter().filter(|c| c.rule == Rule::statement) {
+        let def = match stmt.inner.first() {
+            Some(def) => def,
+            None => continue,
+        };
+        let name_rule = match def.rule {
+            Rule::let_stmt => {
+                // `let (x, y) = ...` のような分割代入では、パターン中の変数がすべて定義される。
+                if let Some(ptn) = def.inner.first() {
+                    names.extend(ptn.pickup(Rule::var).into_iter().map(|var| (false, var)));
+                }
+                continue;
+            }
+            Rule::let_inline_stmt => Rule::inline_cmd_name,
+            Rule::let_block_stmt => Rule::block_cmd_name,
+            Rule::let_math_stmt => Rule::math_cmd_name,
+            Rule::let_mutable_stmt => Rule::var,
+            Rule::type_stmt => Rule::type_name,
+            _ => continue,
+        };
+        if let Some(name) = def.inner.iter().find(|c| c.rule == name_rule) {
+            names.push((def.rule == Rule::type_stmt, name));
+        }
+    }
+    names
+}
+
+/// statement が単一の名前を定義していれば、その種類と名前の Cst を返す。
+pub(crate) fn defined_name(stmt: &Cst) -> Option<(&'static str, &Cst)> {
+    let def = stmt.inner.first()?;
+    let (kind, rule) = match def.rule {
+        Rule::let_inline_stmt => ("inline command", Rule::inline_cmd_name),
+        Rule::let_block_stmt => ("block command", Rule::block_cmd_name),
+        Rule::let_math_stmt => ("math command", Rule::math_cmd_name),
+        Rule::let_stmt => {
+            // `let (x, y) = ...` のような分割代入は一部だけ消すことができないので扱わない。
+            let ptn = def.inner.first()?;
+            return match &ptn.inner[..] {
+                [var] if var.rule == Rule::var => Some(("variable", var)),
+                _ => None,
+            };
+        }
+        _ => return None,
+    };
+    let name = def.inner.iter().find(|c| c.rule == rule)?;
+    Some((kind, name))
+}
+
+/// バッファの構文エラーを返す。修復してパースした箇所に加え、修復できなかったエラーも含む。
+pub fn syntax_errors(buf: &Buffer) -> Vec<Diagnostic> {
+    let mut diagnostics = recovered_syntax_errors(buf);
+    let index = buf.buf_cst.line_index();
+    let unrecovered = buf
+        .error
+        .iter()
+        .filter_map(|e| e.downcast_ref::<pest::error::Error<Rule>>())
+        .map(|e| {
+            let (start, end) = match e.location {
+                pest::error::InputLocation::Pos(pos) => (pos, pos),
+                pest::error::InputLocation::Span(span) => span,
+            };
+            let range = Range::new(index.position(start), index.position(end));
+            let message = match &e.variant {
+                pest::error::ErrorVariant::ParsingError { positives, .. } => describe_expected(positives),
+                pest::error::ErrorVariant::CustomError { message } => message.clone(),
+            };
+            DiagnosticBuilder::new(range, DiagnosticSeverity::Error, SYNTAX_ERROR, message).build()
+        });
+    diagnostics.extend(unrecovered);
+    diagnostics
+}
+
+/// 文書クラスの `document` に渡すレコードに、completion.toml に書かれたフィールドが足りなければ報告する。
+/// `(| r with ... |)` の形のレコードは、足りないフィールドを `r` が持つかもしれないので調べない。
+fn missing_document_fields(buf: &Buffer, config: &Config) -> Vec<Diagnostic> {
+    let cst = match &buf.buf_cst.cst {
+        Some(cst) => cst,
+        None => return vec![],
+    };
+    let class = match document_class(buf, config) {
+        Some(class) => class,
+        None => return vec![],
+    };
+    let fields = document_fields(&class, config);
+    if fields.is_empty() {
+        return vec![];
+    }
+    let records = cst.pickup(Rule::application).into_iter().filter_map(|app| {
+        let (head, args) = app.inner.split_first()?;
+        if head.rule != Rule::var || buf.buf_cst.as_str(head) != "document" {
+            return None;
+        }
+        let arg = args.iter().find(|arg| arg.rule == Rule::unary)?;
+        arg.inner.first().filter(|c| c.rule == Rule::record)
+    });
+    records
+        .filter_map(|record| {
+            let inner = match &record.inner[..] {
+                [inner] if inner.rule == Rule::record_inner => Some(inner),
+                [] => None,
+                _ => return None,
+            };
+            let written = inner
+                .map(|inner| {
+                    inner
+                        .inner
+                        .iter()
+                        .filter_map(|unit| unit.inner.first())
+                        .map(|name| buf.buf_cst.as_str(name))
+                        .collect_vec()
+                })
+                .unwrap_or_default();
+            let missing = fields
+                .iter()
+                .filter(|field| !written.contains(&field.label.as_str()))
+                .map(|field| format!("`{}`", field.label))
+                .collect_vec();
+            if missing.is_empty() {
+                return None;
+            }
+            let message = format!(
+                "the `{}` document record is missing {}",
+                class,
+                missing.join(", ")
+            );
+            let range = record.range.clone().into();
+            Some(DiagnosticBuilder::new(range, DiagnosticSeverity::Warning, MISSING_RECORD_FIELD, message).build())
+        })
+        .collect()
+}
+
+/// パース時に修復した箇所を構文エラーとして報告する。
+fn recovered_syntax_errors(buf: &Buffer) -> Vec<Diagnostic> {
+    buf.buf_cst
+        .recoveries()
+        .iter()
+        .map(|recovery| {
+            DiagnosticBuilder::new(
+                recovery.range,
+                DiagnosticSeverity::Error,
+                SYNTAX_ERROR,
+                recovery.kind.message(),
+            )
+            .build()
+        })
+        .collect()
+}
+
+/// 同じ名前のラベルが複数の別行立て数式に付けられていれば、2つ目以降のラベルを報告する。
+/// どの数式を参照するかが定まらず、生成される PDF での参照が誤ったものになる。
+fn duplicate_labels(buf: &Buffer, uri: &Url) -> Vec<Diagnostic> {
+    let labels = display_maths(buf)
+        .into_iter()
+        .filter_map(|math| Some((math.label?, math.label_range?)))
+        .collect_vec();
+    labels
+        .iter()
+        .enumerate()
+        .filter_map(|(i, (label, range))| {
+            let (_, first) = labels[..i].iter().find(|(l, _)| l == label)?;
+            let location = Location { uri: uri.clone(), range: (*first).into() };
+            let diagnostic = DiagnosticBuilder::new(
+                *range,
+                DiagnosticSeverity::Warning,
+                DUPLICATE_LABEL,
+                format!("label `{}` is already used", label),
+            )
+            .related(location, "first use")
+            .build();
+            Some(diagnostic)
+        })
+        .collect()
+}
+
+/// 同じ名前のコマンドが複数回定義されていれば、2つ目以降の定義を報告する。
+/// `@import:` で読み込んだパッケージに同じ名前のコマンドがある場合も報告する。
+fn duplicate_definitions(buf: &Buffer, uri: &Url) -> Vec<Diagnostic> {
+    // 外から見た名前とその定義箇所。モジュール内部に閉じた定義は比較しない。
+    fn commands(env: &Environment) -> Vec<(String, &'static str, Range)> {
+        [
+            (CmdKind::Inline, "inline command"),
+            (CmdKind::Block, "block command"),
+            (CmdKind::Math, "math command"),
+        ]
+        .iter()
+        .flat_map(|&(kind, label)| {
+            env.commands(kind).filter_map(move |c| {
+                Some((c.visibility.exported_name(c.name)?, label, c.def_range))
+            })
+        })
+        .collect()
+    }
+
+    let imported = buf
+        .packages
+        .iter()
+        .filter(|pkg| pkg.kind == PackageKind::Import)
+        .flat_map(|pkg| {
+            commands(&pkg.env)
+                .into_iter()
+                .map(move |(name, kind, range)| (name, kind, Location { uri: pkg.uri.clone(), range: range.into() }))
+        })
+        .collect_vec();
+
+    let mut seen: Vec<(String, &str, Location)> = vec![];
+    let mut diagnostics = vec![];
+    for (name, kind, range) in commands(&buf.env) {
+        let first = seen
+            .iter()
+            .chain(imported.iter())
+            .find(|(n, k, _)| n == &name && k == &kind);
+        if let Some((_, _, first)) = first {
+            diagnostics.push(
+                DiagnosticBuilder::new(
+                    range,
+                    DiagnosticSeverity::Warning,
+                    DUPLICATE_DEFINITION,
+                    format!("{} `{}` is already defined", kind, name),
+                )
+                .related(first.clone(), "first definition")
+                .build(),
+            );
+        } else {
+            seen.push((name, kind, Location { uri: uri.clone(), range: range.into() }));
+        }
+    }
+    diagnostics
+}
+
+/// 未定義のコマンドの使用箇所を報告する。
+/// 読み込めなかったパッケージがある場合、そこで定義されたコマンドを誤検出しうるため報告しない。
+fn undefined_commands(buf: &Buffer, config: &Config) -> Vec<Diagnostic> {
+    let cst = match &buf.buf_cst.cst {
+        Some(cst) => cst,
+        None => return vec![],
+    };
+    if buf.packages.len() < buf.buf_cst.headers().len() {
+        return vec![];
+    }
+
+    // 文書クラスのコマンドは、そのクラスを読み込んでいなければ使えないため候補にしない。
+    let catalog_labels = config
+        .catalog
+        .items()
+        .filter(|(section, _)| *section != "classes")
+        .map(|(_, item)| item.label.as_str())
+        .sorted()
+        .collect_vec();
+    let envs = std::iter::once(&buf.env)
+        .chain(buf.packages.iter().map(|pkg| &pkg.env))
+        .collect_vec();
+
+    let kinds = [
+        (
+            Rule::inline_cmd,
+            "inline command",
+            envs.iter().flat_map(|env| env.commands(CmdKind::Inline).map(|c| c.name)).collect_vec(),
+        ),
+        (
+            Rule::block_cmd,
+            "block command",
+            envs.iter().flat_map(|env| env.commands(CmdKind::Block).map(|c| c.name)).collect_vec(),
+        ),
+        (
+            Rule::math_cmd,
+            "math command",
+            envs.iter().flat_map(|env| env.commands(CmdKind::Math).map(|c| c.name)).collect_vec(),
+        ),
+    ];
+
+    let mut diagnostics = vec![];
+    for (rule, kind, defined) in kinds.iter() {
+        for usage in cst.pickup(*rule) {
+            let name_cst = match usage.inner.first() {
+                Some(cst) => cst,
+                None => continue,
+            };
+            let name = buf.buf_cst.as_str(name_cst);
+            if defined.contains(&name) {
+                continue;
+            }
+            let sigil = &name[..1];
+            let candidates = defined.iter().copied().chain(
+                catalog_labels
+                    .iter()
+                    .copied()
+                    .filter(|label| label.starts_with(sigil)),
+            );
+            let candidates = candidates.collect_vec();
+            let respelling = cosmetic_match(name, candidates.iter().copied());
+            // 書き方だけが異なるコマンドは、編集距離によらず最初の候補にする。
+            let suggestions = respelling
+                .into_iter()
+                .chain(similar_names(name, candidates, MAX_SUGGESTIONS))
+                .unique()
+                .take(MAX_SUGGESTIONS)
+                .collect_vec();
+
+            let mut message = format!("undefined {} `{}`", kind, name);
+            if !suggestions.is_empty() {
+                let list = suggestions.iter().map(|s| format!("`{}`", s)).join(", ");
+                message.push_str(&format!(". did you mean {}?", list));
+            }
+            let data = UndefinedCommandData {
+                suggestions: suggestions.into_iter().map(str::to_owned).collect(),
+                respelling: respelling.map(str::to_owned),
+            };
+            diagnostics.push(
+                DiagnosticBuilder::new(
+                    name_cst.range.clone().into(),
+                    DiagnosticSeverity::Error,
+                    UNDEFINED_COMMAND,
+                    message,
+                )
+                .data(data)
+                .build(),
+            );
+        }
+    }
+    diagnostics
+}
+
+#[cfg(test)]
+mod tests;
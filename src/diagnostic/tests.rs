@@ -0,0 +1,409 @@
+//! test module for diagnostics.
+
+use super::*;
+use crate::{parser::recovery::RecoveryKind, position::Position, test_utils::temp_dir};
+
+fn uri() -> Url {
+    Url::parse("file:///test.saty").unwrap()
+}
+
+fn diagnostics(text: &str) -> Vec<Diagnostic> {
+    let buf = Buffer::new(text.to_owned());
+    assert!(buf.error.is_empty(), "parse failed: {:?}", buf.error);
+    get_diagnostics(&buf, &uri(), &Config::default())
+}
+
+fn diagnostics_with_code(text: &str, code: &str) -> Vec<Diagnostic> {
+    let code = Some(NumberOrString::String(code.to_owned()));
+    diagnostics(text).into_iter().filter(|d| d.code == code).collect()
+}
+
+#[test]
+fn test_undefined_command_with_suggestion() {
+    let diags = diagnostics_with_code(
+        r#"let-inline ctx \emph inner = inner
+in
+'<
+  +p{ \embf{text} }
+>
+"#,
+        UNDEFINED_COMMAND,
+    );
+    assert_eq!(diags.len(), 2);
+    assert_eq!(diags[0].message, "undefined inline command `\\embf`. did you mean `\\emph`?");
+    let data: UndefinedCommandData =
+        serde_json::from_value(diags[0].data.clone().unwrap()).unwrap();
+    assert_eq!(data.suggestions, vec!["\\emph".to_owned()]);
+    assert_eq!(diags[1].message, "undefined block command `+p`");
+}
+
+#[test]
+fn test_undefined_command_with_respelling() {
+    let diags = diagnostics_with_code(
+        r#"let-inline ctx \line-break-with-space = inline-skip 0pt
+in
+{ \lineBreakWithSpace; }
+"#,
+        UNDEFINED_COMMAND,
+    );
+    assert_eq!(diags.len(), 1);
+    assert_eq!(
+        diags[0].message,
+        "undefined inline command `\\lineBreakWithSpace`. did you mean `\\line-break-with-space`?"
+    );
+    let data: UndefinedCommandData =
+        serde_json::from_value(diags[0].data.clone().unwrap()).unwrap();
+    assert_eq!(data.respelling, Some("\\line-break-with-space".to_owned()));
+    assert_eq!(data.suggestions, vec!["\\line-break-with-space".to_owned()]);
+}
+
+#[test]
+fn test_defined_command() {
+    let diags = diagnostics(
+        r#"let-inline ctx \emph inner = inner
+in
+{ \emph{text} }
+"#,
+    );
+    assert!(diags.is_empty());
+}
+
+#[test]
+fn test_duplicate_definition() {
+    let diags = diagnostics(
+        r#"let-inline ctx \emph inner = inner
+let-inline ctx \emph inner = inner
+let-block ctx +emph inner = inner
+"#,
+    );
+    assert_eq!(diags.len(), 1);
+    assert_eq!(diags[0].message, "inline command `\\emph` is already defined");
+    assert_eq!(diags[0].severity, Some(DiagnosticSeverity::Warning));
+    assert_eq!(diags[0].range.start.line, 1);
+    let related = diags[0].related_information.as_ref().unwrap();
+    assert_eq!(related[0].location.uri, uri());
+    assert_eq!(related[0].location.range.start.line, 0);
+}
+
+#[test]
+fn test_unused_definition() {
+    let unused = diagnostics_with_code(
+        r#"let-inline ctx \used inner = inner
+let-inline ctx \unused inner = inner
+let x = 1
+in
+'<
+  +p{ \used{text} }
+>
+"#,
+        UNUSED_DEFINITION,
+    );
+    assert_eq!(unused.len(), 2);
+    assert_eq!(unused[0].message, "unused inline command `\\unused`");
+    assert_eq!(unused[1].message, "unused variable `x`");
+    let data: UnusedDefinitionData =
+        serde_json::from_value(unused[0].data.clone().unwrap()).unwrap();
+    assert_eq!(data.removal.start, Position { line: 1, character: 0 });
+    assert_eq!(data.removal.end, Position { line: 2, character: 0 });
+}
+
+#[test]
+fn test_unbalanced_backtick() {
+    let diags = diagnostics_with_code("let s = ``a`b``\n", UNBALANCED_BACKTICK);
+    assert_eq!(diags.len(), 1);
+    let data: LongerFenceData = serde_json::from_value(diags[0].data.clone().unwrap()).unwrap();
+    assert_eq!(data.edits.len(), 2);
+    assert_eq!(data.edits[0].new_text, "```");
+    assert_eq!(data.edits[0].range.start.character, 8);
+    assert_eq!(data.edits[0].range.end.character, 10);
+    assert_eq!(data.edits[1].range.start.character, 13);
+    assert_eq!(data.edits[1].range.end.character, 15);
+}
+
+#[test]
+fn test_command_in_literal() {
+    let diags = diagnostics_with_code("let s = `see \\emph`\n", COMMAND_IN_LITERAL);
+    assert_eq!(diags.len(), 1);
+    assert_eq!(diags[0].message, "`\\emph` is not interpreted as a command in a string literal");
+    assert_eq!(diags[0].range.start.character, 13);
+    assert_eq!(diags[0].range.end.character, 18);
+}
+
+#[test]
+fn test_stage_mismatch() {
+    let dir = temp_dir("stage");
+    std::fs::write(dir.join("late.satyh"), "let x = 1\n").unwrap();
+    std::fs::write(dir.join("any.satyg"), "let y = 1\n").unwrap();
+    let uri = Url::from_file_path(dir.join("main.satyh")).unwrap();
+
+    let mut buf = Buffer::new("@stage: 0\n@import: late\n@import: any\n\nlet z = 1\n".to_owned());
+    buf.load_packages(&uri, &Config::default());
+    let diags = get_diagnostics(&buf, &uri, &Config::default())
+        .into_iter()
+        .filter(|d| d.code == Some(NumberOrString::String(STAGE_MISMATCH.to_owned())))
+        .collect_vec();
+    assert_eq!(diags.len(), 1);
+    assert_eq!(diags[0].message, "stage-0 file cannot load stage-1 package `late`");
+    assert_eq!(diags[0].range.start.line, 1);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_import_outside_roots() {
+    let dir = temp_dir("import-outside-roots");
+    std::fs::create_dir_all(dir.join("workspace")).unwrap();
+    std::fs::write(dir.join("secret.satyh"), "let x = 1\n").unwrap();
+    let uri = Url::from_file_path(dir.join("workspace").join("main.saty")).unwrap();
+    let config = Config { workspace_root: Some(dir.join("workspace")), ..Default::default() };
+
+    let mut buf = Buffer::new("@import: ../secret\n\n'<>\n".to_owned());
+    buf.load_packages(&uri, &config);
+    assert!(buf.packages.is_empty());
+    let diags = get_diagnostics(&buf, &uri, &config)
+        .into_iter()
+        .filter(|d| d.code == Some(NumberOrString::String(IMPORT_OUTSIDE_ROOTS.to_owned())))
+        .collect_vec();
+    assert_eq!(diags.len(), 1);
+    assert!(diags[0].message.starts_with("`@import: ../secret` resolves to "));
+    assert_eq!(diags[0].severity, Some(DiagnosticSeverity::Error));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_unexpected_option() {
+    let diags = diagnostics_with_code(
+        r#"let-inline ctx \plain inner = inner
+let-inline ctx \opt ?:size inner = inner
+in
+'<
+  +p{ \plain?:(1){a} \opt?:(1){b} \opt?*?:(2){c} }
+>
+"#,
+        UNEXPECTED_OPTION,
+    );
+    let messages = diags.iter().map(|d| d.message.as_str()).collect::<Vec<_>>();
+    assert_eq!(
+        messages,
+        vec![
+            "`\\plain` takes no optional argument",
+            "`\\opt` takes at most 1 optional argument(s)",
+        ]
+    );
+    assert_eq!(diags[0].range.start, lsp_types::Position { line: 4, character: 12 });
+    assert_eq!(diags[1].range.start, lsp_types::Position { line: 4, character: 40 });
+
+    // 使用箇所から定義箇所をたどれる。
+    let related = diags[1].related_information.as_ref().unwrap();
+    assert_eq!(related.len(), 1);
+    assert_eq!(related[0].message, "command defined here");
+    assert_eq!(related[0].location.uri, uri());
+    assert_eq!(related[0].location.range.start, lsp_types::Position { line: 1, character: 15 });
+}
+
+#[test]
+fn test_mode_mismatch() {
+    let diags = diagnostics_with_code(
+        r#"module M : sig
+  direct \box : [inline-text] inline-cmd
+end = struct
+  let-inline ctx \box it = inline-nil
+end
+let-block ctx +sec title inner =
+  let t = read-inline ctx title in
+  read-block ctx inner
+in
+'<
+  +sec{ok}<
+    +sec<+p{x}>{bad}
+  >
+  +sec(x)<>
+  +p{ \box<+p{x}> }
+>
+"#,
+        MODE_MISMATCH,
+    );
+    let messages = diags.iter().map(|d| d.message.as_str()).collect::<Vec<_>>();
+    assert_eq!(
+        messages,
+        vec![
+            "`\\box` expects inline text `{...}` here, but block text `<...>` is given",
+            "`+sec` expects inline text `{...}` here, but block text `<...>` is given",
+            "`+sec` expects block text `<...>` here, but inline text `{...}` is given",
+        ]
+    );
+    assert_eq!(diags[1].range.start, lsp_types::Position { line: 11, character: 8 });
+}
+
+#[test]
+fn test_invalid_stage() {
+    let diags = diagnostics_with_code("let x = &(lift-int 1) in x\n", INVALID_STAGE);
+    assert_eq!(diags.len(), 2);
+    assert_eq!(diags[0].message, "`&` can only be used at stage 0, but here is stage 1");
+    assert_eq!(diags[1].range.start, lsp_types::Position { line: 0, character: 10 });
+    assert!(diagnostics_with_code("@stage: 0\nlet x = &(~(lift-int 1)) in x\n", INVALID_STAGE).is_empty());
+}
+
+#[test]
+fn test_non_exhaustive_match() {
+    let diags = diagnostics_with_code(
+        r#"type shape = Circle of length | Square of length | Empty
+let area s = match s with
+  | Circle r -> 1
+  | Square 0pt -> 2
+let name s = match s with
+  | (Circle _) -> 1
+  | Square(l) -> 2
+  | Empty -> 3
+let guard s = match s with
+  | Empty -> 1
+  | x -> 2
+let other l = match l with
+  | [] -> 1
+in
+'<>
+"#,
+        NON_EXHAUSTIVE_MATCH,
+    );
+    assert_eq!(diags.len(), 1);
+    assert_eq!(
+        diags[0].message,
+        "non-exhaustive match over `shape`: missing `Square`, `Empty`"
+    );
+    assert_eq!(diags[0].severity, Some(DiagnosticSeverity::Warning));
+    assert_eq!(diags[0].range.start, lsp_types::Position { line: 1, character: 13 });
+    assert_eq!(diags[0].range.end, lsp_types::Position { line: 1, character: 20 });
+    let related = diags[0].related_information.as_ref().unwrap();
+    assert_eq!(related[0].message, "type defined here");
+    assert_eq!(related[0].location.range.start, lsp_types::Position { line: 0, character: 5 });
+}
+
+#[test]
+fn test_builder() {
+    let range = Range::default();
+    let location = Location { uri: uri(), range: range.into() };
+    let diag = DiagnosticBuilder::new(range, DiagnosticSeverity::Hint, UNUSED_DEFINITION, "unused")
+        .tag(DiagnosticTag::Unnecessary)
+        .related(location.clone(), "first")
+        .related(location, "second")
+        .data(UnusedDefinitionData { removal: range })
+        .build();
+    assert_eq!(diag.source.as_deref(), Some(DIAGNOSTIC_SOURCE));
+    assert_eq!(diag.code, Some(NumberOrString::String(UNUSED_DEFINITION.to_owned())));
+    assert_eq!(diag.tags, Some(vec![DiagnosticTag::Unnecessary]));
+    let messages = diag.related_information.unwrap().into_iter().map(|r| r.message).collect::<Vec<_>>();
+    assert_eq!(messages, vec!["first", "second"]);
+    assert!(diag.data.is_some());
+}
+
+#[test]
+fn test_import_in_unsaved_buffer() {
+    let text = "@require: stdjabook\n@import: local\n\n'<>\n";
+    let buf = Buffer::new(text.to_owned());
+    let code = Some(NumberOrString::String(IMPORT_IN_UNSAVED_BUFFER.to_owned()));
+    let untitled = Url::parse("untitled:Untitled-1").unwrap();
+    let diags: Vec<_> = get_diagnostics(&buf, &untitled, &Config::default())
+        .into_iter()
+        .filter(|d| d.code == code)
+        .collect();
+    assert_eq!(diags.len(), 1);
+    assert_eq!(diags[0].range.start, lsp_types::Position { line: 1, character: 9 });
+    assert!(diags[0].message.starts_with("cannot resolve `@import: local`"));
+
+    assert!(diagnostics_with_code(text, IMPORT_IN_UNSAVED_BUFFER).is_empty());
+}
+
+#[test]
+fn test_signature_mismatch() {
+    let text = r#"module Foo : sig
+  type t
+  val make : int -> t
+  val \foo : [] inline-cmd
+  direct +bar : [] block-cmd
+end = struct
+  type t = int
+  let make n = n
+  let helper x = x
+  let-inline ctx \foo = read-inline ctx {}
+end
+"#;
+    let unexported = diagnostics_with_code(text, UNEXPORTED_DEFINITION);
+    assert_eq!(unexported.len(), 1);
+    assert_eq!(
+        unexported[0].message,
+        "value `helper` is defined in module `Foo` but not exported by its signature"
+    );
+    assert_eq!(unexported[0].severity, Some(DiagnosticSeverity::Warning));
+    assert_eq!(unexported[0].range.start, lsp_types::Position { line: 8, character: 6 });
+
+    let missing = diagnostics_with_code(text, MISSING_IMPLEMENTATION);
+    assert_eq!(missing.len(), 1);
+    assert_eq!(
+        missing[0].message,
+        "value `+bar` is declared in the signature of module `Foo` but not defined"
+    );
+    assert_eq!(missing[0].severity, Some(DiagnosticSeverity::Error));
+    assert_eq!(missing[0].range.start, lsp_types::Position { line: 4, character: 9 });
+
+    // 文書ファイルでは報告しない。
+    let document = text.replace("end\n", "end\nin '<>\n");
+    assert!(diagnostics_with_code(&document, MISSING_IMPLEMENTATION).is_empty());
+}
+
+#[test]
+fn test_missing_document_fields() {
+    let text = "@require: stdjabook\n\ndocument (|\n  title = {T};\n  author = {A};\n|) '<>\n";
+    let diags = diagnostics_with_code(text, MISSING_RECORD_FIELD);
+    assert_eq!(diags.len(), 1);
+    assert_eq!(
+        diags[0].message,
+        "the `stdjabook` document record is missing `show-title`, `show-toc`"
+    );
+    assert_eq!(diags[0].range.start, lsp_types::Position { line: 2, character: 9 });
+
+    let text = "@require: stdjareport\n\ndocument (|\n  title = {T};\n  author = {A};\n|) '<>\n";
+    assert!(diagnostics_with_code(text, MISSING_RECORD_FIELD).is_empty());
+    // 文書クラスを読み込んでいなければ調べない。
+    let text = "document (| title = {T}; |) '<>\n";
+    assert!(diagnostics_with_code(text, MISSING_RECORD_FIELD).is_empty());
+}
+
+#[test]
+fn test_duplicate_labels() {
+    let text = "let-block ctx +math ?:label m = block-nil\nin\n'<\n  +math?:(`eq:a`)(${x});\n  +math?:(`eq:b`)(${y});\n  +math?:(`eq:a`)(${z});\n>\n";
+    let diags = diagnostics_with_code(text, DUPLICATE_LABEL);
+    assert_eq!(diags.len(), 1);
+    assert_eq!(diags[0].message, "label `eq:a` is already used");
+    assert_eq!(diags[0].range.start.line, 5);
+    let related = diags[0].related_information.as_ref().unwrap();
+    assert_eq!(related[0].location.range.start, lsp_types::Position { line: 3, character: 11 });
+    assert_eq!(related[0].message, "first use");
+}
+
+#[test]
+fn test_syntax_error_message() {
+    let buf = Buffer::new("'<\n  +p{a}\n  x\n>\n".to_owned());
+    let diags = syntax_errors(&buf);
+    assert_eq!(diags.len(), 1);
+    assert_eq!(
+        diags[0].message,
+        "expected a text argument `{...}` or `<...>`, a block command like `+p`, or `>` to close the block text"
+    );
+    assert_eq!(diags[0].range.start, lsp_types::Position { line: 2, character: 2 });
+}
+
+#[test]
+fn test_recovered_syntax_error_keeps_original_error() {
+    let buf = Buffer::new("let x = 1\n'<\n  +p{a}\n>\n".to_owned());
+    assert!(buf.buf_cst.cst().is_some());
+    let diags = get_diagnostics(&buf, &uri(), &Config::default());
+    let code = Some(NumberOrString::String(SYNTAX_ERROR.to_owned()));
+    let syntax = diags.iter().filter(|d| d.code == code).collect::<Vec<_>>();
+    assert_eq!(syntax.len(), 2);
+    // 修復した箇所に加え、パーサが失敗した位置も報告する。
+    assert_eq!(syntax[0].message, RecoveryKind::MissingIn.message());
+    assert_eq!(syntax[0].range.start, lsp_types::Position { line: 0, character: 9 });
+    assert!(syntax[1].message.starts_with("expected"));
+    assert_eq!(syntax[1].range.start, lsp_types::Position { line: 1, character: 0 });
+}
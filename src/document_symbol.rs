@@ -0,0 +1,197 @@
+//! documentSymbol に関する関数群。
+
+use std::collections::HashMap;
+
+use lsp_types::{
+    DocumentSymbol, DocumentSymbolParams, DocumentSymbolResponse, SymbolKind, Url,
+};
+
+use crate::{
+    diagnostic::defined_name,
+    parser::Rule,
+    position::Range,
+    workspace::{command_usages, WorkspaceIndex},
+    Buffer, BufferCst, Cst, Environment, TypeDefKind,
+};
+
+/// アウトラインに表示する図表のコマンドと、その symbol の種類。
+const FLOAT_COMMANDS: &[(&str, SymbolKind)] = &[
+    ("+figure", SymbolKind::Object),
+    ("+table", SymbolKind::Array),
+];
+
+/// documentSymbol リクエストへの response を返す。
+///
+/// プリアンブルで定義されたコマンドと変数、型を列挙し、detail には使われている回数を示す。
+/// コマンドはこのバッファとワークスペースの索引にある他のファイルでの使用箇所を、
+/// 変数と型はこのバッファ内での出現を数える。
+/// 型はそのコンストラクタやフィールドを子に持つ。
+/// アウトラインから、使われていない定義や多用されている定義を見つけられるようにするためである。
+/// 続けて、本文にある図表をキャプションとともに列挙する。
+pub fn get_document_symbol_response(
+    buf: &Buffer,
+    params: DocumentSymbolParams,
+    index: &WorkspaceIndex,
+) -> Option<DocumentSymbolResponse> {
+    let cst = buf.buf_cst.cst.as_ref()?;
+    let program = cst.inner.first()?;
+    let uri = &params.text_document.uri;
+    let mut symbols = match program.inner.iter().find(|c| c.rule == Rule::preamble) {
+        Some(preamble) => definition_symbols(buf, preamble, uri, index),
+        None => vec![],
+    };
+    let floats = cst.pickup(Rule::block_cmd);
+    symbols.extend(floats.into_iter().filter_map(|cmd| float_symbol(&buf.buf_cst, cmd)));
+    Some(DocumentSymbolResponse::Nested(symbols))
+}
+
+/// プリアンブルで定義されたコマンドと変数、型の symbol を作る。
+fn definition_symbols(
+    buf: &Buffer,
+    preamble: &Cst,
+    uri: &Url,
+    index: &WorkspaceIndex,
+) -> Vec<DocumentSymbol> {
+    let buf_cst = &buf.buf_cst;
+    let occurrences = buf_cst.name_occurrences();
+    let mut command_uses: HashMap<String, usize> = HashMap::new();
+    for usage in command_usages(buf) {
+        *command_uses.entry(usage.name).or_default() += 1;
+    }
+    preamble
+        .inner
+        .iter()
+        .filter_map(|stmt| {
+            if let Some(symbol) = type_symbol(&buf.env, stmt, &occurrences) {
+                return Some(symbol);
+            }
+            let (_, name_cst) = defined_name(stmt)?;
+            let name = buf_cst.as_str(name_cst);
+            let (kind, uses) = match name_cst.rule {
+                // 出現回数は定義箇所を含む。
+                Rule::var => (
+                    SymbolKind::Variable,
+                    occurrences.get(name).copied().unwrap_or_default().saturating_sub(1),
+                ),
+                _ => (
+                    SymbolKind::Function,
+                    command_uses.get(name).copied().unwrap_or_default()
+                        + index.usage_count(name, uri),
+                ),
+            };
+            #[allow(deprecated)]
+            let symbol = DocumentSymbol {
+                name: name.to_owned(),
+                detail: Some(describe_uses(uses)),
+                kind,
+                tags: None,
+                deprecated: None,
+                range: stmt.range().into(),
+                selection_range: name_cst.range().into(),
+                children: None,
+            };
+            Some(symbol)
+        })
+        .collect()
+}
+
+/// type 文であれば、型の symbol を作る。コンストラクタとフィールドを子とする。
+fn type_symbol(
+    env: &Environment,
+    stmt: &Cst,
+    occurrences: &HashMap<&str, usize>,
+) -> Option<DocumentSymbol> {
+    let type_stmt = stmt.inner.first().filter(|cst| cst.rule == Rule::type_stmt)?;
+    let range: Range = type_stmt.range.clone().into();
+    let ty = env.types().iter().find(|ty| ty.stmt_range() == range)?;
+    let kind = match ty.kind() {
+        TypeDefKind::Variant => SymbolKind::Enum,
+        TypeDefKind::Record => SymbolKind::Struct,
+        _ => SymbolKind::TypeParameter,
+    };
+    let constructors = ty
+        .constructors()
+        .iter()
+        .map(|c| member_symbol(c.name(), SymbolKind::EnumMember, c.def_range()));
+    let fields = ty
+        .fields()
+        .iter()
+        .map(|f| member_symbol(f.name(), SymbolKind::Field, f.def_range()));
+    let children: Vec<_> = constructors.chain(fields).collect();
+    let uses = occurrences.get(ty.name()).copied().unwrap_or_default().saturating_sub(1);
+    #[allow(deprecated)]
+    let symbol = DocumentSymbol {
+        name: ty.name().to_owned(),
+        detail: Some(describe_uses(uses)),
+        kind,
+        tags: None,
+        deprecated: None,
+        range: stmt.range().into(),
+        selection_range: ty.def_range().into(),
+        children: if children.is_empty() { None } else { Some(children) },
+    };
+    Some(symbol)
+}
+
+/// コンストラクタやフィールドの symbol を作る。
+fn member_symbol(name: &str, kind: SymbolKind, range: Range) -> DocumentSymbol {
+    #[allow(deprecated)]
+    DocumentSymbol {
+        name: name.to_owned(),
+        detail: None,
+        kind,
+        tags: None,
+        deprecated: None,
+        range: range.into(),
+        selection_range: range.into(),
+        children: None,
+    }
+}
+
+/// 図表のコマンドであれば、キャプションを名前とする symbol を作る。
+/// キャプションは最初のインラインテキストの引数とし、なければ `{...}` を含む最初の引数から探す。
+fn float_symbol(buf_cst: &BufferCst, cmd: &Cst) -> Option<DocumentSymbol> {
+    let name_cst = cmd.inner.first()?;
+    let command = buf_cst.as_str(name_cst);
+    let (_, kind) = FLOAT_COMMANDS.iter().find(|(name, _)| *name == command)?;
+
+    let text_arg = cmd
+        .inner
+        .iter()
+        .filter(|arg| arg.rule == Rule::cmd_text_arg)
+        .find_map(|arg| arg.inner.iter().find(|c| c.rule == Rule::horizontal_mode));
+    let caption = text_arg.or_else(|| {
+        cmd.inner
+            .iter()
+            .filter(|arg| arg.rule == Rule::cmd_expr_arg)
+            .find_map(|arg| arg.pickup(Rule::horizontal_mode).first().copied())
+    });
+    let caption = caption
+        .map(|caption| buf_cst.as_str(caption).split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|caption| !caption.is_empty());
+
+    #[allow(deprecated)]
+    let symbol = DocumentSymbol {
+        name: caption.unwrap_or_else(|| "(no caption)".to_owned()),
+        detail: Some(command.to_owned()),
+        kind: *kind,
+        tags: None,
+        deprecated: None,
+        range: cmd.range().into(),
+        selection_range: name_cst.range().into(),
+        children: None,
+    };
+    Some(symbol)
+}
+
+/// 使われている回数を detail の文字列にする。
+fn describe_uses(uses: usize) -> String {
+    match uses {
+        0 => "unused".to_owned(),
+        1 => "1 use".to_owned(),
+        n => format!("{} uses", n),
+    }
+}
+
+#[cfg(test)]
+mod tests;
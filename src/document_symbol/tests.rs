@@ -0,0 +1,118 @@
+//! test module for document_symbol.
+
+use lsp_types::{TextDocumentIdentifier, Url};
+
+use super::*;
+
+fn symbols(text: &str) -> Vec<DocumentSymbol> {
+    symbols_with_index(text, &WorkspaceIndex::default())
+}
+
+fn symbols_with_index(text: &str, index: &WorkspaceIndex) -> Vec<DocumentSymbol> {
+    let buf = Buffer::new(text.to_owned());
+    let params = DocumentSymbolParams {
+        text_document: TextDocumentIdentifier {
+            uri: Url::parse("file:///main.saty").unwrap(),
+        },
+        work_done_progress_params: Default::default(),
+        partial_result_params: Default::default(),
+    };
+    match get_document_symbol_response(&buf, params, index) {
+        Some(DocumentSymbolResponse::Nested(symbols)) => symbols,
+        res => panic!("unexpected response: {:?}", res),
+    }
+}
+
+#[test]
+fn test_use_counts() {
+    let text = "let x = 1\nlet y = x + x\nlet-inline ctx \\emph = ctx\nin\n'<\n  +p{ \\emph; \\emph; \\emph; }\n>\n";
+    let details = symbols(text)
+        .into_iter()
+        .map(|symbol| (symbol.name, symbol.kind, symbol.detail.unwrap()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        details,
+        vec![
+            ("x".to_owned(), SymbolKind::Variable, "2 uses".to_owned()),
+            ("y".to_owned(), SymbolKind::Variable, "unused".to_owned()),
+            ("\\emph".to_owned(), SymbolKind::Function, "3 uses".to_owned()),
+        ]
+    );
+}
+
+#[test]
+fn test_use_counts_in_workspace() {
+    let text = "let-inline ctx \\emph = ctx\nin\n'<\n  +p{ \\emph; }\n>\n";
+    let mut index = WorkspaceIndex::default();
+    // 索引にある同じファイルの古い版は数えず、他のファイルでの使用だけを加える。
+    let main = Url::parse("file:///main.saty").unwrap();
+    index.update(main, &Buffer::new("'<\n  +p{ \\emph; \\emph; }\n>\n".to_owned()));
+    let other = Url::parse("file:///other.saty").unwrap();
+    index.update(other, &Buffer::new("'<\n  +p{ \\emph; \\emph; \\strong; }\n>\n".to_owned()));
+    let symbol = symbols_with_index(text, &index).remove(0);
+    assert_eq!(symbol.name, "\\emph");
+    assert_eq!(symbol.detail.as_deref(), Some("3 uses"));
+}
+
+#[test]
+fn test_selection_range() {
+    let symbol = symbols("let-block ctx +section = ctx\nin\n'<>\n").remove(0);
+    assert_eq!(symbol.detail.as_deref(), Some("unused"));
+    assert_eq!(symbol.selection_range.start.character, 14);
+    assert_eq!(symbol.range.start.character, 0);
+}
+
+#[test]
+fn test_floats() {
+    let text = "'<\n  +section{Intro}<\n    +figure?:(`fig:a`)(image)<+p{x}>{A   quick\n      fox}\n  >\n  +table([])({Results});\n  +figure;\n>\n";
+    let floats = symbols(text)
+        .into_iter()
+        .map(|symbol| (symbol.name, symbol.kind, symbol.detail.unwrap(), symbol.range.start.line))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        floats,
+        vec![
+            ("A quick fox".to_owned(), SymbolKind::Object, "+figure".to_owned(), 2),
+            ("Results".to_owned(), SymbolKind::Array, "+table".to_owned(), 5),
+            ("(no caption)".to_owned(), SymbolKind::Object, "+figure".to_owned(), 6),
+        ]
+    );
+}
+
+#[test]
+fn test_type_symbols() {
+    let text = "type shape = | Circle of length | Square\ntype point = (| x : length; y : length |)\ntype pt = point\nlet origin : pt = (| x = 0pt; y = 0pt |)\nin\n'<>\n";
+    let symbols = symbols(text);
+    let outline = symbols
+        .iter()
+        .map(|symbol| {
+            let children = symbol
+                .children
+                .iter()
+                .flatten()
+                .map(|child| (child.name.as_str(), child.kind))
+                .collect::<Vec<_>>();
+            (symbol.name.as_str(), symbol.kind, children)
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        outline,
+        vec![
+            (
+                "shape",
+                SymbolKind::Enum,
+                vec![("Circle", SymbolKind::EnumMember), ("Square", SymbolKind::EnumMember)]
+            ),
+            (
+                "point",
+                SymbolKind::Struct,
+                vec![("x", SymbolKind::Field), ("y", SymbolKind::Field)]
+            ),
+            ("pt", SymbolKind::TypeParameter, vec![]),
+            ("origin", SymbolKind::Variable, vec![]),
+        ]
+    );
+    assert_eq!(symbols[1].detail.as_deref(), Some("1 use"));
+    let circle = &symbols[0].children.as_ref().unwrap()[0];
+    assert_eq!(circle.selection_range.start, lsp_types::Position { line: 0, character: 15 });
+}
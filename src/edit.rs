@@ -0,0 +1,197 @@
+//! code action などが返す編集を組み立てる。
+//!
+//! 一つの action で複数の箇所を書き換えるとき、範囲の重なる TextEdit を返すとクライアントは適用できない。
+//! ここでは Cst の範囲から作った編集を集め、重なりを検出し、何も変えない編集を取り除いてから
+//! 位置の順に並べた WorkspaceEdit にする。
+//!
+//! 逆向きに、クライアントから届いた変更を文書に適用する [`apply_content_changes`] もここに置く。
+
+use std::{collections::HashMap, fmt};
+
+use lsp_types::{
+    Position, Range, TextDocumentContentChangeEvent, TextEdit, Url, WorkspaceEdit,
+};
+
+use crate::position::LineIndex;
+
+/// 編集の範囲が、すでに加えた編集の範囲と重なっている。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OverlapError {
+    /// 編集する文書。
+    pub uri: Url,
+    /// すでに加えた編集の範囲。
+    pub existing: Range,
+    /// 加えようとした編集の範囲。
+    pub new: Range,
+}
+
+impl fmt::Display for OverlapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let show = |pos: &Position| format!("{}:{}", pos.line + 1, pos.character + 1);
+        write!(
+            f,
+            "edit at {}-{} overlaps with another edit at {}-{} in {}",
+            show(&self.new.start),
+            show(&self.new.end),
+            show(&self.existing.start),
+            show(&self.existing.end),
+            self.uri
+        )
+    }
+}
+
+impl std::error::Error for OverlapError {}
+
+/// 重ならない TextEdit を文書ごとに集めるもの。
+#[derive(Debug, Default)]
+pub struct EditBuilder {
+    /// 文書ごとの、加えた順の編集。
+    changes: HashMap<Url, Vec<TextEdit>>,
+}
+
+impl EditBuilder {
+    /// 空の EditBuilder を作る。
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// `range` を `new_text` で置き換える編集を加える。
+    /// 空の範囲に空の文字列を入れる編集は何も変えないため加えない。
+    pub fn replace(
+        &mut self,
+        uri: &Url,
+        range: impl Into<Range>,
+        new_text: impl Into<String>,
+    ) -> Result<&mut Self, OverlapError> {
+        let range = range.into();
+        let new_text = new_text.into();
+        if range.start == range.end && new_text.is_empty() {
+            return Ok(self);
+        }
+        let edits = self.changes.entry(uri.clone()).or_default();
+        if let Some(existing) = edits.iter().find(|edit| overlaps(&edit.range, &range)) {
+            return Err(OverlapError { uri: uri.clone(), existing: existing.range, new: range });
+        }
+        edits.push(TextEdit { range, new_text });
+        Ok(self)
+    }
+
+    /// `range` にある `old_text` を `new_text` に置き換える編集を加える。
+    /// 前後の共通する部分は書き換えず、異なる部分だけを置き換える。
+    pub fn replace_text(
+        &mut self,
+        uri: &Url,
+        range: impl Into<Range>,
+        old_text: &str,
+        new_text: &str,
+    ) -> Result<&mut Self, OverlapError> {
+        let range = range.into();
+        let prefix = common_prefix_len(old_text, new_text);
+        let suffix = common_suffix_len(&old_text[prefix..], &new_text[prefix..]);
+        let index = LineIndex::new(old_text);
+        let start = advance(range.start, index.position(prefix).into());
+        let end = advance(range.start, index.position(old_text.len() - suffix).into());
+        let new_changed = &new_text[prefix..new_text.len() - suffix];
+        self.replace(uri, Range { start, end }, new_changed)
+    }
+
+    /// `pos` に `text` を挿入する編集を加える。同じ位置への挿入は加えた順に並ぶ。
+    pub fn insert(
+        &mut self,
+        uri: &Url,
+        pos: impl Into<Position>,
+        text: impl Into<String>,
+    ) -> Result<&mut Self, OverlapError> {
+        let pos = pos.into();
+        self.replace(uri, Range { start: pos, end: pos }, text)
+    }
+
+    /// `range` を削除する編集を加える。
+    pub fn delete(&mut self, uri: &Url, range: impl Into<Range>) -> Result<&mut Self, OverlapError> {
+        self.replace(uri, range, "")
+    }
+
+    /// 編集を一つも加えていないか。
+    pub fn is_empty(&self) -> bool {
+        self.changes.values().all(Vec::is_empty)
+    }
+
+    /// 文書ごとの編集を位置の順に並べ、WorkspaceEdit にする。
+    pub fn build(self) -> WorkspaceEdit {
+        let changes = self
+            .changes
+            .into_iter()
+            .filter(|(_, edits)| !edits.is_empty())
+            .map(|(uri, mut edits)| {
+                // 同じ位置への挿入の順序を保つため、安定なソートを用いる。
+                edits.sort_by_key(|edit| (edit.range.start, edit.range.end));
+                (uri, edits)
+            })
+            .collect();
+        WorkspaceEdit {
+            changes: Some(changes),
+            ..Default::default()
+        }
+    }
+}
+
+/// didChange で届いた変更を text に順に適用した結果を返す。
+/// 範囲のない変更は文書全体を置き換え、範囲のある変更はその範囲だけを書き換える。
+/// 後の変更の範囲は、それより前の変更を適用した後の文書での位置として解釈する。
+pub fn apply_content_changes(text: &str, changes: Vec<TextDocumentContentChangeEvent>) -> String {
+    let mut text = text.to_owned();
+    for change in changes {
+        match change.range {
+            None => text = change.text,
+            Some(range) => {
+                let index = LineIndex::new(&text);
+                let start = index.offset(range.start.into());
+                let end = index.offset(range.end.into()).max(start);
+                text.replace_range(start..end, &change.text);
+            }
+        }
+    }
+    text
+}
+
+/// 二つの編集の範囲が重なるか。
+/// 端が接するだけの範囲や、同じ位置への挿入どうしは重ならないとみなす。
+fn overlaps(a: &Range, b: &Range) -> bool {
+    let strictly_inside = |pos: &Position, r: &Range| &r.start < pos && pos < &r.end;
+    if a.start == a.end {
+        return strictly_inside(&a.start, b);
+    }
+    if b.start == b.end {
+        return strictly_inside(&b.start, a);
+    }
+    a.start < b.end && b.start < a.end
+}
+
+/// 二つの文字列の先頭から共通する部分のバイト数。文字の境界で区切る。
+pub(crate) fn common_prefix_len(a: &str, b: &str) -> usize {
+    a.char_indices()
+        .zip(b.chars())
+        .find(|((_, ca), cb)| ca != cb)
+        .map_or_else(|| a.len().min(b.len()), |((i, _), _)| i)
+}
+
+/// 二つの文字列の末尾から共通する部分のバイト数。文字の境界で区切る。
+pub(crate) fn common_suffix_len(a: &str, b: &str) -> usize {
+    a.chars()
+        .rev()
+        .zip(b.chars().rev())
+        .take_while(|(ca, cb)| ca == cb)
+        .map(|(c, _)| c.len_utf8())
+        .sum()
+}
+
+/// `pos` から始まるテキストの中での位置 `relative` を、文書の中での位置に直す。
+fn advance(pos: Position, relative: Position) -> Position {
+    match relative.line {
+        0 => Position { line: pos.line, character: pos.character + relative.character },
+        line => Position { line: pos.line + line, character: relative.character },
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,100 @@
+//! test module for edit.
+
+use super::*;
+
+fn uri() -> Url {
+    Url::parse("file:///test.saty").unwrap()
+}
+
+fn range(sl: u32, sc: u32, el: u32, ec: u32) -> Range {
+    Range {
+        start: Position { line: sl, character: sc },
+        end: Position { line: el, character: ec },
+    }
+}
+
+fn edits(builder: EditBuilder) -> Vec<TextEdit> {
+    builder.build().changes.unwrap().remove(&uri()).unwrap_or_default()
+}
+
+#[test]
+fn test_sorted_by_position() {
+    let mut builder = EditBuilder::new();
+    builder.replace(&uri(), range(2, 0, 2, 3), "b").unwrap();
+    builder.delete(&uri(), range(0, 4, 1, 0)).unwrap();
+    builder.insert(&uri(), Position { line: 1, character: 0 }, "x").unwrap();
+    builder.insert(&uri(), Position { line: 1, character: 0 }, "y").unwrap();
+    let texts = edits(builder).into_iter().map(|e| e.new_text).collect::<Vec<_>>();
+    assert_eq!(texts, vec!["", "x", "y", "b"]);
+}
+
+#[test]
+fn test_overlap() {
+    let mut builder = EditBuilder::new();
+    builder.replace(&uri(), range(0, 2, 0, 6), "a").unwrap();
+    let err = builder.replace(&uri(), range(0, 5, 0, 8), "b").unwrap_err();
+    assert_eq!(err.existing, range(0, 2, 0, 6));
+    assert_eq!(err.new, range(0, 5, 0, 8));
+    assert_eq!(
+        err.to_string(),
+        "edit at 1:6-1:9 overlaps with another edit at 1:3-1:7 in file:///test.saty"
+    );
+    // 内側への挿入は重なるが、端への挿入や接する範囲は重ならない。
+    assert!(builder.insert(&uri(), Position { line: 0, character: 4 }, "c").is_err());
+    assert!(builder.insert(&uri(), Position { line: 0, character: 6 }, "c").is_ok());
+    assert!(builder.replace(&uri(), range(0, 0, 0, 2), "d").is_ok());
+    // 別の文書の編集とは比べない。
+    let other = Url::parse("file:///other.saty").unwrap();
+    assert!(builder.replace(&other, range(0, 2, 0, 6), "e").is_ok());
+}
+
+#[test]
+fn test_replace_text_minimal() {
+    let mut builder = EditBuilder::new();
+    builder
+        .replace_text(&uri(), range(1, 2, 2, 5), "let-inline\n  \\foo", "let-inline\n  \\bar")
+        .unwrap();
+    assert_eq!(
+        edits(builder),
+        vec![TextEdit { range: range(2, 3, 2, 6), new_text: "bar".to_owned() }]
+    );
+
+    // 最初の行の中の変更は、範囲の始まりの列から数える。
+    let mut builder = EditBuilder::new();
+    builder.replace_text(&uri(), range(0, 4, 0, 10), "{強調 x}", "{強調 y}").unwrap();
+    assert_eq!(
+        edits(builder),
+        vec![TextEdit { range: range(0, 8, 0, 9), new_text: "y".to_owned() }]
+    );
+
+    let mut builder = EditBuilder::new();
+    builder.replace_text(&uri(), range(0, 0, 0, 3), "abc", "abc").unwrap();
+    assert!(builder.is_empty());
+}
+
+#[test]
+fn test_apply_content_changes() {
+    let change = |range: Option<Range>, text: &str| TextDocumentContentChangeEvent {
+        range,
+        range_length: None,
+        text: text.to_owned(),
+    };
+    let text = "let x = 1\nin\n{ 強調 }\n";
+
+    // 後の変更の位置は、前の変更を適用した後の文書で数える。
+    let changes = vec![
+        change(Some(range(0, 4, 0, 5)), "y"),
+        change(Some(range(2, 2, 2, 4)), "\\emph{強調}"),
+        change(Some(range(2, 11, 2, 11)), "!"),
+    ];
+    assert_eq!(apply_content_changes(text, changes), "let y = 1\nin\n{ \\emph{強調}! }\n");
+
+    // 範囲のない変更は文書全体を置き換え、それ以降の変更はその上に適用する。
+    let changes = vec![
+        change(Some(range(0, 0, 0, 3)), "abc"),
+        change(None, "foo\nbar"),
+        change(Some(range(1, 3, 1, 3)), "\nbaz"),
+    ];
+    assert_eq!(apply_content_changes(text, changes), "foo\nbar\nbaz");
+    assert_eq!(apply_content_changes(text, vec![]), text);
+}
@@ -0,0 +1,38 @@
+//! 折り畳み範囲に関する関数群。
+
+use lsp_types::{FoldingRange, FoldingRangeKind, FoldingRangeParams};
+
+use crate::{parser::Rule, Buffer, Cst};
+
+/// 折り畳みの対象となる数式のルール。
+const MATH_FOLDING_RULES: &[Rule] = &[Rule::math_text, Rule::math_list, Rule::math_list_item];
+
+/// foldingRange リクエストへの response を返す。
+pub fn get_folding_range_response(
+    buf: &Buffer,
+    _params: FoldingRangeParams,
+) -> Option<Vec<FoldingRange>> {
+    let cst = buf.buf_cst.cst.as_ref()?;
+    let ranges = MATH_FOLDING_RULES
+        .iter()
+        .flat_map(|&rule| cst.pickup(rule))
+        .filter_map(folding_range)
+        .collect();
+    Some(ranges)
+}
+
+/// Cst の範囲を折り畳み範囲に変換する。1 行に収まるものは折り畳まない。
+fn folding_range(cst: &Cst) -> Option<FoldingRange> {
+    let start = &cst.range.start;
+    let end = &cst.range.end;
+    if start.line >= end.line {
+        return None;
+    }
+    Some(FoldingRange {
+        start_line: start.line,
+        start_character: Some(start.character),
+        end_line: end.line,
+        end_character: Some(end.character),
+        kind: Some(FoldingRangeKind::Region),
+    })
+}
@@ -0,0 +1,68 @@
+//! 名前の曖昧な一致に関する関数群。
+
+use itertools::Itertools;
+
+/// 2 つの文字列の編集距離（Levenshtein 距離）を文字単位で求める。
+pub fn levenshtein(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut cur = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let cost = if ca == *cb { 0 } else { 1 };
+            let value = (prev[j] + cost).min(prev[j + 1] + 1).min(cur[j] + 1);
+            cur.push(value);
+        }
+        prev = cur;
+    }
+    prev[b.len()]
+}
+
+/// candidates のうち name に近いものを、近い順に最大 max 個返す。
+/// 名前の長さの 1/3（切り上げ）を超えて離れているものは候補にしない。
+pub fn similar_names<'a>(
+    name: &str,
+    candidates: impl IntoIterator<Item = &'a str>,
+    max: usize,
+) -> Vec<&'a str> {
+    let len = name.chars().count();
+    let threshold = len.div_ceil(3).max(1);
+    candidates
+        .into_iter()
+        .unique()
+        .filter(|&cand| cand != name)
+        .map(|cand| {
+            // 距離が同じなら、長さの近いものを優先する。
+            let len_diff = (cand.chars().count() as isize - len as isize).abs();
+            (levenshtein(name, cand), len_diff, cand)
+        })
+        .filter(|(dist, _, _)| *dist <= threshold)
+        .sorted()
+        .take(max)
+        .map(|(_, _, cand)| cand)
+        .collect()
+}
+
+/// 名前から大文字小文字の違いと区切りのハイフン・アンダースコアを取り除いた形を返す。
+/// `\lineBreak` と `\line-break` はどちらも `\linebreak` になる。
+pub fn normalize_name(name: &str) -> String {
+    name.chars()
+        .filter(|c| *c != '-' && *c != '_')
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// candidates のうち、name と大文字小文字や区切り方だけが異なるものを返す。
+/// 該当するものが複数あるときは最初に見つかったものを返す。
+pub fn cosmetic_match<'a>(
+    name: &str,
+    candidates: impl IntoIterator<Item = &'a str>,
+) -> Option<&'a str> {
+    let normalized = normalize_name(name);
+    candidates
+        .into_iter()
+        .find(|&cand| cand != name && normalize_name(cand) == normalized)
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,33 @@
+//! test module for fuzzy matching.
+
+use super::*;
+
+#[test]
+fn test_levenshtein() {
+    assert_eq!(levenshtein("", ""), 0);
+    assert_eq!(levenshtein("emph", "emph"), 0);
+    assert_eq!(levenshtein("\\embf", "\\emph"), 2);
+    assert_eq!(levenshtein("kitten", "sitting"), 3);
+    assert_eq!(levenshtein("強調", "協調"), 1);
+}
+
+#[test]
+fn test_similar_names() {
+    let candidates = vec!["\\emph", "\\textbf", "\\em", "\\emph", "\\section"];
+    assert_eq!(
+        similar_names("\\embf", candidates.clone(), 3),
+        vec!["\\emph", "\\em"]
+    );
+    assert_eq!(similar_names("\\emph", candidates, 3), vec!["\\em"]);
+}
+
+#[test]
+fn test_cosmetic_match() {
+    assert_eq!(normalize_name("\\lineBreak"), "\\linebreak");
+    assert_eq!(normalize_name("\\line_break"), "\\linebreak");
+    let candidates = vec!["\\line-break", "\\emph"];
+    assert_eq!(cosmetic_match("\\lineBreak", candidates.clone()), Some("\\line-break"));
+    assert_eq!(cosmetic_match("\\LINEBREAK", candidates.clone()), Some("\\line-break"));
+    assert_eq!(cosmetic_match("\\line-break", candidates.clone()), None);
+    assert_eq!(cosmetic_match("\\line-brake", candidates), None);
+}
@@ -0,0 +1,131 @@
+//! ドキュメントの直近の版を記録する履歴と、それを返す `satysfi/dumpHistory` リクエスト。
+//!
+//! クライアントとサーバの間でテキストの同期がずれると、位置がずれるなどの原因の分かりにくい不具合になる。
+//! 版ごとのバージョン番号とテキストのハッシュを記録しておき、クライアント側の値と突き合わせられるようにする。
+//! 記録する版の数は設定の `history-size` で指定し、0（デフォルト）のときは何も記録しない。
+
+use std::{
+    collections::{HashMap, VecDeque},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use lsp_types::{request::Request, TextDocumentIdentifier, Url};
+use serde::{Deserialize, Serialize};
+
+/// 記録された履歴を返すカスタムリクエスト。
+pub enum DumpHistory {}
+
+impl Request for DumpHistory {
+    type Params = Option<DumpHistoryParams>;
+    type Result = DumpHistoryResult;
+    const METHOD: &'static str = "satysfi/dumpHistory";
+}
+
+/// `satysfi/dumpHistory` のパラメータ。省略した場合はすべてのドキュメントの履歴を返す。
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DumpHistoryParams {
+    /// 対象のドキュメント。省略した場合はすべてのドキュメントの履歴を返す。
+    pub text_document: Option<TextDocumentIdentifier>,
+}
+
+/// `satysfi/dumpHistory` の結果。
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DumpHistoryResult {
+    /// ドキュメントごとの履歴。URI の順に並べる。
+    pub documents: Vec<DocumentHistory>,
+}
+
+/// 1 つのドキュメントの履歴。
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DocumentHistory {
+    /// ドキュメントの URI.
+    pub uri: Url,
+    /// 記録された版。古いものから順に並べる。
+    pub versions: Vec<HistoryEntry>,
+}
+
+/// 記録された 1 つの版。
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryEntry {
+    /// クライアントが付けたバージョン番号。
+    pub version: i32,
+    /// テキストの UTF-8 バイト列の FNV-1a (64 bit) ハッシュを 16 進数で表したもの。
+    pub text_hash: String,
+    /// テキストのバイト数。
+    pub text_length: usize,
+    /// 記録した時刻（UNIX エポックからのミリ秒）。
+    pub timestamp_ms: u64,
+}
+
+/// ドキュメントごとに直近の版を記録するリングバッファ。
+#[derive(Debug, Default)]
+pub struct BufferHistory {
+    /// ドキュメントごとの記録。
+    entries: HashMap<Url, VecDeque<HistoryEntry>>,
+}
+
+impl BufferHistory {
+    /// `uri` の新しい版を記録する。記録が `capacity` を超えた場合は古いものから捨てる。
+    /// `capacity` が 0 の場合は何も記録しない。
+    pub fn record(&mut self, uri: &Url, version: i32, text: &str, capacity: usize) {
+        if capacity == 0 {
+            return;
+        }
+        let timestamp_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_millis() as u64);
+        let entries = self.entries.entry(uri.clone()).or_default();
+        entries.push_back(HistoryEntry {
+            version,
+            text_hash: format!("{:016x}", fnv1a(text.as_bytes())),
+            text_length: text.len(),
+            timestamp_ms,
+        });
+        while entries.len() > capacity {
+            entries.pop_front();
+        }
+    }
+
+    /// `uri` の履歴を返す。`uri` が None の場合はすべてのドキュメントの履歴を返す。
+    pub fn dump(&self, uri: Option<&Url>) -> Vec<DocumentHistory> {
+        let mut documents: Vec<_> = self
+            .entries
+            .iter()
+            .filter(|(doc, _)| uri.is_none_or(|uri| uri == *doc))
+            .map(|(doc, entries)| DocumentHistory {
+                uri: doc.clone(),
+                versions: entries.iter().cloned().collect(),
+            })
+            .collect();
+        documents.sort_by(|a, b| a.uri.as_str().cmp(b.uri.as_str()));
+        documents
+    }
+}
+
+/// バイト列の FNV-1a (64 bit) ハッシュを返す。
+/// クライアントでも同じ値を計算できるよう、実装の簡単な非暗号学的ハッシュを用いる。
+pub(crate) fn fnv1a(bytes: &[u8]) -> u64 {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0100_0000_01b3;
+    bytes
+        .iter()
+        .fold(OFFSET_BASIS, |hash, &b| (hash ^ b as u64).wrapping_mul(PRIME))
+}
+
+/// dumpHistory リクエストへの response を返す。
+pub fn get_dump_history_response(
+    history: &BufferHistory,
+    params: DumpHistoryParams,
+) -> DumpHistoryResult {
+    let uri = params.text_document.map(|doc| doc.uri);
+    DumpHistoryResult {
+        documents: history.dump(uri.as_ref()),
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,43 @@
+//! test module for history.
+
+use super::*;
+
+#[test]
+fn test_fnv1a() {
+    assert_eq!(fnv1a(b""), 0xcbf2_9ce4_8422_2325);
+    assert_eq!(fnv1a(b"a"), 0xaf63_dc4c_8601_ec8c);
+}
+
+#[test]
+fn test_ring_buffer() {
+    let uri = Url::parse("file:///main.saty").unwrap();
+    let mut history = BufferHistory::default();
+    for version in 1..=4 {
+        history.record(&uri, version, "'<>", 3);
+    }
+    let documents = history.dump(Some(&uri));
+    assert_eq!(documents.len(), 1);
+    let versions = documents[0].versions.iter().map(|e| e.version).collect::<Vec<_>>();
+    assert_eq!(versions, vec![2, 3, 4]);
+    assert_eq!(documents[0].versions[0].text_length, 3);
+}
+
+#[test]
+fn test_disabled() {
+    let uri = Url::parse("file:///main.saty").unwrap();
+    let mut history = BufferHistory::default();
+    history.record(&uri, 1, "'<>", 0);
+    assert!(history.dump(None).is_empty());
+}
+
+#[test]
+fn test_dump_filters_by_uri() {
+    let main = Url::parse("file:///main.saty").unwrap();
+    let lib = Url::parse("file:///lib.satyh").unwrap();
+    let mut history = BufferHistory::default();
+    history.record(&main, 1, "a", 2);
+    history.record(&lib, 1, "b", 2);
+    let all = history.dump(None);
+    assert_eq!(all.iter().map(|d| d.uri.as_str()).collect::<Vec<_>>(), vec![lib.as_str(), main.as_str()]);
+    assert_eq!(history.dump(Some(&main)).len(), 1);
+}
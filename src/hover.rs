@@ -0,0 +1,290 @@
+//! hover に関する関数群。
+
+use itertools::Itertools;
+use lsp_types::{Hover, HoverContents, HoverParams, MarkupContent, MarkupKind, Url};
+
+use crate::{
+    completion::{document_class, find_class_command, find_primitive}, config::Config, definition::find_definition,
+    dependency::DependencyGraph,
+    length::{convert, evaluate, format_number, length_literals, Value},
+    lint::find_invisible_chars, parser::Rule, position::Position, scope::local_bindings,
+    workspace::{describe_usage_examples, WorkspaceIndex},
+    Buffer, BufferCst, Cst,
+};
+
+/// hover リクエストへの response を返す。
+pub fn get_hover_response(
+    buf: &Buffer,
+    params: HoverParams,
+    config: &Config,
+    index: &WorkspaceIndex,
+    dependencies: Option<&DependencyGraph>,
+) -> Option<Hover> {
+    let uri = &params.text_document_position_params.text_document.uri;
+    let pos = Position::from(params.text_document_position_params.position);
+    let cst = buf.buf_cst.cst.as_ref()?;
+    let csts = cst.dig(&pos);
+
+    // 長さのリテラルや演算子の上では、式全体の値を説明する。
+    if let Some((expr, value)) = describe_length(&buf.buf_cst, &csts) {
+        return Some(Hover {
+            contents: HoverContents::Markup(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value,
+            }),
+            range: Some(expr.range().into()),
+        });
+    }
+
+    // 文字列に埋め込まれた式の中ではリテラルとしての情報は出さない。
+    let target = csts.iter().copied().find(|cst| {
+        matches!(
+            cst.rule,
+            Rule::string_interior
+                | Rule::string_interpolation
+                | Rule::var
+                | Rule::inline_cmd_name
+                | Rule::block_cmd_name
+                | Rule::math_cmd_name
+        )
+    });
+    let described = target.and_then(|target| {
+        let value = match target.rule {
+            Rule::string_interior => describe_string_interior(&buf.buf_cst, target, config),
+            Rule::var => describe_variable(buf, target, &pos)
+                .or_else(|| describe_primitive(buf, target, &pos, config))?,
+            Rule::inline_cmd_name | Rule::block_cmd_name | Rule::math_cmd_name => {
+                describe_command(buf, target, uri, config, index, dependencies)?
+            }
+            _ => return None,
+        };
+        Some((target, value))
+    });
+    // 説明するものがなければ、入れ子になったコマンドの引数の中であることを説明する。
+    let (target, value) = described.or_else(|| describe_command_chain(&buf.buf_cst, &csts))?;
+
+    Some(Hover {
+        contents: HoverContents::Markup(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value,
+        }),
+        range: Some(target.range().into()),
+    })
+}
+
+/// 単純なリテラルに束縛された変数について、その値を説明する。
+fn describe_variable(buf: &Buffer, var: &Cst, pos: &Position) -> Option<String> {
+    let name = buf.buf_cst.as_str(var);
+    // 局所的な束縛に隠されている場合はトップレベルの定義を見せない。
+    if local_bindings(&buf.buf_cst, pos).iter().any(|b| b.name == name) {
+        return None;
+    }
+    let variable = std::iter::once(&buf.env)
+        .chain(buf.packages.iter().map(|pkg| &pkg.env))
+        .find_map(|env| env.variable(name))?;
+    let value = variable.value()?;
+    Some(format!("```satysfi\nlet {} = {}\n```", name, value))
+}
+
+/// プリミティブについて、補完候補と同じ型と説明を示す。
+/// 同じ名前の変数がバッファやパッケージで定義されていれば、そちらを指すため何も返さない。
+fn describe_primitive(
+    buf: &Buffer,
+    var: &Cst,
+    pos: &Position,
+    config: &Config,
+) -> Option<String> {
+    let name = buf.buf_cst.as_str(var);
+    if local_bindings(&buf.buf_cst, pos).iter().any(|b| b.name == name) {
+        return None;
+    }
+    let defined = std::iter::once(&buf.env)
+        .chain(buf.packages.iter().map(|pkg| &pkg.env))
+        .any(|env| env.variable(name).is_some());
+    if defined {
+        return None;
+    }
+    let item = find_primitive(name, config)?;
+    let signature = item
+        .detail
+        .as_ref()
+        .map(|detail| format!("```satysfi\n{} : {}\n```", name, detail));
+    let value = [signature, item.documentation.clone()].iter().flatten().join("\n\n");
+    (!value.is_empty()).then_some(value)
+}
+
+/// コマンドの定義されたファイルと、ワークスペースの他のファイルでの使用例を説明する。
+/// 定義されたファイルは、バッファを起点とする依存関係グラフ `dependencies` から探す。
+fn describe_command(
+    buf: &Buffer,
+    cmd: &Cst,
+    uri: &Url,
+    config: &Config,
+    index: &WorkspaceIndex,
+    dependencies: Option<&DependencyGraph>,
+) -> Option<String> {
+    let examples = index.usage_examples(buf.buf_cst.as_str(cmd), uri, config.usage_examples());
+    let sections = [
+        describe_class_command(buf, cmd, config),
+        dependencies.and_then(|graph| describe_command_origin(buf, cmd, graph)),
+        describe_usage_examples(&examples),
+    ];
+    let value = sections.iter().flatten().join("\n\n");
+    (!value.is_empty()).then_some(value)
+}
+
+/// 文書クラスが定義するブロックコマンドについて、completion.toml などに書かれた引数の構造と使用例を示す。
+/// バッファ自身で同じ名前のコマンドを定義していれば、そちらを指すため何も返さない。
+fn describe_class_command(buf: &Buffer, cmd: &Cst, config: &Config) -> Option<String> {
+    if cmd.rule != Rule::block_cmd_name {
+        return None;
+    }
+    let name = buf.buf_cst.as_str(cmd);
+    if find_definition(&buf.env, cmd.rule, name).is_some() {
+        return None;
+    }
+    let class = document_class(buf, config)?;
+    let item = find_class_command(&class, name, config)?;
+    if item.structure.is_none() && item.example.is_none() {
+        return None;
+    }
+    let sections = [
+        item.structure.as_ref().map(|structure| format!("```satysfi\n{}\n```", structure)),
+        item.detail.as_ref().map(|detail| format!("{} (`{}`)", detail, class)),
+        item.documentation.clone(),
+        item.example.as_ref().map(|example| format!("example:\n```satysfi\n{}\n```", example)),
+    ];
+    Some(sections.iter().flatten().join("\n\n"))
+}
+
+/// パッケージで定義されたコマンドについて、定義されたファイルとそこに至る読み込みの連鎖を説明する。
+/// バッファ自身で定義されたコマンドについては何も返さない。
+fn describe_command_origin(buf: &Buffer, cmd: &Cst, graph: &DependencyGraph) -> Option<String> {
+    let name = buf.buf_cst.as_str(cmd);
+    if find_definition(&buf.env, cmd.rule, name).is_some() {
+        return None;
+    }
+    // 起点のファイルに近いものほど優先する。
+    let node = graph
+        .nodes_by_distance()
+        .into_iter()
+        .skip(1)
+        .find(|node| find_definition(&node.env, cmd.rule, name).is_some())?;
+    let chain = graph.import_chain(&node.uri)?;
+    let file = node
+        .uri
+        .path_segments()
+        .and_then(|mut segments| segments.next_back())
+        .unwrap_or_else(|| node.uri.as_str());
+    let via = chain
+        .iter()
+        .map(|dep| format!("`{}`", dep.header()))
+        .join(" → ");
+    Some(format!("defined in `{}`, imported via {}", file, via))
+}
+
+/// コマンドの引数を表す規則か。
+fn is_command_argument(rule: Rule) -> bool {
+    matches!(
+        rule,
+        Rule::cmd_expr_arg
+            | Rule::cmd_expr_option
+            | Rule::cmd_text_arg
+            | Rule::math_cmd_expr_arg
+            | Rule::math_cmd_expr_option
+    )
+}
+
+/// カーソル位置を囲むコマンドを外側から順に並べ、それぞれの何番目 (1 始まり) の引数の中にあるかを説明する。
+/// 引数の番号はオプション引数も含めて数える。
+/// 2 つ以上のコマンドの引数の中にある場合に限り、最も内側の引数とその説明を返す。
+fn describe_command_chain<'a>(buf_cst: &BufferCst, csts: &[&'a Cst]) -> Option<(&'a Cst, String)> {
+    // csts は内側から順に並ぶので、コマンドの直前の要素がカーソル位置を含むその子である。
+    let levels = csts
+        .windows(2)
+        .filter(|pair| matches!(pair[1].rule, Rule::inline_cmd | Rule::block_cmd | Rule::math_cmd))
+        .filter(|pair| is_command_argument(pair[0].rule))
+        .map(|pair| {
+            let (arg, cmd) = (pair[0], pair[1]);
+            let args = cmd.inner.iter().filter(|c| is_command_argument(c.rule));
+            let index = args.take_while(|c| !std::ptr::eq(*c, arg)).count() + 1;
+            (arg, buf_cst.as_str(&cmd.inner[0]), index)
+        })
+        .collect_vec();
+    if levels.len() < 2 {
+        return None;
+    }
+    let chain = levels
+        .iter()
+        .rev()
+        .map(|(_, name, index)| format!("`{}` (arg {})", name, index))
+        .join(" › ");
+    Some((levels[0].0, format!("inside {}", chain)))
+}
+
+/// カーソル位置を含む最も大きな長さの式について、各リテラルを pt に換算した値と合計を説明する。
+/// カーソルが長さのリテラルか二項演算子の上になければ None を返す。
+fn describe_length<'a>(buf_cst: &BufferCst, csts: &[&'a Cst]) -> Option<(&'a Cst, String)> {
+    let on_length = csts.iter().any(|c| c.rule == Rule::length_const)
+        || csts.first().is_some_and(|c| c.rule == Rule::bin_operator);
+    if !on_length {
+        return None;
+    }
+    let is_length = |cst: &Cst| matches!(evaluate(buf_cst, cst), Some(Value::Length(_)));
+    let expr = *csts
+        .iter()
+        .skip_while(|c| !is_length(c))
+        .take_while(|c| is_length(c))
+        .last()?;
+    let total = match evaluate(buf_cst, expr)? {
+        Value::Length(pt) => pt,
+        Value::Number(_) => return None,
+    };
+
+    let in_units = |pt: f64| {
+        ["pt", "mm", "cm"]
+            .iter()
+            .filter_map(|unit| Some(format!("{}{}", format_number(convert(pt, unit)?), unit)))
+            .join(" = ")
+    };
+    let mut value = format!("length: **≈ {}**", in_units(total));
+    let literals = length_literals(expr);
+    if literals.len() > 1 {
+        let components = literals
+            .iter()
+            .filter_map(|lit| match evaluate(buf_cst, lit)? {
+                Value::Length(pt) => {
+                    Some(format!("- `{}` = {}pt", buf_cst.as_str(lit), format_number(pt)))
+                }
+                Value::Number(_) => None,
+            })
+            .join("\n");
+        value.push_str("\n\n");
+        value.push_str(&components);
+    }
+    Some((expr, value))
+}
+
+/// 文字列リテラルの文字数と、含まれる目に見えない文字を説明する。
+fn describe_string_interior(buf_cst: &BufferCst, cst: &Cst, config: &Config) -> String {
+    let text = buf_cst.as_str(cst);
+    let count = text.chars().count();
+    let mut value = format!("string literal: **{}** characters", count);
+
+    let invisibles = find_invisible_chars(text)
+        .into_iter()
+        .filter(|c| config.lint.reports(c.kind))
+        .collect_vec();
+    if !invisibles.is_empty() {
+        let list = invisibles
+            .iter()
+            .map(|c| format!("- {} at character {}", c.kind.description(), c.index))
+            .join("\n");
+        value.push_str("\n\ninvisible characters:\n");
+        value.push_str(&list);
+    }
+    value
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,122 @@
+//! test module for hover.
+
+use super::*;
+use crate::test_utils::{temp_dir, TestDocument};
+
+fn hover(doc: &TestDocument, marker: u32) -> Option<String> {
+    hover_with_index(doc, marker, &WorkspaceIndex::default())
+}
+
+fn hover_with_index(doc: &TestDocument, marker: u32, index: &WorkspaceIndex) -> Option<String> {
+    let params = HoverParams {
+        text_document_position_params: doc.text_document_position(marker),
+        work_done_progress_params: Default::default(),
+    };
+    let config = Config::default();
+    let buf = doc.buffer();
+    let dependencies = DependencyGraph::build(&buf, doc.uri(), &config);
+    match get_hover_response(&buf, params, &config, index, Some(&dependencies))?.contents {
+        HoverContents::Markup(content) => Some(content.value),
+        _ => None,
+    }
+}
+
+#[test]
+fn test_command_origin() {
+    let dir = temp_dir("hover");
+    std::fs::write(dir.join("macros.satyh"), "@import: inner\n\nlet-inline ctx \\strong = {}\n").unwrap();
+    std::fs::write(dir.join("inner.satyh"), "let-inline ctx \\emph = {}\n").unwrap();
+    let uri = Url::from_file_path(dir.join("main.saty")).unwrap();
+
+    let doc = TestDocument::new("@import: macros\n\nlet-inline ctx \\mine = {}\nin\n'<\n  +p{ \\^1emph{} \\^2mine{} }\n>\n")
+        .with_uri(uri);
+    assert_eq!(
+        hover(&doc, 1).as_deref(),
+        Some("defined in `inner.satyh`, imported via `@import: macros` → `@import: inner`")
+    );
+    assert_eq!(hover(&doc, 2), None);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_usage_examples() {
+    let doc = TestDocument::new("let-inline ctx \\mine x = x\nin\n'<\n  +p{ \\m^1ine{a} }\n>\n");
+    let mut index = WorkspaceIndex::default();
+    index.update(doc.uri().clone(), &doc.buffer());
+    let other = Url::parse("file:///chapter.saty").unwrap();
+    let chapter = "'<\n  +p{\n    \\mine{first} and \\mine{second}\n  }\n>\n";
+    index.update(other, &Buffer::new(chapter.to_owned()));
+
+    assert_eq!(
+        hover_with_index(&doc, 1, &index).as_deref(),
+        Some("usage in workspace:\n```satysfi\n\\mine{first}  % chapter.saty:3\n\\mine{second}  % chapter.saty:3\n```")
+    );
+    assert_eq!(hover(&doc, 1), None);
+}
+
+#[test]
+fn test_length_expression() {
+    let doc = TestDocument::new("let x = ^3f (12^1pt ^2+' 3mm)\nin\n'<>\n");
+    let expected = "length: **≈ 20.504pt = 7.233mm = 0.723cm**\n\n- `12pt` = 12pt\n- `3mm` = 8.504pt";
+    assert_eq!(hover(&doc, 1).as_deref(), Some(expected));
+    assert_eq!(hover(&doc, 2).as_deref(), Some(expected));
+    assert_eq!(hover(&doc, 3), None);
+
+    let doc = TestDocument::new("let y = 1i^1nch\nin\n'<>\n");
+    assert_eq!(
+        hover(&doc, 1).as_deref(),
+        Some("length: **≈ 72pt = 25.4mm = 2.54cm**")
+    );
+}
+
+#[test]
+fn test_command_chain() {
+    let doc = TestDocument::new("'<\n  +p{ ^3\\emph{^2a \\textbf(1)[2]{bol^1d}} }\n>\n");
+    assert_eq!(
+        hover(&doc, 1).as_deref(),
+        Some("inside `+p` (arg 1) › `\\emph` (arg 1) › `\\textbf` (arg 3)")
+    );
+    assert_eq!(
+        hover(&doc, 2).as_deref(),
+        Some("inside `+p` (arg 1) › `\\emph` (arg 1)")
+    );
+    // 1 つのコマンドの引数の中にあるだけでは説明しない。
+    assert_eq!(hover(&doc, 3), None);
+}
+
+#[test]
+fn test_primitive() {
+    let doc = TestDocument::new("let s = ar^1abic 1\nlet b = st^2ring-same s `1`\nlet t = a^3cos\nin\n'<>\n");
+    assert_eq!(
+        hover(&doc, 1).as_deref(),
+        Some("```satysfi\narabic : int -> string\n```\n\nConvert integer to string with Arabic notation.\n")
+    );
+    assert!(hover(&doc, 2).unwrap().contains("Test whether two strings are the same."));
+    // 型も説明もないプリミティブでは何も出さない。
+    assert_eq!(hover(&doc, 3), None);
+
+    // 同じ名前の定義があれば、プリミティブの説明は出さない。
+    let doc = TestDocument::new("let arabic n = `x`\nlet s = ar^1abic 1\nin\n'<>\n");
+    assert_eq!(hover(&doc, 1), None);
+    let doc = TestDocument::new("let s = (fun arabic -> ar^1abic) 1\nin\n'<>\n");
+    assert_eq!(hover(&doc, 1), None);
+}
+
+#[test]
+fn test_class_command_structure() {
+    let doc = TestDocument::new("@require: stdjareport\n\ndocument (| title = {T}; |) '<\n  +s^1ection{A}<\n    +^2p{B}\n  >\n>\n");
+    assert_eq!(
+        hover(&doc, 1).unwrap(),
+        "```satysfi\n+section{title}<contents>\n```\n\n\
+         section heading (`stdjareport`)\n\n\
+         example:\n```satysfi\n+section{Introduction}<\n  +p{This section describes the background.}\n>\n```"
+    );
+    assert!(hover(&doc, 2).unwrap().starts_with("```satysfi\n+p{text}\n```"));
+
+    // 文書クラスを読み込んでいなければ、クラスのコマンドとしては説明しない。
+    assert_eq!(hover(&TestDocument::new("'<\n  +s^1ection{A}<>\n>\n"), 1), None);
+    // 同じ名前のコマンドを自分で定義していれば、そちらを指す。
+    let doc = TestDocument::new("@require: stdjareport\n\nlet-block ctx +section title inner = inner\nin\n'<\n  +s^1ection{A}<>\n>\n");
+    assert_eq!(hover(&doc, 1), None);
+}
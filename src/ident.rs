@@ -0,0 +1,166 @@
+//! 名前がコマンド名や変数名として正しいかを、SATySFi の文法に従って検査する。
+//!
+//! 名前の変更やコマンドを定義する code action で、ユーザが入力した新しい名前を受け付ける前に用いる。
+//! 文法上の名前は ASCII の文字しか含まないため、それ以外の文字は文字単位で位置を示して報告する。
+
+use std::fmt;
+
+/// 文法の `reserved_word` と同じ予約語。変数名や型名には使えない。
+/// 文法と食い違っていないことはテストで確かめる。
+const RESERVED_WORDS: &[&str] = &[
+    "constraint", "inline-cmd", "block-cmd", "math-cmd", "let-mutable", "let-inline", "let-block",
+    "let-math", "let-rec", "controls", "command", "before", "module", "direct", "struct", "cycle",
+    "match", "while", "false", "else", "open", "then", "true", "type", "when", "with", "and", "end",
+    "fun", "let", "mod", "not", "sig", "val", "as", "do", "if", "in", "of",
+];
+
+/// 検査する名前の種類。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IdentKind {
+    /// `\cmd` の形のインラインコマンド。
+    InlineCmd,
+    /// `+cmd` の形のブロックコマンド。
+    BlockCmd,
+    /// `\cmd` の形の数式コマンド。
+    MathCmd,
+    /// 変数。
+    Variable,
+    /// 型。
+    Type,
+    /// ヴァリアントのコンストラクタ。
+    Variant,
+    /// モジュール。
+    Module,
+}
+
+impl IdentKind {
+    /// 名前の先頭に付く記号。
+    fn sigil(self) -> Option<char> {
+        match self {
+            IdentKind::InlineCmd | IdentKind::MathCmd => Some('\\'),
+            IdentKind::BlockCmd => Some('+'),
+            _ => None,
+        }
+    }
+
+    /// 記号の直後の文字が大文字で始まるべきか。
+    fn is_capitalized(self) -> bool {
+        matches!(self, IdentKind::Variant | IdentKind::Module)
+    }
+}
+
+impl fmt::Display for IdentKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            IdentKind::InlineCmd => "inline command",
+            IdentKind::BlockCmd => "block command",
+            IdentKind::MathCmd => "math command",
+            IdentKind::Variable => "variable",
+            IdentKind::Type => "type",
+            IdentKind::Variant => "variant",
+            IdentKind::Module => "module",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// 名前が正しくない理由。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IdentError {
+    /// 名前が空である。
+    Empty(IdentKind),
+    /// コマンド名の先頭に `\` や `+` がない。
+    MissingSigil(IdentKind),
+    /// 記号の直後の文字が正しくない。`index` は名前の先頭からの文字数。
+    InvalidStart {
+        /// 名前の種類。
+        kind: IdentKind,
+        /// 見つかった文字。記号しかない場合は None.
+        found: Option<char>,
+        /// 見つかった位置。
+        index: usize,
+    },
+    /// 名前に使えない文字がある。`index` は名前の先頭からの文字数。
+    InvalidChar {
+        /// 名前の種類。
+        kind: IdentKind,
+        /// 見つかった文字。
+        found: char,
+        /// 見つかった位置。
+        index: usize,
+    },
+    /// 予約語である。
+    Reserved(IdentKind, String),
+}
+
+impl fmt::Display for IdentError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IdentError::Empty(kind) => write!(f, "{} name must not be empty", kind),
+            IdentError::MissingSigil(kind) => {
+                write!(f, "{} name must start with `{}`", kind, kind.sigil().unwrap_or_default())
+            }
+            IdentError::InvalidStart { kind, found, index } => {
+                let expected = if kind.is_capitalized() {
+                    "an uppercase ASCII letter"
+                } else {
+                    "a lowercase ASCII letter"
+                };
+                let position = if kind.sigil().is_some() { " after the sigil" } else { "" };
+                write!(f, "{} name must have {}{}", kind, expected, position)?;
+                match found {
+                    Some(c) => write!(f, ", found `{}` at character {}", c, index + 1),
+                    None => Ok(()),
+                }
+            }
+            IdentError::InvalidChar { kind, found, index } => write!(
+                f,
+                "{} name can only contain ASCII letters, digits and `-`, found `{}` at character {}",
+                kind,
+                found,
+                index + 1
+            ),
+            IdentError::Reserved(kind, name) => {
+                write!(f, "`{}` is a reserved word and cannot be used as a {} name", name, kind)
+            }
+        }
+    }
+}
+
+impl std::error::Error for IdentError {}
+
+/// `name` が `kind` の名前として定義できるかを検査する。
+/// モジュールで修飾された名前は定義できないため、`.` も使えない文字として扱う。
+pub fn validate_ident(kind: IdentKind, name: &str) -> Result<(), IdentError> {
+    if name.is_empty() {
+        return Err(IdentError::Empty(kind));
+    }
+    let body = match kind.sigil() {
+        Some(sigil) => name.strip_prefix(sigil).ok_or(IdentError::MissingSigil(kind))?,
+        None => name,
+    };
+    let offset = name.chars().count() - body.chars().count();
+
+    let mut chars = body.chars().enumerate();
+    let first = chars.next().map(|(_, c)| c);
+    let valid_start = match first {
+        Some(c) if kind.is_capitalized() => c.is_ascii_uppercase(),
+        Some(c) => c.is_ascii_lowercase(),
+        None => false,
+    };
+    if !valid_start {
+        return Err(IdentError::InvalidStart { kind, found: first, index: offset });
+    }
+    if let Some((i, c)) = chars.find(|&(_, c)| !(c.is_ascii_alphanumeric() || c == '-')) {
+        return Err(IdentError::InvalidChar { kind, found: c, index: offset + i });
+    }
+
+    let checks_reserved = matches!(kind, IdentKind::Variable | IdentKind::Type);
+    if checks_reserved && RESERVED_WORDS.contains(&name) {
+        return Err(IdentError::Reserved(kind, name.to_owned()));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,94 @@
+//! test module for ident.
+
+use pest::Parser;
+
+use super::*;
+use crate::parser::{Rule, SatysfiParser};
+
+#[test]
+fn test_valid_names() {
+    assert_eq!(validate_ident(IdentKind::InlineCmd, "\\emph"), Ok(()));
+    assert_eq!(validate_ident(IdentKind::BlockCmd, "+sub-section2"), Ok(()));
+    assert_eq!(validate_ident(IdentKind::MathCmd, "\\let"), Ok(()));
+    assert_eq!(validate_ident(IdentKind::Variable, "let-x"), Ok(()));
+    assert_eq!(validate_ident(IdentKind::Variant, "Circle"), Ok(()));
+}
+
+#[test]
+fn test_sigil() {
+    assert_eq!(
+        validate_ident(IdentKind::BlockCmd, "section"),
+        Err(IdentError::MissingSigil(IdentKind::BlockCmd))
+    );
+    assert_eq!(
+        validate_ident(IdentKind::BlockCmd, "\\section").unwrap_err().to_string(),
+        "block command name must start with `+`"
+    );
+    assert_eq!(
+        validate_ident(IdentKind::InlineCmd, "\\").unwrap_err().to_string(),
+        "inline command name must have a lowercase ASCII letter after the sigil"
+    );
+}
+
+#[test]
+fn test_invalid_start() {
+    assert_eq!(
+        validate_ident(IdentKind::InlineCmd, "\\Emph").unwrap_err().to_string(),
+        "inline command name must have a lowercase ASCII letter after the sigil, found `E` at character 2"
+    );
+    assert_eq!(
+        validate_ident(IdentKind::Module, "list").unwrap_err().to_string(),
+        "module name must have an uppercase ASCII letter, found `l` at character 1"
+    );
+}
+
+#[test]
+fn test_non_ascii() {
+    // 位置はバイトではなく文字で数える。
+    assert_eq!(
+        validate_ident(IdentKind::Variable, "café-au-lait"),
+        Err(IdentError::InvalidChar { kind: IdentKind::Variable, found: 'é', index: 3 })
+    );
+    assert_eq!(
+        validate_ident(IdentKind::InlineCmd, "\\強調").unwrap_err(),
+        IdentError::InvalidStart { kind: IdentKind::InlineCmd, found: Some('強'), index: 1 }
+    );
+    assert_eq!(
+        validate_ident(IdentKind::InlineCmd, "\\m.emph").unwrap_err().to_string(),
+        "inline command name can only contain ASCII letters, digits and `-`, found `.` at character 3"
+    );
+}
+
+#[test]
+fn test_reserved() {
+    assert_eq!(
+        validate_ident(IdentKind::Variable, "let").unwrap_err().to_string(),
+        "`let` is a reserved word and cannot be used as a variable name"
+    );
+    assert_eq!(validate_ident(IdentKind::Variable, "let-x"), Ok(()));
+    assert_eq!(validate_ident(IdentKind::Type, "of"), Err(IdentError::Reserved(IdentKind::Type, "of".to_owned())));
+}
+
+#[test]
+fn test_reserved_words_match_grammar() {
+    // 手で写した予約語の一覧が、文法の `reserved_word` の選択肢と一致することを確かめる。
+    let grammar = include_str!("../parser/satysfi.pest");
+    let start = grammar.find("\nreserved_word = {").unwrap();
+    let block = &grammar[start..start + grammar[start..].find("\n}").unwrap()];
+    let mut in_grammar = block
+        .lines()
+        .filter_map(|line| line.trim().trim_start_matches('|').trim().strip_prefix('"'))
+        .map(|rest| &rest[..rest.find('"').unwrap()])
+        .collect::<Vec<_>>();
+    let mut listed = RESERVED_WORDS.to_vec();
+    in_grammar.sort_unstable();
+    listed.sort_unstable();
+    assert_eq!(listed, in_grammar);
+
+    // 一覧の語は、どれも文法上の変数名として受け付けられない。
+    for word in RESERVED_WORDS {
+        assert!(SatysfiParser::parse(Rule::var, word).is_err(), "{}", word);
+        let error = IdentError::Reserved(IdentKind::Variable, word.to_string());
+        assert_eq!(validate_ident(IdentKind::Variable, word), Err(error));
+    }
+}
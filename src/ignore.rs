@@ -0,0 +1,166 @@
+//! ワークスペースの走査で読み飛ばすファイルを決める、`.gitignore` と同じ書式の規則。
+//!
+//! 空行と `#` で始まる行は無視し、`!` で始まる行は否定、`/` で終わる行はディレクトリにだけ合致する。
+//! 先頭や途中に `/` を含むパターンは規則を書いたディレクトリからの相対パスに、
+//! 含まないパターンは任意の深さのファイル名に合致する。
+//! `*` と `?` は `/` 以外の文字に、`**` は `/` を含む任意の文字列に合致する。
+//! 複数の規則に合致する場合は、後に書かれたものを優先する。
+//! 設定ファイルで与えた規則は、どの `.gitignore` の規則よりも優先する。
+
+use std::path::{Path, PathBuf};
+
+use log::warn;
+
+/// 読み飛ばすファイルを決める規則の集まり。
+#[derive(Debug, Clone, Default)]
+pub struct IgnoreFilter {
+    /// 設定ファイルで与えた規則。後にあるものほど優先する。
+    overrides: Vec<IgnoreRule>,
+    /// `.gitignore` から読んだ規則。後にあるものほど優先する。
+    rules: Vec<IgnoreRule>,
+}
+
+/// `.gitignore` の1行に相当する規則。
+#[derive(Debug, Clone)]
+struct IgnoreRule {
+    /// 規則を書いたディレクトリ。パターンはここからの相対パスに合致させる。
+    base: PathBuf,
+    /// 先頭と末尾の `/` や `!` を除いたパターン。
+    pattern: String,
+    /// ファイル名ではなく、base からの相対パス全体に合致させるか。
+    anchored: bool,
+    /// ディレクトリにだけ合致するか。
+    dir_only: bool,
+    /// 合致したものを読み飛ばさないようにする規則か。
+    negated: bool,
+}
+
+impl IgnoreFilter {
+    /// `root` を起点とする、設定ファイルで与えた規則からなるものを作る。
+    pub fn new(root: &Path, patterns: &[String]) -> Self {
+        Self {
+            overrides: parse_rules(root, patterns.iter().map(String::as_str)),
+            rules: vec![],
+        }
+    }
+
+    /// `dir` に `.gitignore` があれば、その規則を加えたものを返す。
+    pub fn with_gitignore(&self, dir: &Path) -> Self {
+        let path = dir.join(".gitignore");
+        let mut filter = self.clone();
+        match std::fs::read_to_string(&path) {
+            Ok(text) => filter.rules.extend(parse_rules(dir, text.lines())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => (),
+            Err(e) => warn!("failed to read {}: {}", path.display(), e),
+        }
+        filter
+    }
+
+    /// パスを読み飛ばすか。
+    pub fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        let last_match = |rules: &[IgnoreRule]| {
+            rules.iter().rev().find(|rule| rule.matches(path, is_dir)).map(|rule| !rule.negated)
+        };
+        last_match(&self.overrides)
+            .or_else(|| last_match(&self.rules))
+            .unwrap_or(false)
+    }
+}
+
+/// `.gitignore` と同じ書式の行から、`base` を起点とする規則を作る。
+fn parse_rules<'a>(base: &Path, lines: impl IntoIterator<Item = &'a str>) -> Vec<IgnoreRule> {
+    lines
+        .into_iter()
+        .filter_map(|line| IgnoreRule::parse(base, line))
+        .collect()
+}
+
+impl IgnoreRule {
+    /// `.gitignore` の1行を読む。空行やコメントであれば None を返す。
+    fn parse(base: &Path, line: &str) -> Option<Self> {
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+        let (negated, line) = match line.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        let (dir_only, line) = match line.strip_suffix('/') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        let anchored = line.contains('/');
+        let pattern = line.trim_start_matches('/');
+        if pattern.is_empty() {
+            return None;
+        }
+        Some(Self {
+            base: base.to_owned(),
+            pattern: pattern.to_owned(),
+            anchored,
+            dir_only,
+            negated,
+        })
+    }
+
+    /// パスがこの規則に合致するか。
+    fn matches(&self, path: &Path, is_dir: bool) -> bool {
+        if self.dir_only && !is_dir {
+            return false;
+        }
+        let relative = match path.strip_prefix(&self.base) {
+            Ok(relative) => relative,
+            Err(_) => return false,
+        };
+        let segments: Vec<_> = relative
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect();
+        if self.anchored {
+            glob_match(&self.pattern, &segments.join("/"))
+        } else {
+            segments
+                .last()
+                .is_some_and(|name| glob_match(&self.pattern, name))
+        }
+    }
+}
+
+/// glob パターンが文字列全体に合致するか。
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    match_from(&pattern, &text)
+}
+
+fn match_from(pattern: &[char], text: &[char]) -> bool {
+    match pattern {
+        [] => text.is_empty(),
+        // `**/` は 0 個以上のディレクトリに合致する。
+        ['*', '*', '/', rest @ ..] => {
+            match_from(rest, text)
+                || text
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, c)| **c == '/')
+                    .any(|(i, _)| match_from(rest, &text[i + 1..]))
+        }
+        ['*', '*', rest @ ..] => (0..=text.len()).any(|i| match_from(rest, &text[i..])),
+        ['*', rest @ ..] => {
+            let segment = text.iter().position(|c| *c == '/').unwrap_or(text.len());
+            (0..=segment).any(|i| match_from(rest, &text[i..]))
+        }
+        ['?', rest @ ..] => match text {
+            [c, text @ ..] if *c != '/' => match_from(rest, text),
+            _ => false,
+        },
+        [p, rest @ ..] => match text {
+            [c, text @ ..] if c == p => match_from(rest, text),
+            _ => false,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests;
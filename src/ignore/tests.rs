@@ -0,0 +1,48 @@
+//! test module for ignore rules.
+
+use super::*;
+use crate::test_utils::temp_dir;
+
+fn filter(patterns: &[&str]) -> IgnoreFilter {
+    let patterns = patterns.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+    IgnoreFilter::new(Path::new("/ws"), &patterns)
+}
+
+#[test]
+fn test_glob_match() {
+    assert!(glob_match("*.saty", "main.saty"));
+    assert!(!glob_match("*.saty", "doc/main.saty"));
+    assert!(glob_match("doc/**/*.saty", "doc/main.saty"));
+    assert!(glob_match("doc/**/*.saty", "doc/a/b/main.saty"));
+    assert!(glob_match("vendor/**", "vendor/pkg/a.satyh"));
+    assert!(glob_match("a?c", "abc"));
+    assert!(!glob_match("a?c", "a/c"));
+}
+
+#[test]
+fn test_unanchored_and_anchored() {
+    let f = filter(&["# comment", "", "out/", "/build", "lib/gen/*.satyh"]);
+    assert!(f.is_ignored(Path::new("/ws/out"), true));
+    assert!(f.is_ignored(Path::new("/ws/doc/out"), true));
+    assert!(!f.is_ignored(Path::new("/ws/doc/out"), false));
+    assert!(f.is_ignored(Path::new("/ws/build"), true));
+    assert!(!f.is_ignored(Path::new("/ws/doc/build"), true));
+    assert!(f.is_ignored(Path::new("/ws/lib/gen/a.satyh"), false));
+    assert!(!f.is_ignored(Path::new("/ws/lib/a.satyh"), false));
+    assert!(!f.is_ignored(Path::new("/other/out"), true));
+}
+
+#[test]
+fn test_negation_and_priority() {
+    let f = filter(&["*.satyh", "!keep.satyh"]);
+    assert!(f.is_ignored(Path::new("/ws/a.satyh"), false));
+    assert!(!f.is_ignored(Path::new("/ws/keep.satyh"), false));
+
+    // 設定ファイルの規則は .gitignore の規則より優先する。
+    let dir = temp_dir("gitignore");
+    std::fs::write(dir.join(".gitignore"), "*.satyg\nkeep.satyh\n").unwrap();
+    let f = IgnoreFilter::new(&dir, &["!keep.satyh".to_owned()]).with_gitignore(&dir);
+    assert!(f.is_ignored(&dir.join("a.satyg"), false));
+    assert!(!f.is_ignored(&dir.join("keep.satyh"), false));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,133 @@
+//! 別行立て数式とそのラベルの索引を返す `satysfi/labels` リクエストと、
+//! `\eqref` のような参照コマンドの中でのラベルの補完。
+//!
+//! `+math?:(`label`)(${...});` のように数式を直接引数にとるブロックコマンドを別行立て数式とみなし、
+//! 文書中に現れる順に番号を振る。ラベルはオプション引数に書かれた文字列リテラルとする。
+
+use lsp_types::{request::Request, TextDocumentIdentifier};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::Config,
+    parser::Rule,
+    position::{Position, Range},
+    Buffer, Cst,
+};
+
+/// 文書中の別行立て数式とそのラベルを返すカスタムリクエスト。
+pub enum Labels {}
+
+impl Request for Labels {
+    type Params = LabelsParams;
+    type Result = Vec<DisplayMath>;
+    const METHOD: &'static str = "satysfi/labels";
+}
+
+/// `satysfi/labels` のパラメータ。
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LabelsParams {
+    /// 対象のドキュメント。
+    pub text_document: TextDocumentIdentifier,
+}
+
+/// 別行立て数式。
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DisplayMath {
+    /// 文書中に現れる順に 1 から振った番号。
+    pub number: usize,
+    /// 別行立て数式を作るコマンド名。
+    pub command: String,
+    /// ラベル。
+    pub label: Option<String>,
+    /// 別行立て数式のコマンド全体の範囲。
+    pub range: Range,
+    /// ラベルの文字列の範囲。
+    pub label_range: Option<Range>,
+}
+
+/// labels リクエストへの response を返す。
+pub fn get_labels_response(buf: &Buffer) -> Vec<DisplayMath> {
+    display_maths(buf)
+}
+
+/// 文書中の別行立て数式を、現れる順に列挙する。
+pub fn display_maths(buf: &Buffer) -> Vec<DisplayMath> {
+    let cst = match &buf.buf_cst.cst {
+        Some(cst) => cst,
+        None => return vec![],
+    };
+    cst.pickup(Rule::block_cmd)
+        .into_iter()
+        .filter(|cmd| is_display_math(cmd))
+        .enumerate()
+        .map(|(i, cmd)| {
+            let label = cmd
+                .inner
+                .iter()
+                .filter(|c| c.rule == Rule::cmd_expr_option)
+                .filter_map(|option| option.inner.first())
+                .find_map(string_argument);
+            DisplayMath {
+                number: i + 1,
+                command: buf.buf_cst.as_str(&cmd.inner[0]).to_owned(),
+                label: label.map(|l| buf.buf_cst.as_str(l).to_owned()),
+                range: cmd.range.clone().into(),
+                label_range: label.map(|l| l.range.clone().into()),
+            }
+        })
+        .collect()
+}
+
+/// ラベルを参照するコマンド。設定の `reference-commands` に書いたものもこれに加える。
+pub const REFERENCE_COMMANDS: &[&str] = &["\\ref", "\\eqref", "\\ref-page"];
+
+/// ラベルを参照するコマンドか。
+pub(crate) fn is_reference_command(name: &str, config: &Config) -> bool {
+    REFERENCE_COMMANDS.contains(&name) || config.reference_commands.iter().any(|cmd| cmd == name)
+}
+
+/// 与えられた位置が、参照コマンドの引数の文字列リテラルの中にあるか。
+pub(crate) fn in_reference_argument(cst: &Cst, text: &str, pos: &Position, config: &Config) -> bool {
+    let csts = cst.dig(pos);
+    let interior = match csts.first() {
+        Some(interior) if interior.rule == Rule::string_interior => *interior,
+        _ => return false,
+    };
+    csts.iter()
+        .find(|c| c.rule == Rule::inline_cmd)
+        .filter(|cmd| is_reference_command(cmd.inner[0].as_str(text), config))
+        .and_then(|cmd| {
+            cmd.inner
+                .iter()
+                .filter(|c| c.rule == Rule::cmd_expr_arg)
+                .find_map(string_argument)
+        })
+        .is_some_and(|arg| std::ptr::eq(arg, interior))
+}
+
+/// 数式を直接引数にとるブロックコマンドか。
+fn is_display_math(cmd: &Cst) -> bool {
+    cmd.inner
+        .iter()
+        .filter(|c| c.rule == Rule::cmd_expr_arg)
+        .any(|arg| matches!(unary_child(arg), Some(c) if c.rule == Rule::math_text))
+}
+
+/// コマンドの引数が文字列リテラルであれば、その中身を返す。
+fn string_argument(arg: &Cst) -> Option<&Cst> {
+    let literal = unary_child(arg).filter(|c| c.rule == Rule::literal)?;
+    let string = literal.inner.first().filter(|c| c.rule == Rule::string_const)?;
+    string.inner.iter().find(|c| c.rule == Rule::string_interior)
+}
+
+/// `(expr)` の形の引数から、単項の式の中身を取り出す。
+fn unary_child(arg: &Cst) -> Option<&Cst> {
+    let expr = arg.inner.first().filter(|c| c.rule == Rule::expr)?;
+    let unary = expr.inner.first().filter(|c| c.rule == Rule::unary && expr.inner.len() == 1)?;
+    unary.inner.first().filter(|_| unary.inner.len() == 1)
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,53 @@
+//! test module for display math labels.
+
+use super::*;
+
+const DOCUMENT: &str = r#"let-block ctx +math ?:label m = block-nil
+in
+'<
+  +math?:(`eq:a`)(${x^2});
+  +math(${y});
+  +math?:(`eq:b`)(${z});
+  +p{ see \eqref(`eq:a`); and \emph(`eq:a`); \href(`eq:a`){a} \myref(`eq:a`); }
+>
+"#;
+
+#[test]
+fn test_display_maths() {
+    let buf = Buffer::new(DOCUMENT.to_owned());
+    let maths = display_maths(&buf);
+    let labels = maths
+        .iter()
+        .map(|m| (m.number, m.label.as_deref()))
+        .collect::<Vec<_>>();
+    assert_eq!(labels, vec![(1, Some("eq:a")), (2, None), (3, Some("eq:b"))]);
+    assert_eq!(maths[0].command, "+math");
+    assert_eq!(
+        maths[0].label_range,
+        Some(Range {
+            start: Position { line: 3, character: 11 },
+            end: Position { line: 3, character: 15 },
+        })
+    );
+}
+
+#[test]
+fn test_in_reference_argument() {
+    let buf = Buffer::new(DOCUMENT.to_owned());
+    let cst = buf.buf_cst.cst.as_ref().unwrap();
+    let text = &buf.buf_cst.buffer;
+    let at = |character| Position { line: 6, character };
+    let config = Config::default();
+    assert!(in_reference_argument(cst, text, &at(20), &config));
+    assert!(!in_reference_argument(cst, text, &at(36), &config));
+    assert!(!in_reference_argument(cst, text, &at(8), &config));
+    // ハイパーリンクの URL はラベルではない。
+    assert!(!in_reference_argument(cst, text, &at(53), &config));
+    // 設定に加えたコマンドの引数ではラベルを補完する。
+    assert!(!in_reference_argument(cst, text, &at(72), &config));
+    let config = Config {
+        reference_commands: vec!["\\myref".to_owned()],
+        ..Config::default()
+    };
+    assert!(in_reference_argument(cst, text, &at(72), &config));
+}
@@ -0,0 +1,133 @@
+//! 長さのリテラルとその演算からなる式を、CST の上で畳み込んで評価する。
+//!
+//! `12pt +' 3mm` のように長さのリテラル、数値のリテラルと
+//! `+'`, `-'`, `*'`, `/'` だけからなる式を扱う。変数や関数適用を含む式は評価しない。
+
+use crate::{parser::Rule, BufferCst, Cst};
+
+/// 長さの単位と、1 単位あたりの pt 数。SATySFi の pt は 1/72 inch である。
+pub const UNITS: &[(&str, f64)] = &[
+    ("pt", 1.0),
+    ("mm", 72.0 / 25.4),
+    ("cm", 720.0 / 25.4),
+    ("inch", 72.0),
+];
+
+/// 評価した値。
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Value {
+    /// pt 単位で表した長さ。
+    Length(f64),
+    /// 数値。
+    Number(f64),
+}
+
+/// 式を評価する。長さと数値以外の値になるものや、評価できないものを含めば None を返す。
+pub fn evaluate(buf_cst: &BufferCst, cst: &Cst) -> Option<Value> {
+    match cst.rule {
+        Rule::expr | Rule::unary | Rule::literal => match &cst.inner[..] {
+            [inner] => evaluate(buf_cst, inner),
+            _ => None,
+        },
+        Rule::length_const => {
+            let unit = cst.inner.iter().find(|c| c.rule == Rule::length_unit)?;
+            let text = buf_cst.as_str(cst);
+            let digits = &text[..text.len() - buf_cst.as_str(unit).len()];
+            let (_, pt) = UNITS.iter().find(|(name, _)| *name == buf_cst.as_str(unit))?;
+            Some(Value::Length(digits.parse::<f64>().ok()? * pt))
+        }
+        Rule::float_const | Rule::int_const => {
+            buf_cst.as_str(cst).parse().ok().map(Value::Number)
+        }
+        Rule::unary_operator_expr => match &cst.inner[..] {
+            [op, operand] if buf_cst.as_str(op) == "-" => match evaluate(buf_cst, operand)? {
+                Value::Length(x) => Some(Value::Length(-x)),
+                Value::Number(x) => Some(Value::Number(-x)),
+            },
+            _ => None,
+        },
+        Rule::dyadic_expr => evaluate_dyadic(buf_cst, cst),
+        _ => None,
+    }
+}
+
+/// 式に含まれる長さのリテラルを、現れる順に列挙する。
+pub fn length_literals(cst: &Cst) -> Vec<&Cst> {
+    if cst.rule == Rule::length_const {
+        return vec![cst];
+    }
+    cst.pickup(Rule::length_const)
+}
+
+/// pt 単位の長さを、指定した単位での値に換算する。
+pub fn convert(pt: f64, unit: &str) -> Option<f64> {
+    let (_, per_unit) = UNITS.iter().find(|(name, _)| *name == unit)?;
+    Some(pt / per_unit)
+}
+
+/// 小数点以下 3 桁までに丸め、末尾の 0 を取り除いた文字列にする。
+pub fn format_number(x: f64) -> String {
+    let s = format!("{:.3}", x);
+    let s = s.trim_end_matches('0').trim_end_matches('.');
+    match s {
+        "-0" => "0".to_owned(),
+        s => s.to_owned(),
+    }
+}
+
+/// 二項演算の連なりを評価する。
+/// パーサは二項演算を右に再帰する形で読むため、項と演算子を平らに並べてから
+/// `*'` と `/'` を先に、`+'` と `-'` を後に左から畳み込む。
+fn evaluate_dyadic(buf_cst: &BufferCst, cst: &Cst) -> Option<Value> {
+    let mut operands = vec![];
+    let mut operators = vec![];
+    let mut current = cst;
+    loop {
+        match &current.inner[..] {
+            [lhs, op, rhs] => {
+                operands.push(evaluate(buf_cst, lhs)?);
+                operators.push(buf_cst.as_str(op));
+                if rhs.rule == Rule::dyadic_expr {
+                    current = rhs;
+                } else {
+                    operands.push(evaluate(buf_cst, rhs)?);
+                    break;
+                }
+            }
+            _ => return None,
+        }
+    }
+
+    // 乗除を先に畳み込む。
+    let mut terms = vec![operands[0]];
+    let mut additive = vec![];
+    for (op, rhs) in operators.into_iter().zip(operands.into_iter().skip(1)) {
+        if op == "*'" || op == "/'" {
+            let lhs = terms.pop()?;
+            terms.push(apply(op, lhs, rhs)?);
+        } else {
+            additive.push(op);
+            terms.push(rhs);
+        }
+    }
+    let mut terms = terms.into_iter();
+    let first = terms.next()?;
+    additive
+        .into_iter()
+        .zip(terms)
+        .try_fold(first, |lhs, (op, rhs)| apply(op, lhs, rhs))
+}
+
+/// 二項演算を一つ適用する。
+fn apply(op: &str, lhs: Value, rhs: Value) -> Option<Value> {
+    match (op, lhs, rhs) {
+        ("+'", Value::Length(x), Value::Length(y)) => Some(Value::Length(x + y)),
+        ("-'", Value::Length(x), Value::Length(y)) => Some(Value::Length(x - y)),
+        ("*'", Value::Length(x), Value::Number(y)) => Some(Value::Length(x * y)),
+        ("/'", Value::Length(x), Value::Length(y)) if y != 0.0 => Some(Value::Number(x / y)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests;
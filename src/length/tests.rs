@@ -0,0 +1,51 @@
+//! test module for length evaluation.
+
+use super::*;
+use crate::Buffer;
+
+/// `let x = <expr>` の右辺を評価する。
+fn eval(expr: &str) -> Option<Value> {
+    let buf = Buffer::new(format!("let x = {}\nin\n'<>\n", expr));
+    let cst = buf.buf_cst.cst.as_ref().unwrap();
+    let let_stmt = cst.pickup(Rule::let_stmt)[0];
+    evaluate(&buf.buf_cst, let_stmt.inner.last().unwrap())
+}
+
+fn length(expr: &str) -> String {
+    match eval(expr) {
+        Some(Value::Length(pt)) => format_number(pt),
+        value => panic!("not a length: {:?}", value),
+    }
+}
+
+#[test]
+fn test_literal() {
+    assert_eq!(length("12pt"), "12");
+    assert_eq!(length("1inch"), "72");
+    assert_eq!(length("25.4mm"), "72");
+    assert_eq!(length("-1cm"), "-28.346");
+}
+
+#[test]
+fn test_arithmetic() {
+    assert_eq!(length("12pt +' 3pt"), "15");
+    assert_eq!(length("12pt -' 3pt -' 1pt"), "8");
+    assert_eq!(length("1pt +' 2pt *' 3."), "7");
+    assert_eq!(length("(1pt +' 2pt) *' 3."), "9");
+    assert_eq!(eval("6pt /' 2pt"), Some(Value::Number(3.0)));
+}
+
+#[test]
+fn test_not_length() {
+    assert_eq!(eval("12pt +' x"), None);
+    assert_eq!(eval("12pt +' 3."), None);
+    assert_eq!(eval("12zw"), None);
+}
+
+#[test]
+fn test_format_number() {
+    assert_eq!(format_number(8.50393), "8.504");
+    assert_eq!(format_number(2.5), "2.5");
+    assert_eq!(format_number(-0.0001), "0");
+    assert_eq!(convert(72.0, "inch"), Some(1.0));
+}
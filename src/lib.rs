@@ -1,33 +1,180 @@
 //! Maquette (prototype) of SATySFi Language Server.
 
-#![forbid(unsafe_code)]
+// テスト用の tree-sitter 文法だけは FFI のために unsafe を許す。
+#![cfg_attr(not(test), forbid(unsafe_code))]
+#![cfg_attr(test, deny(unsafe_code))]
 #![warn(missing_docs)]
 #![warn(rust_2018_idioms)]
 
 #[macro_use]
 extern crate pest_derive;
 
+pub mod all_commands;
+pub mod argument;
+pub mod capabilities;
+pub mod catalog;
+pub mod code_action;
 pub mod completion;
+pub mod config;
 pub mod definition;
+pub mod dependency;
+pub mod diagnostic;
+pub mod document_symbol;
+pub mod edit;
+pub mod folding;
+pub mod fuzzy;
+pub mod history;
+pub mod hover;
+pub mod ident;
+pub mod ignore;
+pub mod label;
+pub mod length;
+pub mod lint;
+pub mod on_type_formatting;
+pub mod package_doc;
+pub mod parse_cache;
 pub mod parser;
+pub mod partial;
+pub mod position;
+pub mod prelude;
+pub mod pretty;
+pub mod pull_diagnostic;
+pub mod resolve;
+pub mod scope;
+pub mod selection;
+pub mod semantic_tokens;
+#[cfg(feature = "server")]
+pub mod server;
+pub mod signature_help;
+pub mod stage;
+pub mod statistics;
+pub mod status;
+pub mod symbol_diff;
+pub mod syntax;
+pub mod telemetry;
+#[cfg(any(test, feature = "test-utils"))]
+pub mod test_utils;
+pub mod trace;
+pub mod typing;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+pub mod workspace;
+#[cfg(feature = "server")]
+pub mod workspace_lint;
+pub mod workspace_symbol;
 
-use anyhow::{Error, Result};
-use log::debug;
+use anyhow::Error;
+use config::{Config, DefinitionPattern, LanguageVersion};
+use parse_cache::ParseCache;
+use log::warn;
 use pest::{Parser, Span};
+use serde::{Deserialize, Serialize};
 
-use std::collections::HashMap;
+use std::{
+    collections::{HashMap, HashSet},
+    convert::TryFrom,
+    sync::Arc,
+};
 
 use itertools::Itertools;
-use lsp_types::{Position, Range, Url};
-use parser::{Mode, Pair, Rule, SatysfiParser};
+use lsp_types::Url;
+use parser::{
+    recovery::{recover, Recovered, Recovery},
+    relation::CompareRange,
+    Mode, ModeRegion, Pair, Rule, SatysfiParser,
+};
+use ident::{validate_ident, IdentKind};
+use position::{floor_char_boundary, LineIndex, Position, Range};
+use resolve::{resolve_package, PackageKind, Stage, GENERIC_EXTENSION};
 
+/// このクレートの版。[`prelude`] から再公開している項目は、この版について semver に従って変更する。
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// 文字列、文法構造、環境をまとめて格納したバッファ。
 #[derive(Debug)]
 pub struct Buffer {
-    pub buf_cst: BufferCst,
+    /// バッファの文字列と文法構造。同じ内容のパース結果は [`ParseCache`] と共有する。
+    pub buf_cst: Arc<BufferCst>,
+    /// パース時に発生したエラー。
     pub error: Vec<Error>,
+    /// バッファ内で定義されたコマンドや変数。
+    pub env: Environment,
+    /// `@require:` や `@import:` で読み込まれたパッケージ。
+    pub packages: Vec<Package>,
+    /// クライアントから通知された版。ファイルから読み込んだバッファでは None となる。
+    pub version: Option<i32>,
+    /// パースに用いる文法の版。
+    language_version: LanguageVersion,
+    /// バッファが大きすぎるためにパースを後回しにしているか。
+    deferred: bool,
+    /// パースに失敗している場合に、最後にパースに成功した版のバッファ。
+    last_parsed: Option<Box<Buffer>>,
+}
+
+/// 読み込まれたパッケージ。
+#[derive(Debug)]
+pub struct Package {
+    /// 読み込み方法。
+    pub kind: PackageKind,
+    /// ヘッダに書かれたパッケージ名。
+    pub name: String,
+    /// パッケージファイルの URI.
+    pub uri: Url,
+    /// パッケージの外から見える定義。
     pub env: Environment,
+    /// パッケージが処理されるステージ。`.satyg` のようにどのステージからも読み込める場合は None.
+    pub stage: Option<Stage>,
+}
+
+impl Package {
+    /// `stage` で処理される `base` のファイルから読み込まれるパッケージを探し、読み込む。
+    pub fn load(
+        base: &Url,
+        kind: PackageKind,
+        name: &str,
+        stage: Stage,
+        config: &Config,
+    ) -> Option<Self> {
+        let path = resolve_package(base, kind, name, stage, config)?;
+        let text = std::fs::read_to_string(&path)
+            .map_err(|e| warn!("failed to read {}: {}", path.display(), e))
+            .ok()?;
+        let uri = resolve::path_to_uri(&path)?;
+        let mut buf = Buffer::with_language_version(text, config.language_version);
+        buf.apply_definition_patterns(&config.definition_patterns);
+        let env = buf.env.exported();
+        let stage = if path.extension().is_some_and(|ext| ext == GENERIC_EXTENSION) {
+            None
+        } else {
+            Some(buf.buf_cst.stage())
+        };
+        Some(Self { kind, name: name.to_owned(), uri, env, stage })
+    }
 }
 
+/// Cst の範囲で文字列を切り出せなかったことを表すエラー。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SliceError {
+    /// 範囲の始まりのバイトオフセット。
+    pub start: usize,
+    /// 範囲の終わりのバイトオフセット。
+    pub end: usize,
+    /// 切り出そうとした文字列のバイト数。
+    pub len: usize,
+}
+
+impl std::fmt::Display for SliceError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "byte range {}..{} does not lie on character boundaries of a {}-byte text",
+            self.start, self.end, self.len
+        )
+    }
+}
+
+impl std::error::Error for SliceError {}
+
 /// Cst を格納した Buffer.
 #[derive(Debug, Clone)]
 pub struct BufferCst {
@@ -35,42 +182,311 @@ pub struct BufferCst {
     pub buffer: String,
     /// バッファの文法構造。
     cst: Option<Cst>,
+    /// パースし直すために行った修復。修復せずにパースできた場合は空。
+    recoveries: Vec<Recovery>,
 }
 
 impl Buffer {
     /// 与えられた文字列を消費し、新たな Buffer を作成する。
     pub fn new(text: String) -> Self {
-        let (text, e) = BufferCst::parse_into(text);
+        Self::with_language_version(text, LanguageVersion::default())
+    }
+
+    /// 与えられた文字列を `language_version` の文法でパースし、新たな Buffer を作成する。
+    pub fn with_language_version(text: String, language_version: LanguageVersion) -> Self {
+        let (text, e) = BufferCst::parse_with(text, language_version);
+        Self::from_parsed(Arc::new(text), e, language_version)
+    }
+
+    /// [`Buffer::with_size_guard`] と同じだが、同じ文字列を同じ版の文法でパースした結果が
+    /// `cache` にあれば、パースし直さずにそれを用いる。
+    pub fn with_parse_cache(
+        text: String,
+        max_size: usize,
+        language_version: LanguageVersion,
+        cache: &mut ParseCache,
+    ) -> Self {
+        if text.len() > max_size {
+            return Self::with_size_guard(text, max_size, language_version);
+        }
+        let (text, e) = cache.parse(text, language_version);
+        Self::from_parsed(text, e, language_version)
+    }
+
+    /// パースした結果から定義を集め、新たな Buffer を作成する。
+    fn from_parsed(text: Arc<BufferCst>, e: Option<Error>, language_version: LanguageVersion) -> Self {
         let error = e.into_iter().collect_vec();
         let env = Environment::new(&text);
 
-        Self { buf_cst: text, error, env }
+        Self {
+            buf_cst: text,
+            error,
+            env,
+            packages: vec![],
+            version: None,
+            language_version,
+            deferred: false,
+            last_parsed: None,
+        }
+    }
+
+    /// 与えられた文字列を消費し、新たな Buffer を作成する。
+    /// 文字列が `max_size` バイトを超える場合はパースせず、
+    /// [`Buffer::ensure_parsed`] が呼ばれるまで Cst や定義を持たない。
+    pub fn with_size_guard(
+        text: String,
+        max_size: usize,
+        language_version: LanguageVersion,
+    ) -> Self {
+        if text.len() <= max_size {
+            return Self::with_language_version(text, language_version);
+        }
+        let buf_cst = Arc::new(BufferCst { buffer: text, cst: None, recoveries: vec![] });
+        Self {
+            buf_cst,
+            error: vec![],
+            env: Environment::default(),
+            packages: vec![],
+            version: None,
+            language_version,
+            deferred: true,
+            last_parsed: None,
+        }
+    }
+
+    /// パースを後回しにしているか。
+    pub fn is_deferred(&self) -> bool {
+        self.deferred
+    }
+
+    /// パースを後回しにしていれば、パースしてパッケージを読み込む（[`Config::minimal_mode`] では読み込まない）。
+    /// パースした場合は true を返す。
+    pub fn ensure_parsed(&mut self, uri: &Url, config: &Config) -> bool {
+        if !self.deferred {
+            return false;
+        }
+        let text = std::mem::take(&mut Arc::make_mut(&mut self.buf_cst).buffer);
+        let version = self.version;
+        let last_parsed = self.last_parsed.take();
+        *self = Self::with_language_version(text, self.language_version);
+        self.version = version;
+        if self.buf_cst.cst.is_none() {
+            self.last_parsed = last_parsed;
+        }
+        self.apply_definition_patterns(&config.definition_patterns);
+        if !config.minimal_mode {
+            self.load_packages(uri, config);
+        }
+        true
     }
+
+    /// 設定が変わったときに、`uri` にあるこのバッファへ新しい設定を適用する。
+    /// 文法の版が変わっていればパースし直し、そうでなければ Cst はそのままで定義を集め直す。
+    /// パースを後回しにしているバッファは、パースするときに新しい設定を用いる。
+    pub fn reconfigure(&mut self, uri: &Url, config: &Config) {
+        if self.language_version != config.language_version {
+            let version = self.version;
+            let text = std::mem::take(&mut Arc::make_mut(&mut self.buf_cst).buffer);
+            *self = Self::with_size_guard(text, config.max_parse_size(), config.language_version);
+            self.version = version;
+        } else {
+            self.env = Environment::new(&self.buf_cst);
+            self.packages.clear();
+            if let Some(last_parsed) = &mut self.last_parsed {
+                last_parsed.reconfigure(uri, config);
+            }
+        }
+        if self.deferred {
+            return;
+        }
+        self.apply_definition_patterns(&config.definition_patterns);
+        if !config.minimal_mode {
+            self.load_packages(uri, config);
+        }
+    }
+
+    /// パースに失敗していたりパースを後回しにしていたりすれば、直前の版 `previous` から
+    /// 最後にパースに成功した版を引き継ぐ。
+    /// 書きかけの途中でも、補完などが直前の文法構造と環境を使えるようにするために用いる。
+    pub fn inherit_last_parsed(&mut self, mut previous: Buffer) {
+        if self.buf_cst.cst.is_some() {
+            return;
+        }
+        self.last_parsed = if previous.buf_cst.cst.is_some() {
+            previous.last_parsed = None;
+            Some(Box::new(previous))
+        } else {
+            previous.last_parsed.take()
+        };
+    }
+
+    /// 文字列本体と Cst、引き継いだ最後にパースに成功した版が占めるおおよそのバイト数。
+    pub fn memory_estimate(&self) -> usize {
+        let last_parsed = self.last_parsed.as_ref().map_or(0, |buf| buf.memory_estimate());
+        self.buf_cst.memory_estimate() + last_parsed
+    }
+
+    /// パースに成功していればこのバッファ自身を、失敗していれば最後にパースに成功した版を返す。
+    /// どちらもなければ None を返す。
+    pub fn latest_parsed(&self) -> Option<&Buffer> {
+        if self.buf_cst.cst.is_some() {
+            Some(self)
+        } else {
+            self.last_parsed.as_deref()
+        }
+    }
+
+    /// 与えられた位置のモードと、そのモードが続く範囲を返す。
+    /// エディタのプラグインなどが、カーソル位置に応じて挙動を変えるために用いる。
+    /// バッファのパースに失敗している場合は None を返す。
+    pub fn mode_at(&self, pos: &Position) -> Option<ModeRegion> {
+        let cst = self.buf_cst.cst.as_ref()?;
+        Some(cst.mode_region(pos))
+    }
+
+    /// 設定で与えられた定義の構文から、コマンドの定義を environment に加える。
+    pub fn apply_definition_patterns(&mut self, patterns: &[DefinitionPattern]) {
+        self.env.add_pattern_definitions(&self.buf_cst, patterns);
+    }
+
+    /// `uri` にあるこのバッファが読み込むパッケージを読み込む。
+    pub fn load_packages(&mut self, uri: &Url, config: &Config) {
+        let stage = self.buf_cst.stage();
+        self.packages = self
+            .buf_cst
+            .headers()
+            .into_iter()
+            .filter_map(|(kind, name)| Package::load(uri, kind, &name, stage, config))
+            .collect();
+    }
+}
+
+/// 修復したテキストをパースし、元のテキスト上の位置に戻した Cst と行った修復を返す。
+/// 修復したテキストがパースできなければ None を返す。
+fn recovered_cst(recovered: Recovered, rule: Rule) -> Option<(Cst, Vec<Recovery>)> {
+    let mut pairs = SatysfiParser::parse(rule, &recovered.text).ok()?;
+    let mut cst = Cst::new(pairs.next()?, &recovered.text);
+    if let Some((at, len)) = recovered.insertion {
+        cst.remove_insertion(u32::try_from(at).ok()?, u32::try_from(len).ok()?);
+    }
+    Some((cst, recovered.recoveries))
 }
 
 impl BufferCst {
     /// 与えられた文字列を消費し、新たな BufferCst を作成する。
     pub fn parse_into(buffer: String) -> (Self, Option<Error>) {
-        let pairs = SatysfiParser::parse(Rule::program, &buffer);
+        Self::parse_with(buffer, LanguageVersion::default())
+    }
+
+    /// 与えられた文字列を `language_version` の文法でパースし、新たな BufferCst を作成する。
+    pub fn parse_with(buffer: String, language_version: LanguageVersion) -> (Self, Option<Error>) {
+        if buffer.len() > MAX_BUFFER_SIZE {
+            let error = anyhow::anyhow!("buffer exceeds {} bytes and cannot be parsed", MAX_BUFFER_SIZE);
+            return (Self { buffer, cst: None, recoveries: vec![] }, Some(error));
+        }
+        let rule = language_version.program_rule();
+        let pairs = SatysfiParser::parse(rule, &buffer);
         match pairs {
             Ok(mut pairs) => {
                 let pair = pairs.next().unwrap();
-                let cst = Some(Cst::from(pair));
-                (Self { buffer, cst }, None)
+                let cst = Some(Cst::new(pair, &buffer));
+                (Self { buffer, cst, recoveries: vec![] }, None)
             }
             Err(e) => {
-                let error = Error::from(e);
-                (Self { buffer, cst: None }, Some(error))
+                // 書きかけの文などを修復してパースできれば、その結果を用いる。
+                // 修復しても構文エラーであることは変わらないので、元のエラーも返す。
+                let error = Some(Error::from(e));
+                match recover(&buffer, rule).and_then(|recovered| recovered_cst(recovered, rule)) {
+                    Some((cst, recoveries)) => (Self { buffer, cst: Some(cst), recoveries }, error),
+                    None => (Self { buffer, cst: None, recoveries: vec![] }, error),
+                }
             }
         }
     }
 
+    /// バッファの文法構造を返す。パースに失敗していれば None を返す。
+    pub fn cst(&self) -> Option<&Cst> {
+        self.cst.as_ref()
+    }
+
+    /// パースし直すために行った修復を返す。
+    pub fn recoveries(&self) -> &[Recovery] {
+        &self.recoveries
+    }
+
+    /// ヘッダに書かれたパッケージの読み込み方法と名前を列挙する。
+    /// 0.1 系の `use` はパッケージの探し方が異なるため列挙しない。
+    pub fn headers(&self) -> Vec<(PackageKind, String)> {
+        let cst = match &self.cst {
+            Some(cst) => cst,
+            None => return vec![],
+        };
+        cst.pickup(Rule::header)
+            .into_iter()
+            .filter_map(|header| {
+                let kind = header.inner.iter().find(|c| c.rule == Rule::header_kind)?;
+                let name = header.inner.iter().find(|c| c.rule == Rule::pkgname)?;
+                let kind = match self.as_str(kind) {
+                    "require" => PackageKind::Require,
+                    "import" => PackageKind::Import,
+                    _ => return None,
+                };
+                Some((kind, self.as_str(name).trim_end().to_owned()))
+            })
+            .collect()
+    }
+
+    /// `@stage:` ヘッダで指定されたステージを返す。ヘッダがなければステージ 1 とみなす。
+    pub fn stage(&self) -> Stage {
+        self.cst
+            .as_ref()
+            .and_then(|cst| cst.pickup(Rule::stage).first().copied())
+            .and_then(|stage| Stage::from_header(self.as_str(stage)))
+            .unwrap_or_default()
+    }
+
     /// Cst の示す部分文字列を返す。
-    /// Cst の range が UTF-8 として正しい文字列であることを前提とする
-    /// （そうなっていなければ panic する）。
+    /// Cst の範囲がバッファからはみ出していたり文字の途中を指していたりしても panic せず、
+    /// 文字の境界に丸めた範囲の文字列を返す（[`Cst::as_str`] を参照）。
     pub fn as_str(&self, cst: &Cst) -> &str {
         cst.as_str(&self.buffer)
     }
+
+    /// コマンド名と変数名の、定義箇所も含めた出現回数を返す。パースに失敗している場合は空となる。
+    pub(crate) fn name_occurrences(&self) -> HashMap<&str, usize> {
+        let mut occurrences: HashMap<&str, usize> = HashMap::new();
+        let cst = match &self.cst {
+            Some(cst) => cst,
+            None => return occurrences,
+        };
+        for rule in &[
+            Rule::inline_cmd_name,
+            Rule::block_cmd_name,
+            Rule::math_cmd_name,
+            Rule::var,
+        ] {
+            for name in cst.pickup(*rule) {
+                *occurrences.entry(self.as_str(name)).or_default() += 1;
+            }
+        }
+        occurrences
+    }
+
+    /// Cst を JSON 形式の構造体に変換する。パースに失敗している場合は None を返す。
+    pub fn to_json(&self) -> Option<CstJson> {
+        self.cst.as_ref().map(|cst| cst.to_json(&self.buffer))
+    }
+
+    /// バッファに対する LineIndex を作る。
+    pub fn line_index(&self) -> LineIndex<'_> {
+        LineIndex::new(&self.buffer)
+    }
+
+    /// 文字列本体と Cst の節点が占めるおおよそのバイト数。
+    pub fn memory_estimate(&self) -> usize {
+        let nodes = self.cst.as_ref().map_or(0, Cst::node_count);
+        self.buffer.capacity() + nodes * std::mem::size_of::<Cst>()
+    }
 }
 
 impl std::fmt::Display for BufferCst {
@@ -91,6 +507,9 @@ impl std::fmt::Display for BufferCst {
 
 /// 参照をなくして BufferCst が自己参照構造体になることを回避した
 /// pest::iterators::Pair 的なもの。再帰構造を持つ。
+///
+/// 開いているファイルや索引化したファイルの数だけ保持されるため、節点は小さく保つ。
+/// テキストは持たずに範囲だけを持ち、子は余分な容量を持たない boxed slice に詰める。
 #[derive(Debug, Clone)]
 pub struct Cst {
     /// そのルールが何であるか。
@@ -98,22 +517,72 @@ pub struct Cst {
     /// Cst が表す範囲。
     range: CstRange,
     /// 子 Cst。
-    inner: Vec<Cst>,
+    inner: Box<[Cst]>,
 }
 
-impl<'a> From<Pair<'a>> for Cst {
-    fn from(pair: Pair<'a>) -> Self {
+/// ルールで検索したり、ある位置を含む Pair を探索したりできるもの。
+impl Cst {
+    /// `text` をパースして得た Pair から Cst を作る。
+    /// 各位置の行と列は `text` の [`LineIndex`] から求める。
+    pub fn new(pair: Pair<'_>, text: &str) -> Self {
+        Self::with_index(pair, &LineIndex::new(text))
+    }
+
+    fn with_index(pair: Pair<'_>, index: &LineIndex<'_>) -> Self {
         let rule = pair.as_rule();
-        let range = CstRange::from(pair.as_span());
-        let inner = pair.into_inner().map(Cst::from).collect_vec();
+        let range = CstRange::new(pair.as_span(), index);
+        let inner = pair.into_inner().map(|pair| Cst::with_index(pair, index)).collect();
         Self { rule, range, inner }
     }
-}
 
-/// ルールで検索したり、ある位置を含む Pair を探索したりできるもの。
-impl Cst {
+    /// `at` バイト目に `len` バイトの ASCII 文字列を挿入したテキストから作った Cst の位置を、
+    /// 挿入前のテキストでの位置に戻す。挿入は行末に行われたものとする。
+    fn remove_insertion(&mut self, at: u32, len: u32) {
+        for pos in [&mut self.range.start, &mut self.range.end] {
+            if pos.byte >= at + len {
+                pos.byte -= len;
+            } else if pos.byte > at {
+                pos.character -= pos.byte - at;
+                pos.byte = at;
+            }
+        }
+        for cst in &mut self.inner {
+            cst.remove_insertion(at, len);
+        }
+    }
+
+    /// テキストの途中から切り出した部分をパースした Cst の位置を、元のテキストでの位置に直す。
+    /// 切り出した部分は元のテキストの `byte` バイト目、`line` 行目の `character` 文字目から始まる。
+    fn shift(&mut self, byte: u32, line: u32, character: u32) {
+        for pos in [&mut self.range.start, &mut self.range.end] {
+            if pos.line == 0 {
+                pos.character += character;
+            }
+            pos.byte += byte;
+            pos.line += line;
+        }
+        for cst in &mut self.inner {
+            cst.shift(byte, line, character);
+        }
+    }
+
+    /// そのルールが何であるか。
+    pub fn rule(&self) -> Rule {
+        self.rule
+    }
+
+    /// Cst が表す範囲。
+    pub fn range(&self) -> Range {
+        self.range.clone().into()
+    }
+
+    /// 子 Cst.
+    pub fn children(&self) -> &[Cst] {
+        &self.inner
+    }
+
     /// 与えられたルールの Cst を再帰的に抽出する。
-    fn pickup(&self, rule: Rule) -> Vec<&Cst> {
+    pub fn pickup(&self, rule: Rule) -> Vec<&Cst> {
         let mut vec = vec![];
         for cst in &self.inner {
             if cst.rule == rule {
@@ -125,18 +594,19 @@ impl Cst {
         vec
     }
 
+    /// 自身を含む節点の数。
+    fn node_count(&self) -> usize {
+        1 + self.inner.iter().map(Cst::node_count).sum::<usize>()
+    }
+
     /// 自分の子のうち、与えられた pos を含むものを返す。
     fn choose(&self, pos: &Position) -> Option<&Cst> {
-        for cst in &self.inner {
-            if cst.range.includes(pos) {
-                return Some(cst);
-            }
-        }
-        None
+        self.inner.iter().find(|cst| cst.range.includes(pos))
     }
 
     /// 与えられた pos を含む Pair を再帰的に探索する。
-    fn dig(&self, pos: &Position) -> Vec<&Cst> {
+    /// 内側にある Cst から順に並べて返す。
+    pub fn dig(&self, pos: &Position) -> Vec<&Cst> {
         let child = self.choose(pos);
         if let Some(child) = child {
             let mut v = child.dig(pos);
@@ -149,7 +619,7 @@ impl Cst {
 
     /// Cst の構造を箇条書き形式で出力する。
     fn pretty_text(&self, text: &str, indent: usize) -> String {
-        let content = if self.inner.len() == 0 {
+        let content = if self.inner.is_empty() {
             format!(
                 "| [{rule:?}] ({sl}:{sc}..{el}:{ec}): \"{text}\"\n",
                 rule = self.rule,
@@ -177,35 +647,105 @@ impl Cst {
         format!("{indent}{content}", indent = " ".repeat(indent), content = content)
     }
 
-    fn as_str<'a>(&self, text: &'a str) -> &'a str {
-        let start = self.range.start.byte;
-        let end = self.range.end.byte;
-        std::str::from_utf8(&text.as_bytes()[start..end]).unwrap()
+    /// Cst を、外部のツールが読み込める JSON 形式の構造体に変換する。
+    /// `text` は Cst を作った元の文字列。
+    pub fn to_json(&self, text: &str) -> CstJson {
+        let children = self.inner.iter().map(|cst| cst.to_json(text)).collect_vec();
+        // 子を持つ Cst の文字列は子から復元できるので、葉にのみ持たせる。
+        let text = if children.is_empty() {
+            Some(self.as_str(text).to_owned())
+        } else {
+            None
+        };
+        CstJson {
+            rule: format!("{:?}", self.rule),
+            range: self.range.clone().into(),
+            byte_range: (
+                self.range.start.byte as usize,
+                self.range.end.byte as usize,
+            ),
+            text,
+            children,
+        }
+    }
+
+    /// Cst が表す範囲の文字列。`text` は Cst を作った元の文字列。
+    /// 範囲が text からはみ出していたり文字の途中を指していたりすれば、
+    /// 両端をそれより前の文字の境界に丸めた範囲の文字列を返す。
+    pub fn as_str<'a>(&self, text: &'a str) -> &'a str {
+        self.try_as_str(text).unwrap_or_else(|err| {
+            warn!("{}", err);
+            let start = floor_char_boundary(text, err.start);
+            let end = floor_char_boundary(text, err.end).max(start);
+            &text[start..end]
+        })
+    }
+
+    /// Cst が表す範囲の文字列。`text` は Cst を作った元の文字列。
+    /// 範囲が text からはみ出していたり文字の途中を指していたりすれば Err を返す。
+    pub fn try_as_str<'a>(&self, text: &'a str) -> Result<&'a str, SliceError> {
+        let start = self.range.start.byte as usize;
+        let end = self.range.end.byte as usize;
+        text.get(start..end).ok_or(SliceError { start, end, len: text.len() })
     }
 
     fn mode(&self, pos: &Position) -> Mode {
-        let csts = self.dig(pos);
-        let rules = csts.iter().map(|cst| cst.rule);
-
-        for rule in rules {
-            match rule {
-                Rule::vertical_mode => return Mode::Vertical,
-                Rule::horizontal_mode => return Mode::Horizontal,
-                Rule::math_mode => return Mode::Math,
-                Rule::headers | Rule::header_stage => return Mode::Header,
-                Rule::COMMENT => return Mode::Comment,
-                Rule::string_interior => return Mode::Literal,
-                Rule::cmd_expr_arg
+        self.mode_region(pos).mode
+    }
+
+    /// 与えられた pos におけるモードと、そのモードが続く範囲を返す。
+    /// どのモードの領域にも含まれない場合は、Cst 全体をプログラムモードとみなす。
+    fn mode_region(&self, pos: &Position) -> ModeRegion {
+        for cst in self.dig(pos) {
+            let mode = match cst.rule {
+                Rule::vertical_mode => Mode::Vertical,
+                Rule::horizontal_mode => Mode::Horizontal,
+                Rule::math_mode => Mode::Math,
+                Rule::headers | Rule::headers_next | Rule::header_stage => Mode::Header,
+                Rule::COMMENT => Mode::Comment,
+                Rule::string_interior => Mode::Literal,
+                // 数式コマンドの `!{...}` や `!<...>` の中ではテキストのモードに戻る。
+                // 括弧の内側の空白は中身の Cst に含まれないため、引数の中身から判断する。
+                Rule::math_cmd_expr_arg => match cst.inner.first().map(|inner| inner.rule) {
+                    Some(Rule::horizontal_mode) => Mode::Horizontal,
+                    Some(Rule::vertical_mode) => Mode::Vertical,
+                    Some(Rule::math_mode) => Mode::Math,
+                    _ => Mode::Program,
+                },
+                Rule::string_interpolation
+                | Rule::cmd_expr_arg
                 | Rule::cmd_expr_option
-                | Rule::math_cmd_expr_arg
-                | Rule::math_cmd_expr_option => return Mode::Program,
+                | Rule::math_cmd_expr_option => Mode::Program,
                 _ => continue,
-            }
+            };
+            let range = cst.range.clone().into();
+            return ModeRegion { mode, range };
+        }
+        ModeRegion {
+            mode: Mode::Program,
+            range: self.range.clone().into(),
         }
-        Mode::Program
     }
 }
 
+/// JSON に直列化するための Cst の表現。
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CstJson {
+    /// ルール名。
+    pub rule: String,
+    /// Cst が表す範囲。
+    pub range: Range,
+    /// Cst が表す範囲のバイトオフセット。
+    pub byte_range: (usize, usize),
+    /// Cst の示す部分文字列。子を持たない場合のみ存在する。
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    /// 子 Cst。
+    pub children: Vec<CstJson>,
+}
+
+/// Cst が表す範囲。
 #[derive(Debug, Clone)]
 pub struct CstRange {
     /// 始まりの位置。
@@ -214,19 +754,19 @@ pub struct CstRange {
     end: CstPosition,
 }
 
-impl<'a> From<Span<'a>> for CstRange {
-    fn from(span: Span<'a>) -> Self {
-        let start = CstPosition::from(span.start_pos());
-        let end = CstPosition::from(span.end_pos());
+impl CstRange {
+    fn new(span: Span<'_>, index: &LineIndex<'_>) -> Self {
+        let start = CstPosition::new(span.start(), index);
+        let end = CstPosition::new(span.end(), index);
         Self { start, end }
     }
 }
 
-impl Into<lsp_types::Range> for CstRange {
-    fn into(self) -> lsp_types::Range {
-        lsp_types::Range {
-            start: self.start.into(),
-            end: self.end.into(),
+impl From<CstRange> for Range {
+    fn from(range: CstRange) -> Self {
+        Range {
+            start: range.start.into(),
+            end: range.end.into(),
         }
     }
 }
@@ -239,22 +779,27 @@ impl CstRange {
     }
 }
 
+/// パースするテキストの最大バイト数。
+/// Cst の位置を 32 bit に収めるため、これより大きなテキストはパースしない。
+/// 修復や補完のために数バイトを補ってパースすることがあるので、`u32::MAX` より十分に小さくする。
+pub const MAX_BUFFER_SIZE: usize = (u32::MAX / 2) as usize;
+
+/// Cst における位置。
+/// パースするテキストは [`MAX_BUFFER_SIZE`] を超えないので、いずれも 32 bit に収める。
 #[derive(Debug, Clone)]
 pub struct CstPosition {
     /// スタートから何バイト目にあるか。
-    byte: usize,
+    byte: u32,
     /// 何行目にあるか。
     line: u32,
     /// その行の何文字目にあるか。
     character: u32,
 }
 
-impl<'a> From<pest::Position<'a>> for CstPosition {
-    fn from(pos: pest::Position<'a>) -> Self {
-        let byte = pos.pos();
-        let (line, character) = pos.line_col();
-        let line = (line - 1) as u32;
-        let character = (character - 1) as u32;
+impl CstPosition {
+    fn new(byte: usize, index: &LineIndex<'_>) -> Self {
+        let Position { line, character } = index.position(byte);
+        let byte = u32::try_from(byte).expect("texts larger than MAX_BUFFER_SIZE must not be parsed");
         Self {
             byte,
             line,
@@ -263,11 +808,11 @@ impl<'a> From<pest::Position<'a>> for CstPosition {
     }
 }
 
-impl Into<lsp_types::Position> for CstPosition {
-    fn into(self) -> lsp_types::Position {
-        lsp_types::Position {
-            line: self.line,
-            character: self.character,
+impl From<CstPosition> for Position {
+    fn from(pos: CstPosition) -> Self {
+        Position {
+            line: pos.line,
+            character: pos.character,
         }
     }
 }
@@ -292,7 +837,7 @@ impl Buffers {
 }
 
 /// 定義済みのコマンドなど。
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone)]
 pub struct Environment {
     /// インラインコマンド
     inline_cmds: Vec<InlineCmd>,
@@ -302,6 +847,8 @@ pub struct Environment {
     math_cmds: Vec<MathCmd>,
     /// let 式で定義された変数
     variables: Vec<Variable>,
+    /// type 文で定義された型
+    types: Vec<TypeDef>,
 }
 
 impl Environment {
@@ -320,13 +867,18 @@ impl Environment {
                             // let-inline \cmd の形
                             let name = text.as_str(fst).to_owned();
                             let def_range = fst.range.clone().into();
-                            InlineCmd {name, def_range}
+                            // 名前の後ろには引数が並び、最後に本体の式がある。
+                            let (arity, optional_arity) = command_arity(children.as_slice());
+                            let param_kinds = command_param_kinds(text, children.as_slice());
+                            InlineCmd {name, def_range, stmt_range: cst.range.clone().into(), visibility: Visibility::Public, arity, optional_arity, param_kinds}
                         } else {
                             // let-inline ctx \cmd の形
                             let scd = children.next().unwrap();
                             let name = text.as_str(scd).to_owned();
                             let def_range = scd.range.clone().into();
-                            InlineCmd {name, def_range}
+                            let (arity, optional_arity) = command_arity(children.as_slice());
+                            let param_kinds = command_param_kinds(text, children.as_slice());
+                            InlineCmd {name, def_range, stmt_range: cst.range.clone().into(), visibility: Visibility::Public, arity, optional_arity, param_kinds}
                         }
                     })
                     .collect_vec();
@@ -341,13 +893,17 @@ impl Environment {
                                 // let-block +cmd の形
                             let name = text.as_str(fst).to_owned();
                             let def_range = fst.range.clone().into();
-                            BlockCmd {name, def_range}
+                            let (arity, optional_arity) = command_arity(children.as_slice());
+                            let param_kinds = command_param_kinds(text, children.as_slice());
+                            BlockCmd {name, def_range, stmt_range: cst.range.clone().into(), visibility: Visibility::Public, arity, optional_arity, param_kinds}
                             } else {
                                 // let-block ctx +cmd の形
                                 let scd = children.next().unwrap();
                                 let name = text.as_str(scd).to_owned();
                                 let def_range = scd.range.clone().into();
-                                BlockCmd {name, def_range}
+                                let (arity, optional_arity) = command_arity(children.as_slice());
+                                let param_kinds = command_param_kinds(text, children.as_slice());
+                                BlockCmd {name, def_range, stmt_range: cst.range.clone().into(), visibility: Visibility::Public, arity, optional_arity, param_kinds}
                             }
                     })
                     .collect_vec();
@@ -360,63 +916,843 @@ impl Environment {
                         let fst = children.next().unwrap();
                         let name = text.as_str(fst).to_owned();
                         let def_range = fst.range.clone().into();
-                        MathCmd { name, def_range }
+                        let (arity, optional_arity) = command_arity(children.as_slice());
+                        MathCmd { name, def_range, stmt_range: cst.range.clone().into(), visibility: Visibility::Public, arity, optional_arity }
                     })
                     .collect_vec();
 
+                // let ... in で束縛される局所的な変数は scope モジュールで扱うため、
+                // ここではプリアンブルやモジュールの文として定義されたものだけを集める。
                 let variables = cst
-                    .pickup(Rule::let_stmt)
+                    .pickup(Rule::statement)
                     .into_iter()
-                    .map(|cst| {
+                    .filter_map(|stmt| stmt.inner.first().filter(|cst| cst.rule == Rule::let_stmt))
+                    .flat_map(|cst| {
                         let mut children = cst.inner.iter();
                         let ptn = children.next().unwrap();
-                        ptn.pickup(Rule::var).into_iter().map(|cst| {
+                        let value = simple_literal_value(text, cst);
+                        let annotation = type_annotation(text, cst);
+                        let stmt_range: Range = cst.range.clone().into();
+                        ptn.pickup(Rule::var).into_iter().map(move |cst| {
                             let name = text.as_str(cst).to_owned();
                             let def_range = cst.range.clone().into();
-                            Variable{ name, def_range }
+                            let value = value.clone();
+                            let annotation = annotation.clone();
+                            Variable{ name, def_range, stmt_range, visibility: Visibility::Public, value, annotation }
                         })
-                    }).flatten()
+                    })
                 .collect_vec();
 
-                Self { inline_cmds, block_cmds, math_cmds, variables }
+                let types = cst
+                    .pickup(Rule::statement)
+                    .into_iter()
+                    .filter_map(|stmt| stmt.inner.first().filter(|cst| cst.rule == Rule::type_stmt))
+                    .filter_map(|stmt| {
+                        let name = stmt.inner.iter().find(|c| c.rule == Rule::type_name)?;
+                        let constructors = stmt
+                            .pickup(Rule::type_variant)
+                            .into_iter()
+                            .filter_map(|variant| {
+                                let name = variant.inner.first()?;
+                                Some(Constructor {
+                                    name: text.as_str(name).to_owned(),
+                                    def_range: name.range.clone().into(),
+                                    has_argument: variant.inner.len() > 1,
+                                })
+                            })
+                            .collect_vec();
+                        let record = stmt.inner.last().and_then(record_type);
+                        let fields = record
+                            .map(|record| {
+                                record
+                                    .inner
+                                    .iter()
+                                    .filter_map(|unit| unit.inner.first())
+                                    .map(|name| TypeField {
+                                        name: text.as_str(name).to_owned(),
+                                        def_range: name.range.clone().into(),
+                                    })
+                                    .collect()
+                            })
+                            .unwrap_or_default();
+                        let kind = if !constructors.is_empty() {
+                            TypeDefKind::Variant
+                        } else if record.is_some() {
+                            TypeDefKind::Record
+                        } else {
+                            TypeDefKind::Alias
+                        };
+                        Some(TypeDef {
+                            name: text.as_str(name).to_owned(),
+                            def_range: name.range.clone().into(),
+                            stmt_range: stmt.range.clone().into(),
+                            visibility: Visibility::Public,
+                            kind,
+                            constructors,
+                            fields,
+                        })
+                    })
+                    .collect_vec();
+
+                let mut env = Self { inline_cmds, block_cmds, math_cmds, variables, types };
+
+                // 外側のモジュールから順に処理し、内側のモジュールの可視性で上書きする。
+                let modules = cst.pickup(Rule::module_stmt);
+                for module in &modules {
+                    env.apply_module_visibility(text, module, &modules);
+                }
+                env
+            }
+        }
+
+    }
+
+    /// module_stmt の signature を読み、struct 内の定義の可視性を設定する。
+    /// 入れ子のモジュールの名前は、`modules` のうちそれを囲むものの名前で外側から順に修飾する。
+    fn apply_module_visibility(&mut self, text: &BufferCst, module: &Cst, modules: &[&Cst]) {
+        if !matches!(module.inner.first(), Some(cst) if cst.rule == Rule::module_name) {
+            return;
+        }
+        let module_range: Range = module.range.clone().into();
+        let module_name = modules
+            .iter()
+            .filter(|outer| Range::from(outer.range.clone()).includes(&module_range))
+            .filter_map(|outer| outer.inner.first().filter(|cst| cst.rule == Rule::module_name))
+            .map(|name| text.as_str(name))
+            .join(".");
+        let body = match module.inner.iter().find(|cst| cst.rule == Rule::struct_stmt) {
+            Some(body) => body,
+            None => return,
+        };
+        let body_range: Range = body.range.clone().into();
+
+        // signature で公開されている名前（コマンドの種類と組にしたもの）と、それが direct かどうか。
+        // signature がなければすべての定義が公開される。
+        let exports: Option<HashMap<(Option<CmdKind>, String), bool>> = module
+            .inner
+            .iter()
+            .find(|cst| cst.rule == Rule::sig_stmt)
+            .map(|sig| {
+                let vals = sig.pickup(Rule::sig_val_stmt).into_iter().map(|s| (s, false));
+                let directs = sig.pickup(Rule::sig_direct_stmt).into_iter().map(|s| (s, true));
+                vals.chain(directs)
+                    .filter_map(|(stmt, direct)| {
+                        let (kind, name) = signature_entry(text, stmt)?;
+                        Some(((kind, name.to_owned()), direct))
+                    })
+                    .collect()
+            });
+
+        // signature に書かれたコマンドの型から求めた引数の種類。
+        let signatures: HashMap<(CmdKind, String), Vec<ParamKind>> = module
+            .inner
+            .iter()
+            .filter(|cst| cst.rule == Rule::sig_stmt)
+            .flat_map(|sig| {
+                let vals = sig.pickup(Rule::sig_val_stmt);
+                vals.into_iter().chain(sig.pickup(Rule::sig_direct_stmt))
+            })
+            .filter_map(|stmt| {
+                let (kind, name) = signature_entry(text, stmt)?;
+                let kind = kind?;
+                let ty = stmt.inner.iter().find(|c| c.rule == Rule::type_expr)?;
+                Some(((kind, name.to_owned()), signature_param_kinds(text, ty)?))
+            })
+            .collect();
+
+        // signature で公開されている型の名前。
+        let type_exports: Option<HashSet<String>> = module
+            .inner
+            .iter()
+            .find(|cst| cst.rule == Rule::sig_stmt)
+            .map(|sig| {
+                sig.pickup(Rule::sig_type_stmt)
+                    .into_iter()
+                    .filter_map(|stmt| stmt.inner.iter().find(|cst| cst.rule == Rule::var))
+                    .map(|name| text.as_str(name).to_owned())
+                    .collect()
+            });
+
+        let visibility = |kind: Option<CmdKind>, name: &str, def_range: &Range| {
+            if !body_range.includes(def_range) {
+                return None;
+            }
+            let key = (kind, name.to_owned());
+            let visibility = match exports.as_ref().map(|exports| exports.get(&key)) {
+                None | Some(Some(false)) => Visibility::Qualified(module_name.clone()),
+                Some(Some(true)) => Visibility::Public,
+                Some(None) => Visibility::Private,
+            };
+            Some(visibility)
+        };
+
+        for cmd in &mut self.inline_cmds {
+            if let Some(v) = visibility(Some(CmdKind::Inline), &cmd.name, &cmd.def_range) {
+                cmd.visibility = v;
+                if let Some(kinds) = signatures.get(&(CmdKind::Inline, cmd.name.clone())) {
+                    cmd.param_kinds = kinds.clone();
+                }
+            }
+        }
+        for cmd in &mut self.block_cmds {
+            if let Some(v) = visibility(Some(CmdKind::Block), &cmd.name, &cmd.def_range) {
+                cmd.visibility = v;
+                if let Some(kinds) = signatures.get(&(CmdKind::Block, cmd.name.clone())) {
+                    cmd.param_kinds = kinds.clone();
+                }
+            }
+        }
+        for cmd in &mut self.math_cmds {
+            if let Some(v) = visibility(Some(CmdKind::Math), &cmd.name, &cmd.def_range) {
+                cmd.visibility = v;
+            }
+        }
+        for var in &mut self.variables {
+            if let Some(v) = visibility(None, &var.name, &var.def_range) {
+                var.visibility = v;
+            }
+        }
+        // 型は direct で公開できないため、公開されていればモジュール名で修飾して見える。
+        for ty in &mut self.types {
+            if !body_range.includes(&ty.def_range) {
+                continue;
+            }
+            ty.visibility = match &type_exports {
+                Some(names) if !names.contains(&ty.name) => Visibility::Private,
+                _ => Visibility::Qualified(module_name.clone()),
+            };
+        }
+    }
+
+    /// 与えられた種類のコマンドを、定義された順に返す。
+    pub fn commands(&self, kind: CmdKind) -> impl Iterator<Item = CommandDef<'_>> {
+        let inline = self
+            .inline_cmds
+            .iter()
+            .filter(move |_| kind == CmdKind::Inline)
+            .map(|c| CommandDef {
+                kind: CmdKind::Inline,
+                name: &c.name,
+                def_range: c.def_range,
+                stmt_range: c.stmt_range,
+                visibility: &c.visibility,
+                arity: c.arity,
+                optional_arity: c.optional_arity,
+                param_kinds: &c.param_kinds,
+            });
+        let block = self
+            .block_cmds
+            .iter()
+            .filter(move |_| kind == CmdKind::Block)
+            .map(|c| CommandDef {
+                kind: CmdKind::Block,
+                name: &c.name,
+                def_range: c.def_range,
+                stmt_range: c.stmt_range,
+                visibility: &c.visibility,
+                arity: c.arity,
+                optional_arity: c.optional_arity,
+                param_kinds: &c.param_kinds,
+            });
+        let math = self
+            .math_cmds
+            .iter()
+            .filter(move |_| kind == CmdKind::Math)
+            .map(|c| CommandDef {
+                kind: CmdKind::Math,
+                name: &c.name,
+                def_range: c.def_range,
+                stmt_range: c.stmt_range,
+                visibility: &c.visibility,
+                arity: c.arity,
+                optional_arity: c.optional_arity,
+                param_kinds: &[],
+            });
+        inline.chain(block).chain(math)
+    }
+
+    /// 与えられた種類のコマンドのうち、名前が `prefix` で始まるものを定義された順に返す。
+    pub fn commands_with_prefix<'a>(
+        &'a self,
+        kind: CmdKind,
+        prefix: &'a str,
+    ) -> impl Iterator<Item = CommandDef<'a>> {
+        self.commands(kind).filter(move |c| c.name.starts_with(prefix))
+    }
+
+    /// 与えられた種類と名前のコマンドを探す。同じ名前の定義が複数あれば最後のものを返す。
+    pub fn lookup(&self, kind: CmdKind, name: &str) -> Option<CommandDef<'_>> {
+        self.commands(kind).filter(|c| c.name == name).last()
+    }
+
+    /// let 文で定義された変数を、定義された順に返す。
+    pub fn variables(&self) -> &[Variable] {
+        &self.variables
+    }
+
+    /// 与えられた名前の変数を探す。同じ名前の定義が複数あれば最後のものを返す。
+    pub fn variable(&self, name: &str) -> Option<&Variable> {
+        self.variables.iter().rfind(|v| v.name == name)
+    }
+
+    /// 与えられた位置から参照できる変数、すなわち定義する文がその位置より前で終わる変数を返す。
+    /// この environment を作ったバッファの中の位置を与える。
+    pub fn visible_at(&self, pos: &Position) -> impl Iterator<Item = &Variable> + '_ {
+        let pos = *pos;
+        self.variables.iter().filter(move |v| v.stmt_range.end <= pos)
+    }
+
+    /// type 文で定義された型を、定義された順に返す。
+    pub fn types(&self) -> &[TypeDef] {
+        &self.types
+    }
+
+    /// 与えられた名前の型を探す。同じ名前の定義が複数あれば最後のものを返す。
+    pub fn type_def(&self, name: &str) -> Option<&TypeDef> {
+        self.types.iter().rfind(|ty| ty.name == name)
+    }
+
+    /// 与えられた名前のコンストラクタと、それを持つ型を探す。
+    /// 同じ名前のコンストラクタが複数あれば最後に定義された型のものを返す。
+    pub fn constructor(&self, name: &str) -> Option<(&TypeDef, &Constructor)> {
+        self.types
+            .iter()
+            .rev()
+            .find_map(|ty| Some((ty, ty.constructors.iter().find(|c| c.name == name)?)))
+    }
+
+    /// `patterns` の構文で定義されたコマンドを加える。
+    /// 名前にあたる子がその種類のコマンド名として正しくないものは無視する。
+    /// 引数の数は、名前より後ろの子を let-inline と同じく引数と本体の式とみなして求める。
+    fn add_pattern_definitions(&mut self, text: &BufferCst, patterns: &[DefinitionPattern]) {
+        let cst = match &text.cst {
+            Some(cst) => cst,
+            None => return,
+        };
+        for pattern in patterns {
+            for node in cst.pickup(pattern.rule) {
+                let name_cst = match node.inner.get(pattern.child) {
+                    Some(name_cst) => name_cst,
+                    None => continue,
+                };
+                let name = text.as_str(name_cst).to_owned();
+                if validate_ident(pattern.kind.ident_kind(), &name).is_err() {
+                    continue;
+                }
+                let def_range = name_cst.range.clone().into();
+                let stmt_range = node.range.clone().into();
+                let visibility = Visibility::Public;
+                let rest = &node.inner[pattern.child + 1..];
+                let (arity, optional_arity) = command_arity(rest);
+                match pattern.kind {
+                    CmdKind::Inline => {
+                        let param_kinds = command_param_kinds(text, rest);
+                        self.inline_cmds.push(InlineCmd {name, def_range, stmt_range, visibility, arity, optional_arity, param_kinds});
+                    }
+                    CmdKind::Block => {
+                        let param_kinds = command_param_kinds(text, rest);
+                        self.block_cmds.push(BlockCmd {name, def_range, stmt_range, visibility, arity, optional_arity, param_kinds});
+                    }
+                    CmdKind::Math => {
+                        self.math_cmds.push(MathCmd {name, def_range, stmt_range, visibility, arity, optional_arity});
+                    }
+                }
             }
         }
+    }
 
+    /// パッケージの外から見える定義のみを、外から見たときの名前で集めた environment を返す。
+    pub fn exported(&self) -> Environment {
+        let inline_cmds = self
+            .inline_cmds
+            .iter()
+            .filter_map(|cmd| {
+                let name = cmd.visibility.exported_name(&cmd.name)?;
+                Some(InlineCmd { name, ..cmd.clone() })
+            })
+            .collect();
+        let block_cmds = self
+            .block_cmds
+            .iter()
+            .filter_map(|cmd| {
+                let name = cmd.visibility.exported_name(&cmd.name)?;
+                Some(BlockCmd { name, ..cmd.clone() })
+            })
+            .collect();
+        let math_cmds = self
+            .math_cmds
+            .iter()
+            .filter_map(|cmd| {
+                let name = cmd.visibility.exported_name(&cmd.name)?;
+                Some(MathCmd { name, ..cmd.clone() })
+            })
+            .collect();
+        let variables = self
+            .variables
+            .iter()
+            .filter_map(|var| {
+                let name = var.visibility.exported_name(&var.name)?;
+                Some(Variable { name, ..var.clone() })
+            })
+            .collect();
+        let types = self
+            .types
+            .iter()
+            .filter_map(|ty| {
+                let name = ty.visibility.exported_name(&ty.name)?;
+                Some(TypeDef { name, ..ty.clone() })
+            })
+            .collect();
+        Self { inline_cmds, block_cmds, math_cmds, variables, types }
     }
 }
 
+/// 定義がパッケージの外からどのように見えるか。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Visibility {
+    /// そのままの名前で見える。トップレベルの定義や direct で公開されたコマンド。
+    Public,
+    /// 与えられたモジュール名で修飾した名前で見える。
+    Qualified(String),
+    /// 定義されたパッケージの中でのみ見える。
+    Private,
+}
+
+impl Visibility {
+    /// 定義名 name をパッケージの外から参照するときの名前。外から見えなければ None.
+    fn exported_name(&self, name: &str) -> Option<String> {
+        match self {
+            Visibility::Public => Some(name.to_owned()),
+            Visibility::Qualified(module) => {
+                // \cmd や +cmd は \Module.cmd や +Module.cmd の形になる。
+                let (sigil, body) = match name.chars().next() {
+                    Some(c @ '\\') | Some(c @ '+') => name.split_at(c.len_utf8()),
+                    _ => ("", name),
+                };
+                Some(format!("{}{}.{}", sigil, module, body))
+            }
+            Visibility::Private => None,
+        }
+    }
+}
+
+/// コマンドの種類。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CmdKind {
+    /// インラインコマンド。
+    Inline,
+    /// ブロックコマンド。
+    Block,
+    /// 数式コマンド。
+    Math,
+}
+
+impl CmdKind {
+    /// コマンドの使用箇所 (`inline_cmd` など) またはコマンド名 (`inline_cmd_name` など)
+    /// の構文規則から、コマンドの種類を得る。
+    pub fn from_rule(rule: Rule) -> Option<Self> {
+        match rule {
+            Rule::inline_cmd | Rule::inline_cmd_name => Some(CmdKind::Inline),
+            Rule::block_cmd | Rule::block_cmd_name => Some(CmdKind::Block),
+            Rule::math_cmd | Rule::math_cmd_name => Some(CmdKind::Math),
+            _ => None,
+        }
+    }
+
+    /// 名前の検査に用いる種類。
+    fn ident_kind(self) -> IdentKind {
+        match self {
+            CmdKind::Inline => IdentKind::InlineCmd,
+            CmdKind::Block => IdentKind::BlockCmd,
+            CmdKind::Math => IdentKind::MathCmd,
+        }
+    }
+}
+
+/// Environment に含まれるコマンドの定義を、種類によらず参照するもの。
+#[derive(Debug, Clone, Copy)]
+pub struct CommandDef<'a> {
+    /// コマンドの種類
+    pub kind: CmdKind,
+    /// コマンド名
+    pub name: &'a str,
+    /// 定義の場所
+    pub def_range: Range,
+    /// 定義する文全体の場所
+    pub stmt_range: Range,
+    /// パッケージの外からの見え方
+    pub visibility: &'a Visibility,
+    /// コンテキストとオプション引数を除いた引数の数
+    pub arity: usize,
+    /// オプション引数の数
+    pub optional_arity: usize,
+    /// オプション引数を除いた各引数に期待されるテキストの種類。数式コマンドでは空
+    pub param_kinds: &'a [ParamKind],
+}
+
 /// インラインコマンド。
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct InlineCmd {
     /// コマンド名
     name: String,
     /// 定義の場所
     def_range: Range,
+    /// 定義する文全体の場所
+    stmt_range: Range,
+    /// パッケージの外からの見え方
+    visibility: Visibility,
+    /// コンテキストとオプション引数を除いた引数の数
+    arity: usize,
+    /// オプション引数の数
+    optional_arity: usize,
+    /// オプション引数を除いた各引数に期待されるテキストの種類
+    param_kinds: Vec<ParamKind>,
 }
 
 /// ブロックコマンド。
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct BlockCmd {
     /// コマンド名
     name: String,
     /// 定義の場所
     def_range: Range,
+    /// 定義する文全体の場所
+    stmt_range: Range,
+    /// パッケージの外からの見え方
+    visibility: Visibility,
+    /// コンテキストとオプション引数を除いた引数の数
+    arity: usize,
+    /// オプション引数の数
+    optional_arity: usize,
+    /// オプション引数を除いた各引数に期待されるテキストの種類
+    param_kinds: Vec<ParamKind>,
 }
 
 /// 数式コマンド。
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct MathCmd {
     /// コマンド名
     name: String,
     /// 定義の場所
     def_range: Range,
+    /// 定義する文全体の場所
+    stmt_range: Range,
+    /// パッケージの外からの見え方
+    visibility: Visibility,
+    /// コンテキストとオプション引数を除いた引数の数
+    arity: usize,
+    /// オプション引数の数
+    optional_arity: usize,
 }
 
 /// 変数
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Variable {
     /// 変数名
     name: String,
     /// 定義の場所
     def_range: Range,
+    /// 定義する文全体の場所
+    stmt_range: Range,
+    /// パッケージの外からの見え方
+    visibility: Visibility,
+    /// 単純なリテラルに束縛されている場合、その値のテキスト
+    value: Option<String>,
+    /// 型注釈が書かれている場合、その型のテキスト
+    annotation: Option<String>,
+}
+
+/// 型
+#[derive(Debug, Clone)]
+pub struct TypeDef {
+    /// 型名
+    name: String,
+    /// 定義の場所
+    def_range: Range,
+    /// 定義する文全体の場所
+    stmt_range: Range,
+    /// パッケージの外からの見え方
+    visibility: Visibility,
+    /// 型の定義の種類
+    kind: TypeDefKind,
+    /// ヴァリアント型であれば、そのコンストラクタ
+    constructors: Vec<Constructor>,
+    /// レコード型であれば、そのフィールド
+    fields: Vec<TypeField>,
+}
+
+/// type 文による型の定義の種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum TypeDefKind {
+    /// 他の型に別名を付けるもの
+    Alias,
+    /// `| A of int | B` の形のヴァリアント型
+    Variant,
+    /// `(| x : int |)` の形のレコード型
+    Record,
+}
+
+/// ヴァリアント型のコンストラクタ
+#[derive(Debug, Clone)]
+pub struct Constructor {
+    /// コンストラクタ名
+    name: String,
+    /// 定義の場所
+    def_range: Range,
+    /// `of` で引数の型が与えられているか
+    has_argument: bool,
 }
+
+/// レコード型のフィールド
+#[derive(Debug, Clone)]
+pub struct TypeField {
+    /// フィールド名
+    name: String,
+    /// 定義の場所
+    def_range: Range,
+}
+
+impl Variable {
+    /// 変数名
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// 定義の場所
+    pub fn def_range(&self) -> Range {
+        self.def_range
+    }
+
+    /// 定義する文全体の場所
+    pub fn stmt_range(&self) -> Range {
+        self.stmt_range
+    }
+
+    /// 単純なリテラルに束縛されている場合、その値のテキスト
+    pub fn value(&self) -> Option<&str> {
+        self.value.as_deref()
+    }
+
+    /// 型注釈が書かれている場合、その型のテキスト
+    pub fn annotation(&self) -> Option<&str> {
+        self.annotation.as_deref()
+    }
+}
+
+impl TypeDef {
+    /// 型名
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// 定義の場所
+    pub fn def_range(&self) -> Range {
+        self.def_range
+    }
+
+    /// 定義する文全体の場所
+    pub fn stmt_range(&self) -> Range {
+        self.stmt_range
+    }
+
+    /// 型の定義の種類
+    pub fn kind(&self) -> TypeDefKind {
+        self.kind
+    }
+
+    /// ヴァリアント型であれば、そのコンストラクタ
+    pub fn constructors(&self) -> &[Constructor] {
+        &self.constructors
+    }
+
+    /// レコード型であれば、そのフィールド
+    pub fn fields(&self) -> &[TypeField] {
+        &self.fields
+    }
+}
+
+impl Constructor {
+    /// コンストラクタ名
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// 定義の場所
+    pub fn def_range(&self) -> Range {
+        self.def_range
+    }
+
+    /// `of` で引数の型が与えられているか
+    pub fn has_argument(&self) -> bool {
+        self.has_argument
+    }
+}
+
+impl TypeField {
+    /// フィールド名
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// 定義の場所
+    pub fn def_range(&self) -> Range {
+        self.def_range
+    }
+}
+
+/// 型の式が括弧などを除いてレコード型だけからなれば、その type_record を返す。
+fn record_type(ty: &Cst) -> Option<&Cst> {
+    match ty.rule {
+        Rule::type_record => Some(ty),
+        Rule::type_expr | Rule::type_prod | Rule::type_unary if ty.inner.len() == 1 => {
+            record_type(&ty.inner[0])
+        }
+        _ => None,
+    }
+}
+
+/// コマンド定義の名前より後ろの子（引数と本体の式）から、
+/// オプション引数を除いた引数の数とオプション引数の数を求める。
+fn command_arity(rest: &[Cst]) -> (usize, usize) {
+    let params = &rest[..rest.len().saturating_sub(1)];
+    let optional = params
+        .iter()
+        .filter(|param| param.inner.first().is_some_and(|c| c.rule == Rule::opt_arg))
+        .count();
+    (params.len() - optional, optional)
+}
+
+/// コマンドの引数として期待されるテキストの種類。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ParamKind {
+    /// `{...}` で与えるインラインテキスト。
+    InlineText,
+    /// `<...>` で与えるブロックテキスト。
+    BlockText,
+    /// テキスト以外か、種類が分からないもの。
+    Unknown,
+}
+
+impl ParamKind {
+    /// 型の文字列から引数の種類を求める。
+    fn from_type(ty: &str) -> Self {
+        match ty.trim() {
+            "inline-text" => ParamKind::InlineText,
+            "block-text" => ParamKind::BlockText,
+            _ => ParamKind::Unknown,
+        }
+    }
+}
+
+/// コマンド定義の名前より後ろの子から、オプション引数を除いた各引数の種類を推測する。
+/// 本体で `read-inline ctx x` や `read-block ctx x` のように読まれている引数をテキストとみなす。
+fn command_param_kinds(text: &BufferCst, rest: &[Cst]) -> Vec<ParamKind> {
+    let (body, params) = match rest.split_last() {
+        Some(split) => split,
+        None => return vec![],
+    };
+    let reads = body
+        .pickup(Rule::application)
+        .into_iter()
+        .filter_map(|app| match &app.inner[..] {
+            [func, _, arg] => {
+                let kind = ParamKind::from_type(match text.as_str(func) {
+                    "read-inline" => "inline-text",
+                    "read-block" => "block-text",
+                    _ => return None,
+                });
+                Some((text.as_str(arg).trim(), kind))
+            }
+            _ => None,
+        })
+        .collect_vec();
+    params
+        .iter()
+        .filter(|param| param.inner.first().is_none_or(|c| c.rule != Rule::opt_arg))
+        .map(|param| match param.pickup(Rule::var).as_slice() {
+            [var] => reads
+                .iter()
+                .find(|(name, _)| *name == text.as_str(var))
+                .map_or(ParamKind::Unknown, |(_, kind)| *kind),
+            _ => ParamKind::Unknown,
+        })
+        .collect()
+}
+
+/// signature の `val` または `direct` の行が宣言する名前と、その種類を返す。
+/// 変数であれば種類は `None` とする。
+/// `\cmd` の形の名前は、型が `math-cmd` であれば数式コマンド、そうでなければインラインコマンドとみなす。
+fn signature_entry<'a>(text: &'a BufferCst, stmt: &Cst) -> Option<(Option<CmdKind>, &'a str)> {
+    let name = stmt.inner.first()?;
+    let kind = match name.rule {
+        Rule::var => None,
+        Rule::block_cmd_name => Some(CmdKind::Block),
+        Rule::inline_cmd_name => {
+            let ty = stmt.inner.iter().find(|c| c.rule == Rule::type_expr)?;
+            if text.as_str(ty).trim_end().ends_with("math-cmd") {
+                Some(CmdKind::Math)
+            } else {
+                Some(CmdKind::Inline)
+            }
+        }
+        _ => return None,
+    };
+    Some((kind, text.as_str(name)))
+}
+
+/// signature に書かれたコマンドの型 `[inline-text; int?] inline-cmd` から、
+/// オプション引数を除いた各引数の種類を求める。
+fn signature_param_kinds(text: &BufferCst, type_expr: &Cst) -> Option<Vec<ParamKind>> {
+    let list = type_expr.pickup(Rule::type_list).into_iter().next()?;
+    // オプション引数は `?` の付いた type_prod として、それ以外は type_expr として現れる。
+    let kinds = list
+        .inner
+        .iter()
+        .filter(|c| c.rule == Rule::type_expr)
+        .map(|c| ParamKind::from_type(text.as_str(c)))
+        .collect();
+    Some(kinds)
+}
+
+/// `let x = 1cm` のように、単一の変数を引数なしでリテラルに束縛している let_stmt について、
+/// そのリテラルのテキストを返す。
+fn simple_literal_value(text: &BufferCst, let_stmt: &Cst) -> Option<String> {
+    let (ptn, expr) = match &let_stmt.inner[..] {
+        [ptn, expr] => (ptn, expr),
+        _ => return None,
+    };
+    if ptn.inner.len() != 1 || ptn.inner[0].rule != Rule::var {
+        return None;
+    }
+
+    // expr -> unary -> literal のように子が1つだけの連なりをたどる。
+    // `-1cm` のような符号付きのリテラルも認める。
+    let mut cst = expr;
+    loop {
+        match (cst.rule, &cst.inner[..]) {
+            (Rule::literal, _) => return Some(text.as_str(expr).to_owned()),
+            (Rule::expr | Rule::unary, [child]) => cst = child,
+            (Rule::unary_operator_expr, [op, child]) if text.as_str(op) == "-" => cst = child,
+            _ => return None,
+        }
+    }
+}
+
+/// `let x : t = ...` のように、引数をとらない単一の変数の let_stmt に書かれた型注釈のテキストを返す。
+fn type_annotation(text: &BufferCst, let_stmt: &Cst) -> Option<String> {
+    let (ptn, annotation) = match &let_stmt.inner[..] {
+        [ptn, annotation, _] if annotation.rule == Rule::type_annotation => (ptn, annotation),
+        _ => return None,
+    };
+    if ptn.inner.len() != 1 || ptn.inner[0].rule != Rule::var {
+        return None;
+    }
+    let ty = annotation.inner.first()?;
+    Some(text.as_str(ty).to_owned())
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,99 @@
+//! バッファ内の文字列に対する簡単な検査。
+
+/// レイアウト上の問題を起こしやすい、目に見えない文字。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InvisibleKind {
+    /// 行末の半角スペース。
+    TrailingSpace,
+    /// タブ文字。
+    Tab,
+    /// 全角スペース（U+3000）。
+    FullWidthSpace,
+}
+
+impl InvisibleKind {
+    /// 人間が読むための説明。
+    pub fn description(&self) -> &'static str {
+        match self {
+            InvisibleKind::TrailingSpace => "trailing space",
+            InvisibleKind::Tab => "tab (U+0009)",
+            InvisibleKind::FullWidthSpace => "full-width space (U+3000)",
+        }
+    }
+}
+
+/// 文字列中に見つかった目に見えない文字。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvisibleChar {
+    /// 文字の種類。
+    pub kind: InvisibleKind,
+    /// 文字列中の何文字目にあるか（0 始まり）。
+    pub index: usize,
+}
+
+/// 文字列に含まれる目に見えない文字を列挙する。
+pub fn find_invisible_chars(text: &str) -> Vec<InvisibleChar> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut found = vec![];
+    for (index, &c) in chars.iter().enumerate() {
+        let kind = match c {
+            '\t' => InvisibleKind::Tab,
+            '\u{3000}' => InvisibleKind::FullWidthSpace,
+            ' ' if is_trailing(&chars, index) => InvisibleKind::TrailingSpace,
+            _ => continue,
+        };
+        found.push(InvisibleChar { kind, index });
+    }
+    found
+}
+
+/// index 番目の文字から行末（または文字列末尾）まで空白しかないかどうか。
+fn is_trailing(chars: &[char], index: usize) -> bool {
+    chars[index..]
+        .iter()
+        .take_while(|&&c| c != '\n' && c != '\r')
+        .all(|&c| c == ' ')
+}
+
+/// 文字列リテラル中の、コマンドのように見える文字列。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommandLike {
+    /// `\emph` のような、コマンドに見える部分。
+    pub name: String,
+    /// 文字列中の何バイト目から始まるか（0 始まり）。
+    pub offset: usize,
+}
+
+/// 文字列中に含まれるバッククォートの数が奇数かどうか。
+/// 対になっていないバッククォートは、リテラルの終わりを読み違える原因となる。
+pub fn has_unbalanced_backticks(text: &str) -> bool {
+    text.matches('`').count() % 2 == 1
+}
+
+/// 文字列中で最も長いバッククォートの連続の長さ。
+pub fn longest_backtick_run(text: &str) -> usize {
+    text.split(|c| c != '`').map(str::len).max().unwrap_or(0)
+}
+
+/// 文字列中の `\emph` のようなコマンドに見える部分を列挙する。
+/// 文字列リテラルの中ではコマンドは解釈されないため、書き間違いの可能性がある。
+pub fn find_command_like(text: &str) -> Vec<CommandLike> {
+    let mut found = vec![];
+    for (offset, _) in text.match_indices('\\') {
+        let rest = &text[offset + 1..];
+        if !rest.starts_with(|c: char| c.is_ascii_alphabetic()) {
+            continue;
+        }
+        let len = rest
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '-'))
+            .unwrap_or(rest.len());
+        found.push(CommandLike {
+            name: text[offset..offset + 1 + len].to_owned(),
+            offset,
+        });
+    }
+    found
+}
+
+#[cfg(test)]
+mod tests;
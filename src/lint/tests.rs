@@ -0,0 +1,43 @@
+//! test module for lint.
+
+use super::*;
+
+#[test]
+fn test_find_invisible_chars() {
+    let found = find_invisible_chars("a\tb\u{3000}c  \nd ");
+    assert_eq!(
+        found,
+        vec![
+            InvisibleChar { kind: InvisibleKind::Tab, index: 1 },
+            InvisibleChar { kind: InvisibleKind::FullWidthSpace, index: 3 },
+            InvisibleChar { kind: InvisibleKind::TrailingSpace, index: 5 },
+            InvisibleChar { kind: InvisibleKind::TrailingSpace, index: 6 },
+            InvisibleChar { kind: InvisibleKind::TrailingSpace, index: 9 },
+        ]
+    );
+}
+
+#[test]
+fn test_inner_space_is_not_trailing() {
+    assert!(find_invisible_chars("a b").is_empty());
+}
+
+#[test]
+fn test_backticks() {
+    assert!(has_unbalanced_backticks("a`b"));
+    assert!(!has_unbalanced_backticks("`a` and `b`"));
+    assert_eq!(longest_backtick_run("a``b`c"), 2);
+    assert_eq!(longest_backtick_run("abc"), 0);
+}
+
+#[test]
+fn test_find_command_like() {
+    let found = find_command_like(r"see \emph{this} \\ or \1 and \ref-page");
+    assert_eq!(
+        found,
+        vec![
+            CommandLike { name: r"\emph".to_owned(), offset: 4 },
+            CommandLike { name: r"\ref-page".to_owned(), offset: 29 },
+        ]
+    );
+}
@@ -1,14 +1,130 @@
-use std::{collections::HashMap, error::Error};
+use std::{
+    error::Error,
+    path::{Component, Path, PathBuf},
+};
 
-use log::{debug, error, info};
-use maquette_satysfi_language_server::{Buffer, BufferCst, completion::get_completion_response, definition::get_definition_response};
+use log::{error, info};
+use lsp_types::Url;
+use maquette_satysfi_language_server::{
+    config::{Config, FormatConfig},
+    dependency::DependencyGraph,
+    ignore::{glob_match, IgnoreFilter},
+    position::Position,
+    pretty::format_document,
+    server::{self, ServerOptions},
+    statistics::document_statistics,
+    trace::trace_parse,
+    workspace::scan_files,
+    Buffer, BufferCst,
+};
+use rayon::prelude::*;
 use simplelog::*;
+use structopt::StructOpt;
 
-use lsp_types::{CompletionOptions, InitializeParams, OneOf, ServerCapabilities, TextDocumentSyncCapability, TextDocumentSyncKind, Url, notification::{DidChangeTextDocument, DidOpenTextDocument}, request::{Completion, GotoDefinition}};
+use lsp_server::Connection;
 
-use lsp_server::{Connection, Message, Notification, Request, RequestId, Response};
+// コマンドライン引数。doc comment は structopt のヘルプに使われるため通常のコメントとする。
+#[derive(Debug, StructOpt)]
+#[structopt(about = "Maquette (prototype) of SATySFi Language Server")]
+struct Opt {
+    // 省略した場合は language server として起動する。
+    #[structopt(subcommand)]
+    cmd: Option<Command>,
+    /// Directory to search for packages loaded by @require (can be given multiple times).
+    #[structopt(long = "package-path", number_of_values = 1, parse(from_os_str))]
+    package_paths: Vec<PathBuf>,
+}
+
+// サブコマンド。
+#[derive(Debug, StructOpt)]
+enum Command {
+    /// Parses a file and prints its concrete syntax tree.
+    Parse {
+        /// File to parse.
+        #[structopt(parse(from_os_str))]
+        file: PathBuf,
+        /// Prints the tree as JSON.
+        #[structopt(long)]
+        json: bool,
+        /// Prints which grammar rules matched or failed around LINE:COL (1-based) instead of the tree.
+        #[structopt(long = "trace-parse", value_name = "LINE:COL")]
+        trace_parse: Option<String>,
+    },
+    /// Formats SATySFi files in place, using the [format] section of satysfi-ls.toml if present.
+    Fmt {
+        /// Files, directories or glob patterns such as "src/**/*.saty" to format.
+        #[structopt(required = true)]
+        paths: Vec<String>,
+        /// Lists files that are not formatted without rewriting them, and fails if there are any.
+        #[structopt(long)]
+        check: bool,
+    },
+    /// Prints the graph of packages loaded by @require and @import, starting from a file.
+    Deps {
+        /// File to start from.
+        #[structopt(parse(from_os_str))]
+        file: PathBuf,
+        /// Prints the graph in Graphviz DOT format (the default).
+        #[structopt(long, conflicts_with = "json")]
+        dot: bool,
+        /// Prints the graph as JSON.
+        #[structopt(long)]
+        json: bool,
+    },
+    /// Counts paragraphs, command usages, defined commands, and words and characters in the text.
+    Stats {
+        /// File to count.
+        #[structopt(parse(from_os_str))]
+        file: PathBuf,
+        /// Prints the counts as JSON.
+        #[structopt(long)]
+        json: bool,
+    },
+}
 
 fn main() {
+    let opt = Opt::from_args();
+    match opt.cmd {
+        Some(Command::Parse { file, json, trace_parse }) => {
+            let result = match trace_parse {
+                Some(pos) => trace(&file, &pos),
+                None => parse(&file, json),
+            };
+            if let Err(e) = result {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+            return;
+        }
+        Some(Command::Fmt { paths, check }) => {
+            match fmt(&paths, check) {
+                Ok(true) => (),
+                Ok(false) => std::process::exit(1),
+                Err(e) => {
+                    eprintln!("{}", e);
+                    std::process::exit(1);
+                }
+            }
+            return;
+        }
+        Some(Command::Deps { file, dot, json }) => {
+            // --dot と --json は同時に指定できないので、--dot は既定の形式を明示するだけである。
+            if let Err(e) = deps(&file, json && !dot, opt.package_paths) {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+            return;
+        }
+        Some(Command::Stats { file, json }) => {
+            if let Err(e) = stats(&file, json) {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+            return;
+        }
+        None => (),
+    }
+
     let log_conf = ConfigBuilder::new()
         .set_time_to_local(true)
         .set_location_level(LevelFilter::Info)
@@ -20,164 +136,217 @@ fn main() {
     )
     .unwrap();
 
-    let result = sub();
+    let options = ServerOptions {
+        package_paths: opt.package_paths,
+    };
+    let result = sub(&options);
     if let Err(e) = result {
         error!("{}", e);
         std::process::exit(1);
     }
 }
 
-fn sub() -> Result<(), Box<dyn Error + Sync + Send>> {
-    // Note that  we must have our logging only write out to stderr.
-    info!("starting generic LSP server");
+/// ファイルをパースし、CST を標準出力に書き出す。
+fn parse(file: &Path, json: bool) -> Result<(), Box<dyn Error + Sync + Send>> {
+    let text = std::fs::read_to_string(file)?;
+    let (buf_cst, error) = BufferCst::parse_into(text);
+    if let Some(e) = error {
+        return Err(e.into());
+    }
+    if json {
+        let json = buf_cst.to_json().expect("parsed buffer must have CST");
+        println!("{}", serde_json::to_string_pretty(&json)?);
+    } else {
+        println!("{}", buf_cst);
+    }
+    Ok(())
+}
 
-    // Create the transport. Includes the stdio (stdin and stdout) versions but this could
-    // also be implemented to use sockets or HTTP.
-    let (connection, io_threads) = Connection::stdio();
+/// ファイルの `LINE:COL` のまわりで文法規則を試した結果を、1 行に 1 つずつ標準出力に書き出す。
+fn trace(file: &Path, pos: &str) -> Result<(), Box<dyn Error + Sync + Send>> {
+    let (line, col) = pos
+        .split_once(':')
+        .and_then(|(line, col)| Some((line.parse::<u32>().ok()?, col.parse::<u32>().ok()?)))
+        .filter(|&(line, col)| line > 0 && col > 0)
+        .ok_or_else(|| format!("invalid position `{}`: expected LINE:COL (1-based)", pos))?;
+    let text = std::fs::read_to_string(file)?;
+    let pos = Position { line: line - 1, character: col - 1 };
+    for step in trace_parse(&text, pos) {
+        println!("{}", step);
+    }
+    Ok(())
+}
 
-    // Run the server and wait for the two threads to end (typically by trigger LSP Exit event).
-    let server_capabilities = {
-        let mut server_capabilities = ServerCapabilities::default();
-        server_capabilities.definition_provider = Some(OneOf::Left(true));
-        server_capabilities.text_document_sync =
-            Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::Full));
-        let mut compopt = CompletionOptions::default();
-        compopt.trigger_characters = Some( vec!["\\".to_owned(), "+".to_owned() , "#".to_owned()]);
-        server_capabilities.completion_provider = Some(compopt);
-        serde_json::to_value(&server_capabilities).unwrap()
-    };
-    info!("server_capabilities: {:?}", server_capabilities);
-    let initialization_params = connection.initialize(server_capabilities)?;
-    main_loop(&connection, initialization_params)?;
-    io_threads.join()?;
+/// ファイルから推移的に読み込まれるパッケージの依存関係グラフを、DOT 形式か JSON で標準出力に書き出す。
+/// パッケージの探索には、カレントディレクトリの設定ファイルと `--package-path` を用いる。
+fn deps(
+    file: &Path,
+    json: bool,
+    package_paths: Vec<PathBuf>,
+) -> Result<(), Box<dyn Error + Sync + Send>> {
+    let mut config = Config::load(&std::env::current_dir()?)?;
+    config.package_paths = package_paths;
+    let path = file.canonicalize()?;
+    let uri = Url::from_file_path(&path)
+        .map_err(|_| format!("invalid file path: {}", path.display()))?;
+    let buf = Buffer::new(std::fs::read_to_string(&path)?);
+    let graph = DependencyGraph::build(&buf, &uri, &config);
+    if json {
+        println!("{}", serde_json::to_string_pretty(&graph.to_json())?);
+    } else {
+        print!("{}", graph.to_dot());
+    }
+    Ok(())
+}
 
-    // Shut down gracefully.
-    info!("shutting down server");
+/// ファイルの分量を、1 行に 1 項目ずつか JSON で標準出力に書き出す。
+fn stats(file: &Path, json: bool) -> Result<(), Box<dyn Error + Sync + Send>> {
+    let mut buf = Buffer::new(std::fs::read_to_string(file)?);
+    if !buf.error.is_empty() {
+        return Err(buf.error.remove(0).into());
+    }
+    let stats = document_statistics(&buf).expect("parsed buffer must have CST");
+    if json {
+        println!("{}", serde_json::to_string_pretty(&stats)?);
+    } else {
+        for (name, count) in stats.fields().iter() {
+            println!("{}: {}", name, count);
+        }
+    }
     Ok(())
 }
 
-fn main_loop(
-    connection: &Connection,
-    params: serde_json::Value,
-) -> Result<(), Box<dyn Error + Sync + Send>> {
-    let _params: InitializeParams = serde_json::from_value(params).unwrap();
-    info!("starting example main loop");
-
-    // let mut buffers = Buffers::default();
-    let mut buffers: HashMap<Url, Buffer> = HashMap::new();
-
-    for msg in &connection.receiver {
-        info!("got msg: {:?}", msg);
-        match msg {
-            Message::Request(req) => {
-                if connection.handle_shutdown(&req)? {
-                    return Ok(());
-                }
-                info!("got request: {:?}", req);
-                let method = &req.method;
-                match method.as_str() {
-                    "textDocument/completion" => {
-                        let (id, params) = cast_req::<Completion>(req).unwrap();
-
-                        let uri = &params.text_document_position.text_document.uri;
-                        // let text = buffers.get(&uri);
-                        let resp = buffers
-                            .get(uri)
-                            .and_then(|buf| get_completion_response(&buf, params));
-
-                        if let Some(resp) = resp {
-                            let result = serde_json::to_value(&resp).unwrap();
-                            let resp = Response {
-                                id,
-                                result: Some(result),
-                                error: None,
-                            };
-                            connection.sender.send(Message::Response(resp))?;
-                            continue;
-                        }
-                    }
-                    "textDocument/definition" => {
-                        let (id, params) = cast_req::<GotoDefinition>(req).unwrap();
-
-                        let uri = &params.text_document_position_params.text_document.uri;
-                        let resp = buffers
-                            .get(uri)
-                            .and_then(|buf| get_definition_response(&buf, params))
-                            ;
-
-                        if let Some(resp) = resp {
-                            let result = serde_json::to_value(&resp).unwrap();
-                            let resp = Response {
-                                id,
-                                result: Some(result),
-                                error: None,
-                            };
-                            connection.sender.send(Message::Response(resp))?;
-                            continue;
-                        }
-
-                    }
-                    _ => unreachable!(),
-                }
-                // ...
-            }
+/// `fmt` サブコマンドでの、1 つのファイルの結果。
+enum FmtOutcome {
+    /// すでに整形されていた。
+    Unchanged,
+    /// 整形した（`--check` のときは、整形が必要だった）。
+    Formatted,
+    /// 読み書きやパースに失敗した。
+    Failed(String),
+}
 
-            Message::Response(resp) => {
-                info!("got response: {:?}", resp);
-            }
+/// パターンに合うファイルを並列に整形し、結果の一覧と集計を出力する。
+/// `check` のときはファイルを書き換えない。
+/// 整形が必要なファイル（`check` のとき）か失敗したファイルがあれば false を返す。
+fn fmt(patterns: &[String], check: bool) -> Result<bool, Box<dyn Error + Sync + Send>> {
+    let config = Config::load(&std::env::current_dir()?)?;
+    let files = expand_patterns(patterns)?;
+    let outcomes: Vec<_> = files
+        .par_iter()
+        .map(|path| fmt_file(path, check, &config.format))
+        .collect();
 
-            Message::Notification(not) => {
-                info!("got notification: {:?}", not);
-                let method = &not.method;
-                match method.as_str() {
-                    "textDocument/didChange" => {
-                        let params = cast_notif::<DidChangeTextDocument>(not).unwrap();
-                        let uri = params.text_document.uri;
-                        if let Some(change) = params.content_changes.get(0) {
-                            let text = change.text.clone();
-
-                            let buf = Buffer::new(text);
-                            debug!("buffer cst: {}", &buf.buf_cst);
-                            debug!("buffer env: {:?}", buf.env);
-                            if let Some(e) = buf.error.get(0) {
-                                debug!("error: {:?}", e)
-                            }
-                            buffers.insert(uri, buf);
-                        }
-                    }
-                    "textDocument/didOpen" => {
-                        let params = cast_notif::<DidOpenTextDocument>(not).unwrap();
-                        let uri = params.text_document.uri;
-                        let text = params.text_document.text;
-
-                        let buf = Buffer::new(text);
-                        debug!("buffer cst: {}", buf.buf_cst);
-                        debug!("buffer env: {:?}", buf.env);
-                        if let Some(e) = buf.error.get(0) {
-                            debug!("error: {:?}", e)
-                        }
-                        buffers.insert(uri, buf);
-                    }
-                    _ => (),
-                }
+    let (mut formatted, mut unchanged, mut failed) = (0, 0, 0);
+    for (path, outcome) in files.iter().zip(outcomes) {
+        match outcome {
+            FmtOutcome::Unchanged => unchanged += 1,
+            FmtOutcome::Formatted => {
+                formatted += 1;
+                let verb = if check { "would reformat" } else { "formatted" };
+                println!("{} {}", verb, path.display());
+            }
+            FmtOutcome::Failed(e) => {
+                failed += 1;
+                eprintln!("error: {}: {}", path.display(), e);
             }
         }
     }
-    Ok(())
+    let verb = if check { "would be reformatted" } else { "formatted" };
+    println!(
+        "{} file(s) {}, {} unchanged, {} failed",
+        formatted, verb, unchanged, failed
+    );
+    Ok(failed == 0 && !(check && formatted > 0))
 }
 
-fn cast_req<R>(req: Request) -> Result<(RequestId, R::Params), Request>
-where
-    R: lsp_types::request::Request,
-    R::Params: serde::de::DeserializeOwned,
-{
-    req.extract(R::METHOD)
+/// 1 つのファイルを整形する。
+fn fmt_file(path: &Path, check: bool, config: &FormatConfig) -> FmtOutcome {
+    let text = match std::fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) => return FmtOutcome::Failed(e.to_string()),
+    };
+    let formatted = match format_document(text.clone(), config) {
+        Ok(formatted) => formatted,
+        Err(e) => return FmtOutcome::Failed(e.to_string()),
+    };
+    if formatted == text {
+        return FmtOutcome::Unchanged;
+    }
+    if !check {
+        if let Err(e) = std::fs::write(path, formatted) {
+            return FmtOutcome::Failed(e.to_string());
+        }
+    }
+    FmtOutcome::Formatted
 }
 
-fn cast_notif<R>(not: Notification) -> Result<R::Params, Notification>
-where
-    R: lsp_types::notification::Notification,
-    R::Params: serde::de::DeserializeOwned,
-{
-    not.extract(R::METHOD)
+/// コマンドライン引数のファイル、ディレクトリ、glob パターンを、整形するファイルの一覧にする。
+/// ディレクトリとパターンは、ワークスペースの索引化と同じく `.gitignore` に従って走査する。
+fn expand_patterns(patterns: &[String]) -> Result<Vec<PathBuf>, Box<dyn Error + Sync + Send>> {
+    let mut files = vec![];
+    for pattern in patterns {
+        let path = Path::new(pattern);
+        if !pattern.contains(['*', '?']) {
+            if path.is_dir() {
+                files.extend(scan_files(path, &IgnoreFilter::default()));
+            } else {
+                files.push(path.to_owned());
+            }
+            continue;
+        }
+        // glob 文字を含まない先頭の部分から走査し、残りの部分をパターンとして照合する。
+        let mut base = PathBuf::new();
+        let mut components = path.components().peekable();
+        while let Some(component) = components.peek() {
+            if component.as_os_str().to_string_lossy().contains(['*', '?']) {
+                break;
+            }
+            base.push(component);
+            components.next();
+        }
+        let rest = components
+            .map(|c| c.as_os_str().to_string_lossy().into_owned())
+            .collect::<Vec<_>>()
+            .join("/");
+        let dir = if base.as_os_str().is_empty() { Path::new(".") } else { base.as_path() };
+        let matched = scan_files(dir, &IgnoreFilter::default())
+            .into_iter()
+            .filter(|file| {
+                let relative = file.strip_prefix(dir).unwrap_or(file);
+                let relative = relative
+                    .components()
+                    .filter(|c| matches!(c, Component::Normal(_)))
+                    .map(|c| c.as_os_str().to_string_lossy().into_owned())
+                    .collect::<Vec<_>>()
+                    .join("/");
+                glob_match(&rest, &relative)
+            })
+            .collect::<Vec<_>>();
+        if matched.is_empty() {
+            return Err(format!("no files match `{}`", pattern).into());
+        }
+        files.extend(matched);
+    }
+    files.sort();
+    files.dedup();
+    Ok(files)
+}
+
+fn sub(options: &ServerOptions) -> Result<(), Box<dyn Error + Sync + Send>> {
+    // Note that  we must have our logging only write out to stderr.
+    info!("starting generic LSP server");
+
+    // Create the transport. Includes the stdio (stdin and stdout) versions but this could
+    // also be implemented to use sockets or HTTP.
+    let (connection, io_threads) = Connection::stdio();
+
+    // Run the server and wait for the two threads to end (typically by trigger LSP Exit event).
+    server::run(&connection, options)?;
+    io_threads.join()?;
+
+    // Shut down gracefully.
+    info!("shutting down server");
+    Ok(())
 }
+
@@ -0,0 +1,81 @@
+//! onTypeFormatting に関する関数群。
+//!
+//! 箇条書きの項目の途中で改行したとき、同じ深さの `*` を次の行に補う。
+//! 何も書かれていない項目で改行したときは、その項目の `*` を取り除いて箇条書きを抜ける。
+
+use lsp_types::{DocumentOnTypeFormattingParams, TextEdit};
+
+use crate::{
+    parser::Rule,
+    position::{Position, Range},
+    Buffer, Cst,
+};
+
+/// onTypeFormatting のトリガーとなる文字。
+pub const TRIGGER_CHARACTER: &str = "\n";
+
+/// onTypeFormatting リクエストへの response を返す。
+pub fn get_on_type_formatting_response(
+    buf: &Buffer,
+    params: DocumentOnTypeFormattingParams,
+) -> Option<Vec<TextEdit>> {
+    if params.ch != TRIGGER_CHARACTER {
+        return None;
+    }
+    let pos = Position::from(params.text_document_position.position);
+    let cst = buf.buf_cst.cst.as_ref()?;
+    let index = buf.buf_cst.line_index();
+    if pos.line == 0 || pos.line as usize >= index.line_count() {
+        return None;
+    }
+    let prev_line = pos.line - 1;
+
+    // カーソルより前に空白以外があれば、改行で項目を分割したわけではない。
+    let text = &buf.buf_cst.buffer;
+    let line_start = |line: u32| index.offset(Position { line, character: 0 });
+    let before_cursor = &text[line_start(pos.line)..index.offset(pos)];
+    if !before_cursor.chars().all(char::is_whitespace) {
+        return None;
+    }
+
+    let bullet = enclosing_bullet(cst, &pos, prev_line)?;
+    let star = bullet.inner.first().filter(|c| c.rule == Rule::horizontal_bullet_star)?;
+    let stars = buf.buf_cst.as_str(star).trim_end();
+    let star_start: Position = star.range.start.clone().into();
+
+    let prev = index.line(prev_line as usize);
+    if star_start.line == prev_line && prev.trim() == stars {
+        // 空の項目で改行したので、その項目を取り除く。
+        let end = index.position(line_start(prev_line) + prev.trim_end().len());
+        return Some(vec![TextEdit {
+            range: Range { start: star_start, end }.into(),
+            new_text: String::new(),
+        }]);
+    }
+
+    let indent = &text[line_start(star_start.line)..star.range.start.byte as usize];
+    Some(vec![TextEdit {
+        range: Range {
+            start: Position { line: pos.line, character: 0 },
+            end: pos,
+        }
+        .into(),
+        new_text: format!("{}{} ", indent, stars),
+    }])
+}
+
+/// `pos` を含む最も内側の箇条書きのうち、`line` 行目以前から始まる最後の項目を返す。
+fn enclosing_bullet<'a>(cst: &'a Cst, pos: &Position, line: u32) -> Option<&'a Cst> {
+    let list = cst
+        .dig(pos)
+        .into_iter()
+        .find(|c| c.rule == Rule::horizontal_bullet_list)?;
+    list.inner
+        .iter()
+        .filter(|bullet| bullet.rule == Rule::horizontal_bullet)
+        .take_while(|bullet| bullet.range.start.line <= line)
+        .last()
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,60 @@
+//! test module for onTypeFormatting.
+
+use lsp_types::{
+    FormattingOptions, Position, Range, TextDocumentIdentifier, TextDocumentPositionParams, Url,
+};
+
+use super::*;
+
+fn on_enter(text: &str, line: u32, character: u32) -> Option<Vec<TextEdit>> {
+    let buf = Buffer::new(text.to_owned());
+    let params = DocumentOnTypeFormattingParams {
+        text_document_position: TextDocumentPositionParams {
+            text_document: TextDocumentIdentifier {
+                uri: Url::parse("file:///tmp/test.saty").unwrap(),
+            },
+            position: Position { line, character },
+        },
+        ch: "\n".to_owned(),
+        options: FormattingOptions {
+            tab_size: 2,
+            insert_spaces: true,
+            ..Default::default()
+        },
+    };
+    get_on_type_formatting_response(&buf, params)
+}
+
+fn range(sl: u32, sc: u32, el: u32, ec: u32) -> Range {
+    Range {
+        start: Position { line: sl, character: sc },
+        end: Position { line: el, character: ec },
+    }
+}
+
+#[test]
+fn test_next_bullet() {
+    let text = "'<\n  +listing{\n    * foo\n      ** bar\n    \n  }\n>\n";
+    let edits = on_enter(text, 4, 4).unwrap();
+    assert_eq!(edits[0].range, range(4, 0, 4, 4));
+    assert_eq!(edits[0].new_text, "      ** ");
+
+    let text = "'<\n  +listing{\n    * foo\n  \n  }\n>\n";
+    let edits = on_enter(text, 3, 2).unwrap();
+    assert_eq!(edits[0].range, range(3, 0, 3, 2));
+    assert_eq!(edits[0].new_text, "    * ");
+}
+
+#[test]
+fn test_remove_empty_bullet() {
+    let text = "'<\n  +listing{\n    * foo\n    * \n    \n  }\n>\n";
+    let edits = on_enter(text, 4, 4).unwrap();
+    assert_eq!(edits[0].range, range(3, 4, 3, 5));
+    assert_eq!(edits[0].new_text, "");
+}
+
+#[test]
+fn test_outside_bullet() {
+    let text = "'<\n  +p{\n    foo\n    \n  }\n>\n";
+    assert_eq!(on_enter(text, 3, 4), None);
+}
@@ -0,0 +1,121 @@
+//! パッケージのドキュメントを生成する `satysfi/packageDoc` リクエスト。
+
+use std::collections::HashMap;
+
+use itertools::Itertools;
+use log::warn;
+use lsp_types::{request::Request, TextDocumentIdentifier, Url};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::Config,
+    resolve::{path_to_uri, resolve_package, PackageKind, Stage},
+    Buffer, CmdKind,
+};
+
+/// パッケージのドキュメントを Markdown で返すカスタムリクエスト。
+pub enum PackageDoc {}
+
+impl Request for PackageDoc {
+    type Params = PackageDocParams;
+    type Result = Option<PackageDocResult>;
+    const METHOD: &'static str = "satysfi/packageDoc";
+}
+
+/// `satysfi/packageDoc` のパラメータ。
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PackageDocParams {
+    /// パッケージを読み込む側のドキュメント。
+    pub text_document: TextDocumentIdentifier,
+    /// パッケージ名。
+    pub package: String,
+    /// パッケージの読み込み方法。
+    pub kind: PackageKind,
+}
+
+/// `satysfi/packageDoc` の結果。
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PackageDocResult {
+    /// パッケージファイルの URI.
+    pub uri: Url,
+    /// 生成された Markdown 文書。
+    pub contents: String,
+}
+
+/// packageDoc リクエストへの response を返す。
+pub fn get_package_doc_response(
+    params: PackageDocParams,
+    config: &Config,
+) -> Option<PackageDocResult> {
+    let path = resolve_package(
+        &params.text_document.uri,
+        params.kind,
+        &params.package,
+        Stage::default(),
+        config,
+    )?;
+    let text = std::fs::read_to_string(&path)
+        .map_err(|e| warn!("failed to read {}: {}", path.display(), e))
+        .ok()?;
+    let uri = path_to_uri(&path)?;
+    let buf = Buffer::new(text);
+    let contents = generate_markdown(&params.package, &path.display().to_string(), &buf, config);
+    Some(PackageDocResult { uri, contents })
+}
+
+/// パッケージのバッファから Markdown 文書を生成する。
+fn generate_markdown(package: &str, path: &str, buf: &Buffer, config: &Config) -> String {
+    let docs = load_catalog_documentation(config);
+    let env = buf.env.exported();
+
+    let sections = [
+        (
+            "Inline commands",
+            env.commands(CmdKind::Inline).map(|c| (c.name, c.def_range)).collect_vec(),
+        ),
+        (
+            "Block commands",
+            env.commands(CmdKind::Block).map(|c| (c.name, c.def_range)).collect_vec(),
+        ),
+        (
+            "Math commands",
+            env.commands(CmdKind::Math).map(|c| (c.name, c.def_range)).collect_vec(),
+        ),
+    ];
+
+    let mut text = format!("# {}\n\nSource: `{}`\n", package, path);
+    for (title, cmds) in sections.iter() {
+        if cmds.is_empty() {
+            continue;
+        }
+        text.push_str(&format!("\n## {}\n", title));
+        for (name, range) in cmds {
+            let line = buf
+                .buf_cst
+                .buffer
+                .lines()
+                .nth(range.start.line as usize)
+                .unwrap_or("")
+                .trim();
+            text.push_str(&format!("\n### `{}`\n\n```satysfi\n{}\n```\n", name, line));
+            if let Some(doc) = docs.get(*name) {
+                text.push_str(&format!("\n{}\n", doc.trim_end()));
+            }
+        }
+    }
+    text
+}
+
+/// completion.toml に書かれたドキュメントをラベルをキーとして集める。
+pub(crate) fn load_catalog_documentation(config: &Config) -> HashMap<String, String> {
+    config
+        .catalog
+        .items()
+        .filter_map(|(_, item)| Some((item.label.clone(), item.documentation.clone()?)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,51 @@
+//! test module for package documentation.
+
+use std::fs;
+
+use super::*;
+use crate::test_utils::temp_dir;
+
+#[test]
+fn test_generate_markdown_lists_exported_commands() {
+    let buf = Buffer::new(
+        concat!(
+            "module Pkg : sig\n",
+            "  direct \\shown : [inline-text] inline-cmd\n",
+            "end = struct\n",
+            "  let-inline ctx \\shown it = it\n",
+            "  let-inline ctx \\hidden it = it\n",
+            "end\n",
+            "let-block ctx +para it = '<>\n",
+        )
+        .to_owned(),
+    );
+    let text = generate_markdown("pkg", "/path/to/pkg.satyh", &buf, &Config::default());
+    assert!(text.starts_with("# pkg\n\nSource: `/path/to/pkg.satyh`\n"));
+    assert!(text.contains(
+        "\n## Inline commands\n\n### `\\shown`\n\n```satysfi\nlet-inline ctx \\shown it = it\n```\n"
+    ));
+    assert!(text.contains("\n## Block commands\n\n### `+para`\n"));
+    // signature で隠されたコマンドや、定義のない種類の節は含めない。
+    assert!(!text.contains("\\hidden"));
+    assert!(!text.contains("Math commands"));
+}
+
+#[test]
+fn test_package_doc_response() {
+    let dir = temp_dir("package-doc");
+    fs::write(dir.join("local.satyh"), "let-inline ctx \\foo = {}\n").unwrap();
+    let uri = Url::from_file_path(dir.join("main.saty")).unwrap();
+    let params = |package: &str| PackageDocParams {
+        text_document: TextDocumentIdentifier { uri: uri.clone() },
+        package: package.to_owned(),
+        kind: PackageKind::Import,
+    };
+
+    let result = get_package_doc_response(params("local"), &Config::default()).unwrap();
+    assert_eq!(result.uri, Url::from_file_path(dir.join("local.satyh")).unwrap());
+    assert!(result.contents.starts_with("# local\n"));
+    assert!(result.contents.contains("### `\\foo`"));
+    assert!(get_package_doc_response(params("missing"), &Config::default()).is_none());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,129 @@
+//! 同じ文字列のパース結果を使い回すためのキャッシュ。
+//!
+//! 閉じたファイルを開き直したり、undo で以前の内容に戻したりしたときに、
+//! パースし直さずに以前の Cst を用いる。文字列の FNV-1a ハッシュと文法の版で引き、
+//! ハッシュが衝突しても誤った Cst を返さないよう、文字列そのものも比べる。
+//! パースに失敗した結果は保持しない。
+
+use std::{collections::HashMap, sync::Arc};
+
+use anyhow::Error;
+use serde::{Deserialize, Serialize};
+
+use crate::{config::LanguageVersion, history::fnv1a, BufferCst};
+
+/// 最近使われたものから一定数のパース結果を保持するキャッシュ。
+#[derive(Debug)]
+pub struct ParseCache {
+    /// 保持するパース結果の最大数。0 のときは何も保持しない。
+    capacity: usize,
+    /// 文字列のハッシュと文法の版をキーとした、パース結果。
+    entries: HashMap<(u64, LanguageVersion), CacheEntry>,
+    /// 使われた順を表すために、引くたびに増やす値。
+    clock: u64,
+    /// キャッシュにあったパース結果を用いた回数。
+    hits: u64,
+    /// キャッシュになくパースした回数。
+    misses: u64,
+}
+
+/// キャッシュに保持するパース結果。
+#[derive(Debug)]
+struct CacheEntry {
+    /// パース結果。バッファと共有する。
+    buf_cst: Arc<BufferCst>,
+    /// 最後に使われたときの [`ParseCache::clock`] の値。
+    last_used: u64,
+}
+
+/// キャッシュの状態。`satysfi/serverStatus` で返す。
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ParseCacheStats {
+    /// 保持しているパース結果の数。
+    pub entries: usize,
+    /// キャッシュにあったパース結果を用いた回数。
+    pub hits: u64,
+    /// キャッシュになくパースした回数。
+    pub misses: u64,
+}
+
+impl ParseCache {
+    /// 最大で `capacity` 個のパース結果を保持するキャッシュを作る。
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: HashMap::new(),
+            clock: 0,
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    /// 保持するパース結果の最大数を変える。超えた分は使われていないものから捨てる。
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        self.evict();
+    }
+
+    /// 文字列を `language_version` の文法でパースする。
+    /// 同じ文字列を同じ版でパースした結果を保持していれば、パースし直さずにそれを共有して返す。
+    pub fn parse(
+        &mut self,
+        text: String,
+        language_version: LanguageVersion,
+    ) -> (Arc<BufferCst>, Option<Error>) {
+        if self.capacity == 0 {
+            let (buf_cst, error) = BufferCst::parse_with(text, language_version);
+            return (Arc::new(buf_cst), error);
+        }
+        self.clock += 1;
+        let key = (fnv1a(text.as_bytes()), language_version);
+        if let Some(entry) = self.entries.get_mut(&key).filter(|entry| entry.buf_cst.buffer == text) {
+            entry.last_used = self.clock;
+            self.hits += 1;
+            return (Arc::clone(&entry.buf_cst), None);
+        }
+        self.misses += 1;
+        let (buf_cst, error) = BufferCst::parse_with(text, language_version);
+        let buf_cst = Arc::new(buf_cst);
+        if error.is_none() {
+            let entry = CacheEntry { buf_cst: Arc::clone(&buf_cst), last_used: self.clock };
+            self.entries.insert(key, entry);
+            self.evict();
+        }
+        (buf_cst, error)
+    }
+
+    /// キャッシュの状態。
+    pub fn stats(&self) -> ParseCacheStats {
+        ParseCacheStats {
+            entries: self.entries.len(),
+            hits: self.hits,
+            misses: self.misses,
+        }
+    }
+
+    /// 保持しているパース結果。
+    pub fn entries(&self) -> impl Iterator<Item = &Arc<BufferCst>> {
+        self.entries.values().map(|entry| &entry.buf_cst)
+    }
+
+    /// 最大数を超えていれば、最も長く使われていないものから捨てる。
+    fn evict(&mut self) {
+        while self.entries.len() > self.capacity {
+            let oldest = self
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| *key);
+            match oldest {
+                Some(key) => self.entries.remove(&key),
+                None => break,
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests;
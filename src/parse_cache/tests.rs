@@ -0,0 +1,62 @@
+//! test module for parse cache.
+
+use super::*;
+use crate::Cst;
+
+#[test]
+fn test_reuse_parse_result() {
+    let mut cache = ParseCache::new(4);
+    let text = "let x = 1 in x\n";
+    let (first, _) = cache.parse(text.to_owned(), LanguageVersion::Stable);
+    let (second, error) = cache.parse(text.to_owned(), LanguageVersion::Stable);
+    assert!(error.is_none());
+    assert_eq!(second.buffer, text);
+    assert_eq!(second.cst().map(Cst::node_count), first.cst().map(Cst::node_count));
+    assert_eq!(cache.stats(), ParseCacheStats { entries: 1, hits: 1, misses: 1 });
+    // 同じパース結果を複製せずに共有する。
+    assert!(Arc::ptr_eq(&first, &second));
+
+    // 文法の版が異なればパースし直す。
+    cache.parse(text.to_owned(), LanguageVersion::Next);
+    assert_eq!(cache.stats(), ParseCacheStats { entries: 2, hits: 1, misses: 2 });
+}
+
+#[test]
+fn test_failed_parse_is_not_cached() {
+    let mut cache = ParseCache::new(4);
+    let text = "'<\n  +p{a}\n  x\n>\n";
+    let (_, error) = cache.parse(text.to_owned(), LanguageVersion::Stable);
+    assert!(error.is_some());
+    // エラーも含めてパースし直す。
+    let (_, error) = cache.parse(text.to_owned(), LanguageVersion::Stable);
+    assert!(error.is_some());
+    assert_eq!(cache.stats(), ParseCacheStats { entries: 0, hits: 0, misses: 2 });
+}
+
+#[test]
+fn test_evict_least_recently_used() {
+    let mut cache = ParseCache::new(2);
+    let texts = ["let a = 1 in a\n", "let b = 1 in b\n", "let c = 1 in c\n"];
+    cache.parse(texts[0].to_owned(), LanguageVersion::Stable);
+    cache.parse(texts[1].to_owned(), LanguageVersion::Stable);
+    // a を使ったので、c を加えると b が捨てられる。
+    cache.parse(texts[0].to_owned(), LanguageVersion::Stable);
+    cache.parse(texts[2].to_owned(), LanguageVersion::Stable);
+    assert_eq!(cache.stats().entries, 2);
+    cache.parse(texts[0].to_owned(), LanguageVersion::Stable);
+    assert_eq!(cache.stats().hits, 2);
+    cache.parse(texts[1].to_owned(), LanguageVersion::Stable);
+    assert_eq!(cache.stats().misses, 4);
+
+    cache.set_capacity(1);
+    assert_eq!(cache.stats().entries, 1);
+}
+
+#[test]
+fn test_disabled_cache() {
+    let mut cache = ParseCache::new(0);
+    let (buf_cst, _) = cache.parse("let x = 1 in x\n".to_owned(), LanguageVersion::Stable);
+    assert!(buf_cst.cst().is_some());
+    cache.parse("let x = 1 in x\n".to_owned(), LanguageVersion::Stable);
+    assert_eq!(cache.stats(), ParseCacheStats::default());
+}
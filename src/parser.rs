@@ -8,14 +8,19 @@ mod satysfi_parser {
     pub struct SatysfiParser;
 }
 
+pub mod expected;
+pub mod recovery;
 pub mod relation;
+pub mod rule_name;
 
 pub use satysfi_parser::{Rule, SatysfiParser};
 
 /// CalculatorParser で用いられる Pair.
 pub type Pair<'i> = pest::iterators::Pair<'i, Rule>;
 
-#[derive(Debug, PartialEq, Eq)]
+/// カーソル位置のモード。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
 pub enum Mode {
     /// プログラムモード。
     Program,
@@ -33,5 +38,14 @@ pub enum Mode {
     Comment,
 }
 
+/// あるモードと、そのモードが続く範囲。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ModeRegion {
+    /// モード。
+    pub mode: Mode,
+    /// そのモードを囲む領域の範囲。
+    pub range: crate::position::Range,
+}
+
 #[cfg(test)]
 mod tests;
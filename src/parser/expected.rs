@@ -0,0 +1,165 @@
+//! 構文エラーで期待されていた規則を、SATySFi の利用者に通じる言葉で説明するための関数群。
+//!
+//! pest の構文エラーは、その位置で読めたはずの規則を文法の内部の名前で列挙する。
+//! ここでは規則を「式」や「ブロックコマンド」のような概念にまとめ、
+//! テキストやブロックの途中であれば、それを閉じる括弧も期待されるものとして添える。
+//! `>` や `}` のような記号は規則ではないため、pest の構文エラーには現れない。
+
+use itertools::Itertools;
+
+use super::Rule;
+
+/// 期待されていたものが一つも分からないときの説明。
+const UNEXPECTED_INPUT: &str = "unexpected input";
+
+/// 構文エラーで期待されていた規則の一覧から、`expected ...` の形の説明を作る。
+pub fn describe_expected(positives: &[Rule]) -> String {
+    let concepts = positives.iter().filter_map(|rule| concept(*rule));
+    let closers = positives.iter().filter_map(|rule| closer(*rule));
+    let items = concepts.chain(closers).unique().collect_vec();
+    match &items[..] {
+        [] => UNEXPECTED_INPUT.to_owned(),
+        [item] => format!("expected {}", item),
+        [init @ .., last] => format!("expected {}, or {}", init.join(", "), last),
+    }
+}
+
+/// 規則が表す概念。説明に含めない規則では None を返す。
+fn concept(rule: Rule) -> Option<&'static str> {
+    let concept = match rule {
+        Rule::headers | Rule::header | Rule::header_kind | Rule::header_stage | Rule::stage => {
+            "a header like `@require:`"
+        }
+        Rule::headers_next | Rule::header_use | Rule::use_package | Rule::use_open => {
+            "a header like `use package`"
+        }
+        Rule::pkgname => "a package name",
+        Rule::preamble
+        | Rule::statement
+        | Rule::let_stmt
+        | Rule::let_inline_stmt
+        | Rule::let_block_stmt
+        | Rule::let_math_stmt
+        | Rule::let_mutable_stmt
+        | Rule::type_stmt
+        | Rule::module_stmt => "a statement like `let`",
+        Rule::sig_inner
+        | Rule::sig_stmt
+        | Rule::sig_type_stmt
+        | Rule::sig_val_stmt
+        | Rule::sig_direct_stmt => "a signature like `val`",
+        Rule::struct_stmt => "a module body `struct ... end`",
+        Rule::stmt_argument | Rule::arg | Rule::opt_arg => "a parameter",
+        Rule::type_annotation => "a type annotation `: type`",
+        Rule::type_variants | Rule::type_variant => "a variant like `Some of int`",
+        Rule::type_expr
+        | Rule::type_optional_name
+        | Rule::type_prod
+        | Rule::type_unary
+        | Rule::type_application
+        | Rule::type_application_unit
+        | Rule::type_name
+        | Rule::type_list
+        | Rule::type_list_unit
+        | Rule::type_record
+        | Rule::type_record_inner
+        | Rule::type_record_unit
+        | Rule::type_param
+        | Rule::constraint => "a type",
+        Rule::match_ptn | Rule::pattern | Rule::pat_variant | Rule::pat_list | Rule::pat_tuple | Rule::var_ptn => {
+            "a pattern"
+        }
+        Rule::match_arm => "a match arm like `| x -> ...`",
+        Rule::expr
+        | Rule::match_expr
+        | Rule::ctrl_while
+        | Rule::ctrl_if
+        | Rule::application
+        | Rule::unary
+        | Rule::staged_expr
+        | Rule::stage_operator
+        | Rule::unary_operator_expr
+        | Rule::unary_operator
+        | Rule::variant_constructor
+        | Rule::record_member
+        | Rule::tuple
+        | Rule::list
+        | Rule::record
+        | Rule::var
+        | Rule::modvar
+        | Rule::module_name
+        | Rule::variant_name
+        | Rule::expr_with_mod
+        | Rule::block_text
+        | Rule::horizontal_text
+        | Rule::math_text
+        | Rule::dyadic_expr
+        | Rule::bind_stmt
+        | Rule::let_in_stmt
+        | Rule::literal
+        | Rule::unit_const
+        | Rule::bool_const
+        | Rule::int_decimal_const
+        | Rule::int_const
+        | Rule::float_const
+        | Rule::length_const
+        | Rule::string_const => "an expression",
+        Rule::bin_operator => "a binary operator like `+`",
+        Rule::application_option | Rule::option_omitted | Rule::cmd_expr_option => {
+            "an optional argument `?:(...)` or `?*`"
+        }
+        Rule::cmd_expr_arg => "an argument `(...)`",
+        Rule::cmd_text_arg => "a text argument `{...}` or `<...>`",
+        Rule::vertical_mode
+        | Rule::vertical_element
+        | Rule::block_cmd
+        | Rule::block_cmd_name
+        | Rule::block_text_embedding => "a block command like `+p`",
+        Rule::horizontal_mode
+        | Rule::horizontal_single
+        | Rule::horizontal_list
+        | Rule::horizontal_bullet_list
+        | Rule::horizontal_bullet
+        | Rule::horizontal_bullet_star
+        | Rule::horizontal_token
+        | Rule::regular_text
+        | Rule::horizontal_special_char
+        | Rule::inline_cmd
+        | Rule::inline_cmd_name
+        | Rule::horizontal_text_embedding => "text or an inline command like `\\emph`",
+        Rule::math_mode
+        | Rule::math_single
+        | Rule::math_list
+        | Rule::math_list_item
+        | Rule::math_token
+        | Rule::math_group
+        | Rule::math_unary
+        | Rule::math_cmd
+        | Rule::math_cmd_name
+        | Rule::math_special_char
+        | Rule::math_symbol => "a math symbol or a math command like `\\frac`",
+        Rule::math_cmd_expr_arg
+        | Rule::math_cmd_list_arg
+        | Rule::math_cmd_record_arg
+        | Rule::math_cmd_expr_option => "a math command argument",
+        // コメントや空白はどこにでも書けるため、期待されるものとしては挙げない。
+        // 閉じ括弧だけを添えるものも、ここでは挙げない。
+        _ => return None,
+    };
+    Some(concept)
+}
+
+/// 規則が期待されるとき、代わりに書けるはずの閉じ括弧の説明。
+fn closer(rule: Rule) -> Option<&'static str> {
+    let closer = match rule {
+        Rule::vertical_element => "`>` to close the block text",
+        Rule::horizontal_token => "`}` to close the inline text",
+        Rule::math_token | Rule::math_unary => "`}` to close the math",
+        Rule::string_interpolation => "backquotes to close the string",
+        _ => return None,
+    };
+    Some(closer)
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,41 @@
+//! test module for describing expected rules.
+
+use super::*;
+
+#[test]
+fn test_describe_expected() {
+    assert_eq!(
+        describe_expected(&[Rule::COMMENT, Rule::cmd_text_arg, Rule::vertical_element]),
+        "expected a text argument `{...}` or `<...>`, a block command like `+p`, or `>` to close the block text"
+    );
+    assert_eq!(
+        describe_expected(&[Rule::COMMENT, Rule::horizontal_token]),
+        "expected text or an inline command like `\\emph`, or `}` to close the inline text"
+    );
+    assert_eq!(describe_expected(&[Rule::pkgname]), "expected a package name");
+    assert_eq!(describe_expected(&[Rule::string_interpolation]), "expected backquotes to close the string");
+}
+
+#[test]
+fn test_summarize_expression() {
+    // 式の一部にあたる規則はまとめて「式」とする。
+    let positives = [
+        Rule::COMMENT,
+        Rule::application,
+        Rule::unary,
+        Rule::unary_operator,
+        Rule::variant_name,
+        Rule::dyadic_expr,
+    ];
+    assert_eq!(describe_expected(&positives), "expected an expression");
+    assert_eq!(
+        describe_expected(&[Rule::type_variants, Rule::type_expr]),
+        "expected a variant like `Some of int`, or a type"
+    );
+}
+
+#[test]
+fn test_nothing_expected() {
+    assert_eq!(describe_expected(&[]), "unexpected input");
+    assert_eq!(describe_expected(&[Rule::COMMENT]), "unexpected input");
+}
@@ -0,0 +1,268 @@
+//! パースに失敗したバッファを、書きかけの部分を取り除いたり補ったりしてパースし直すための関数群。
+//!
+//! プリアンブルを書いている途中は `let` の `=` や右辺、プリアンブル末尾の `in` が
+//! 欠けていることが多く、そのままでは CST 全体が得られない。
+//! ここでは行頭のキーワードを手がかりに文の区切りを推測し、
+//! 書きかけの文を空白で塗りつぶしたり、欠けている `in` を補ったりする。
+//! 塗りつぶしは改行を残すため、位置は元のテキストと変わらない。
+
+use pest::{error::InputLocation, Parser};
+
+use super::{Rule, SatysfiParser};
+use crate::position::{LineIndex, Range};
+
+/// 塗りつぶしを試みる最大の回数。
+const MAX_ATTEMPTS: usize = 8;
+
+/// 行頭にあるとき、プリアンブルの文の始まりとみなすキーワード。
+const STATEMENT_KEYWORDS: &[&str] = &["let", "type", "module"];
+
+/// 行頭にあるとき、プリアンブルの文の終わりとみなすキーワード。
+/// モジュールの `end` はモジュールの文に含めたいので区切りとしない。
+const BOUNDARY_KEYWORDS: &[&str] = &["let", "type", "module", "in", "document"];
+
+/// 補った `in` の文字列。
+const SYNTHETIC_IN: &str = " in";
+
+/// パースし直すために行った修復。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Recovery {
+    /// 修復の種類。
+    pub kind: RecoveryKind,
+    /// 修復した元のテキスト上の範囲。
+    pub range: Range,
+}
+
+/// 修復の種類。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum RecoveryKind {
+    /// プリアンブルの後の `in` が欠けていたため補った。
+    MissingIn,
+    /// 書きかけの文を取り除いた。
+    IncompleteStatement,
+}
+
+impl RecoveryKind {
+    /// ユーザに示す説明。
+    pub fn message(self) -> &'static str {
+        match self {
+            RecoveryKind::MissingIn => "missing `in` after the preamble",
+            RecoveryKind::IncompleteStatement => "incomplete statement",
+        }
+    }
+}
+
+/// 修復したテキスト。
+#[derive(Debug)]
+pub(crate) struct Recovered {
+    /// パースに成功するよう修復したテキスト。
+    pub(crate) text: String,
+    /// `in` を補った場合、その元のテキスト上のバイトオフセットと補った長さ。
+    pub(crate) insertion: Option<(usize, usize)>,
+    /// 行った修復。
+    pub(crate) recoveries: Vec<Recovery>,
+}
+
+/// `rule` から読んでパースに失敗したテキストを修復する。修復してもパースできなければ None を返す。
+pub(crate) fn recover(text: &str, rule: Rule) -> Option<Recovered> {
+    let index = LineIndex::new(text);
+    let mut patched = text.to_owned();
+    let mut recoveries = vec![];
+
+    let insertion = missing_in_offset(text).map(|offset| {
+        patched.insert_str(offset, SYNTHETIC_IN);
+        let pos = index.position(offset);
+        recoveries.push(Recovery {
+            kind: RecoveryKind::MissingIn,
+            range: Range { start: pos, end: pos },
+        });
+        (offset, SYNTHETIC_IN.len())
+    });
+    // 修復後のテキスト上の文の範囲から、元のテキスト上での文の1行目の範囲を作る。
+    let incomplete_statement = |patched: &str, start: usize, end: usize| {
+        let original = |offset: usize| match insertion {
+            Some((at, len)) if offset >= at + len => offset - len,
+            Some((at, _)) if offset > at => at,
+            _ => offset,
+        };
+        let first_line_end = patched[start..end]
+            .find(['\r', '\n'])
+            .map_or(end, |i| start + i);
+        Recovery {
+            kind: RecoveryKind::IncompleteStatement,
+            range: Range {
+                start: index.position(original(start)),
+                end: index.position(original(first_line_end)),
+            },
+        }
+    };
+
+    // まずは単独でパースできない文を塗りつぶす。
+    for (start, end) in statements(&patched) {
+        if !is_complete_statement(&patched[start..end]) {
+            recoveries.push(incomplete_statement(&patched, start, end));
+            blank_out(&mut patched, start, end);
+        }
+    }
+
+    // それでもパースできなければ、エラー位置の直前にある文を順に塗りつぶす。
+    for _ in 0..MAX_ATTEMPTS {
+        let error = match SatysfiParser::parse(rule, &patched) {
+            Ok(_) => {
+                return Some(Recovered {
+                    text: patched,
+                    insertion,
+                    recoveries,
+                })
+            }
+            Err(e) => e,
+        };
+        let error_offset = match error.location {
+            InputLocation::Pos(pos) => pos,
+            InputLocation::Span((start, _)) => start,
+        };
+        let (start, end) = statement_around(&patched, error_offset)?;
+        recoveries.push(incomplete_statement(&patched, start, end));
+        blank_out(&mut patched, start, end);
+    }
+    None
+}
+
+/// 行頭から始まる行のバイトオフセットと、その行の内容を列挙する。
+fn lines(text: &str) -> impl Iterator<Item = (usize, &str)> {
+    text.split_inclusive('\n').scan(0, |offset, line| {
+        let start = *offset;
+        *offset += line.len();
+        Some((start, line))
+    })
+}
+
+/// 行がキーワードのいずれかで始まっているか。
+fn starts_with_keyword(line: &str, keywords: &[&str]) -> bool {
+    keywords.iter().any(|kw| {
+        line.strip_prefix(kw).is_some_and(|rest| {
+            !rest.starts_with(|c: char| c.is_ascii_alphanumeric() || c == '_')
+        })
+    })
+}
+
+/// 文書本体が始まっているのに、直前にプリアンブルを閉じる `in` がない場合、
+/// `in` を補うべきバイトオフセット（直前の行の末尾）を返す。
+fn missing_in_offset(text: &str) -> Option<usize> {
+    let mut has_statement = false;
+    let mut last_line: Option<(usize, &str)> = None;
+    for (offset, line) in lines(text) {
+        if starts_with_keyword(line, &["document"]) || line.starts_with("'<") {
+            if !has_statement {
+                return None;
+            }
+            let (prev_offset, prev) = last_line?;
+            // 行末のコメントの中に補わないよう、コメントの前に補う。
+            let content = strip_comment(prev).trim_end();
+            if content == "in" || content.ends_with(" in") {
+                return None;
+            }
+            return Some(prev_offset + content.len());
+        }
+        if starts_with_keyword(line, STATEMENT_KEYWORDS) {
+            has_statement = true;
+        }
+        let trimmed = line.trim();
+        if !trimmed.is_empty() && !trimmed.starts_with('%') {
+            last_line = Some((offset, line));
+        }
+    }
+    None
+}
+
+/// 行末のコメントを取り除いた行。文字列リテラル中の `%` と `\%` はコメントの始まりとみなさない。
+fn strip_comment(line: &str) -> &str {
+    let mut i = 0;
+    while let Some(c) = line[i..].chars().next() {
+        match c {
+            '%' => return &line[..i],
+            // エスケープされた文字は読み飛ばす。
+            '\\' => i += 1 + line[i + 1..].chars().next().map_or(0, char::len_utf8),
+            // 開きと同じ数の backquote が続くところまでが文字列リテラル。
+            '`' => {
+                let quotes = line[i..].chars().take_while(|c| *c == '`').count();
+                let body = i + quotes;
+                match line[body..].find(&line[i..body]) {
+                    Some(end) => i = body + end + quotes,
+                    None => return line,
+                }
+            }
+            c => i += c.len_utf8(),
+        }
+    }
+    line
+}
+
+/// 行頭のキーワードで区切られた位置と、そこから文が始まるかどうかを列挙する。
+fn boundaries(text: &str) -> Vec<(usize, bool)> {
+    lines(text)
+        .filter(|(_, line)| starts_with_keyword(line, BOUNDARY_KEYWORDS) || line.starts_with("'<"))
+        .map(|(start, line)| (start, starts_with_keyword(line, STATEMENT_KEYWORDS)))
+        .collect()
+}
+
+/// 行頭のキーワードで区切られた文の範囲を列挙する。
+fn statements(text: &str) -> Vec<(usize, usize)> {
+    let boundaries = boundaries(text);
+    boundaries
+        .iter()
+        .enumerate()
+        .filter(|(_, (_, is_statement))| *is_statement)
+        .map(|(i, (start, _))| {
+            let end = boundaries.get(i + 1).map_or(text.len(), |(next, _)| *next);
+            (*start, end)
+        })
+        .collect()
+}
+
+/// 文の範囲のテキストが、単独で1つ以上の文としてパースできるか。
+/// 末尾に残るのは（補った `in` を除いて）空白とコメントだけでなければならない。
+fn is_complete_statement(text: &str) -> bool {
+    let end = match SatysfiParser::parse(Rule::preamble, text) {
+        Ok(mut pairs) => pairs.next().map_or(0, |pair| pair.as_span().end()),
+        Err(_) => return false,
+    };
+    let rest = text[end..].trim_start();
+    let rest = rest.strip_prefix("in").unwrap_or(rest);
+    rest.lines().all(|line| {
+        let line = line.trim();
+        line.is_empty() || line.starts_with('%')
+    })
+}
+
+/// `offset` の直前から始まる、行頭のキーワードで区切られた文の範囲を返す。
+/// オフセットが文の先頭にある場合は、その1つ前の文を返す。
+fn statement_around(text: &str, offset: usize) -> Option<(usize, usize)> {
+    let boundaries = boundaries(text);
+    let i = boundaries.iter().rposition(|(start, _)| *start < offset)?;
+    let (start, is_statement) = boundaries[i];
+    if !is_statement {
+        return None;
+    }
+    let end = boundaries.get(i + 1).map_or(text.len(), |(next, _)| *next);
+    if text[start..end].trim().is_empty() {
+        return None;
+    }
+    Some((start, end))
+}
+
+/// 範囲内の改行以外の文字を空白に置き換える。マルチバイト文字はそのバイト数分の空白にする。
+fn blank_out(text: &mut String, start: usize, end: usize) {
+    let blanked: String = text[start..end]
+        .chars()
+        .flat_map(|c| {
+            let fill = if c == '\n' || c == '\r' { c } else { ' ' };
+            std::iter::repeat_n(fill, if fill == ' ' { c.len_utf8() } else { 1 })
+        })
+        .collect();
+    text.replace_range(start..end, &blanked);
+}
+
+#[cfg(test)]
+mod tests;
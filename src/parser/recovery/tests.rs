@@ -0,0 +1,56 @@
+//! test module for parse recovery.
+
+use crate::position::Position;
+
+use super::*;
+
+fn pos(line: u32, character: u32) -> Position {
+    Position { line, character }
+}
+
+#[test]
+fn test_incomplete_let() {
+    let text = "let x\nlet-inline ctx \\foo = {}\nin\n'<>\n";
+    let recovered = recover(text, Rule::program).unwrap();
+    assert_eq!(recovered.insertion, None);
+    assert_eq!(
+        recovered.recoveries,
+        vec![Recovery {
+            kind: RecoveryKind::IncompleteStatement,
+            range: Range { start: pos(0, 0), end: pos(0, 5) },
+        }]
+    );
+    assert!(recovered.text.starts_with("     \nlet-inline"));
+}
+
+#[test]
+fn test_missing_in() {
+    let text = "let x = 1\n\n'<>\n";
+    let recovered = recover(text, Rule::program).unwrap();
+    assert_eq!(recovered.text, "let x = 1 in\n\n'<>\n");
+    assert_eq!(recovered.insertion, Some((9, 3)));
+    assert_eq!(recovered.recoveries[0].kind, RecoveryKind::MissingIn);
+    assert_eq!(recovered.recoveries[0].range.start, pos(0, 9));
+}
+
+#[test]
+fn test_unrecoverable() {
+    assert!(recover("'< +p{ \n", Rule::program).is_none());
+}
+
+#[test]
+fn test_missing_in_before_comment() {
+    let text = "let x = 1 % note\n\n'<>\n";
+    let recovered = recover(text, Rule::program).unwrap();
+    assert_eq!(recovered.text, "let x = 1 in % note\n\n'<>\n");
+    assert_eq!(recovered.insertion, Some((9, 3)));
+}
+
+#[test]
+fn test_strip_comment() {
+    assert_eq!(strip_comment("let x = 1 % note"), "let x = 1 ");
+    assert_eq!(strip_comment("let s = `50%` % note"), "let s = `50%` ");
+    assert_eq!(strip_comment("let s = ``a`%`` in"), "let s = ``a`%`` in");
+    assert_eq!(strip_comment("let t = {100\\%} % note"), "let t = {100\\%} ");
+    assert_eq!(strip_comment("let s = `unclosed %"), "let s = `unclosed %");
+}
@@ -1,5 +1,7 @@
 //! 2つの区間同士の関係を表す Trait.
 
+use crate::position::Range;
+
 /// 2つの区間同士の関係を表す Trait. Range に実装する。
 pub trait CompareRange: Sized {
 
@@ -16,3 +18,27 @@ pub trait CompareRange: Sized {
     fn has_intersect(&self, other: &Self) -> bool;
 
 }
+
+impl CompareRange for Range {
+    fn includes(&self, other: &Self) -> bool {
+        self.start <= other.start && other.end <= self.end
+    }
+
+    fn is_included(&self, other: &Self) -> bool {
+        other.includes(self)
+    }
+
+    fn intersect(&self, other: &Self) -> Option<Self> {
+        let start = std::cmp::max(self.start, other.start);
+        let end = std::cmp::min(self.end, other.end);
+        if start <= end {
+            Some(Range { start, end })
+        } else {
+            None
+        }
+    }
+
+    fn has_intersect(&self, other: &Self) -> bool {
+        self.intersect(other).is_some()
+    }
+}
@@ -0,0 +1,179 @@
+//! 文法規則の名前と [`Rule`] との対応。
+//! 設定ファイルなどで `satysfi.pest` での名前を使って文法規則を指定するために用いる。
+
+use super::Rule;
+
+/// 文法規則の名前の一覧から、名前と [`Rule`] を相互に変換する関数を作る。
+/// [`rule_name`] の match は網羅的でなければならないため、文法に規則を加えて一覧に書き忘れるとコンパイルに失敗する。
+macro_rules! rule_names {
+    ($($name:ident,)*) => {
+        /// `satysfi.pest` での名前が `name` である文法規則を返す。
+        pub fn rule_by_name(name: &str) -> Option<Rule> {
+            match name {
+                $(stringify!($name) => Some(Rule::$name),)*
+                _ => None,
+            }
+        }
+
+        /// 文法規則の `satysfi.pest` での名前を返す。
+        pub fn rule_name(rule: Rule) -> &'static str {
+            match rule {
+                $(Rule::$name => stringify!($name),)*
+            }
+        }
+    };
+}
+
+rule_names! {
+    WHITESPACE,
+    COMMENT,
+    comment_inner,
+    program,
+    program_saty,
+    program_satyh,
+    program_next,
+    program_saty_next,
+    program_satyh_next,
+    header_stage,
+    stage,
+    headers,
+    header,
+    header_kind,
+    pkgname,
+    headers_next,
+    header_use,
+    use_package,
+    use_open,
+    preamble,
+    statement,
+    let_stmt,
+    let_inline_stmt,
+    let_block_stmt,
+    let_math_stmt,
+    let_mutable_stmt,
+    type_stmt,
+    type_variants,
+    type_variant,
+    stmt_argument,
+    type_annotation,
+    arg,
+    opt_arg,
+    module_stmt,
+    sig_stmt,
+    struct_stmt,
+    sig_inner,
+    sig_type_stmt,
+    sig_val_stmt,
+    sig_direct_stmt,
+    dummy_sig_stmt,
+    type_expr,
+    type_optional_name,
+    type_prod,
+    type_unary,
+    type_application,
+    type_application_unit,
+    type_name,
+    type_list,
+    type_list_unit,
+    type_record,
+    type_record_inner,
+    type_record_unit,
+    type_param,
+    constraint,
+    match_ptn,
+    pattern,
+    pat_variant,
+    pat_list,
+    pat_tuple,
+    expr,
+    match_expr,
+    match_arm,
+    ctrl_while,
+    ctrl_if,
+    application,
+    application_option,
+    option_omitted,
+    unary,
+    staged_expr,
+    stage_operator,
+    unary_operator_expr,
+    unary_operator,
+    variant_constructor,
+    record_member,
+    tuple,
+    list,
+    record,
+    record_inner,
+    record_unit,
+    var,
+    var_ptn,
+    module_name,
+    variant_name,
+    modvar,
+    expr_with_mod,
+    expr_with_mod_begin,
+    reserved_word,
+    block_text,
+    horizontal_text,
+    math_text,
+    dyadic_expr,
+    bin_operator,
+    bin_operator_start,
+    bin_operator_succ,
+    bind_stmt,
+    let_in_stmt,
+    literal,
+    unit_const,
+    bool_const,
+    int_decimal_const,
+    float_inner,
+    int_const,
+    int_hex_const,
+    float_const,
+    length_const,
+    length_digit,
+    length_unit,
+    string_const,
+    string_omit_space_identifier,
+    string_interior,
+    string_interpolation,
+    horizontal_mode,
+    horizontal_single,
+    horizontal_list,
+    horizontal_bullet_list,
+    horizontal_bullet,
+    horizontal_bullet_star,
+    horizontal_token,
+    regular_text,
+    horizontal_special_char,
+    dummy_inline_cmd_incomplete,
+    inline_cmd_name,
+    inline_cmd,
+    cmd_expr_arg,
+    cmd_expr_option,
+    cmd_text_arg,
+    horizontal_text_embedding,
+    vertical_mode,
+    vertical_element,
+    block_cmd_name,
+    block_cmd,
+    block_text_embedding,
+    dummy_block_cmd_incomplete,
+    math_mode,
+    math_single,
+    math_list,
+    math_list_item,
+    math_token,
+    math_group,
+    math_unary,
+    math_cmd,
+    math_special_char,
+    math_symbol,
+    math_cmd_name,
+    math_cmd_expr_arg,
+    math_cmd_list_arg,
+    math_cmd_record_arg,
+    math_cmd_expr_option,
+    dummy_math_cmd_incomplete,
+    EOI,
+}
@@ -111,3 +111,121 @@ mod statement {
         )
     }
 }
+
+mod expr {
+
+    use super::*;
+
+    #[test]
+    fn test_staged_expr() {
+        assert_parsed(
+            "~x",
+            pair(
+                Rule::staged_expr,
+                "~x",
+                &[
+                    pair(Rule::stage_operator, "~", &[]),
+                    pair(Rule::unary, "x", &[pair(Rule::var, "x", &[])]),
+                ],
+            ),
+        );
+    }
+
+    #[test]
+    fn test_lone_operator_tokens() {
+        assert_parsed("&&", pair(Rule::bin_operator, "&&", &[]));
+        assert_parsed("||", pair(Rule::bin_operator, "||", &[]));
+        assert_parsed("->>", pair(Rule::bin_operator, "->>", &[]));
+        assert!(SatysfiParser::parse(Rule::bin_operator, "& x").is_err());
+        assert!(SatysfiParser::parse(Rule::bin_operator, "| x").is_err());
+        assert!(SatysfiParser::parse(Rule::bin_operator, "-> x").is_err());
+    }
+}
+
+mod literal {
+
+    use super::*;
+
+    #[test]
+    fn test_string_interpolation() {
+        assert_parsed(
+            "`a#{x}b`",
+            pair(
+                Rule::string_const,
+                "`a#{x}b`",
+                &[pair(
+                    Rule::string_interior,
+                    "a#{x}b",
+                    &[pair(
+                        Rule::string_interpolation,
+                        "#{x}",
+                        &[pair(
+                            Rule::expr,
+                            "x",
+                            &[pair(Rule::unary, "x", &[pair(Rule::var, "x", &[])])],
+                        )],
+                    )],
+                )],
+            ),
+        );
+    }
+
+    #[test]
+    fn test_string_without_interpolation() {
+        assert_parsed(
+            "`#{`",
+            pair(Rule::string_const, "`#{`", &[pair(Rule::string_interior, "#{", &[])]),
+        );
+    }
+}
+
+mod math {
+
+    use super::*;
+
+    #[test]
+    fn test_math_list() {
+        assert_parsed(
+            "| a | b |",
+            pair(
+                Rule::math_list,
+                "| a | b |",
+                &[
+                    pair(
+                        Rule::math_list_item,
+                        "a",
+                        &[pair(Rule::math_single, "a", &[pair(Rule::math_unary, "a", &[])])],
+                    ),
+                    pair(
+                        Rule::math_list_item,
+                        "b",
+                        &[pair(Rule::math_single, "b", &[pair(Rule::math_unary, "b", &[])])],
+                    ),
+                ],
+            ),
+        );
+    }
+}
+
+mod rule_name {
+
+    use super::super::rule_name::{rule_by_name, rule_name};
+    use super::*;
+
+    #[test]
+    fn test_rule_names() {
+        assert_eq!(rule_by_name("sig_direct_stmt"), Some(Rule::sig_direct_stmt));
+        assert_eq!(rule_by_name("no_such_rule"), None);
+        // satysfi.pest のすべての規則を名前から引ける。
+        let grammar = include_str!("satysfi.pest");
+        let names = grammar
+            .lines()
+            .filter_map(|line| line.split_once('=').map(|(name, _)| name.trim()))
+            .filter(|name| !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_'));
+        for name in names {
+            let rule = rule_by_name(name).unwrap_or_else(|| panic!("no rule named {}", name));
+            assert_eq!(rule_name(rule), name);
+            assert_eq!(format!("{:?}", rule), name);
+        }
+    }
+}
@@ -0,0 +1,106 @@
+//! カーソルを含む段落やプリアンブルの文だけを切り出してパースする関数群。
+//!
+//! 入力の途中でバッファ全体のパースに失敗している間も、補完などがカーソル位置のモードを
+//! すぐに決められるようにする。切り出す範囲は行の先頭の字句から推測し、
+//! 長さは [`MAX_CHUNK_SIZE`] までに抑える。
+
+use std::convert::TryFrom;
+
+use pest::Parser;
+
+use crate::{
+    parser::{Mode, ModeRegion, Rule, SatysfiParser},
+    position::{floor_char_boundary, LineIndex, Position},
+    Cst,
+};
+
+/// 切り出す範囲の最大のバイト数。カーソルは切り出す範囲の先頭からこの長さ以内になければならない。
+pub const MAX_CHUNK_SIZE: usize = 4096;
+
+/// 行頭にあればプリアンブルの文の始まりとみなすキーワード。
+const STATEMENT_KEYWORDS: &[&str] = &[
+    "let",
+    "let-inline",
+    "let-block",
+    "let-math",
+    "let-mutable",
+    "type",
+    "module",
+];
+
+/// 切り出した部分をパースした結果。
+#[derive(Debug)]
+pub struct PartialParse {
+    /// 切り出した部分の Cst。位置はバッファ全体での位置に直してある。
+    pub cst: Cst,
+    /// 切り出した部分をパースした文法規則。`statement` か `vertical_mode` のいずれか。
+    pub rule: Rule,
+}
+
+impl PartialParse {
+    /// 与えられた pos におけるモードと、そのモードが続く範囲を返す。
+    /// どのモードの領域にも含まれない場合は、切り出した部分全体を規則に応じたモードとみなす。
+    pub fn mode_region(&self, pos: &Position) -> ModeRegion {
+        let region = self.cst.mode_region(pos);
+        if region.mode == Mode::Program && region.range == self.cst.range() {
+            let mode = match self.rule {
+                Rule::vertical_mode => Mode::Vertical,
+                _ => Mode::Program,
+            };
+            return ModeRegion { mode, ..region };
+        }
+        region
+    }
+}
+
+/// text のうち pos を含む段落（ブロックコマンド）またはプリアンブルの文だけをパースする。
+/// 切り出す範囲を推測できない場合や、その範囲のパースが pos まで届かない場合は None を返す。
+pub fn parse_around(text: &str, pos: &Position) -> Option<PartialParse> {
+    let index = LineIndex::new(text);
+    let cursor = index.offset(*pos);
+    let (start, rule) = chunk_start(text, cursor)?;
+    let end = floor_char_boundary(text, start + MAX_CHUNK_SIZE);
+
+    // 規則の後ろに EOI を要求しないため、途中で壊れていてもその手前まではパースできる。
+    let pair = SatysfiParser::parse(rule, &text[start..end]).ok()?.next()?;
+    let mut cst = Cst::new(pair, &text[start..end]);
+    if start + (cst.range.end.byte as usize) < cursor {
+        return None;
+    }
+    let head = index.position(start);
+    cst.shift(u32::try_from(start).ok()?, head.line, head.character);
+    Some(PartialParse { cst, rule })
+}
+
+/// cursor を含む段落または文の始まる位置と、その部分をパースする規則を行単位で推測する。
+/// 行頭の `+` はブロックコマンドの、インデントのない行頭のキーワードは文の始まりとみなす。
+/// インデントのない行がそれ以外で始まる場合（`in` や `document` など）は推測をあきらめる。
+fn chunk_start(text: &str, cursor: usize) -> Option<(usize, Rule)> {
+    let mut line_end = cursor;
+    loop {
+        let line_start = text[..line_end].rfind('\n').map_or(0, |i| i + 1);
+        if cursor - line_start > MAX_CHUNK_SIZE {
+            return None;
+        }
+        let line_len = text[line_start..].find('\n').unwrap_or(text.len() - line_start);
+        let line = &text[line_start..line_start + line_len];
+        let trimmed = line.trim_start();
+        let head = line_start + line.len() - trimmed.len();
+        if trimmed.starts_with('+') && head <= cursor {
+            return Some((head, Rule::vertical_mode));
+        }
+        if head == line_start && !trimmed.is_empty() {
+            let word = trimmed.split(|c: char| c.is_whitespace()).next()?;
+            return STATEMENT_KEYWORDS
+                .contains(&word)
+                .then_some((head, Rule::statement));
+        }
+        if line_start == 0 {
+            return None;
+        }
+        line_end = line_start - 1;
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,53 @@
+//! test module for partial parsing.
+
+use super::*;
+use crate::test_utils::TestDocument;
+
+fn mode(doc: &TestDocument, marker: u32) -> Option<Mode> {
+    let pos = doc.position(marker);
+    parse_around(doc.text(), &pos).map(|partial| partial.mode_region(&pos).mode)
+}
+
+#[test]
+fn test_paragraph() {
+    // 2 つ目の段落が書きかけでも、3 つ目の段落はパースできる。
+    let doc = TestDocument::new("'<\n  +p{ x }\n  +q(\n  +p^3{ a ^1${b^2} \\f }\n>\n");
+    assert_eq!(mode(&doc, 1), Some(Mode::Horizontal));
+    assert_eq!(mode(&doc, 2), Some(Mode::Math));
+    assert_eq!(mode(&doc, 3), Some(Mode::Vertical));
+
+    let partial = parse_around(doc.text(), &doc.position(1)).unwrap();
+    assert_eq!(partial.rule, Rule::vertical_mode);
+    assert_eq!(partial.cst.range().start, Position { line: 3, character: 2 });
+    let dummy = partial.cst.pickup(Rule::dummy_inline_cmd_incomplete);
+    assert_eq!(dummy[0].range().start, Position { line: 3, character: 13 });
+}
+
+#[test]
+fn test_statement() {
+    let doc = TestDocument::new("@require: stdjabook\nlet-inline ctx \\foo =\n  re^1ad-inline ctx {^2a}\nin\n'<\n  +p(\n>\n");
+    assert_eq!(mode(&doc, 1), Some(Mode::Program));
+    assert_eq!(mode(&doc, 2), Some(Mode::Horizontal));
+    let partial = parse_around(doc.text(), &doc.position(1)).unwrap();
+    assert_eq!(partial.rule, Rule::statement);
+}
+
+#[test]
+fn test_unknown_chunk() {
+    let doc = TestDocument::new("let x = 1\ni^2n\ndocument (|\n  ti^1tle = {a};\n|) '<\n>\n");
+    assert_eq!(mode(&doc, 1), None);
+    assert_eq!(mode(&doc, 2), None);
+    // パースが位置まで届かない場合。
+    assert_eq!(mode(&TestDocument::new("'<\n  +p{ a } )\n  b^1\n>\n"), 1), None);
+}
+
+#[test]
+fn test_chunk_size_limit() {
+    // カーソルは段落の最後の行に置く。
+    let paragraph = |lines: usize| {
+        TestDocument::new(&format!("'<\n  +p{{\n{}  ^1a\n  }}\n>\n", "  a\n".repeat(lines - 1)))
+    };
+    assert_eq!(mode(&paragraph(10), 1), Some(Mode::Horizontal));
+    // 段落の始まりがカーソルから離れすぎている場合は切り出さない。
+    assert_eq!(mode(&paragraph(MAX_CHUNK_SIZE / 4 + 1), 1), None);
+}
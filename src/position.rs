@@ -0,0 +1,145 @@
+//! バッファ中の位置と範囲の型、およびそれらとバイトオフセットとを相互に変換する関数群。
+//!
+//! パーサや Cst、[`LineIndex`] は LSP に依存しない [`Position`] と [`Range`] を用いる。
+//! LSP の型とは [`From`] で相互に変換する。
+//! Position の character は、このサーバ全体で Unicode scalar value 単位の列として扱う。
+
+use serde::{Deserialize, Serialize};
+
+/// バッファ中の位置。0 始まりの行と、行頭からの列で表す。
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct Position {
+    /// 行番号。
+    pub line: u32,
+    /// 行頭からの列。
+    pub character: u32,
+}
+
+impl Position {
+    /// 行と列から Position を作る。
+    pub fn new(line: u32, character: u32) -> Self {
+        Self { line, character }
+    }
+}
+
+/// バッファ中の範囲。終端は範囲に含まない。
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Range {
+    /// 範囲の始まり。
+    pub start: Position,
+    /// 範囲の終わり。
+    pub end: Position,
+}
+
+impl Range {
+    /// 始まりと終わりから Range を作る。
+    pub fn new(start: Position, end: Position) -> Self {
+        Self { start, end }
+    }
+}
+
+impl From<lsp_types::Position> for Position {
+    fn from(pos: lsp_types::Position) -> Self {
+        Self { line: pos.line, character: pos.character }
+    }
+}
+
+impl From<Position> for lsp_types::Position {
+    fn from(pos: Position) -> Self {
+        Self { line: pos.line, character: pos.character }
+    }
+}
+
+impl From<lsp_types::Range> for Range {
+    fn from(range: lsp_types::Range) -> Self {
+        Self { start: range.start.into(), end: range.end.into() }
+    }
+}
+
+impl From<Range> for lsp_types::Range {
+    fn from(range: Range) -> Self {
+        Self { start: range.start.into(), end: range.end.into() }
+    }
+}
+
+/// テキストの各行の開始位置を保持し、Position とバイトオフセットを変換する。
+#[derive(Debug, Clone)]
+pub struct LineIndex<'a> {
+    /// 対象のテキスト。
+    text: &'a str,
+    /// 各行の開始バイトオフセット。最初の要素は常に 0。
+    line_starts: Vec<usize>,
+}
+
+impl<'a> LineIndex<'a> {
+    /// テキストから各行の開始位置を計算する。
+    pub fn new(text: &'a str) -> Self {
+        let line_starts = std::iter::once(0)
+            .chain(text.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        Self { text, line_starts }
+    }
+
+    /// Position をバイトオフセットに変換する。
+    /// 行末より後ろの列は行末に、最終行より後ろの行はテキストの末尾に丸める。
+    pub fn offset(&self, pos: Position) -> usize {
+        let start = match self.line_starts.get(pos.line as usize) {
+            Some(&start) => start,
+            None => return self.text.len(),
+        };
+        let line = self.line(pos.line as usize);
+        let column: usize = line
+            .chars()
+            .take(pos.character as usize)
+            .map(char::len_utf8)
+            .sum();
+        start + column
+    }
+
+    /// バイトオフセットを Position に変換する。
+    /// 文字の途中を指すオフセットは、その文字の先頭に丸める。
+    pub fn position(&self, offset: usize) -> Position {
+        let offset = offset.min(self.text.len());
+        let line = match self.line_starts.binary_search(&offset) {
+            Ok(line) => line,
+            Err(next) => next - 1,
+        };
+        let start = self.line_starts[line];
+        // offset より前で終わる文字だけを数え、文字の途中を指す場合はその文字を数えない。
+        let character = self.text[start..]
+            .char_indices()
+            .take_while(|(i, c)| start + i + c.len_utf8() <= offset)
+            .count();
+        Position {
+            line: line as u32,
+            character: character as u32,
+        }
+    }
+
+    /// 行数。
+    pub fn line_count(&self) -> usize {
+        self.line_starts.len()
+    }
+
+    /// `line` 行目の内容を改行文字を除いて返す。
+    pub(crate) fn line(&self, line: usize) -> &'a str {
+        let start = self.line_starts[line];
+        let end = self
+            .line_starts
+            .get(line + 1)
+            .map_or(self.text.len(), |next| next - 1);
+        &self.text[start..end]
+    }
+}
+
+/// offset を text の長さ以下で、それ以前の最も近い文字の境界に丸める。
+pub fn floor_char_boundary(text: &str, offset: usize) -> usize {
+    let mut offset = offset.min(text.len());
+    while !text.is_char_boundary(offset) {
+        offset -= 1;
+    }
+    offset
+}
+
+#[cfg(test)]
+mod tests;
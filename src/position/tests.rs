@@ -0,0 +1,92 @@
+//! test module for LineIndex.
+
+use super::*;
+
+fn pos(line: u32, character: u32) -> Position {
+    Position { line, character }
+}
+
+#[test]
+fn test_offset() {
+    let index = LineIndex::new("ab\nあいう\n");
+    assert_eq!(index.offset(pos(0, 1)), 1);
+    assert_eq!(index.offset(pos(1, 0)), 3);
+    assert_eq!(index.offset(pos(1, 2)), 9);
+    // 行末より後ろは行末に丸める。
+    assert_eq!(index.offset(pos(0, 10)), 2);
+    assert_eq!(index.offset(pos(5, 0)), 13);
+}
+
+#[test]
+fn test_position() {
+    let index = LineIndex::new("ab\nあいう\n");
+    assert_eq!(index.line_count(), 3);
+    assert_eq!(index.position(1), pos(0, 1));
+    assert_eq!(index.position(3), pos(1, 0));
+    assert_eq!(index.position(9), pos(1, 2));
+    assert_eq!(index.position(13), pos(2, 0));
+}
+
+#[test]
+fn test_roundtrip() {
+    let text = "let x = `全角`\n  in x\n";
+    let index = LineIndex::new(text);
+    for (offset, _) in text.char_indices() {
+        assert_eq!(index.offset(index.position(offset)), offset);
+    }
+}
+
+/// テストのための決定的な擬似乱数列 (xorshift)。
+struct Rng(u64);
+
+impl Rng {
+    fn below(&mut self, n: usize) -> usize {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 % n as u64) as usize
+    }
+}
+
+/// 多バイト文字や改行を混ぜた文字列を作る。
+fn random_text(rng: &mut Rng) -> String {
+    const PIECES: &[&str] = &["a", " ", "\n", "\r\n", "é", "あ", "😀", "\\emph{", "}"];
+    let len = rng.below(30);
+    (0..len).map(|_| PIECES[rng.below(PIECES.len())]).collect()
+}
+
+#[test]
+fn test_random_conversions() {
+    let mut rng = Rng(0x5eed_1234_abcd_0001);
+    for _ in 0..500 {
+        let text = random_text(&mut rng);
+        let index = LineIndex::new(&text);
+        for offset in 0..=text.len() + 4 {
+            let floor = floor_char_boundary(&text, offset);
+            assert!(text.is_char_boundary(floor) && floor <= offset, "{:?} {}", text, offset);
+            assert!(offset > text.len() || offset - floor < 4);
+            // 文字の途中を指すオフセットは、その文字の先頭に丸めてから往復する。
+            assert_eq!(index.offset(index.position(offset)), floor, "{:?} {}", text, offset);
+        }
+        for _ in 0..20 {
+            let p = pos(rng.below(index.line_count() + 2) as u32, rng.below(12) as u32);
+            let offset = index.offset(p);
+            assert!(text.is_char_boundary(offset), "{:?} {:?}", text, p);
+            // 行末やテキストの末尾より後ろを指す位置は、そこに丸められる。
+            let back = index.position(offset);
+            assert!(back <= p, "{:?} {:?} {:?}", text, p, back);
+            assert_eq!(index.offset(back), offset);
+        }
+    }
+}
+
+#[test]
+fn test_lsp_conversion() {
+    let range = Range::new(pos(1, 2), pos(3, 4));
+    let lsp: lsp_types::Range = range.into();
+    assert_eq!(lsp.start, lsp_types::Position::new(1, 2));
+    assert_eq!(lsp.end, lsp_types::Position::new(3, 4));
+    assert_eq!(Range::from(lsp), range);
+    // JSON にしたときの形も LSP の型と変わらない。
+    assert_eq!(serde_json::to_value(range).unwrap(), serde_json::to_value(lsp).unwrap());
+}
@@ -0,0 +1,17 @@
+//! ライブラリを利用する外部のツール（フォーマッタ、リンタ、エディタへの組み込みなど）のための prelude.
+//!
+//! `use maquette_satysfi_language_server::prelude::*;` とすれば、バッファのパースと
+//! Cst の探索、定義の参照に必要な型がまとめて手に入る。
+//! ここから再公開している項目は [`VERSION`] について semver に従って変更し、
+//! 拡張しうる列挙型には `#[non_exhaustive]` を付けている。
+//! その他のモジュールの項目は language server の実装の都合で変わりうる。
+
+pub use crate::{
+    config::{Config, LanguageVersion},
+    parser::{recovery::RecoveryKind, Mode, ModeRegion, Rule},
+    position::{LineIndex, Position, Range},
+    resolve::{PackageKind, Stage},
+    scope::{local_bindings, BindingKind, LocalBinding},
+    Buffer, BufferCst, CmdKind, CommandDef, Cst, Environment, Package, ParamKind, SliceError,
+    TypeDef, TypeDefKind, Variable, VERSION,
+};
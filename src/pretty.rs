@@ -0,0 +1,250 @@
+//! 選択した式を整形して返す `satysfi/prettyPrintRange` リクエスト。
+//!
+//! 文書全体の整形を有効にしていなくても、カーソル下の式だけを整形できるようにする。
+//! 字句の間の空白を正規化し、`let ... in` の後と match の各腕の前で改行する。
+//! テキストや数式、文字列リテラル、コメントは書かれたとおりに残す。
+
+use anyhow::{anyhow, Result};
+use lsp_types::{request::Request, TextDocumentIdentifier};
+use serde::{Deserialize, Serialize};
+
+use crate::{config::FormatConfig, parser::Rule, position::Range, Buffer, BufferCst, Cst};
+
+/// 選択範囲の式を整形するカスタムリクエスト。
+pub enum PrettyPrintRange {}
+
+impl Request for PrettyPrintRange {
+    type Params = PrettyPrintRangeParams;
+    type Result = Option<PrettyPrintRangeResult>;
+    const METHOD: &'static str = "satysfi/prettyPrintRange";
+}
+
+/// `satysfi/prettyPrintRange` のパラメータ。
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PrettyPrintRangeParams {
+    /// 対象のドキュメント。
+    pub text_document: TextDocumentIdentifier,
+    /// 整形したい範囲。空であればカーソル位置とみなす。
+    pub range: Range,
+}
+
+/// `satysfi/prettyPrintRange` の結果。
+#[derive(Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PrettyPrintRangeResult {
+    /// 整形した式や文の範囲。選択範囲を含む。
+    pub range: Range,
+    /// 整形した結果。`range` をこれで置き換えればよい。
+    pub new_text: String,
+}
+
+/// 整形の単位とする構文。選択範囲を含む最も内側のものを整形する。
+const FORMAT_UNITS: &[Rule] = &[Rule::expr, Rule::statement, Rule::preamble];
+
+/// 書かれたとおりに残す構文。リテラルは `12pt` のように子を持つものも一つの語とする。
+const VERBATIM: &[Rule] = &[
+    Rule::horizontal_text,
+    Rule::block_text,
+    Rule::math_text,
+    Rule::literal,
+    Rule::COMMENT,
+];
+
+/// prettyPrintRange リクエストへの response を返す。
+/// バッファのパースに失敗しているか、範囲を含む式がなければ None を返す。
+pub fn get_pretty_print_range_response(
+    buf: &Buffer,
+    params: PrettyPrintRangeParams,
+    config: &FormatConfig,
+) -> Option<PrettyPrintRangeResult> {
+    let cst = buf.buf_cst.cst.as_ref()?;
+    let Range { start, end } = params.range;
+    let unit = cst
+        .dig(&start)
+        .into_iter()
+        .filter(|c| FORMAT_UNITS.contains(&c.rule))
+        .find(|c| c.range.includes(&end))?;
+    Some(PrettyPrintRangeResult {
+        range: unit.range(),
+        new_text: pretty_print(&buf.buf_cst, unit, config),
+    })
+}
+
+/// Cst を整形した文字列を返す。2 行目以降は、Cst の始まる行の字下げに加えて
+/// 入れ子の深さに応じて `indent-width` ずつ字下げする。
+pub fn pretty_print(buf_cst: &BufferCst, cst: &Cst, config: &FormatConfig) -> String {
+    let start = cst.range.start.byte as usize;
+    let line_start = buf_cst.buffer[..start].rfind('\n').map_or(0, |i| i + 1);
+    let line = &buf_cst.buffer[line_start..];
+    let base_indent = &line[..line.len() - line.trim_start_matches([' ', '\t']).len()];
+
+    let mut printer = Printer {
+        buf_cst,
+        tokens: vec![],
+        depth: 0,
+    };
+    printer.node(cst);
+
+    let mut output = String::new();
+    let mut prev: Option<&str> = None;
+    for token in &printer.tokens {
+        match token {
+            Token::Word(word) => {
+                if prev.is_some_and(|prev| needs_space(prev, word)) {
+                    output.push(' ');
+                }
+                output.push_str(word);
+                prev = Some(word);
+            }
+            Token::Newline(depth) => {
+                let trimmed = output.trim_end_matches([' ', '\t']).len();
+                output.truncate(trimmed);
+                output.push('\n');
+                output.push_str(base_indent);
+                output.push_str(&" ".repeat(depth * config.indent_width));
+                prev = None;
+            }
+        }
+    }
+    output.trim_end().to_owned()
+}
+
+/// 文書全体を整形した文字列を返す。
+/// 整形するのはプリアンブルだけで、ヘッダと文書本体のテキストは書かれたとおりに残す。
+/// パースに失敗した場合や、書きかけの文を修復しなければパースできない場合はエラーを返す。
+pub fn format_document(text: String, config: &FormatConfig) -> Result<String> {
+    let (buf_cst, error) = BufferCst::parse_into(text);
+    if let Some(e) = error {
+        return Err(e);
+    }
+    if let Some(recovery) = buf_cst.recoveries().first() {
+        let start = recovery.range.start;
+        return Err(anyhow!(
+            "{} at {}:{}",
+            recovery.kind.message(),
+            start.line + 1,
+            start.character + 1
+        ));
+    }
+    let cst = buf_cst.cst.as_ref().expect("parsed buffer must have CST");
+    let preamble = cst
+        .inner
+        .first()
+        .and_then(|program| program.inner.iter().find(|c| c.rule == Rule::preamble));
+    let preamble = match preamble {
+        Some(preamble) => preamble,
+        None => return Ok(buf_cst.buffer.clone()),
+    };
+    // プリアンブルの範囲は `in` の前の空白まで含む。プリアンブルを閉じる `in` は次の行に置く。
+    let start = preamble.range.start.byte as usize;
+    let end = buf_cst.buffer[..preamble.range.end.byte as usize].trim_end().len();
+    let rest = &buf_cst.buffer[end..];
+    let rest = match rest.trim_start().strip_prefix("in") {
+        Some(after) if after.starts_with(char::is_whitespace) => format!("\nin{}", after),
+        _ => rest.to_owned(),
+    };
+    Ok(format!(
+        "{}{}{}",
+        &buf_cst.buffer[..start],
+        pretty_print(&buf_cst, preamble, config),
+        rest
+    ))
+}
+
+/// 整形した出力の字句。
+#[derive(Debug)]
+enum Token {
+    /// 空白で区切られる語。
+    Word(String),
+    /// 与えられた深さまで字下げした改行。
+    Newline(usize),
+}
+
+/// Cst をたどって字句を並べるもの。
+struct Printer<'a> {
+    /// 元のバッファ。
+    buf_cst: &'a BufferCst,
+    /// 並べた字句。
+    tokens: Vec<Token>,
+    /// 現在の字下げの深さ。
+    depth: usize,
+}
+
+impl Printer<'_> {
+    /// Cst の字句を並べる。
+    fn node(&mut self, cst: &Cst) {
+        let text = self.buf_cst.as_str(cst);
+        if VERBATIM.contains(&cst.rule) {
+            self.word(text.trim_end());
+            // コメントは行末まで続くので、その後で改行する。
+            if cst.rule == Rule::COMMENT {
+                self.newline(self.depth);
+            }
+            return;
+        }
+        if cst.inner.is_empty() {
+            self.gap(text);
+            return;
+        }
+
+        let mut cursor = cst.range.start.byte as usize;
+        for child in cst.inner.iter() {
+            let start = child.range.start.byte as usize;
+            let gap = &self.buf_cst.buffer[cursor..start];
+            if cst.rule == Rule::match_expr && child.rule == Rule::match_arm {
+                // 各腕を `|` から始まる行に置く。
+                let gap = gap.trim_end();
+                self.gap(gap.strip_suffix('|').unwrap_or(gap));
+                self.newline(self.depth + 1);
+                self.word("|");
+                self.depth += 1;
+                self.node(child);
+                self.depth -= 1;
+            } else {
+                self.gap(gap);
+                self.node(child);
+            }
+            if matches!(child.rule, Rule::statement) {
+                self.newline(self.depth);
+            }
+            cursor = child.range.end.byte as usize;
+        }
+        let end = cst.range.end.byte as usize;
+        self.gap(&self.buf_cst.buffer[cursor..end]);
+        if cst.rule == Rule::let_in_stmt {
+            self.newline(self.depth);
+        }
+    }
+
+    /// 子の間にある、キーワードや記号からなる文字列の字句を並べる。
+    fn gap(&mut self, text: &str) {
+        for word in text.split_whitespace() {
+            self.word(word);
+        }
+    }
+
+    /// 語を一つ並べる。
+    fn word(&mut self, word: &str) {
+        self.tokens.push(Token::Word(word.to_owned()));
+    }
+
+    /// 改行する。直前も改行であれば、字下げだけを更新する。
+    fn newline(&mut self, depth: usize) {
+        if let Some(Token::Newline(last)) = self.tokens.last_mut() {
+            *last = depth;
+        } else if !self.tokens.is_empty() {
+            self.tokens.push(Token::Newline(depth));
+        }
+    }
+}
+
+/// 並んだ二つの語の間に空白を置くか。括弧の内側と区切り記号の前には置かない。
+fn needs_space(prev: &str, next: &str) -> bool {
+    let opens = prev.ends_with(['(', '[']);
+    let closes = next.starts_with([')', ']', ',', ';']);
+    !(opens || closes)
+}
+
+#[cfg(test)]
+mod tests;
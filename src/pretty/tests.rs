@@ -0,0 +1,63 @@
+//! test module for pretty printing.
+
+use lsp_types::Url;
+
+use super::*;
+use crate::position::Position;
+
+fn format(text: &str, start: (u32, u32), end: (u32, u32)) -> Option<PrettyPrintRangeResult> {
+    let buf = Buffer::new(text.to_owned());
+    assert!(buf.error.is_empty(), "parse failed: {:?}", buf.error);
+    let params = PrettyPrintRangeParams {
+        text_document: TextDocumentIdentifier::new(Url::parse("file:///test.saty").unwrap()),
+        range: Range {
+            start: Position { line: start.0, character: start.1 },
+            end: Position { line: end.0, character: end.1 },
+        },
+    };
+    get_pretty_print_range_response(&buf, params, &FormatConfig::default())
+}
+
+#[test]
+fn test_spacing() {
+    let text = "let x = f  ( 1 ,2)   [ a;b ]  in\n'<>\n";
+    let result = format(text, (0, 8), (0, 9)).unwrap();
+    assert_eq!(result.new_text, "f (1, 2) [a; b]");
+    assert_eq!(result.range.start, Position { line: 0, character: 8 });
+}
+
+#[test]
+fn test_let_in_and_match() {
+    let text = "let f s =\n    let t = s in match t with Circle r -> r  |Empty  ->  0pt\nin\n'<>\n";
+    let result = format(text, (1, 4), (1, 4)).unwrap();
+    assert_eq!(
+        result.new_text,
+        "let t = s in\n    match t with\n        | Circle r -> r\n        | Empty -> 0pt"
+    );
+}
+
+#[test]
+fn test_verbatim_text() {
+    let text = "let x = f {  two  spaces } `a  b` in\n'<>\n";
+    let result = format(text, (0, 9), (0, 9)).unwrap();
+    assert_eq!(result.new_text, "f {  two  spaces } `a  b`");
+}
+
+#[test]
+fn test_statements() {
+    let text = "let x   = 1  let y=2\nin\n'<>\n";
+    let result = format(text, (0, 1), (0, 20)).unwrap();
+    assert_eq!(result.new_text, "let x = 1\nlet y = 2");
+}
+
+#[test]
+fn test_format_document() {
+    let text = "@require: stdjabook\n\nlet x = f  ( 1 ,2)\nlet y=x  in\n'<\n  +p{ a  b }\n>\n";
+    let formatted = format_document(text.to_owned(), &FormatConfig::default()).unwrap();
+    assert_eq!(formatted, "@require: stdjabook\n\nlet x = f (1, 2)\nlet y = x\nin\n'<\n  +p{ a  b }\n>\n");
+    let again = format_document(formatted.clone(), &FormatConfig::default()).unwrap();
+    assert_eq!(again, formatted);
+
+    assert!(format_document("let x =\nin\n'<>\n".to_owned(), &FormatConfig::default()).is_err());
+    assert!(format_document("'<\n".to_owned(), &FormatConfig::default()).is_err());
+}
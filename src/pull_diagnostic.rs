@@ -0,0 +1,189 @@
+//! LSP 3.17 の pull model による diagnostics (`textDocument/diagnostic`)。
+//!
+//! 使っている lsp-types が pull model に対応していないため、リクエストと結果の型をここで定義する。
+//! diagnostics は publish する場合と同じく [`get_diagnostics`] で計算し、
+//! バッファごとに `resultId` を付けて保持する。
+//! クライアントが前回受け取った `resultId` が最新のものと一致すれば、計算し直さずに unchanged を返す。
+
+use std::collections::HashMap;
+
+use lsp_types::{request::Request, Diagnostic, TextDocumentIdentifier, Url};
+use serde::{Deserialize, Serialize};
+
+use crate::{config::Config, diagnostic::get_diagnostics, Buffer};
+
+/// ドキュメントの diagnostics をクライアントから要求するリクエスト。
+pub enum DocumentDiagnosticRequest {}
+
+impl Request for DocumentDiagnosticRequest {
+    type Params = DocumentDiagnosticParams;
+    type Result = DocumentDiagnosticReport;
+    const METHOD: &'static str = "textDocument/diagnostic";
+}
+
+/// 開かれているドキュメントの diagnostics を取り直すよう、サーバからクライアントに依頼するリクエスト。
+/// 他のファイルの変更によって diagnostics が変わりうるときに送る。
+pub enum WorkspaceDiagnosticRefresh {}
+
+impl Request for WorkspaceDiagnosticRefresh {
+    type Params = ();
+    type Result = ();
+    const METHOD: &'static str = "workspace/diagnostic/refresh";
+}
+
+/// `textDocument/diagnostic` のパラメータ。
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DocumentDiagnosticParams {
+    /// 対象のドキュメント。
+    pub text_document: TextDocumentIdentifier,
+    /// サーバが登録時に指定した識別子。
+    pub identifier: Option<String>,
+    /// クライアントが前回受け取った結果の `resultId`.
+    pub previous_result_id: Option<String>,
+}
+
+/// diagnostics を pull model で提供することを示す capability.
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DiagnosticOptions {
+    /// あるファイルの編集が、他のファイルの diagnostics に影響しうるか。
+    pub inter_file_dependencies: bool,
+    /// `workspace/diagnostic` に対応しているか。
+    pub workspace_diagnostics: bool,
+}
+
+/// サーバが提供する pull model の capability.
+/// 読み込んだパッケージの定義によって diagnostics が変わるため、ファイル間の依存があるとする。
+pub fn diagnostic_options() -> DiagnosticOptions {
+    DiagnosticOptions {
+        inter_file_dependencies: true,
+        workspace_diagnostics: false,
+    }
+}
+
+/// `textDocument/diagnostic` の結果。
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "kind")]
+pub enum DocumentDiagnosticReport {
+    /// すべての diagnostics を含む結果。
+    #[serde(rename = "full")]
+    Full(FullDocumentDiagnosticReport),
+    /// 前回の結果から変わっていないことを示す結果。
+    #[serde(rename = "unchanged")]
+    Unchanged(UnchangedDocumentDiagnosticReport),
+}
+
+/// すべての diagnostics を含む結果。
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FullDocumentDiagnosticReport {
+    /// 次のリクエストで `previousResultId` として送られる識別子。
+    pub result_id: Option<String>,
+    /// diagnostics.
+    pub items: Vec<Diagnostic>,
+}
+
+/// 前回の結果から変わっていないことを示す結果。
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UnchangedDocumentDiagnosticReport {
+    /// 前回の結果の識別子。
+    pub result_id: String,
+}
+
+/// バッファごとに計算済みの diagnostics.
+#[derive(Debug, Clone)]
+struct CachedDiagnostics {
+    /// 計算した結果の識別子。
+    result_id: String,
+    /// 計算したときにバッファのパースが後回しにされていたか。
+    deferred: bool,
+    /// diagnostics.
+    items: Vec<Diagnostic>,
+}
+
+/// publish と pull で共有する、計算済みの diagnostics.
+#[derive(Debug, Default)]
+pub struct DiagnosticCache {
+    /// 最後に振った `resultId` の番号。
+    last_id: u64,
+    /// URI ごとの計算済みの diagnostics.
+    entries: HashMap<Url, CachedDiagnostics>,
+}
+
+impl DiagnosticCache {
+    /// バッファの diagnostics を計算し直して保持し、その内容を返す。
+    /// バッファが更新されたときに呼ぶ。
+    pub fn refresh(&mut self, uri: &Url, buf: &Buffer, config: &Config) -> Vec<Diagnostic> {
+        self.last_id += 1;
+        let entry = CachedDiagnostics {
+            result_id: self.last_id.to_string(),
+            deferred: buf.is_deferred(),
+            items: get_diagnostics(buf, uri, config),
+        };
+        let items = entry.items.clone();
+        self.entries.insert(uri.clone(), entry);
+        items
+    }
+
+    /// バッファが閉じられたときに、保持している diagnostics を捨てる。
+    pub fn remove(&mut self, uri: &Url) {
+        self.entries.remove(uri);
+    }
+
+    /// pull model のリクエストに対する結果を返す。
+    /// 保持している結果がなかったり、その後にバッファがパースされていたりすれば計算し直す。
+    pub fn report(
+        &mut self,
+        uri: &Url,
+        buf: &Buffer,
+        config: &Config,
+        previous_result_id: Option<&str>,
+    ) -> DocumentDiagnosticReport {
+        let fresh = self
+            .entries
+            .get(uri)
+            .is_some_and(|entry| entry.deferred == buf.is_deferred());
+        if !fresh {
+            self.refresh(uri, buf, config);
+        }
+        let entry = &self.entries[uri];
+        if previous_result_id == Some(entry.result_id.as_str()) {
+            DocumentDiagnosticReport::Unchanged(UnchangedDocumentDiagnosticReport {
+                result_id: entry.result_id.clone(),
+            })
+        } else {
+            DocumentDiagnosticReport::Full(FullDocumentDiagnosticReport {
+                result_id: Some(entry.result_id.clone()),
+                items: entry.items.clone(),
+            })
+        }
+    }
+
+    /// 保持しているすべての結果を捨てる。設定が変わったときなどに呼ぶ。
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+/// diagnostic リクエストへの response を返す。
+/// バッファが開かれていなければ、diagnostics のない結果を返す。
+pub fn get_document_diagnostic_response(
+    cache: &mut DiagnosticCache,
+    buf: Option<&Buffer>,
+    params: DocumentDiagnosticParams,
+    config: &Config,
+) -> DocumentDiagnosticReport {
+    let uri = &params.text_document.uri;
+    match buf {
+        Some(buf) => cache.report(uri, buf, config, params.previous_result_id.as_deref()),
+        None => DocumentDiagnosticReport::Full(FullDocumentDiagnosticReport {
+            result_id: None,
+            items: vec![],
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,59 @@
+//! test module for pull diagnostics.
+
+use super::*;
+
+fn params(previous_result_id: Option<&str>) -> DocumentDiagnosticParams {
+    DocumentDiagnosticParams {
+        text_document: TextDocumentIdentifier {
+            uri: Url::parse("file:///main.saty").unwrap(),
+        },
+        identifier: None,
+        previous_result_id: previous_result_id.map(str::to_owned),
+    }
+}
+
+fn full(report: DocumentDiagnosticReport) -> FullDocumentDiagnosticReport {
+    match report {
+        DocumentDiagnosticReport::Full(full) => full,
+        DocumentDiagnosticReport::Unchanged(_) => panic!("unexpected unchanged report"),
+    }
+}
+
+#[test]
+fn test_result_id() {
+    let config = Config::default();
+    let uri = Url::parse("file:///main.saty").unwrap();
+    let mut cache = DiagnosticCache::default();
+    let buf = Buffer::new("'<\n  +sec;\n>\n".to_owned());
+
+    let published = cache.refresh(&uri, &buf, &config);
+    let report = full(get_document_diagnostic_response(&mut cache, Some(&buf), params(None), &config));
+    assert_eq!(report.items, published);
+    let result_id = report.result_id.unwrap();
+
+    let report = get_document_diagnostic_response(&mut cache, Some(&buf), params(Some(&result_id)), &config);
+    assert_eq!(
+        report,
+        DocumentDiagnosticReport::Unchanged(UnchangedDocumentDiagnosticReport {
+            result_id: result_id.clone()
+        })
+    );
+
+    // バッファが更新されれば、前回の resultId を送っても全体を返す。
+    let buf = Buffer::new("let-block ctx +sec = block-nil\nin\n'<\n  +sec;\n>\n".to_owned());
+    cache.refresh(&uri, &buf, &config);
+    let report = full(get_document_diagnostic_response(&mut cache, Some(&buf), params(Some(&result_id)), &config));
+    assert!(report.items.is_empty());
+    assert_ne!(report.result_id.as_deref(), Some(result_id.as_str()));
+}
+
+#[test]
+fn test_report_serialization() {
+    let report = DocumentDiagnosticReport::Unchanged(UnchangedDocumentDiagnosticReport {
+        result_id: "1".to_owned(),
+    });
+    assert_eq!(
+        serde_json::to_value(report).unwrap(),
+        serde_json::json!({"kind": "unchanged", "resultId": "1"})
+    );
+}
@@ -0,0 +1,284 @@
+//! `@require:` や `@import:` で指定されたパッケージの実体を探す関数群。
+
+use std::{
+    ffi::OsString,
+    path::{Path, PathBuf},
+};
+
+use lsp_types::{request::Request, TextDocumentIdentifier, Url};
+use serde::{Deserialize, Serialize};
+
+use crate::{config::Config, Buffer};
+
+/// どのステージからも読み込めるパッケージファイルの拡張子。
+pub const GENERIC_EXTENSION: &str = "satyg";
+
+/// ファイルが処理されるステージ。`@stage:` ヘッダで指定する。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+pub enum Stage {
+    /// `@stage: 0`
+    #[serde(rename = "0")]
+    Zero,
+    /// `@stage: 1`。ヘッダがない場合もこのステージとなる。
+    #[default]
+    #[serde(rename = "1")]
+    One,
+    /// `@stage: persistent`
+    #[serde(rename = "persistent")]
+    Persistent,
+}
+
+impl Stage {
+    /// `@stage:` ヘッダに書かれた文字列からステージを得る。
+    pub fn from_header(text: &str) -> Option<Self> {
+        match text {
+            "0" => Some(Stage::Zero),
+            "1" => Some(Stage::One),
+            "persistent" => Some(Stage::Persistent),
+            _ => None,
+        }
+    }
+
+    /// `@stage:` ヘッダに書く文字列。
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Stage::Zero => "0",
+            Stage::One => "1",
+            Stage::Persistent => "persistent",
+        }
+    }
+
+    /// このステージのファイルから読み込むパッケージの拡張子を優先度順に返す。
+    /// `.satyh` はステージ 1 のパッケージとして書かれることが多いため、
+    /// それ以外のステージでは `.satyg` を優先する。
+    fn package_extensions(self) -> &'static [&'static str] {
+        match self {
+            Stage::One => &["satyh", GENERIC_EXTENSION],
+            Stage::Zero | Stage::Persistent => &[GENERIC_EXTENSION, "satyh"],
+        }
+    }
+}
+
+/// パッケージの読み込み方法。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PackageKind {
+    /// `@require:` で読み込むパッケージ。
+    Require,
+    /// `@import:` で読み込むパッケージ。
+    Import,
+}
+
+/// `stage` で処理される `base` のファイルから読み込まれるパッケージのファイルパスを返す。
+/// 見つからなければ None を返す。
+pub fn resolve_package(
+    base: &Url,
+    kind: PackageKind,
+    name: &str,
+    stage: Stage,
+    config: &Config,
+) -> Option<PathBuf> {
+    match kind {
+        PackageKind::Require => resolve_require(name, stage, &require_dirs(config)),
+        PackageKind::Import => resolve_import(base, name, stage)
+            .filter(|path| is_within_roots(path, &import_roots(base, config))),
+    }
+}
+
+/// `@import:` で指定されたパッケージが、読み込みを許されたディレクトリの外にあれば、そのファイルパスを返す。
+/// 信頼できない文書を開いたときに、思わぬファイルが読まれるのを防ぐために用いる。
+pub fn import_outside_roots(
+    base: &Url,
+    name: &str,
+    stage: Stage,
+    config: &Config,
+) -> Option<PathBuf> {
+    resolve_import(base, name, stage)
+        .filter(|path| !is_within_roots(path, &import_roots(base, config)))
+}
+
+/// `@import:` で読み込めるファイルを置けるディレクトリを返す。
+///
+/// 設定ファイルの `allowed-roots` が空でなければそれを用いる。
+/// 空であれば、ワークスペースのルート（なければ `base` のファイルのあるディレクトリ）と
+/// SATySFi のライブラリのルート（[`library_roots`]）を用いる。
+pub fn import_roots(base: &Url, config: &Config) -> Vec<PathBuf> {
+    if !config.allowed_roots.is_empty() {
+        return config.allowed_roots.clone();
+    }
+    let workspace = config.workspace_root.clone().or_else(|| {
+        let path = uri_to_path(base)?;
+        path.parent().map(Path::to_path_buf)
+    });
+    workspace.into_iter().chain(library_roots()).collect()
+}
+
+/// `path` が `roots` のいずれかの下にあるか。
+/// シンボリックリンクや `..` を解決してから比べるため、どちらも実在しなければならない。
+pub fn is_within_roots(path: &Path, roots: &[PathBuf]) -> bool {
+    let path = match path.canonicalize() {
+        Ok(path) => path,
+        Err(_) => return false,
+    };
+    roots
+        .iter()
+        .filter_map(|root| root.canonicalize().ok())
+        .any(|root| path.starts_with(root))
+}
+
+/// `@require:` で指定されたパッケージを探すディレクトリを優先度順に返す。
+///
+/// 1. コマンドライン引数 `--package-path` で指定されたディレクトリ
+/// 2. 設定ファイルの `search-paths` で指定されたディレクトリ
+/// 3. SATySFi のライブラリの `dist/packages`（[`library_roots`] の順）
+pub fn require_dirs(config: &Config) -> Vec<PathBuf> {
+    let lib_dirs = library_roots()
+        .into_iter()
+        .map(|root| root.join("dist").join("packages"));
+    config
+        .package_paths
+        .iter()
+        .chain(&config.search_paths)
+        .cloned()
+        .chain(lib_dirs)
+        .collect()
+}
+
+/// `@require:` で指定されたパッケージのファイルパスを返す。
+/// `dirs` に含まれるディレクトリを順に探し、見つからなければ None を返す。
+pub fn resolve_require(name: &str, stage: Stage, dirs: &[PathBuf]) -> Option<PathBuf> {
+    dirs.iter().find_map(|dir| find_package_file(dir, name, stage))
+}
+
+/// URI がディスク上のファイルを指すか。
+/// エディタが保存前のバッファに与える `untitled:` などの URI はファイルを持たないため、
+/// 相対パスで指定する `@import:` を解決できない。
+pub fn is_file_uri(uri: &Url) -> bool {
+    uri.scheme() == "file"
+}
+
+/// ファイルを指す URI をパスに変換する。
+/// `wasm32-unknown-unknown` のようにファイルシステムを持たない環境では常に None を返す。
+pub fn uri_to_path(uri: &Url) -> Option<PathBuf> {
+    #[cfg(any(unix, windows, target_os = "redox", target_os = "wasi"))]
+    return uri.to_file_path().ok();
+    #[cfg(not(any(unix, windows, target_os = "redox", target_os = "wasi")))]
+    return {
+        let _ = uri;
+        None
+    };
+}
+
+/// 絶対パスをファイルを指す URI に変換する。
+/// `wasm32-unknown-unknown` のようにファイルシステムを持たない環境では常に None を返す。
+pub fn path_to_uri(path: &Path) -> Option<Url> {
+    #[cfg(any(unix, windows, target_os = "redox", target_os = "wasi"))]
+    return Url::from_file_path(path).ok();
+    #[cfg(not(any(unix, windows, target_os = "redox", target_os = "wasi")))]
+    return {
+        let _ = path;
+        None
+    };
+}
+
+/// `@import:` で指定されたパッケージのファイルパスを返す。
+/// パスは `base` で示されるファイルのあるディレクトリからの相対パスとして解釈する。
+/// `base` がファイルを指さない URI であれば None を返す。
+pub fn resolve_import(base: &Url, name: &str, stage: Stage) -> Option<PathBuf> {
+    if !is_file_uri(base) {
+        return None;
+    }
+    let base = uri_to_path(base)?;
+    let dir = base.parent()?;
+    find_package_file(dir, name, stage)
+}
+
+/// SATySFi のライブラリのルートを指定する環境変数。
+pub const LIB_ROOT_ENV: &str = "SATYSFI_LIB_ROOT";
+
+/// SATySFi のライブラリが置かれうるディレクトリの一覧を優先度順に返す。
+/// 環境変数 `SATYSFI_LIB_ROOT` が設定されていれば、それを最優先とする。
+pub fn library_roots() -> Vec<PathBuf> {
+    library_roots_with(|key| std::env::var_os(key))
+}
+
+/// 環境変数を `var` で引いて、[`library_roots`] と同じ一覧を返す。
+fn library_roots_with(var: impl Fn(&str) -> Option<OsString>) -> Vec<PathBuf> {
+    let mut roots = vec![];
+    if let Some(root) = var(LIB_ROOT_ENV).filter(|root| !root.is_empty()) {
+        roots.push(PathBuf::from(root));
+    }
+    if let Some(home) = var("HOME") {
+        roots.push(PathBuf::from(home).join(".satysfi"));
+    }
+    roots.push(PathBuf::from("/usr/local/share/satysfi"));
+    roots.push(PathBuf::from("/usr/share/satysfi"));
+    roots
+}
+
+/// パッケージがどのファイルに解決されるかを返すカスタムリクエスト。
+/// 意図しないファイルが読み込まれているときに原因を調べるために用いる。
+pub enum ResolvePackage {}
+
+impl Request for ResolvePackage {
+    type Params = ResolvePackageParams;
+    type Result = ResolvePackageResult;
+    const METHOD: &'static str = "satysfi/resolvePackage";
+}
+
+/// `satysfi/resolvePackage` のパラメータ。
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResolvePackageParams {
+    /// パッケージを読み込むドキュメント。
+    pub text_document: TextDocumentIdentifier,
+    /// 読み込み方法。
+    pub kind: PackageKind,
+    /// ヘッダに書かれたパッケージ名。
+    pub name: String,
+}
+
+/// `satysfi/resolvePackage` の結果。
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResolvePackageResult {
+    /// 解決されたファイルの URI. 見つからなければ None.
+    pub uri: Option<Url>,
+    /// パッケージを読み込むドキュメントのステージ。
+    pub stage: Stage,
+    /// パッケージを探したディレクトリ。優先度順に並ぶ。
+    pub searched: Vec<PathBuf>,
+}
+
+/// resolvePackage リクエストへの response を返す。
+/// ドキュメントが開かれていなければ、ステージ 1 のファイルとして解決する。
+pub fn get_resolve_package_response(
+    buf: Option<&Buffer>,
+    params: ResolvePackageParams,
+    config: &Config,
+) -> ResolvePackageResult {
+    let base = &params.text_document.uri;
+    let stage = buf.map(|buf| buf.buf_cst.stage()).unwrap_or_default();
+    let searched = match params.kind {
+        PackageKind::Require => require_dirs(config),
+        PackageKind::Import => uri_to_path(base)
+            .and_then(|path| path.parent().map(Path::to_path_buf))
+            .into_iter()
+            .collect(),
+    };
+    let uri = resolve_package(base, params.kind, &params.name, stage, config)
+        .and_then(|path| path_to_uri(&path));
+    ResolvePackageResult { uri, stage, searched }
+}
+
+/// `dir` 直下から `name` に拡張子をつけたファイルを探す。
+fn find_package_file(dir: &Path, name: &str, stage: Stage) -> Option<PathBuf> {
+    stage
+        .package_extensions()
+        .iter()
+        .map(|ext| dir.join(format!("{}.{}", name, ext)))
+        .find(|path| path.is_file())
+}
+
+#[cfg(test)]
+mod tests;
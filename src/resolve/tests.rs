@@ -0,0 +1,102 @@
+//! test module for package resolution.
+
+use std::fs;
+
+use super::*;
+use crate::test_utils::temp_dir;
+
+#[test]
+fn test_resolve_import_by_stage() {
+    let dir = temp_dir("resolve");
+    fs::write(dir.join("both.satyh"), "").unwrap();
+    fs::write(dir.join("both.satyg"), "").unwrap();
+    fs::write(dir.join("generic.satyg"), "").unwrap();
+    let base = Url::from_file_path(dir.join("main.saty")).unwrap();
+
+    assert_eq!(resolve_import(&base, "both", Stage::One), Some(dir.join("both.satyh")));
+    assert_eq!(resolve_import(&base, "both", Stage::Zero), Some(dir.join("both.satyg")));
+    assert_eq!(resolve_import(&base, "generic", Stage::One), Some(dir.join("generic.satyg")));
+    assert_eq!(resolve_import(&base, "missing", Stage::One), None);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_stage_from_header() {
+    assert_eq!(Stage::from_header("0"), Some(Stage::Zero));
+    assert_eq!(Stage::from_header("persistent"), Some(Stage::Persistent));
+    assert_eq!(Stage::from_header("2"), None);
+}
+
+#[test]
+fn test_require_priority() {
+    let dir = temp_dir("require");
+    fs::create_dir_all(dir.join("cli")).unwrap();
+    fs::create_dir_all(dir.join("config")).unwrap();
+    fs::write(dir.join("cli").join("pkg.satyh"), "").unwrap();
+    fs::write(dir.join("config").join("pkg.satyh"), "").unwrap();
+    fs::write(dir.join("config").join("other.satyh"), "").unwrap();
+
+    let config = Config {
+        search_paths: vec![dir.join("config")],
+        package_paths: vec![dir.join("cli")],
+        ..Default::default()
+    };
+    let dirs = require_dirs(&config);
+    assert_eq!(dirs[..2], [dir.join("cli"), dir.join("config")]);
+    assert!(dirs[2..].iter().all(|d| d.ends_with("dist/packages")));
+
+    assert_eq!(resolve_require("pkg", Stage::One, &dirs), Some(dir.join("cli").join("pkg.satyh")));
+    assert_eq!(
+        resolve_require("other", Stage::One, &dirs),
+        Some(dir.join("config").join("other.satyh"))
+    );
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_lib_root_env() {
+    let env = |vars: &'static [(&'static str, &'static str)]| {
+        move |key: &str| vars.iter().find(|(k, _)| *k == key).map(|(_, v)| OsString::from(v))
+    };
+    let roots = library_roots_with(env(&[(LIB_ROOT_ENV, "/opt/satysfi"), ("HOME", "/home/user")]));
+    assert_eq!(roots[..2], [PathBuf::from("/opt/satysfi"), PathBuf::from("/home/user/.satysfi")]);
+    // 空の値は設定されていないものとみなす。
+    let roots = library_roots_with(env(&[(LIB_ROOT_ENV, "")]));
+    assert_eq!(roots, [PathBuf::from("/usr/local/share/satysfi"), PathBuf::from("/usr/share/satysfi")]);
+}
+
+#[test]
+fn test_resolve_import_from_untitled() {
+    let untitled = Url::parse("untitled:Untitled-1").unwrap();
+    assert!(!is_file_uri(&untitled));
+    assert_eq!(resolve_import(&untitled, "local", Stage::One), None);
+}
+
+#[test]
+fn test_import_outside_roots() {
+    let dir = temp_dir("sandbox");
+    fs::create_dir_all(dir.join("workspace")).unwrap();
+    fs::write(dir.join("workspace").join("local.satyh"), "").unwrap();
+    fs::write(dir.join("outside.satyh"), "").unwrap();
+    let base = Url::from_file_path(dir.join("workspace").join("main.saty")).unwrap();
+    let config = Config { workspace_root: Some(dir.join("workspace")), ..Default::default() };
+
+    let resolve = |name: &str, config: &Config| {
+        resolve_package(&base, PackageKind::Import, name, Stage::One, config)
+    };
+    assert_eq!(resolve("local", &config), Some(dir.join("workspace").join("local.satyh")));
+    assert_eq!(resolve("../outside", &config), None);
+    assert_eq!(
+        import_outside_roots(&base, "../outside", Stage::One, &config),
+        Some(dir.join("workspace").join("../outside.satyh"))
+    );
+    assert_eq!(import_outside_roots(&base, "local", Stage::One, &config), None);
+
+    // allowed-roots を指定すれば、その下のファイルを読み込める。
+    let config = Config { allowed_roots: vec![dir.clone()], ..config };
+    assert_eq!(resolve("../outside", &config), Some(dir.join("workspace").join("../outside.satyh")));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,163 @@
+//! 局所的な束縛のスコープ解析。
+//!
+//! `let ... in` で束縛された変数、関数やコマンド定義の引数、match の各パターンで
+//! 束縛された変数のうち、カーソル位置から見えるものを求める。
+
+use crate::{
+    parser::Rule,
+    position::{Position, Range},
+    type_annotation, BufferCst, Cst,
+};
+
+/// 局所的な束縛の種類。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum BindingKind {
+    /// `let x = ... in` などで束縛された値。
+    Variable,
+    /// `let f x = ... in` で束縛された関数。
+    Function,
+    /// 関数やコマンドの引数。
+    Parameter,
+}
+
+/// カーソル位置から見える局所的な束縛。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LocalBinding {
+    /// 変数名。
+    pub name: String,
+    /// 束縛された場所。
+    pub def_range: Range,
+    /// 束縛する構文全体の範囲。let であればその文、引数であれば関数やコマンドの定義、
+    /// match のパターンであればその腕。
+    pub stmt_range: Range,
+    /// 束縛の種類。
+    pub kind: BindingKind,
+    /// `let x : t = ... in` のように型注釈が書かれていれば、その型のテキスト。
+    pub annotation: Option<String>,
+}
+
+/// pos から見える局所的な束縛を、内側のスコープから順に返す。
+/// 同じ名前の束縛が複数ある場合は、先に現れるものが後のものを隠す。
+pub fn local_bindings(buf_cst: &BufferCst, pos: &Position) -> Vec<LocalBinding> {
+    let cst = match &buf_cst.cst {
+        Some(cst) => cst,
+        None => return vec![],
+    };
+
+    let mut bindings = vec![];
+    for node in cst.dig(pos) {
+        match node.rule {
+            // let ... in expr の expr の中では、let で束縛された名前が見える。
+            Rule::expr => {
+                if let [let_in, body, ..] = &node.inner[..] {
+                    if let_in.rule == Rule::let_in_stmt && body.range.includes(pos) {
+                        if let Some(let_stmt) = let_in.inner.first() {
+                            bindings.extend(let_stmt_bindings(buf_cst, let_stmt));
+                        }
+                    }
+                }
+            }
+            // let f x = expr の expr の中では、引数が見える。
+            Rule::let_stmt if in_body(node, pos) => {
+                let params = node
+                    .inner
+                    .iter()
+                    .filter(|cst| cst.rule == Rule::stmt_argument)
+                    .flat_map(|cst| cst.pickup(Rule::var));
+                bindings.extend(params.map(|var| binding(buf_cst, var, node, BindingKind::Parameter)));
+            }
+            // コマンド定義の本体では、コンテキストと引数が見える。
+            // コマンド名より前に書かれた変数はコンテキストであり、その型は context である。
+            Rule::let_inline_stmt | Rule::let_block_stmt | Rule::let_math_stmt
+                if in_body(node, pos) =>
+            {
+                let (_, params) = node.inner.split_last().unwrap();
+                for (i, param) in params.iter().enumerate() {
+                    if param.rule == Rule::var {
+                        let is_context = i == 0;
+                        bindings.push(LocalBinding {
+                            annotation: is_context.then(|| "context".to_owned()),
+                            ..binding(buf_cst, param, node, BindingKind::Parameter)
+                        });
+                        continue;
+                    }
+                    bindings.extend(
+                        param
+                            .pickup(Rule::var)
+                            .into_iter()
+                            .map(|var| binding(buf_cst, var, node, BindingKind::Parameter)),
+                    );
+                }
+            }
+            // match の各腕では、パターンで束縛された名前が見える。
+            Rule::match_arm => {
+                if let Some(ptn) = node.inner.first() {
+                    if !ptn.range.includes(pos) {
+                        bindings.extend(
+                            ptn.pickup(Rule::var)
+                                .into_iter()
+                                .map(|var| binding(buf_cst, var, node, BindingKind::Variable)),
+                        );
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+
+    // 内側の束縛で隠された外側の束縛を取り除く。
+    let mut visible: Vec<LocalBinding> = vec![];
+    for b in bindings {
+        if visible.iter().all(|v| v.name != b.name) {
+            visible.push(b);
+        }
+    }
+    visible
+}
+
+/// let_stmt で束縛される名前。引数をとるものは関数とみなす。
+fn let_stmt_bindings(buf_cst: &BufferCst, let_stmt: &Cst) -> Vec<LocalBinding> {
+    let is_function = let_stmt
+        .inner
+        .iter()
+        .any(|cst| cst.rule == Rule::stmt_argument);
+    let kind = if is_function {
+        BindingKind::Function
+    } else {
+        BindingKind::Variable
+    };
+    let annotation = type_annotation(buf_cst, let_stmt);
+    match let_stmt.inner.first() {
+        Some(ptn) => ptn
+            .pickup(Rule::var)
+            .into_iter()
+            .map(|var| LocalBinding {
+                annotation: annotation.clone(),
+                ..binding(buf_cst, var, let_stmt, kind)
+            })
+            .collect(),
+        None => vec![],
+    }
+}
+
+/// pos が定義の本体（最後の子である expr）の中にあるか。
+fn in_body(node: &Cst, pos: &Position) -> bool {
+    node.inner
+        .last()
+        .map(|body| body.rule == Rule::expr && body.range.includes(pos))
+        .unwrap_or(false)
+}
+
+fn binding(buf_cst: &BufferCst, var: &Cst, stmt: &Cst, kind: BindingKind) -> LocalBinding {
+    LocalBinding {
+        name: buf_cst.as_str(var).to_owned(),
+        def_range: var.range.clone().into(),
+        stmt_range: stmt.range.clone().into(),
+        kind,
+        annotation: None,
+    }
+}
+
+#[cfg(test)]
+mod tests;
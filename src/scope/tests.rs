@@ -0,0 +1,64 @@
+//! test module for scope analysis.
+
+use super::*;
+use crate::Buffer;
+
+fn bindings(text: &str, line: u32, character: u32) -> Vec<(String, BindingKind)> {
+    let buf = Buffer::new(text.to_owned());
+    assert!(buf.error.is_empty(), "parse failed: {:?}", buf.error);
+    local_bindings(&buf.buf_cst, &Position { line, character })
+        .into_iter()
+        .map(|b| (b.name, b.kind))
+        .collect()
+}
+
+#[test]
+fn test_let_in() {
+    let text = "let x =\n  let y = 1 in\n  let f a = a in\n  f y\nin {}\n";
+    assert_eq!(
+        bindings(text, 3, 2),
+        vec![
+            ("f".to_owned(), BindingKind::Function),
+            ("y".to_owned(), BindingKind::Variable),
+        ]
+    );
+    // let の右辺ではその束縛自身は見えない。
+    assert_eq!(bindings(text, 1, 10), vec![]);
+}
+
+#[test]
+fn test_function_parameters() {
+    let text = "let f a (b, c) =\n  a\nin {}\n";
+    assert_eq!(
+        bindings(text, 1, 2),
+        vec![
+            ("a".to_owned(), BindingKind::Parameter),
+            ("b".to_owned(), BindingKind::Parameter),
+            ("c".to_owned(), BindingKind::Parameter),
+        ]
+    );
+}
+
+#[test]
+fn test_command_parameters() {
+    let text = "let-inline ctx \\foo x =\n  read-inline ctx x\nin {}\n";
+    assert_eq!(
+        bindings(text, 1, 2),
+        vec![
+            ("ctx".to_owned(), BindingKind::Parameter),
+            ("x".to_owned(), BindingKind::Parameter),
+        ]
+    );
+}
+
+#[test]
+fn test_match_arm_and_shadowing() {
+    let text = "let f x =\n  match x with\n  | (x, y) -> y\nin {}\n";
+    assert_eq!(
+        bindings(text, 2, 15),
+        vec![
+            ("x".to_owned(), BindingKind::Variable),
+            ("y".to_owned(), BindingKind::Variable),
+        ]
+    );
+}
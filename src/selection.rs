@@ -0,0 +1,51 @@
+//! 選択範囲の拡大に関する関数群。
+
+use lsp_types::{SelectionRange, SelectionRangeParams};
+
+use crate::{
+    position::{Position, Range},
+    Buffer, Cst,
+};
+
+/// selectionRange リクエストへの response を返す。
+pub fn get_selection_range_response(
+    buf: &Buffer,
+    params: SelectionRangeParams,
+) -> Option<Vec<SelectionRange>> {
+    let cst = buf.buf_cst.cst.as_ref()?;
+    let ranges = params
+        .positions
+        .iter()
+        .map(|&pos| selection_range(cst, &pos.into()))
+        .collect();
+    Some(ranges)
+}
+
+/// pos を含む Cst を内側から順に並べ、入れ子の SelectionRange を作る。
+/// 数式リストの各要素 (math_list_item) も 1 つの選択単位となる。
+fn selection_range(cst: &Cst, pos: &Position) -> SelectionRange {
+    let mut ranges: Vec<Range> = vec![];
+    for cst in cst.dig(pos) {
+        let range = cst.range();
+        if ranges.last() != Some(&range) {
+            ranges.push(range);
+        }
+    }
+
+    // 外側から順に親として積み上げていく。
+    let mut selection: Option<SelectionRange> = None;
+    for range in ranges.into_iter().rev() {
+        selection = Some(SelectionRange {
+            range: range.into(),
+            parent: selection.map(Box::new),
+        });
+    }
+    selection.unwrap_or(SelectionRange {
+        range: Range {
+            start: *pos,
+            end: *pos,
+        }
+        .into(),
+        parent: None,
+    })
+}
@@ -0,0 +1,308 @@
+//! semantic tokens に関する関数群。
+//!
+//! トークンは Cst のうち、コマンド名や変数、リテラルなどの規則にあたる節点から作る。
+//! バッファは変更のたびに全体をパースし直すため、トークンも Cst 全体から作り直すことになる。
+//! そこで作ったトークンを `resultId` とともにバッファごとに保持し、
+//! 同じ版のバッファに対するリクエストでは作り直さず、`semanticTokens/full/delta` では
+//! 前回の結果から変わった部分だけを送る。
+
+use std::collections::HashMap;
+
+use lsp_types::{
+    Range, SemanticToken, SemanticTokenModifier, SemanticTokenType, SemanticTokens,
+    SemanticTokensDelta, SemanticTokensEdit, SemanticTokensFullDeltaResult,
+    SemanticTokensFullOptions, SemanticTokensLegend, SemanticTokensOptions, Url,
+};
+
+use crate::{parser::Rule, position::LineIndex, Buffer, Cst};
+
+/// トークンの種類。順序が legend での番号になる。
+const TOKEN_TYPES: &[SemanticTokenType] = &[
+    SemanticTokenType::FUNCTION,
+    SemanticTokenType::VARIABLE,
+    SemanticTokenType::NAMESPACE,
+    SemanticTokenType::ENUM_MEMBER,
+    SemanticTokenType::TYPE,
+    SemanticTokenType::STRING,
+    SemanticTokenType::NUMBER,
+    SemanticTokenType::COMMENT,
+    SemanticTokenType::KEYWORD,
+];
+
+/// トークンの修飾子。順序が legend でのビットの位置になる。
+const TOKEN_MODIFIERS: &[SemanticTokenModifier] = &[SemanticTokenModifier::DECLARATION];
+
+/// コマンド名を表す種類の番号。
+const FUNCTION_TYPE: u32 = 0;
+
+/// 変数を表す種類の番号。
+const VARIABLE_TYPE: u32 = 1;
+
+/// 定義される名前であることを表す修飾子のビット。
+const DECLARATION_BIT: u32 = 1;
+
+/// サーバが提供する semantic tokens の capability.
+pub fn semantic_tokens_options() -> SemanticTokensOptions {
+    SemanticTokensOptions {
+        legend: SemanticTokensLegend {
+            token_types: TOKEN_TYPES.to_vec(),
+            token_modifiers: TOKEN_MODIFIERS.to_vec(),
+        },
+        range: Some(true),
+        full: Some(SemanticTokensFullOptions::Delta { delta: Some(true) }),
+        ..Default::default()
+    }
+}
+
+/// 規則にあたるトークンの種類の番号。トークンにならない規則であれば None を返す。
+fn token_type(rule: Rule) -> Option<u32> {
+    let ty = match rule {
+        Rule::inline_cmd_name | Rule::block_cmd_name | Rule::math_cmd_name => {
+            SemanticTokenType::FUNCTION
+        }
+        Rule::var | Rule::var_ptn => SemanticTokenType::VARIABLE,
+        Rule::module_name => SemanticTokenType::NAMESPACE,
+        Rule::variant_name => SemanticTokenType::ENUM_MEMBER,
+        Rule::type_name => SemanticTokenType::TYPE,
+        Rule::string_const | Rule::pkgname => SemanticTokenType::STRING,
+        Rule::int_const | Rule::float_const | Rule::length_const => SemanticTokenType::NUMBER,
+        Rule::COMMENT => SemanticTokenType::COMMENT,
+        Rule::header_kind => SemanticTokenType::KEYWORD,
+        _ => return None,
+    };
+    TOKEN_TYPES.iter().position(|t| t == &ty).map(|i| i as u32)
+}
+
+/// 節点の子に付ける修飾子。`inherited` はその節点に付けられた修飾子。
+/// コマンドの定義とパターンの中の名前は、定義される名前とする。
+fn child_modifiers(rule: Rule, inherited: u32) -> u32 {
+    match rule {
+        Rule::let_inline_stmt | Rule::let_block_stmt | Rule::let_math_stmt | Rule::pattern => {
+            DECLARATION_BIT
+        }
+        Rule::arg | Rule::pat_variant | Rule::pat_list | Rule::pat_tuple => inherited,
+        _ => 0,
+    }
+}
+
+/// 文書中の位置を絶対的に表したトークン。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct AbsoluteToken {
+    /// 行。
+    line: u32,
+    /// 行の中での開始位置。
+    start: u32,
+    /// 文字数。
+    length: u32,
+    /// 種類の番号。
+    token_type: u32,
+    /// 修飾子のビット列。
+    modifiers: u32,
+}
+
+/// Cst からトークンを文書の先頭から順に集める。複数行にわたる節点は行ごとのトークンに分ける。
+fn collect_tokens(cst: &Cst, index: &LineIndex<'_>) -> Vec<AbsoluteToken> {
+    fn walk(
+        cst: &Cst,
+        modifiers: u32,
+        index: &LineIndex<'_>,
+        tokens: &mut Vec<AbsoluteToken>,
+    ) {
+        let token_type = match token_type(cst.rule) {
+            Some(token_type) => token_type,
+            None => {
+                let modifiers = child_modifiers(cst.rule, modifiers);
+                for child in cst.inner.iter() {
+                    walk(child, modifiers, index, tokens);
+                }
+                return;
+            }
+        };
+        // 定義される名前となるのは変数とコマンド名だけである。
+        let is_name = matches!(token_type, FUNCTION_TYPE | VARIABLE_TYPE);
+        let modifiers = if is_name { modifiers } else { 0 };
+        let range = cst.range();
+        for line in range.start.line..=range.end.line {
+            let start = if line == range.start.line { range.start.character } else { 0 };
+            let end = if line == range.end.line {
+                range.end.character
+            } else {
+                index.line(line as usize).chars().count() as u32
+            };
+            if start < end {
+                tokens.push(AbsoluteToken {
+                    line,
+                    start,
+                    length: end - start,
+                    token_type,
+                    modifiers,
+                });
+            }
+        }
+    }
+
+    let mut tokens = vec![];
+    walk(cst, 0, index, &mut tokens);
+    tokens
+}
+
+/// トークンを、直前のトークンからの相対位置で表した LSP の形式にする。
+fn encode(tokens: &[AbsoluteToken]) -> Vec<SemanticToken> {
+    let mut prev = (0, 0);
+    tokens
+        .iter()
+        .map(|token| {
+            let delta_line = token.line - prev.0;
+            let delta_start = if delta_line == 0 { token.start - prev.1 } else { token.start };
+            prev = (token.line, token.start);
+            SemanticToken {
+                delta_line,
+                delta_start,
+                length: token.length,
+                token_type: token.token_type,
+                token_modifiers_bitset: token.modifiers,
+            }
+        })
+        .collect()
+}
+
+/// 前回のトークン列 `old` を新しいトークン列 `new` にする編集を返す。
+/// 前後の共通する部分を除いた一つの編集とし、変わっていなければ空を返す。
+/// 編集の位置と削除する数は、トークンを 5 つの整数に展開した列での数で表す。
+fn diff_tokens(old: &[SemanticToken], new: &[SemanticToken]) -> Vec<SemanticTokensEdit> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let deleted = old.len() - prefix - suffix;
+    let inserted = &new[prefix..new.len() - suffix];
+    if deleted == 0 && inserted.is_empty() {
+        return vec![];
+    }
+    vec![SemanticTokensEdit {
+        start: prefix as u32 * 5,
+        delete_count: deleted as u32 * 5,
+        data: Some(inserted.to_vec()),
+    }]
+}
+
+/// バッファごとに作ったトークン。
+#[derive(Debug, Clone)]
+struct CachedTokens {
+    /// 作った結果の識別子。
+    result_id: String,
+    /// 作ったときのバッファの版。
+    version: Option<i32>,
+    /// 文書の先頭から順に並べたトークン。
+    tokens: Vec<AbsoluteToken>,
+    /// `tokens` を LSP の形式にしたもの。
+    data: Vec<SemanticToken>,
+}
+
+/// full, range, delta の各リクエストで共有する、作ったトークン。
+#[derive(Debug, Default)]
+pub struct SemanticTokensCache {
+    /// 最後に振った `resultId` の番号。
+    last_id: u64,
+    /// URI ごとの作ったトークン。
+    entries: HashMap<Url, CachedTokens>,
+}
+
+impl SemanticTokensCache {
+    /// バッファの最新のトークンを保持する。バッファの版が前回と同じであれば作り直さない。
+    /// 作り直した場合は、それまで保持していたものを返す。
+    /// パースに失敗しているバッファやパースを後回しにしているバッファでは、何もせず None を返す。
+    fn refresh(&mut self, uri: &Url, buf: &Buffer) -> Option<Option<CachedTokens>> {
+        let cst = buf.buf_cst.cst.as_ref()?;
+        // 版を持たないバッファは同じ内容か分からないため、毎回作り直す。
+        if let Some(entry) = self.entries.get(uri) {
+            if entry.version == buf.version && buf.version.is_some() {
+                return Some(None);
+            }
+        }
+        self.last_id += 1;
+        let tokens = collect_tokens(cst, &buf.buf_cst.line_index());
+        let entry = CachedTokens {
+            result_id: self.last_id.to_string(),
+            version: buf.version,
+            data: encode(&tokens),
+            tokens,
+        };
+        Some(self.entries.insert(uri.clone(), entry))
+    }
+
+    /// バッファが閉じられたときに、保持しているトークンを捨てる。
+    pub fn remove(&mut self, uri: &Url) {
+        self.entries.remove(uri);
+    }
+
+    /// 保持しているトークンを取り出す。
+    fn get(&self, uri: &Url) -> Option<&CachedTokens> {
+        self.entries.get(uri)
+    }
+}
+
+/// semanticTokens/full リクエストへの response を返す。
+pub fn get_semantic_tokens_full_response(
+    cache: &mut SemanticTokensCache,
+    uri: &Url,
+    buf: &Buffer,
+) -> Option<SemanticTokens> {
+    cache.refresh(uri, buf)?;
+    let entry = cache.get(uri)?;
+    Some(SemanticTokens {
+        result_id: Some(entry.result_id.clone()),
+        data: entry.data.clone(),
+    })
+}
+
+/// semanticTokens/range リクエストへの response を返す。
+/// `range` と同じ行にかかるトークンを返す。
+pub fn get_semantic_tokens_range_response(
+    cache: &mut SemanticTokensCache,
+    uri: &Url,
+    buf: &Buffer,
+    range: Range,
+) -> Option<SemanticTokens> {
+    cache.refresh(uri, buf)?;
+    let entry = cache.get(uri)?;
+    let tokens = entry
+        .tokens
+        .iter()
+        .filter(|token| range.start.line <= token.line && token.line <= range.end.line)
+        .copied()
+        .collect::<Vec<_>>();
+    Some(SemanticTokens { result_id: None, data: encode(&tokens) })
+}
+
+/// semanticTokens/full/delta リクエストへの response を返す。
+/// `previous_result_id` が保持している前回の結果のものであれば差分を、そうでなければすべてのトークンを返す。
+pub fn get_semantic_tokens_delta_response(
+    cache: &mut SemanticTokensCache,
+    uri: &Url,
+    buf: &Buffer,
+    previous_result_id: &str,
+) -> Option<SemanticTokensFullDeltaResult> {
+    let previous = match cache.refresh(uri, buf)? {
+        Some(previous) => Some(previous),
+        // 作り直していなければ、保持しているものが前回の結果である。
+        None => cache.get(uri).cloned(),
+    };
+    let entry = cache.get(uri)?;
+    match previous.filter(|previous| previous.result_id == previous_result_id) {
+        Some(previous) => Some(SemanticTokensFullDeltaResult::TokensDelta(SemanticTokensDelta {
+            result_id: Some(entry.result_id.clone()),
+            edits: diff_tokens(&previous.data, &entry.data),
+        })),
+        None => Some(SemanticTokensFullDeltaResult::Tokens(SemanticTokens {
+            result_id: Some(entry.result_id.clone()),
+            data: entry.data.clone(),
+        })),
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,107 @@
+//! test module for semantic tokens.
+
+use super::*;
+
+fn uri() -> Url {
+    Url::parse("file:///main.saty").unwrap()
+}
+
+fn buffer(text: &str, version: i32) -> Buffer {
+    let mut buf = Buffer::new(text.to_owned());
+    buf.version = Some(version);
+    buf
+}
+
+/// トークンを (行, 開始位置, 長さ, 種類, 修飾子) の組にする。
+fn absolute(text: &str) -> Vec<(u32, u32, u32, SemanticTokenType, u32)> {
+    let buf = Buffer::new(text.to_owned());
+    let cst = buf.buf_cst.cst.as_ref().unwrap();
+    collect_tokens(cst, &buf.buf_cst.line_index())
+        .into_iter()
+        .map(|t| {
+            let ty = TOKEN_TYPES[t.token_type as usize].clone();
+            (t.line, t.start, t.length, ty, t.modifiers)
+        })
+        .collect()
+}
+
+#[test]
+fn test_collect_tokens() {
+    let text = "% note\nlet-inline ctx \\emph it = it\nlet s = `s`\nin\n'<\n  +p{ \\emph{a} }\n>\n";
+    assert_eq!(
+        absolute(text),
+        vec![
+            (0, 0, 6, SemanticTokenType::COMMENT, 0),
+            (1, 11, 3, SemanticTokenType::VARIABLE, DECLARATION_BIT),
+            (1, 15, 5, SemanticTokenType::FUNCTION, DECLARATION_BIT),
+            (1, 21, 2, SemanticTokenType::VARIABLE, DECLARATION_BIT),
+            (1, 26, 2, SemanticTokenType::VARIABLE, 0),
+            (2, 4, 1, SemanticTokenType::VARIABLE, DECLARATION_BIT),
+            (2, 8, 3, SemanticTokenType::STRING, 0),
+            (5, 2, 2, SemanticTokenType::FUNCTION, 0),
+            (5, 6, 5, SemanticTokenType::FUNCTION, 0),
+        ]
+    );
+}
+
+#[test]
+fn test_multiline_token() {
+    let text = "let s = ``a\nbc``\nin\n'<>\n";
+    let strings = absolute(text)
+        .into_iter()
+        .filter(|t| t.3 == SemanticTokenType::STRING)
+        .map(|t| (t.0, t.1, t.2))
+        .collect::<Vec<_>>();
+    assert_eq!(strings, vec![(0, 8, 3), (1, 0, 4)]);
+}
+
+#[test]
+fn test_range() {
+    let text = "let x = 1\nlet y = 2\nlet z = 3\nin\n'<>\n";
+    let mut cache = SemanticTokensCache::default();
+    let range = Range {
+        start: lsp_types::Position { line: 1, character: 0 },
+        end: lsp_types::Position { line: 1, character: 9 },
+    };
+    let tokens = get_semantic_tokens_range_response(&mut cache, &uri(), &buffer(text, 1), range)
+        .unwrap();
+    assert_eq!(tokens.data.len(), 2);
+    assert_eq!(tokens.data[0].delta_line, 1);
+    assert_eq!(tokens.data[0].delta_start, 4);
+}
+
+#[test]
+fn test_delta() {
+    let mut cache = SemanticTokensCache::default();
+    let first = buffer("let x = 1\nin\n'<>\n", 1);
+    let full = get_semantic_tokens_full_response(&mut cache, &uri(), &first).unwrap();
+    let first_id = full.result_id.unwrap();
+
+    // 同じ版に対しては作り直さず、変更のない差分を返す。
+    match get_semantic_tokens_delta_response(&mut cache, &uri(), &first, &first_id).unwrap() {
+        SemanticTokensFullDeltaResult::TokensDelta(delta) => {
+            assert_eq!(delta.result_id, Some(first_id.clone()));
+            assert!(delta.edits.is_empty());
+        }
+        result => panic!("unexpected result: {:?}", result),
+    }
+
+    let second = buffer("let x = 1\nlet y = 2\nin\n'<>\n", 2);
+    match get_semantic_tokens_delta_response(&mut cache, &uri(), &second, &first_id).unwrap() {
+        SemanticTokensFullDeltaResult::TokensDelta(delta) => {
+            assert_ne!(delta.result_id, Some(first_id.clone()));
+            assert_eq!(delta.edits.len(), 1);
+            assert_eq!(delta.edits[0].start, 10);
+            assert_eq!(delta.edits[0].delete_count, 0);
+            assert_eq!(delta.edits[0].data.as_ref().unwrap().len(), 2);
+        }
+        result => panic!("unexpected result: {:?}", result),
+    }
+
+    // 保持していない結果からの差分は求められないため、すべてのトークンを返す。
+    let third = buffer("let x = 1\nin\n'<>\n", 3);
+    match get_semantic_tokens_delta_response(&mut cache, &uri(), &third, &first_id).unwrap() {
+        SemanticTokensFullDeltaResult::Tokens(tokens) => assert_eq!(tokens.data.len(), 2),
+        result => panic!("unexpected result: {:?}", result),
+    }
+}
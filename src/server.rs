@@ -0,0 +1,873 @@
+//! language server の本体。クライアントとの接続を受け取り、メッセージを処理する。
+
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    error::Error,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    thread,
+    time::Instant,
+};
+
+use log::{debug, info, warn};
+use lsp_types::{
+    notification::{
+        DidChangeTextDocument, DidChangeWatchedFiles, DidCloseTextDocument, Notification as _,
+        Progress, PublishDiagnostics,
+    },
+    request::{RegisterCapability, Request as _, WorkDoneProgressCreate},
+    CodeActionProviderCapability, CompletionOptions, DidChangeWatchedFilesRegistrationOptions,
+    DocumentOnTypeFormattingOptions, ExecuteCommandOptions, FileSystemWatcher,
+    FoldingRangeProviderCapability, HoverProviderCapability, InitializeParams, NumberOrString,
+    OneOf, ProgressParams, ProgressParamsValue, ProgressToken, PublishDiagnosticsParams,
+    Registration, RegistrationParams, SelectionRangeProviderCapability, ServerCapabilities,
+    TextDocumentSyncCapability, TextDocumentSyncKind, TraceOption, Url, WorkDoneProgress,
+    WorkDoneProgressBegin, WorkDoneProgressCreateParams, WorkDoneProgressEnd,
+    WorkDoneProgressReport,
+};
+
+use lsp_server::{Connection, ErrorCode, Message, Notification, Request, RequestId, Response};
+use serde::Deserialize;
+
+use crate::{
+    capabilities::ClientSupport,
+    config::{Config, CONFIG_FILE_NAME},
+    dependency::DependencyGraph,
+    history::BufferHistory,
+    on_type_formatting::TRIGGER_CHARACTER,
+    parse_cache::ParseCache,
+    pull_diagnostic::{diagnostic_options, DiagnosticCache, WorkspaceDiagnosticRefresh},
+    semantic_tokens::{semantic_tokens_options, SemanticTokensCache},
+    signature_help::signature_help_options,
+    status::ServerStats,
+    symbol_diff::SymbolsChanged,
+    telemetry::{LogTrace, Timing},
+    workspace::WorkspaceIndex,
+    workspace_lint::{lint_file, progress_message, summary_message, LINT_WORKSPACE_COMMAND},
+    Buffer, Environment,
+};
+
+mod handlers;
+
+/// サーバが提供する機能。
+pub fn server_capabilities() -> ServerCapabilities {
+    ServerCapabilities {
+        definition_provider: Some(OneOf::Left(true)),
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::Full)),
+        completion_provider: Some(completion_options()),
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        signature_help_provider: Some(signature_help_options()),
+        folding_range_provider: Some(FoldingRangeProviderCapability::Simple(true)),
+        selection_range_provider: Some(SelectionRangeProviderCapability::Simple(true)),
+        document_symbol_provider: Some(OneOf::Left(true)),
+        workspace_symbol_provider: Some(OneOf::Left(true)),
+        code_action_provider: Some(CodeActionProviderCapability::Simple(true)),
+        document_on_type_formatting_provider: Some(DocumentOnTypeFormattingOptions {
+            first_trigger_character: TRIGGER_CHARACTER.to_owned(),
+            more_trigger_character: None,
+        }),
+        semantic_tokens_provider: Some(semantic_tokens_options().into()),
+        execute_command_provider: Some(ExecuteCommandOptions {
+            commands: vec![LINT_WORKSPACE_COMMAND.to_owned()],
+            ..Default::default()
+        }),
+        ..Default::default()
+    }
+}
+
+/// [`Config::minimal_mode`] のときにサーバが提供する機能。補完と定義ジャンプだけを提供する。
+pub fn minimal_server_capabilities() -> ServerCapabilities {
+    ServerCapabilities {
+        definition_provider: Some(OneOf::Left(true)),
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::Full)),
+        completion_provider: Some(completion_options()),
+        ..Default::default()
+    }
+}
+
+/// 補完の capability.
+fn completion_options() -> CompletionOptions {
+    CompletionOptions {
+        trigger_characters: Some(vec!["\\".to_owned(), "+".to_owned(), "#".to_owned(), "<".to_owned()]),
+        ..Default::default()
+    }
+}
+
+/// initialize リクエストへの応答に含める capability を JSON にする。
+fn capabilities_json(minimal_mode: bool) -> serde_json::Value {
+    if minimal_mode {
+        return serde_json::to_value(minimal_server_capabilities()).unwrap();
+    }
+    let mut capabilities = serde_json::to_value(server_capabilities()).unwrap();
+    // lsp-types の ServerCapabilities は diagnosticProvider を持たないため、JSON に直接加える。
+    capabilities["diagnosticProvider"] = serde_json::to_value(diagnostic_options()).unwrap();
+    capabilities
+}
+
+/// 起動時に与えられるサーバの設定。
+#[derive(Debug, Clone, Default)]
+pub struct ServerOptions {
+    /// コマンドライン引数 `--package-path` で指定されたディレクトリ。
+    pub package_paths: Vec<PathBuf>,
+}
+
+/// クライアントが initialize リクエストの `initializationOptions` で指定する設定。
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+struct InitializationOptions {
+    /// [`Config::minimal_mode`] を有効にするか。
+    minimal_mode: bool,
+}
+
+impl InitializationOptions {
+    /// initialize リクエストのパラメータから読み取る。読み取れなければデフォルトの設定を用いる。
+    fn from_params(params: &InitializeParams) -> Self {
+        match &params.initialization_options {
+            Some(value) => serde_json::from_value(value.clone()).unwrap_or_else(|e| {
+                warn!("ignoring invalid initializationOptions: {}", e);
+                Self::default()
+            }),
+            None => Self::default(),
+        }
+    }
+}
+
+/// クライアントとの接続を初期化し、shutdown リクエストを受け取るまでメッセージを処理する。
+pub fn run(
+    connection: &Connection,
+    options: &ServerOptions,
+) -> Result<(), Box<dyn Error + Sync + Send>> {
+    // 返す capability が initializationOptions によって変わるため、先にパラメータを読む。
+    let (id, params) = connection.initialize_start()?;
+    let client = ClientSupport::from_json(params["capabilities"].clone())?;
+    let params: InitializeParams = serde_json::from_value(params)?;
+    let init_options = InitializationOptions::from_params(&params);
+    let server_capabilities = capabilities_json(init_options.minimal_mode);
+    info!("server_capabilities: {:?}", server_capabilities);
+    connection.initialize_finish(id, serde_json::json!({ "capabilities": server_capabilities }))?;
+    main_loop(connection, params, client, init_options, options)
+}
+
+fn main_loop(
+    connection: &Connection,
+    params: InitializeParams,
+    client: ClientSupport,
+    init_options: InitializationOptions,
+    options: &ServerOptions,
+) -> Result<(), Box<dyn Error + Sync + Send>> {
+    info!("starting example main loop");
+
+    let mut state = ServerState::new(connection, options, params, client, init_options);
+    // 動的な登録に対応していないクライアントには、登録を依頼しても拒否されるだけである。
+    if let Some(root) = &state.root {
+        if state.client.watched_files_registration_support() {
+            register_config_watcher(connection, root)?;
+        }
+    }
+    let registry = handlers::registry();
+
+    // 届いているメッセージをまとめて取り出しておき、リクエストを処理する前に
+    // 同じドキュメントへの変更が後に控えていないかを確かめられるようにする。
+    let mut queue = VecDeque::new();
+    loop {
+        if queue.is_empty() {
+            match connection.receiver.recv() {
+                Ok(msg) => queue.push_back(msg),
+                Err(_) => break,
+            }
+        }
+        queue.extend(connection.receiver.try_iter());
+        let msg = match queue.pop_front() {
+            Some(msg) => msg,
+            None => break,
+        };
+        info!("got msg: {:?}", msg);
+        let start = Instant::now();
+        let parse_before = state.stats.parse_time();
+        match msg {
+            Message::Request(req) => {
+                if connection.handle_shutdown(&req)? {
+                    return Ok(());
+                }
+                info!("got request: {:?}", req);
+                let method = req.method.clone();
+                let resp = match stale_request_uri(&req, &queue) {
+                    // 古いテキストに対して計算した位置は、変更後のバッファでは誤った位置を指す。
+                    Some(uri) => {
+                        info!("{} was modified after {} was sent; dropping it", uri, method);
+                        Response::new_err(
+                            req.id,
+                            ErrorCode::ContentModified as i32,
+                            format!("{} was modified before the request was handled", uri),
+                        )
+                    }
+                    None => registry.handle_request(&mut state, req),
+                };
+                let handled = start.elapsed();
+                connection.sender.send(Message::Response(resp))?;
+                let parse = state.stats.parse_time() - parse_before;
+                state.report_timing(Timing {
+                    method,
+                    parse,
+                    analysis: handled.saturating_sub(parse),
+                    respond: Some(start.elapsed() - handled),
+                })?;
+            }
+
+            Message::Response(resp) => {
+                info!("got response: {:?}", resp);
+                state.handle_response(resp);
+            }
+
+            Message::Notification(not) => {
+                info!("got notification: {:?}", not);
+                let method = not.method.clone();
+                registry.handle_notification(&mut state, not)?;
+                let parse = state.stats.parse_time() - parse_before;
+                state.report_timing(Timing {
+                    method,
+                    parse,
+                    analysis: start.elapsed().saturating_sub(parse),
+                    respond: None,
+                })?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// リクエストが対象とするドキュメントに対して、まだ処理していない変更や close の通知が
+/// queue に控えていれば、そのドキュメントの URI を返す。
+fn stale_request_uri(req: &Request, queue: &VecDeque<Message>) -> Option<Url> {
+    let uri = req.params.pointer("/textDocument/uri")?.as_str()?;
+    let modified = queue.iter().any(|msg| match msg {
+        Message::Notification(not)
+            if not.method == DidChangeTextDocument::METHOD
+                || not.method == DidCloseTextDocument::METHOD =>
+        {
+            not.params.pointer("/textDocument/uri").and_then(|u| u.as_str()) == Some(uri)
+        }
+        _ => false,
+    });
+    if modified {
+        Url::parse(uri).ok()
+    } else {
+        None
+    }
+}
+
+/// メッセージの処理をまたいで保持するサーバの状態。
+struct ServerState<'a> {
+    /// クライアントとの接続。
+    connection: &'a Connection,
+    /// 起動時に与えられた設定。
+    options: &'a ServerOptions,
+    /// ワークスペースのルート。
+    root: Option<PathBuf>,
+    /// クライアントが対応している機能。response はこれに合わせて調整してから返す。
+    client: ClientSupport,
+    /// 設定。
+    config: Config,
+    /// ワークスペースの索引。
+    index: WorkspaceIndex,
+    /// 開かれているバッファ。
+    buffers: HashMap<Url, Buffer>,
+    /// 閉じられたが、開かれている他のバッファから読み込まれているため保持しているバッファ。
+    closed: HashSet<Url>,
+    /// エディタで開かれているバッファの URI. ワークスペースの検査のスレッドと共有し、
+    /// 開かれているファイルの diagnostics を検査の結果で上書きしないようにする。
+    opened: Arc<Mutex<HashSet<Url>>>,
+    /// 計算済みの diagnostics.
+    diagnostics: DiagnosticCache,
+    /// 作成済みの semantic tokens.
+    semantic_tokens: SemanticTokensCache,
+    /// hover で用いる、開かれているバッファを起点とする依存関係グラフ。
+    /// 必要になったときに作り、バッファや読み込むファイルが変わったときに作り直す。
+    dependencies: HashMap<Url, DependencyGraph>,
+    /// 同じ内容のバッファをパースし直さないための、最近のパース結果。
+    parse_cache: ParseCache,
+    /// 状態の報告に用いる統計。
+    stats: ServerStats,
+    /// 同期のずれを調べるための、ドキュメントの直近の版の記録。
+    history: BufferHistory,
+    /// クライアントが指定した trace の設定。`off` 以外であれば処理にかかった時間を知らせる。
+    trace: TraceOption,
+    /// progress の作成をクライアントに依頼し、その応答を待っているワークスペースの検査。
+    pending_lint: Option<(RequestId, WorkspaceLint)>,
+    /// これまでに依頼されたワークスペースの検査の数。progress の token を区別するために用いる。
+    lint_count: u32,
+    /// これまでにクライアントに送った `workspace/diagnostic/refresh` の数。リクエストの id を区別するために用いる。
+    refresh_count: u32,
+}
+
+impl<'a> ServerState<'a> {
+    /// initialize リクエストのパラメータから状態を作る。
+    fn new(
+        connection: &'a Connection,
+        options: &'a ServerOptions,
+        params: InitializeParams,
+        client: ClientSupport,
+        init_options: InitializationOptions,
+    ) -> Self {
+        let root = params.root_uri.and_then(|uri| uri.to_file_path().ok());
+        let trace = params.trace.unwrap_or_default();
+        let mut stats = ServerStats::default();
+        let mut config = load_config(root.as_deref(), options, params.locale, &mut stats);
+        config.minimal_mode = init_options.minimal_mode;
+        let index = if config.minimal_mode {
+            info!("minimal mode: skipping workspace indexing");
+            WorkspaceIndex::default()
+        } else {
+            build_index(root.as_deref(), &config, &mut stats)
+        };
+        let parse_cache = ParseCache::new(config.parse_cache_size());
+        Self {
+            connection,
+            options,
+            root,
+            client,
+            config,
+            index,
+            buffers: HashMap::new(),
+            closed: HashSet::new(),
+            opened: Arc::default(),
+            diagnostics: DiagnosticCache::default(),
+            semantic_tokens: SemanticTokensCache::default(),
+            dependencies: HashMap::new(),
+            parse_cache,
+            stats,
+            history: BufferHistory::default(),
+            trace,
+            pending_lint: None,
+            lint_count: 0,
+            refresh_count: 0,
+        }
+    }
+
+    /// ワークスペースの設定ファイルを読み込み直し、再起動せずに新しい設定を適用する。
+    /// 索引を作り直し、保持しているバッファに設定を適用してから、diagnostics を送り直す。
+    fn reload_config(&mut self) -> Result<(), Box<dyn Error + Sync + Send>> {
+        info!("reloading {}", CONFIG_FILE_NAME);
+        let locale = self.config.locale.take();
+        let minimal_mode = self.config.minimal_mode;
+        self.config = load_config(self.root.as_deref(), self.options, locale, &mut self.stats);
+        self.config.minimal_mode = minimal_mode;
+        self.parse_cache.set_capacity(self.config.parse_cache_size());
+        self.diagnostics.clear();
+        // パッケージを探すディレクトリが変わったかもしれない。
+        self.dependencies.clear();
+        if !self.config.minimal_mode {
+            self.index = build_index(self.root.as_deref(), &self.config, &mut self.stats);
+        }
+
+        let uris: Vec<Url> = self.buffers.keys().cloned().collect();
+        for uri in &uris {
+            let buf = self.buffers.get_mut(uri).unwrap();
+            buf.reconfigure(uri, &self.config);
+            self.semantic_tokens.remove(uri);
+        }
+        // 開かれているパッケージの定義は、すべてのバッファに設定を適用してから差し替える。
+        for uri in &uris {
+            let mut buf = self.buffers.remove(uri).unwrap();
+            if !self.config.minimal_mode {
+                self.overlay_open_packages(&mut buf);
+                self.index.update(uri.clone(), &buf);
+                if !self.closed.contains(uri) {
+                    self.publish_diagnostics(uri.clone(), &buf)?;
+                }
+            }
+            self.buffers.insert(uri.clone(), buf);
+        }
+        Ok(())
+    }
+
+    /// uri のバッファを起点とする依存関係グラフを、まだ作っていなければ作る。
+    /// minimal mode ではパッケージを読み込まないため作らない。
+    fn build_dependency_graph(&mut self, uri: &Url) {
+        if self.config.minimal_mode || self.dependencies.contains_key(uri) {
+            return;
+        }
+        if let Some(buf) = self.buffers.get(uri) {
+            let graph = DependencyGraph::build(buf, uri, &self.config);
+            self.dependencies.insert(uri.clone(), graph);
+        }
+    }
+
+    /// uri のファイルを含む依存関係グラフを捨て、次に必要になったときに作り直させる。
+    fn invalidate_dependency_graphs(&mut self, uri: &Url) {
+        self.dependencies.retain(|_, graph| graph.get(uri).is_none());
+    }
+
+    /// バッファの内容を更新し、diagnostics と定義の増減をクライアントに知らせる。
+    fn update_buffer(
+        &mut self,
+        uri: Url,
+        version: i32,
+        text: String,
+    ) -> Result<(), Box<dyn Error + Sync + Send>> {
+        let start = Instant::now();
+        let mut buf = Buffer::with_parse_cache(
+            text,
+            self.config.max_parse_size(),
+            self.config.language_version,
+            &mut self.parse_cache,
+        );
+        self.stats.record_parse(start.elapsed());
+        buf.version = Some(version);
+        buf.apply_definition_patterns(&self.config.definition_patterns);
+        // minimal mode では開かれているバッファだけを扱い、パッケージは読み込まない。
+        if !self.config.minimal_mode {
+            buf.load_packages(&uri, &self.config);
+            self.overlay_open_packages(&mut buf);
+        }
+        if buf.is_deferred() {
+            info!("deferred parsing of large buffer: {}", uri);
+        } else {
+            debug!("buffer cst: {}", buf.buf_cst);
+        }
+        debug!("buffer env: {:?}", buf.env);
+        if let Some(e) = buf.error.first() {
+            debug!("error: {:?}", e)
+        }
+        // 検査のスレッドが diagnostics を送るより前に、開かれたことを知らせておく。
+        self.opened.lock().unwrap().insert(uri.clone());
+        if !self.config.minimal_mode {
+            self.publish_diagnostics(uri.clone(), &buf)?;
+        }
+        let previous = self.buffers.remove(&uri);
+        let opened = previous.is_none() || self.closed.remove(&uri);
+        // 書きかけでパースに失敗しても定義が消えたとみなさないよう、
+        // 最後にパースに成功した版どうしを比べ、索引にもその版を用いる。
+        let old_env = previous
+            .as_ref()
+            .and_then(Buffer::latest_parsed)
+            .map(|prev| prev.env.clone());
+        if let Some(previous) = previous {
+            buf.inherit_last_parsed(previous);
+        }
+        self.notify_symbols_changed(uri.clone(), old_env.as_ref(), &buf)?;
+        if !self.config.minimal_mode {
+            self.index.update(uri.clone(), &buf);
+        }
+        self.buffers.insert(uri.clone(), buf);
+        self.invalidate_dependency_graphs(&uri);
+        self.refresh_dependents(&uri)?;
+        // 読み込むパッケージが変わり、保持していたバッファが不要になったかもしれない。
+        self.release_unused_buffers()?;
+        if opened {
+            self.log_memory();
+        }
+        Ok(())
+    }
+
+    /// 開かれているバッファをパッケージとして読み込む場合は、ディスク上の内容ではなく
+    /// エディタで編集中の内容の定義を用いる。
+    fn overlay_open_packages(&self, buf: &mut Buffer) {
+        for pkg in &mut buf.packages {
+            if let Some(env) = self.open_package_env(&pkg.uri) {
+                pkg.env = env;
+            }
+        }
+    }
+
+    /// 開かれているバッファの、パッケージとして外から見える定義を返す。
+    /// 入力中でパースに失敗していれば、最後にパースに成功した版の定義を返す。
+    fn open_package_env(&self, uri: &Url) -> Option<Environment> {
+        if self.closed.contains(uri) {
+            return None;
+        }
+        let buf = self.buffers.get(uri)?;
+        if buf.is_deferred() {
+            return None;
+        }
+        Some(buf.latest_parsed()?.env.exported())
+    }
+
+    /// uri のバッファを直接または推移的に読み込んでいる、開かれている他のバッファについて、
+    /// 直接読み込んでいるパッケージの定義を今の内容に差し替え、diagnostics を計算し直してクライアントに送る。
+    /// 読み込んでいるかどうかは、それぞれのバッファを起点とする依存関係グラフで調べる。
+    /// pull model のクライアントには、diagnostics を取り直すよう `workspace/diagnostic/refresh` で依頼する。
+    fn refresh_dependents(&mut self, uri: &Url) -> Result<(), Box<dyn Error + Sync + Send>> {
+        let env = match self.open_package_env(uri) {
+            Some(env) => env,
+            None => return Ok(()),
+        };
+        let open: Vec<Url> = self
+            .buffers
+            .keys()
+            .filter(|other| *other != uri && !self.closed.contains(*other))
+            .cloned()
+            .collect();
+        let mut dependents = vec![];
+        for other in open {
+            self.build_dependency_graph(&other);
+            if self.dependencies.get(&other).is_some_and(|graph| graph.get(uri).is_some()) {
+                dependents.push(other);
+            }
+        }
+        if dependents.is_empty() {
+            return Ok(());
+        }
+        for dependent in dependents {
+            info!("refreshing diagnostics of {} after a change to {}", dependent, uri);
+            let mut buf = self.buffers.remove(&dependent).unwrap();
+            for pkg in buf.packages.iter_mut().filter(|pkg| &pkg.uri == uri) {
+                pkg.env = env.clone();
+            }
+            let result = self.publish_diagnostics(dependent.clone(), &buf);
+            self.buffers.insert(dependent, buf);
+            result?;
+        }
+        if self.client.diagnostic_refresh_support() {
+            self.refresh_count += 1;
+            let id = RequestId::from(format!("diagnostic-refresh-{}", self.refresh_count));
+            let req = Request::new(id, WorkspaceDiagnosticRefresh::METHOD.to_owned(), ());
+            self.connection.sender.send(Message::Request(req))?;
+        }
+        Ok(())
+    }
+
+    /// メッセージの処理にかかった時間をログに出力し、trace が有効であればクライアントにも知らせる。
+    fn report_timing(&self, timing: Timing) -> Result<(), Box<dyn Error + Sync + Send>> {
+        debug!("timing {}", timing.fields());
+        if let Some(params) = timing.to_log_trace(self.trace) {
+            let not = Notification::new(LogTrace::METHOD.to_owned(), params);
+            self.connection.sender.send(Message::Notification(not))?;
+        }
+        Ok(())
+    }
+
+    /// 閉じられたバッファを捨てる。ただし開かれている他のバッファから読み込まれていれば、
+    /// それが読み込まなくなるまで保持する。
+    fn close_buffer(&mut self, uri: Url) -> Result<(), Box<dyn Error + Sync + Send>> {
+        if !self.buffers.contains_key(&uri) {
+            return Ok(());
+        }
+        self.closed.insert(uri.clone());
+        self.opened.lock().unwrap().remove(&uri);
+        self.release_unused_buffers()?;
+        if self.closed.contains(&uri) {
+            info!("keeping closed buffer loaded by another document: {}", uri);
+        }
+        self.log_memory();
+        Ok(())
+    }
+
+    /// 閉じられたバッファのうち、どの開かれているバッファからも読み込まれていないものを捨て、
+    /// その diagnostics をクライアントから消す。
+    fn release_unused_buffers(&mut self) -> Result<(), Box<dyn Error + Sync + Send>> {
+        for uri in releasable_buffers(&self.buffers, &self.closed) {
+            debug!("releasing closed buffer: {}", uri);
+            self.closed.remove(&uri);
+            self.buffers.remove(&uri);
+            self.diagnostics.remove(&uri);
+            self.semantic_tokens.remove(&uri);
+            self.dependencies.remove(&uri);
+            // ワークスペースの外のファイルは、開かれていた間だけ索引に含める。
+            let in_workspace = match (&self.root, uri.to_file_path()) {
+                (Some(root), Ok(path)) => path.starts_with(root),
+                _ => false,
+            };
+            if !in_workspace {
+                self.index.remove(&uri);
+            }
+            let params = PublishDiagnosticsParams {
+                uri,
+                diagnostics: vec![],
+                version: None,
+            };
+            let not = Notification::new(PublishDiagnostics::METHOD.to_owned(), params);
+            self.connection.sender.send(Message::Notification(not))?;
+        }
+        Ok(())
+    }
+
+    /// 保持しているバッファの数と、それが占めるおおよそのバイト数をログに出力する。
+    fn log_memory(&self) {
+        let bytes: usize = self.buffers.values().map(Buffer::memory_estimate).sum();
+        info!(
+            "holding {} buffers ({} closed), about {} bytes",
+            self.buffers.len(),
+            self.closed.len(),
+            bytes
+        );
+    }
+
+    /// パースを後回しにしているバッファを、必要になった時点でパースする。
+    /// パースして定義が増減すれば、クライアントに通知する。
+    fn ensure_parsed(&mut self, uri: &Url) -> Result<(), Box<dyn Error + Sync + Send>> {
+        let buf = match self.buffers.get_mut(uri) {
+            Some(buf) if buf.is_deferred() => buf,
+            _ => return Ok(()),
+        };
+        let old_env = buf.latest_parsed().map(|prev| prev.env.clone());
+        let start = Instant::now();
+        buf.ensure_parsed(uri, &self.config);
+        self.stats.record_parse(start.elapsed());
+        info!("parsed deferred buffer on demand: {}", uri);
+        if !self.config.minimal_mode {
+            self.index.update(uri.clone(), buf);
+        }
+        self.invalidate_dependency_graphs(uri);
+        self.notify_symbols_changed(uri.clone(), old_env.as_ref(), &self.buffers[uri])
+    }
+
+    /// バッファに対する diagnostics を計算し直してクライアントに送る。
+    /// 計算した結果は pull model のリクエストにも用いる。
+    /// クライアントが pull model に対応していれば、同じ diagnostics が二重に示されないよう送らない。
+    fn publish_diagnostics(
+        &mut self,
+        uri: Url,
+        buf: &Buffer,
+    ) -> Result<(), Box<dyn Error + Sync + Send>> {
+        let diagnostics = self.diagnostics.refresh(&uri, buf, &self.config);
+        if self.client.pull_diagnostic_support() {
+            return Ok(());
+        }
+        let diagnostics = self.client.adapt_diagnostics(diagnostics);
+        let params = PublishDiagnosticsParams {
+            uri,
+            diagnostics,
+            version: buf.version,
+        };
+        let not = Notification::new(PublishDiagnostics::METHOD.to_owned(), params);
+        self.connection.sender.send(Message::Notification(not))?;
+        Ok(())
+    }
+
+    /// ワークスペースの索引にあるファイルのうち、開かれていないものを別のスレッドで検査する。
+    /// token が与えられず、クライアントが対応していれば、progress の作成を依頼してから始める。
+    /// 検査するファイルの数を返す。
+    fn lint_workspace(
+        &mut self,
+        token: Option<ProgressToken>,
+    ) -> Result<usize, Box<dyn Error + Sync + Send>> {
+        let mut targets: Vec<Url> = self
+            .index
+            .iter()
+            .map(|(uri, _)| uri)
+            .filter(|uri| !self.buffers.contains_key(*uri) || self.closed.contains(*uri))
+            .cloned()
+            .collect();
+        targets.sort();
+        let count = targets.len();
+        self.lint_count += 1;
+        info!("linting {} files in the workspace", count);
+
+        if token.is_some() || !self.client.work_done_progress_support() {
+            self.spawn_lint(WorkspaceLint { targets, token });
+            return Ok(count);
+        }
+        // progress は、クライアントが token の作成に応じてから送る。
+        let token = NumberOrString::String(format!("satysfi-ls/lintWorkspace/{}", self.lint_count));
+        let id = RequestId::from(format!("create-lint-progress-{}", self.lint_count));
+        let params = WorkDoneProgressCreateParams { token: token.clone() };
+        let req = Request::new(id.clone(), WorkDoneProgressCreate::METHOD.to_owned(), params);
+        self.connection.sender.send(Message::Request(req))?;
+        self.pending_lint = Some((id, WorkspaceLint { targets, token: Some(token) }));
+        Ok(count)
+    }
+
+    /// クライアントからの応答を処理する。progress の作成を待っている検査があれば始める。
+    fn handle_response(&mut self, resp: Response) {
+        if !matches!(&self.pending_lint, Some((id, _)) if *id == resp.id) {
+            return;
+        }
+        let (_, mut lint) = self.pending_lint.take().unwrap();
+        if let Some(err) = resp.error {
+            warn!("failed to create progress for workspace lint: {}", err.message);
+            lint.token = None;
+        }
+        self.spawn_lint(lint);
+    }
+
+    /// 別のスレッドでワークスペースの検査を行う。
+    fn spawn_lint(&self, lint: WorkspaceLint) {
+        let sender = self.connection.sender.clone();
+        let config = self.config.clone();
+        let client = self.client.clone();
+        let opened = Arc::clone(&self.opened);
+        thread::spawn(move || {
+            lint.run(&config, &client, &opened, |msg| sender.send(msg).is_ok())
+        });
+    }
+
+    /// 再パース前後で定義の増減があれば、クライアントに通知する。
+    /// 定義は最後にパースに成功した版のものを比べる。
+    fn notify_symbols_changed(
+        &self,
+        uri: Url,
+        old_env: Option<&Environment>,
+        new: &Buffer,
+    ) -> Result<(), Box<dyn Error + Sync + Send>> {
+        let empty = Environment::default();
+        let old_env = old_env.unwrap_or(&empty);
+        let new_env = new.latest_parsed().map_or(&empty, |buf| &buf.env);
+        let diff = Environment::diff(old_env, new_env);
+        if diff.is_empty() {
+            return Ok(());
+        }
+        let not = Notification::new(SymbolsChanged::METHOD.to_owned(), diff.into_params(uri));
+        self.connection.sender.send(Message::Notification(not))?;
+        Ok(())
+    }
+}
+
+/// 開かれていないファイルも含めた、ワークスペース全体の検査。
+#[derive(Debug)]
+struct WorkspaceLint {
+    /// 検査するファイル。
+    targets: Vec<Url>,
+    /// 進み具合を知らせる progress の token. None のときは知らせない。
+    token: Option<ProgressToken>,
+}
+
+impl WorkspaceLint {
+    /// ファイルを順に検査し、diagnostics と進み具合を `send` でクライアントに送る。
+    /// `send` が false を返せば、接続が閉じられたものとして途中でやめる。
+    /// 検査の間に開かれたファイル（`opened` に含まれるもの）の diagnostics は送らない。
+    fn run(
+        self,
+        config: &Config,
+        client: &ClientSupport,
+        opened: &Mutex<HashSet<Url>>,
+        send: impl Fn(Message) -> bool,
+    ) {
+        let total = self.targets.len();
+        let progress = |value: WorkDoneProgress| match &self.token {
+            Some(token) => {
+                let params = ProgressParams {
+                    token: token.clone(),
+                    value: ProgressParamsValue::WorkDone(value),
+                };
+                send(Message::Notification(Notification::new(Progress::METHOD.to_owned(), params)))
+            }
+            None => true,
+        };
+        let begin = WorkDoneProgressBegin {
+            title: "Linting workspace".to_owned(),
+            cancellable: Some(false),
+            message: Some(progress_message(0, total)),
+            percentage: Some(0),
+        };
+        if !progress(WorkDoneProgress::Begin(begin)) {
+            return;
+        }
+        let mut problems = 0;
+        for (i, uri) in self.targets.iter().enumerate() {
+            if let Some(diagnostics) = lint_file(uri, config) {
+                problems += diagnostics.len();
+                let params = PublishDiagnosticsParams {
+                    uri: uri.clone(),
+                    diagnostics: client.adapt_diagnostics(diagnostics),
+                    version: None,
+                };
+                let not = Notification::new(PublishDiagnostics::METHOD.to_owned(), params);
+                // 開かれたことを知らされてから送るまでの間に、開いたときの diagnostics が
+                // 送られないよう、送り終えるまでロックを保つ。
+                let opened = opened.lock().unwrap();
+                if opened.contains(uri) {
+                    debug!("skipping lint result of opened document: {}", uri);
+                } else if !send(Message::Notification(not)) {
+                    return;
+                }
+            }
+            let report = WorkDoneProgressReport {
+                cancellable: Some(false),
+                message: Some(progress_message(i + 1, total)),
+                percentage: Some(((i + 1) * 100 / total) as u32),
+            };
+            if !progress(WorkDoneProgress::Report(report)) {
+                return;
+            }
+        }
+        let summary = summary_message(problems, total);
+        info!("workspace lint finished: {}", summary);
+        progress(WorkDoneProgress::End(WorkDoneProgressEnd { message: Some(summary) }));
+    }
+}
+
+/// 閉じられたバッファのうち、閉じられていないどのバッファからもパッケージとして読み込まれていないものを返す。
+fn releasable_buffers(buffers: &HashMap<Url, Buffer>, closed: &HashSet<Url>) -> Vec<Url> {
+    closed
+        .iter()
+        .filter(|uri| {
+            !buffers
+                .iter()
+                .filter(|(other, _)| !closed.contains(*other))
+                .any(|(_, buf)| buf.packages.iter().any(|pkg| &pkg.uri == *uri))
+        })
+        .cloned()
+        .collect()
+}
+
+/// ワークスペースのルートにある設定ファイルを読み込み、起動時の設定を加える。
+/// 読み込みに失敗した場合はデフォルトの設定を用いる。
+fn load_config(
+    root: Option<&Path>,
+    options: &ServerOptions,
+    locale: Option<String>,
+    stats: &mut ServerStats,
+) -> Config {
+    let mut config = match root {
+        Some(root) => Config::load(root).unwrap_or_else(|e| {
+            stats.record_error(format!("failed to load {}: {}", CONFIG_FILE_NAME, e));
+            Config::default()
+        }),
+        None => Config::default(),
+    };
+    config.package_paths = options.package_paths.clone();
+    config.locale = locale;
+    config.workspace_root = root.map(Path::to_path_buf);
+    if let Err(e) = config.build_catalog() {
+        stats.record_error(format!("{:#}", e));
+    }
+    debug!("config: {:?}", config);
+    config
+}
+
+/// ワークスペース内のファイルを索引化する。
+/// ルートがない場合や索引化に失敗した場合は空の索引を用いる。
+fn build_index(root: Option<&Path>, config: &Config, stats: &mut ServerStats) -> WorkspaceIndex {
+    let root = match root {
+        Some(root) => root,
+        None => return WorkspaceIndex::default(),
+    };
+    let start = Instant::now();
+    let index = WorkspaceIndex::build(root, config).unwrap_or_else(|e| {
+        stats.record_error(format!("failed to index workspace: {}", e));
+        WorkspaceIndex::default()
+    });
+    stats.record_parse(start.elapsed());
+    index
+}
+
+/// ワークスペースのルート `root` にある設定ファイルの変更を通知してもらうよう、クライアントに登録を依頼する。
+fn register_config_watcher(
+    connection: &Connection,
+    root: &Path,
+) -> Result<(), Box<dyn Error + Sync + Send>> {
+    let options = DidChangeWatchedFilesRegistrationOptions {
+        watchers: vec![FileSystemWatcher {
+            glob_pattern: root.join(CONFIG_FILE_NAME).to_string_lossy().into_owned(),
+            kind: None,
+        }],
+    };
+    let params = RegistrationParams {
+        registrations: vec![Registration {
+            id: "satysfi-ls-config-watcher".to_owned(),
+            method: DidChangeWatchedFiles::METHOD.to_owned(),
+            register_options: Some(serde_json::to_value(options)?),
+        }],
+    };
+    let req = Request::new(
+        RequestId::from("register-config-watcher".to_owned()),
+        RegisterCapability::METHOD.to_owned(),
+        params,
+    );
+    connection.sender.send(Message::Request(req))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests;
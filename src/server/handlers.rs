@@ -0,0 +1,505 @@
+//! リクエストと通知のメソッド名から、それを処理する関数を引く registry.
+//!
+//! 新しい機能を加えるときは、`R::Params` を受け取って `R::Result` を返す関数を書き、
+//! [`registry`] に登録すればよい。パラメータの変換や response の組み立て、
+//! パースを後回しにしているバッファのパースは registry が行う。
+
+use std::{collections::HashMap, error::Error};
+
+use log::warn;
+use lsp_server::{ErrorCode, Notification, Request, Response};
+use lsp_types::{
+    notification::{
+        DidChangeTextDocument, DidChangeWatchedFiles, DidCloseTextDocument, DidOpenTextDocument,
+        Notification as LspNotification,
+    },
+    request::{
+        CodeActionRequest, Completion, DocumentSymbolRequest, FoldingRangeRequest, GotoDefinition, HoverRequest,
+        OnTypeFormatting, Request as LspRequest, SelectionRangeRequest,
+        SemanticTokensFullDeltaRequest, SemanticTokensFullRequest, SemanticTokensRangeRequest,
+        ExecuteCommand, SignatureHelpRequest, WorkspaceSymbol,
+    },
+    CodeActionParams, CodeActionResponse, CompletionParams, CompletionResponse, ExecuteCommandParams,
+    DidChangeTextDocumentParams, DidChangeWatchedFilesParams, DidCloseTextDocumentParams,
+    DidOpenTextDocumentParams,
+    DocumentOnTypeFormattingParams, DocumentSymbolParams, DocumentSymbolResponse, FoldingRange, FoldingRangeParams, GotoDefinitionParams,
+    GotoDefinitionResponse, Hover, HoverParams, SelectionRange, SelectionRangeParams,
+    SemanticTokensDeltaParams, SemanticTokensFullDeltaResult, SemanticTokensParams,
+    SemanticTokensRangeParams, SemanticTokensRangeResult, SemanticTokensResult, SignatureHelp,
+    SignatureHelpParams, SymbolInformation,
+    TextEdit, Url, WorkspaceSymbolParams,
+};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+
+use super::ServerState;
+use crate::{
+    all_commands::{get_all_commands_response, AllCommands, AllCommandsParams, CommandInfo},
+    code_action::get_code_action_response,
+    completion::get_completion_response,
+    config::CONFIG_FILE_NAME,
+    definition::get_definition_response,
+    document_symbol::get_document_symbol_response,
+    edit::apply_content_changes,
+    folding::get_folding_range_response,
+    history::{get_dump_history_response, DumpHistory, DumpHistoryParams, DumpHistoryResult},
+    hover::get_hover_response,
+    label::{get_labels_response, DisplayMath, Labels, LabelsParams},
+    on_type_formatting::get_on_type_formatting_response,
+    package_doc::{get_package_doc_response, PackageDoc, PackageDocParams, PackageDocResult},
+    pretty::{
+        get_pretty_print_range_response, PrettyPrintRange, PrettyPrintRangeParams,
+        PrettyPrintRangeResult,
+    },
+    pull_diagnostic::{
+        get_document_diagnostic_response, DocumentDiagnosticParams, DocumentDiagnosticReport,
+        DocumentDiagnosticRequest,
+    },
+    resolve::{
+        get_resolve_package_response, ResolvePackage, ResolvePackageParams, ResolvePackageResult,
+    },
+    selection::get_selection_range_response,
+    semantic_tokens::{
+        get_semantic_tokens_delta_response, get_semantic_tokens_full_response,
+        get_semantic_tokens_range_response,
+    },
+    signature_help::get_signature_help_response,
+    statistics::{get_statistics_response, DocumentStatistics, Statistics, StatisticsParams},
+    status::{get_server_status_response, ServerStatus, ServerStatusParams, ServerStatusResult},
+    telemetry::{SetTrace, SetTraceParams},
+    trace::{get_trace_parse_response, TraceParse, TraceParseParams, TraceParseResult},
+    workspace_lint::LINT_WORKSPACE_COMMAND,
+    workspace_symbol::get_workspace_symbol_response,
+};
+
+/// 通知の処理で起きたエラー。
+type HandlerError = Box<dyn Error + Sync + Send>;
+
+/// メソッド名で引かれる、型を消したリクエストの処理。
+type RequestHandler = Box<dyn Fn(&mut ServerState<'_>, Request) -> Response>;
+
+/// メソッド名で引かれる、型を消した通知の処理。
+type NotificationHandler =
+    Box<dyn Fn(&mut ServerState<'_>, Notification) -> Result<(), HandlerError>>;
+
+/// メソッド名と、それを処理する関数の対応。
+#[derive(Default)]
+pub(super) struct Registry {
+    /// リクエストの処理。
+    requests: HashMap<&'static str, RequestHandler>,
+    /// 通知の処理。
+    notifications: HashMap<&'static str, NotificationHandler>,
+}
+
+impl Registry {
+    /// リクエスト `R` を処理する関数を登録する。
+    fn on<R>(mut self, handler: fn(&mut ServerState<'_>, R::Params) -> R::Result) -> Self
+    where
+        R: LspRequest,
+        R::Params: DeserializeOwned + 'static,
+        R::Result: Serialize + 'static,
+    {
+        let handler = move |state: &mut ServerState<'_>, req: Request| {
+            match serde_json::from_value::<R::Params>(req.params) {
+                Ok(params) => Response::new_ok(req.id, handler(state, params)),
+                Err(e) => Response::new_err(req.id, ErrorCode::InvalidParams as i32, e.to_string()),
+            }
+        };
+        self.requests.insert(R::METHOD, Box::new(handler));
+        self
+    }
+
+    /// 通知 `N` を処理する関数を登録する。
+    fn on_notification<N>(
+        mut self,
+        handler: fn(&mut ServerState<'_>, N::Params) -> Result<(), HandlerError>,
+    ) -> Self
+    where
+        N: LspNotification,
+        N::Params: DeserializeOwned + 'static,
+    {
+        let handler = move |state: &mut ServerState<'_>, not: Notification| {
+            let params = serde_json::from_value::<N::Params>(not.params)?;
+            handler(state, params)
+        };
+        self.notifications.insert(N::METHOD, Box::new(handler));
+        self
+    }
+
+    /// リクエストを処理して response を返す。登録されていないメソッドにはエラーを返す。
+    /// ドキュメントを対象とするリクエストでは、パースを後回しにしているバッファを先にパースする。
+    pub(super) fn handle_request(&self, state: &mut ServerState<'_>, req: Request) -> Response {
+        match self.requests.get(req.method.as_str()) {
+            Some(handler) => {
+                if let Some(uri) = document_uri(&req.params) {
+                    if let Err(e) = state.ensure_parsed(&uri) {
+                        warn!("failed to notify symbols of {}: {}", uri, e);
+                    }
+                }
+                handler(state, req)
+            }
+            None => Response::new_err(
+                req.id,
+                ErrorCode::MethodNotFound as i32,
+                format!("unknown method: {}", req.method),
+            ),
+        }
+    }
+
+    /// 通知を処理する。登録されていないメソッドの通知は無視する。
+    pub(super) fn handle_notification(
+        &self,
+        state: &mut ServerState<'_>,
+        not: Notification,
+    ) -> Result<(), HandlerError> {
+        match self.notifications.get(not.method.as_str()) {
+            Some(handler) => handler(state, not),
+            None => Ok(()),
+        }
+    }
+}
+
+/// リクエストのパラメータの `textDocument.uri`.
+fn document_uri(params: &Value) -> Option<Url> {
+    let uri = params.pointer("/textDocument/uri")?.as_str()?;
+    Url::parse(uri).ok()
+}
+
+/// サーバが処理するすべてのリクエストと通知を登録した registry を返す。
+pub(super) fn registry() -> Registry {
+    Registry::default()
+        .on::<Completion>(completion)
+        .on::<GotoDefinition>(definition)
+        .on::<HoverRequest>(hover)
+        .on::<SignatureHelpRequest>(signature_help)
+        .on::<FoldingRangeRequest>(folding_range)
+        .on::<SelectionRangeRequest>(selection_range)
+        .on::<DocumentSymbolRequest>(document_symbol)
+        .on::<WorkspaceSymbol>(workspace_symbol)
+        .on::<ExecuteCommand>(execute_command)
+        .on::<OnTypeFormatting>(on_type_formatting)
+        .on::<DocumentDiagnosticRequest>(document_diagnostic)
+        .on::<CodeActionRequest>(code_action)
+        .on::<SemanticTokensFullRequest>(semantic_tokens_full)
+        .on::<SemanticTokensRangeRequest>(semantic_tokens_range)
+        .on::<SemanticTokensFullDeltaRequest>(semantic_tokens_delta)
+        .on::<PackageDoc>(package_doc)
+        .on::<AllCommands>(all_commands)
+        .on::<Statistics>(statistics)
+        .on::<ResolvePackage>(resolve_package)
+        .on::<Labels>(labels)
+        .on::<ServerStatus>(server_status)
+        .on::<PrettyPrintRange>(pretty_print_range)
+        .on::<TraceParse>(trace_parse)
+        .on::<DumpHistory>(dump_history)
+        .on_notification::<DidOpenTextDocument>(did_open)
+        .on_notification::<DidChangeTextDocument>(did_change)
+        .on_notification::<DidCloseTextDocument>(did_close)
+        .on_notification::<DidChangeWatchedFiles>(did_change_watched_files)
+        .on_notification::<SetTrace>(set_trace)
+}
+
+fn completion(
+    state: &mut ServerState<'_>,
+    params: CompletionParams,
+) -> Option<CompletionResponse> {
+    let uri = &params.text_document_position.text_document.uri;
+    state
+        .buffers
+        .get(uri)
+        .and_then(|buf| get_completion_response(buf, params, &state.config, &state.index))
+        .map(|response| state.client.adapt_completion(response))
+}
+
+fn definition(
+    state: &mut ServerState<'_>,
+    params: GotoDefinitionParams,
+) -> Option<GotoDefinitionResponse> {
+    let uri = &params.text_document_position_params.text_document.uri;
+    state
+        .buffers
+        .get(uri)
+        .and_then(|buf| get_definition_response(buf, params, state.client.link_support()))
+}
+
+fn hover(state: &mut ServerState<'_>, params: HoverParams) -> Option<Hover> {
+    let uri = params.text_document_position_params.text_document.uri.clone();
+    state.build_dependency_graph(&uri);
+    let dependencies = state.dependencies.get(&uri);
+    state
+        .buffers
+        .get(&uri)
+        .and_then(|buf| get_hover_response(buf, params, &state.config, &state.index, dependencies))
+        .map(|hover| state.client.adapt_hover(hover))
+}
+
+fn signature_help(state: &mut ServerState<'_>, params: SignatureHelpParams) -> Option<SignatureHelp> {
+    let uri = params.text_document_position_params.text_document.uri.clone();
+    state
+        .buffers
+        .get(&uri)
+        .and_then(|buf| get_signature_help_response(buf, params, &uri))
+        .map(|help| state.client.adapt_signature_help(help))
+}
+
+fn document_symbol(
+    state: &mut ServerState<'_>,
+    params: DocumentSymbolParams,
+) -> Option<DocumentSymbolResponse> {
+    let uri = params.text_document.uri.clone();
+    state
+        .buffers
+        .get(&uri)
+        .and_then(|buf| get_document_symbol_response(buf, params, &state.index))
+        .map(|response| state.client.adapt_document_symbols(&uri, response))
+}
+
+fn workspace_symbol(
+    state: &mut ServerState<'_>,
+    params: WorkspaceSymbolParams,
+) -> Option<Vec<SymbolInformation>> {
+    get_workspace_symbol_response(&state.index, params)
+}
+
+fn execute_command(state: &mut ServerState<'_>, params: ExecuteCommandParams) -> Option<Value> {
+    match params.command.as_str() {
+        LINT_WORKSPACE_COMMAND => {
+            let token = params.work_done_progress_params.work_done_token;
+            match state.lint_workspace(token) {
+                Ok(files) => Some(serde_json::json!({ "files": files })),
+                Err(e) => {
+                    warn!("failed to start workspace lint: {}", e);
+                    None
+                }
+            }
+        }
+        command => {
+            warn!("unknown command: {}", command);
+            None
+        }
+    }
+}
+
+fn folding_range(
+    state: &mut ServerState<'_>,
+    params: FoldingRangeParams,
+) -> Option<Vec<FoldingRange>> {
+    let uri = &params.text_document.uri;
+    state
+        .buffers
+        .get(uri)
+        .and_then(|buf| get_folding_range_response(buf, params))
+}
+
+fn selection_range(
+    state: &mut ServerState<'_>,
+    params: SelectionRangeParams,
+) -> Option<Vec<SelectionRange>> {
+    let uri = &params.text_document.uri;
+    state
+        .buffers
+        .get(uri)
+        .and_then(|buf| get_selection_range_response(buf, params))
+}
+
+fn on_type_formatting(
+    state: &mut ServerState<'_>,
+    params: DocumentOnTypeFormattingParams,
+) -> Option<Vec<TextEdit>> {
+    let uri = &params.text_document_position.text_document.uri;
+    state
+        .buffers
+        .get(uri)
+        .and_then(|buf| get_on_type_formatting_response(buf, params))
+}
+
+fn document_diagnostic(
+    state: &mut ServerState<'_>,
+    params: DocumentDiagnosticParams,
+) -> DocumentDiagnosticReport {
+    let buf = state.buffers.get(&params.text_document.uri);
+    match get_document_diagnostic_response(&mut state.diagnostics, buf, params, &state.config) {
+        DocumentDiagnosticReport::Full(mut report) => {
+            report.items = state.client.adapt_diagnostics(report.items);
+            DocumentDiagnosticReport::Full(report)
+        }
+        report => report,
+    }
+}
+
+fn code_action(
+    state: &mut ServerState<'_>,
+    params: CodeActionParams,
+) -> Option<CodeActionResponse> {
+    let buf = state.buffers.get(&params.text_document.uri);
+    get_code_action_response(buf, params).map(|response| state.client.adapt_code_actions(response))
+}
+
+fn semantic_tokens_full(
+    state: &mut ServerState<'_>,
+    params: SemanticTokensParams,
+) -> Option<SemanticTokensResult> {
+    let uri = &params.text_document.uri;
+    let buf = state.buffers.get(uri)?;
+    get_semantic_tokens_full_response(&mut state.semantic_tokens, uri, buf).map(Into::into)
+}
+
+fn semantic_tokens_range(
+    state: &mut ServerState<'_>,
+    params: SemanticTokensRangeParams,
+) -> Option<SemanticTokensRangeResult> {
+    let uri = &params.text_document.uri;
+    let buf = state.buffers.get(uri)?;
+    get_semantic_tokens_range_response(&mut state.semantic_tokens, uri, buf, params.range)
+        .map(Into::into)
+}
+
+fn semantic_tokens_delta(
+    state: &mut ServerState<'_>,
+    params: SemanticTokensDeltaParams,
+) -> Option<SemanticTokensFullDeltaResult> {
+    let uri = &params.text_document.uri;
+    let buf = state.buffers.get(uri)?;
+    let previous = &params.previous_result_id;
+    get_semantic_tokens_delta_response(&mut state.semantic_tokens, uri, buf, previous)
+}
+
+fn package_doc(state: &mut ServerState<'_>, params: PackageDocParams) -> Option<PackageDocResult> {
+    get_package_doc_response(params, &state.config)
+}
+
+fn all_commands(state: &mut ServerState<'_>, params: AllCommandsParams) -> Vec<CommandInfo> {
+    let uri = &params.text_document.uri;
+    state
+        .buffers
+        .get(uri)
+        .map(|buf| get_all_commands_response(buf, uri, &state.config))
+        .unwrap_or_default()
+}
+
+fn statistics(
+    state: &mut ServerState<'_>,
+    params: StatisticsParams,
+) -> Option<DocumentStatistics> {
+    let uri = &params.text_document.uri;
+    get_statistics_response(state.buffers.get(uri)?)
+}
+
+fn resolve_package(
+    state: &mut ServerState<'_>,
+    params: ResolvePackageParams,
+) -> ResolvePackageResult {
+    let buf = state.buffers.get(&params.text_document.uri);
+    get_resolve_package_response(buf, params, &state.config)
+}
+
+fn labels(state: &mut ServerState<'_>, params: LabelsParams) -> Vec<DisplayMath> {
+    let uri = &params.text_document.uri;
+    state.buffers.get(uri).map(get_labels_response).unwrap_or_default()
+}
+
+fn server_status(
+    state: &mut ServerState<'_>,
+    _params: Option<ServerStatusParams>,
+) -> ServerStatusResult {
+    get_server_status_response(
+        &state.stats,
+        &state.index,
+        &state.buffers,
+        &state.parse_cache,
+        &state.config,
+    )
+}
+
+fn pretty_print_range(
+    state: &mut ServerState<'_>,
+    params: PrettyPrintRangeParams,
+) -> Option<PrettyPrintRangeResult> {
+    let uri = &params.text_document.uri;
+    state
+        .buffers
+        .get(uri)
+        .and_then(|buf| get_pretty_print_range_response(buf, params, &state.config.format))
+}
+
+fn trace_parse(state: &mut ServerState<'_>, params: TraceParseParams) -> Option<TraceParseResult> {
+    let uri = &params.text_document.uri;
+    state
+        .buffers
+        .get(uri)
+        .map(|buf| get_trace_parse_response(buf, params))
+}
+
+fn dump_history(
+    state: &mut ServerState<'_>,
+    params: Option<DumpHistoryParams>,
+) -> DumpHistoryResult {
+    get_dump_history_response(&state.history, params.unwrap_or_default())
+}
+
+fn did_open(
+    state: &mut ServerState<'_>,
+    params: DidOpenTextDocumentParams,
+) -> Result<(), HandlerError> {
+    let doc = params.text_document;
+    let capacity = state.config.history_size;
+    state.history.record(&doc.uri, doc.version, &doc.text, capacity);
+    state.update_buffer(doc.uri, doc.version, doc.text)
+}
+
+fn did_change(
+    state: &mut ServerState<'_>,
+    params: DidChangeTextDocumentParams,
+) -> Result<(), HandlerError> {
+    // 1 つの通知に複数の変更がまとめて届くことがあるため、すべてを順に適用する。
+    let doc = params.text_document;
+    if params.content_changes.is_empty() {
+        return Ok(());
+    }
+    // 版が進んでいない変更は、すでに反映したものか順序の入れ替わった古い変更なので捨てる。
+    let current = state.buffers.get(&doc.uri).and_then(|buf| buf.version);
+    if current.is_some_and(|current| doc.version <= current) {
+        warn!(
+            "ignoring change to {} at version {} (current version {:?})",
+            doc.uri, doc.version, current
+        );
+        return Ok(());
+    }
+    let previous = state.buffers.get(&doc.uri).map_or("", |buf| buf.buf_cst.buffer.as_str());
+    let text = apply_content_changes(previous, params.content_changes);
+    let capacity = state.config.history_size;
+    state.history.record(&doc.uri, doc.version, &text, capacity);
+    state.update_buffer(doc.uri, doc.version, text)
+}
+
+fn did_close(
+    state: &mut ServerState<'_>,
+    params: DidCloseTextDocumentParams,
+) -> Result<(), HandlerError> {
+    state.close_buffer(params.text_document.uri)
+}
+
+fn did_change_watched_files(
+    state: &mut ServerState<'_>,
+    params: DidChangeWatchedFilesParams,
+) -> Result<(), HandlerError> {
+    // 設定ファイルとして読み込むのは、ワークスペースのルートにあるものだけである。
+    let config_changed = state.root.as_ref().is_some_and(|root| {
+        let config_path = root.join(CONFIG_FILE_NAME);
+        params
+            .changes
+            .iter()
+            .any(|change| change.uri.to_file_path().is_ok_and(|path| path == config_path))
+    });
+    // 読み込まれているパッケージがディスク上で変わったかもしれない。
+    state.dependencies.clear();
+    if config_changed {
+        state.reload_config()?;
+    }
+    Ok(())
+}
+
+fn set_trace(state: &mut ServerState<'_>, params: SetTraceParams) -> Result<(), HandlerError> {
+    state.trace = params.value;
+    Ok(())
+}
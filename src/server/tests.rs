@@ -0,0 +1,322 @@
+//! test module for the server, replaying recorded JSON-RPC sessions.
+//!
+//! セッションは `sessions/` 以下の JSON ファイルに、次のいずれかを順に並べて記述する。
+//!
+//! - `{"send": message}`: クライアントからメッセージを送る。
+//! - `{"expect": message}`: `id` または `method` が一致するメッセージをサーバから受け取るまで待ち、
+//!   その内容が `message` を含むことを確かめる。間に届いた他のメッセージは読み飛ばす。
+//! - `{"write": {"uri": uri, "text": text}}`: セッションの途中でディスク上のファイルを書き換える。
+
+use std::{thread, time::Duration};
+
+use serde_json::Value;
+
+use super::*;
+use crate::test_utils::temp_dir;
+
+/// サーバからのメッセージを待つ最大の時間。
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// `expected` が `actual` に含まれるか。
+/// オブジェクトは `expected` のキーだけを比べ、配列は `expected` の各要素を含む要素が
+/// `actual` のどこかにあればよい。ただし空の配列は `actual` も空であることを求める。
+fn contains(actual: &Value, expected: &Value) -> bool {
+    match (actual, expected) {
+        (Value::Object(actual), Value::Object(expected)) => expected
+            .iter()
+            .all(|(key, e)| actual.get(key).is_some_and(|a| contains(a, e))),
+        (Value::Array(actual), Value::Array(expected)) if expected.is_empty() => actual.is_empty(),
+        (Value::Array(actual), Value::Array(expected)) => expected
+            .iter()
+            .all(|e| actual.iter().any(|a| contains(a, e))),
+        _ => actual == expected,
+    }
+}
+
+/// 受け取ったメッセージが、期待するメッセージと同じ応答や通知であるか。
+fn is_counterpart(actual: &Value, expected: &Value) -> bool {
+    match (expected.get("id"), expected.get("method")) {
+        (Some(id), _) => actual.get("id") == Some(id) && actual.get("method").is_none(),
+        (None, Some(method)) => actual.get("method") == Some(method),
+        (None, None) => panic!("expected message must have either id or method: {}", expected),
+    }
+}
+
+/// 記録されたセッションをサーバに対して再生する。
+fn replay(session: &str) {
+    let steps: Vec<Value> = serde_json::from_str(session).unwrap();
+    let (server, client) = Connection::memory();
+    let handle = thread::spawn(move || {
+        run(&server, &ServerOptions::default()).map_err(|e| e.to_string())
+    });
+
+    for step in steps {
+        if let Some(message) = step.get("send") {
+            let mut message = message.clone();
+            message["jsonrpc"] = "2.0".into();
+            let message: Message = serde_json::from_value(message).unwrap();
+            client.sender.send(message).unwrap();
+        } else if let Some(write) = step.get("write") {
+            let uri = Url::parse(write["uri"].as_str().unwrap()).unwrap();
+            std::fs::write(uri.to_file_path().unwrap(), write["text"].as_str().unwrap()).unwrap();
+        } else if let Some(expected) = step.get("expect") {
+            let actual = loop {
+                let message = client
+                    .receiver
+                    .recv_timeout(TIMEOUT)
+                    .unwrap_or_else(|_| panic!("no message for {}", expected));
+                let message = serde_json::to_value(message).unwrap();
+                if is_counterpart(&message, expected) {
+                    break message;
+                }
+            };
+            assert!(
+                contains(&actual, expected),
+                "unexpected message\n  actual: {}\nexpected: {}",
+                actual,
+                expected
+            );
+        } else {
+            panic!("unknown step: {}", step);
+        }
+    }
+
+    handle.join().unwrap().unwrap();
+}
+
+#[test]
+fn test_contains() {
+    let actual = serde_json::json!({"a": [{"b": 1, "c": 2}, {"b": 3}], "d": []});
+    assert!(contains(&actual, &serde_json::json!({"a": [{"b": 3}], "d": []})));
+    assert!(!contains(&actual, &serde_json::json!({"a": [{"b": 2}]})));
+    assert!(!contains(&actual, &serde_json::json!({"a": []})));
+}
+
+#[test]
+fn test_session_open_and_query() {
+    replay(include_str!("sessions/open_and_query.json"));
+}
+
+#[test]
+fn test_session_change() {
+    replay(include_str!("sessions/change.json"));
+}
+
+#[test]
+fn test_session_broken_edit() {
+    replay(include_str!("sessions/broken_edit.json"));
+}
+
+#[test]
+fn test_session_pull() {
+    replay(include_str!("sessions/pull.json"));
+}
+
+#[test]
+fn test_session_close() {
+    replay(include_str!("sessions/close.json"));
+}
+
+#[test]
+fn test_releasable_buffers() {
+    let uri = |name: &str| Url::parse(&format!("file:///session/{}", name)).unwrap();
+    let mut main = Buffer::new("'<>\n".to_owned());
+    main.packages.push(crate::Package {
+        kind: crate::resolve::PackageKind::Import,
+        name: "lib".to_owned(),
+        uri: uri("lib.satyh"),
+        env: Environment::default(),
+        stage: None,
+    });
+    let mut buffers = HashMap::new();
+    buffers.insert(uri("main.saty"), main);
+    buffers.insert(uri("lib.satyh"), Buffer::new("let x = 1\n".to_owned()));
+    buffers.insert(uri("other.saty"), Buffer::new("'<>\n".to_owned()));
+
+    // 開かれているバッファから読み込まれているものは捨てない。
+    let closed: HashSet<_> = vec![uri("lib.satyh"), uri("other.saty")].into_iter().collect();
+    assert_eq!(releasable_buffers(&buffers, &closed), vec![uri("other.saty")]);
+
+    // 読み込んでいるバッファも閉じられていれば、どちらも捨てる。
+    let closed: HashSet<_> = vec![uri("main.saty"), uri("lib.satyh")].into_iter().collect();
+    let mut released = releasable_buffers(&buffers, &closed);
+    released.sort();
+    assert_eq!(released, vec![uri("lib.satyh"), uri("main.saty")]);
+}
+
+#[test]
+fn test_session_minimal() {
+    replay(include_str!("sessions/minimal.json"));
+}
+
+#[test]
+fn test_minimal_capabilities() {
+    let full = capabilities_json(false);
+    assert!(full.get("diagnosticProvider").is_some());
+    assert!(full.get("semanticTokensProvider").is_some());
+
+    let minimal = capabilities_json(true);
+    assert!(minimal.get("completionProvider").is_some());
+    assert!(minimal.get("definitionProvider").is_some());
+    for capability in [
+        "diagnosticProvider",
+        "semanticTokensProvider",
+        "hoverProvider",
+        "workspaceSymbolProvider",
+        "executeCommandProvider",
+    ] {
+        assert!(minimal.get(capability).is_none(), "{} must be disabled", capability);
+    }
+}
+
+#[test]
+fn test_initialization_options() {
+    let params = |options: Value| -> InitializeParams {
+        serde_json::from_value(serde_json::json!({"capabilities": {}, "initializationOptions": options})).unwrap()
+    };
+    assert!(InitializationOptions::from_params(&params(serde_json::json!({"minimalMode": true}))).minimal_mode);
+    assert!(!InitializationOptions::from_params(&params(serde_json::json!({}))).minimal_mode);
+    // 読み取れない値は無視する。
+    assert!(!InitializationOptions::from_params(&params(serde_json::json!({"minimalMode": "yes"}))).minimal_mode);
+}
+
+#[test]
+fn test_session_version() {
+    replay(include_str!("sessions/version.json"));
+}
+
+#[test]
+fn test_session_dependents() {
+    let dir = temp_dir("dependents");
+    std::fs::write(dir.join("lib.satyh"), "let-block ctx +sec = block-nil\n").unwrap();
+    let dir_uri = Url::from_directory_path(&dir).unwrap();
+    let session = include_str!("sessions/dependents.json");
+    replay(&session.replace("$DIR/", dir_uri.as_str()));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_session_transitive_dependents() {
+    let dir = temp_dir("transitive-dependents");
+    std::fs::write(dir.join("mid.satyh"), "@import: lib\n\nlet-block ctx +mid = block-nil\n").unwrap();
+    std::fs::write(dir.join("lib.satyh"), "let-block ctx +sec = block-nil\n").unwrap();
+    let dir_uri = Url::from_directory_path(&dir).unwrap();
+    let session = include_str!("sessions/transitive_dependents.json");
+    replay(&session.replace("$DIR/", dir_uri.as_str()));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_session_deferred() {
+    let dir = temp_dir("deferred");
+    std::fs::write(dir.join(CONFIG_FILE_NAME), "max-parse-size = 16\n").unwrap();
+    let dir_uri = Url::from_directory_path(&dir).unwrap();
+    let session = include_str!("sessions/deferred.json");
+    replay(&session.replace("$DIR/", dir_uri.as_str()));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_session_reload_config() {
+    let dir = temp_dir("reload-config");
+    // 設定ファイルを書き換えて、`+other` も定義するパッケージを読み込ませる。
+    std::fs::write(dir.join(CONFIG_FILE_NAME), "search-paths = [\"pkgs\"]\n").unwrap();
+    for (pkgs, text) in [
+        ("pkgs", "let-block ctx +sec = block-nil\n"),
+        ("new-pkgs", "let-block ctx +sec = block-nil\nlet-block ctx +other = block-nil\n"),
+    ] {
+        std::fs::create_dir(dir.join(pkgs)).unwrap();
+        std::fs::write(dir.join(pkgs).join("lib.satyh"), text).unwrap();
+    }
+    let dir_uri = Url::from_directory_path(&dir).unwrap();
+    let session = include_str!("sessions/reload_config.json");
+    replay(&session.replace("$DIR/", dir_uri.as_str()));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_session_lint_workspace() {
+    let dir = temp_dir("lint-workspace");
+    std::fs::write(dir.join("broken.saty"), "'<\n  +sec{A}\n>\n").unwrap();
+    std::fs::write(dir.join("clean.saty"), "let-block ctx +sec inner = block-nil\nin\n'<\n  +sec{A}\n>\n").unwrap();
+    std::fs::write(dir.join("open.saty"), "").unwrap();
+    let dir_uri = Url::from_directory_path(&dir).unwrap();
+    let session = include_str!("sessions/lint_workspace.json");
+    replay(&session.replace("$DIR/", dir_uri.as_str()));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_lint_skips_opened_documents() {
+    let dir = temp_dir("lint-opened");
+    std::fs::write(dir.join("closed.saty"), "'<\n  +sec{A}\n>\n").unwrap();
+    std::fs::write(dir.join("opened.saty"), "'<\n  +sec{A}\n>\n").unwrap();
+    let closed = Url::from_file_path(dir.join("closed.saty")).unwrap();
+    let opened = Url::from_file_path(dir.join("opened.saty")).unwrap();
+
+    let lint = WorkspaceLint {
+        targets: vec![closed.clone(), opened.clone()],
+        token: None,
+    };
+    let open_set = Mutex::new(vec![opened].into_iter().collect());
+    let sent = Mutex::new(vec![]);
+    lint.run(&Config::default(), &ClientSupport::default(), &open_set, |msg| {
+        sent.lock().unwrap().push(msg);
+        true
+    });
+    let uris: Vec<Url> = sent
+        .into_inner()
+        .unwrap()
+        .into_iter()
+        .filter_map(|msg| match msg {
+            Message::Notification(not) => {
+                let params: PublishDiagnosticsParams = serde_json::from_value(not.params).ok()?;
+                Some(params.uri)
+            }
+            _ => None,
+        })
+        .collect();
+    assert_eq!(uris, vec![closed]);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_stale_request_uri() {
+    let uri = "file:///session/main.saty";
+    let text_document = serde_json::json!({"textDocument": {"uri": uri}});
+    let req = Request::new(RequestId::from(1), "textDocument/completion".to_owned(), text_document.clone());
+    let change = |uri: &str| {
+        Message::Notification(Notification::new(
+            DidChangeTextDocument::METHOD.to_owned(),
+            serde_json::json!({"textDocument": {"uri": uri, "version": 2}, "contentChanges": []}),
+        ))
+    };
+
+    // 控えている変更がなければ、リクエストはそのまま処理する。
+    let mut queue = VecDeque::new();
+    assert_eq!(stale_request_uri(&req, &queue), None);
+
+    // 別のドキュメントへの変更や、変更でない通知は関係しない。
+    queue.push_back(change("file:///session/other.saty"));
+    queue.push_back(Message::Notification(Notification::new(
+        "textDocument/didSave".to_owned(),
+        text_document.clone(),
+    )));
+    assert_eq!(stale_request_uri(&req, &queue), None);
+
+    // 同じドキュメントへの変更や close が控えていれば、古いテキストに対するリクエストとみなす。
+    queue.push_back(change(uri));
+    assert_eq!(stale_request_uri(&req, &queue), Some(Url::parse(uri).unwrap()));
+    let close = Notification::new(DidCloseTextDocument::METHOD.to_owned(), text_document);
+    let queue = vec![Message::Notification(close)].into_iter().collect();
+    assert_eq!(stale_request_uri(&req, &queue), Some(Url::parse(uri).unwrap()));
+
+    // ドキュメントを対象としないリクエストは対象外。
+    let status = Request::new(RequestId::from(2), "satysfi/serverStatus".to_owned(), Value::Null);
+    assert_eq!(stale_request_uri(&status, &queue), None);
+}
+
+#[test]
+fn test_session_trace() {
+    replay(include_str!("sessions/trace.json"));
+}
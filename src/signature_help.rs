@@ -0,0 +1,107 @@
+//! signature help に関する関数群。
+//!
+//! カーソルのあるコマンドの使用箇所について、定義から組み立てたシグネチャと、
+//! カーソルが定義のどの引数を書いているかを返す。
+//! 引数の数え方は [`crate::argument`] に従うため、引数に関する diagnostics と食い違わない。
+
+use lsp_types::{
+    ParameterInformation, ParameterLabel, SignatureHelp, SignatureHelpOptions,
+    SignatureHelpParams, SignatureInformation, Url,
+};
+
+use crate::{
+    argument::{argument_slot_at, ArgumentSlot},
+    diagnostic::{lookup_command, visible_envs},
+    parser::Rule,
+    position::Position,
+    Buffer, CmdKind, CommandDef, ParamKind,
+};
+
+/// サーバが提供する signature help の capability.
+pub fn signature_help_options() -> SignatureHelpOptions {
+    SignatureHelpOptions {
+        trigger_characters: Some(vec!["(".to_owned(), "{".to_owned(), "<".to_owned()]),
+        retrigger_characters: Some(vec![":".to_owned(), ")".to_owned(), "}".to_owned(), ">".to_owned()]),
+        ..Default::default()
+    }
+}
+
+/// signature help リクエストへの response を返す。
+/// パースに失敗している間や、カーソルがコマンドの引数の位置にない場合は None を返す。
+pub fn get_signature_help_response(
+    buf: &Buffer,
+    params: SignatureHelpParams,
+    uri: &Url,
+) -> Option<SignatureHelp> {
+    let pos = Position::from(params.text_document_position_params.position);
+    let cst = buf.buf_cst.cst.as_ref()?;
+
+    // 入れ子になったコマンドでは、カーソルに最も近いものを対象にする。
+    let (usage, slot) = cst.dig(&pos).into_iter().find_map(|cst| {
+        if !matches!(cst.rule, Rule::inline_cmd | Rule::block_cmd | Rule::math_cmd) {
+            return None;
+        }
+        let slot = argument_slot_at(&buf.buf_cst, cst, &pos)?;
+        Some((cst, slot))
+    })?;
+    let name = buf.buf_cst.as_str(usage.inner.first()?);
+    let (def, _) = lookup_command(&visible_envs(buf, uri), usage.rule, name)?;
+
+    let active_parameter = active_parameter(&def, slot);
+    Some(SignatureHelp {
+        signatures: vec![signature_information(&def, active_parameter)],
+        active_signature: Some(0),
+        active_parameter,
+    })
+}
+
+/// 引数がシグネチャの何番目の引数にあたるかを返す。定義の引数より多く与えていれば None を返す。
+fn active_parameter(def: &CommandDef<'_>, slot: ArgumentSlot) -> Option<u32> {
+    let index = match slot {
+        ArgumentSlot::Optional(i) if i < def.optional_arity => i,
+        ArgumentSlot::Mandatory(i) if i < def.arity => def.optional_arity + i,
+        _ => return None,
+    };
+    Some(index as u32)
+}
+
+/// コマンドの定義から、オプション引数を先に並べたシグネチャを組み立てる。
+fn signature_information(def: &CommandDef<'_>, active_parameter: Option<u32>) -> SignatureInformation {
+    let mut label = def.name.to_owned();
+    let mut parameters = vec![];
+    let optionals = std::iter::repeat_n("?:(...)", def.optional_arity);
+    let mandatories = (0..def.arity).map(|i| parameter_text(def.kind, def.param_kinds.get(i)));
+    for text in optionals.chain(mandatories) {
+        label.push(' ');
+        let start = utf16_len(&label);
+        label.push_str(text);
+        parameters.push(ParameterInformation {
+            label: ParameterLabel::LabelOffsets([start, utf16_len(&label)]),
+            documentation: None,
+        });
+    }
+    SignatureInformation {
+        label,
+        documentation: None,
+        parameters: Some(parameters),
+        active_parameter,
+    }
+}
+
+/// 引数の種類に応じた、シグネチャ中の引数の表記。
+fn parameter_text(kind: CmdKind, param: Option<&ParamKind>) -> &'static str {
+    match (kind, param) {
+        (_, Some(ParamKind::InlineText)) => "{...}",
+        (_, Some(ParamKind::BlockText)) => "<...>",
+        (CmdKind::Math, _) => "{...}",
+        _ => "(...)",
+    }
+}
+
+/// UTF-16 で数えた文字列の長さ。
+fn utf16_len(s: &str) -> u32 {
+    s.encode_utf16().count() as u32
+}
+
+#[cfg(test)]
+mod tests;
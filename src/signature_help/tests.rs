@@ -0,0 +1,69 @@
+//! test module for signature help.
+
+use lsp_types::SignatureHelpParams;
+
+use super::*;
+use crate::test_utils::TestDocument;
+
+const PREAMBLE: &str = "let-inline ctx \\opt ?:size ?:color inner = inner\nlet-inline ctx \\plain inner = inner\nin\n";
+
+/// マーカーの位置で signature help を求める。
+fn signature_help(doc: &TestDocument, marker: u32) -> Option<SignatureHelp> {
+    let params = SignatureHelpParams {
+        context: None,
+        text_document_position_params: doc.text_document_position(marker),
+        work_done_progress_params: Default::default(),
+    };
+    get_signature_help_response(&doc.buffer(), params, doc.uri())
+}
+
+#[test]
+fn test_signature_label() {
+    let doc = TestDocument::new(&format!("{}'<\n  +p{{ \\opt{{^1a}} }}\n>\n", PREAMBLE));
+    let help = signature_help(&doc, 1).unwrap();
+    let signature = &help.signatures[0];
+    assert_eq!(signature.label, "\\opt ?:(...) ?:(...) (...)");
+    let offsets = signature
+        .parameters
+        .as_ref()
+        .unwrap()
+        .iter()
+        .map(|param| match param.label {
+            ParameterLabel::LabelOffsets(offsets) => offsets,
+            ParameterLabel::Simple(_) => panic!("label must be given by offsets"),
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(offsets, vec![[5, 12], [13, 20], [21, 26]]);
+}
+
+#[test]
+fn test_active_parameter() {
+    let doc = TestDocument::new(&format!(
+        "{}'<\n  +p{{ \\opt?:(1)?:(^1x){{b}} \\opt?*?:(2){{^2b}} \\opt{{^3b}} \\opt?:(1)^4{{b}} \\opt?:(^5x); }}\n>\n",
+        PREAMBLE
+    ));
+    let active = |marker| signature_help(&doc, marker).unwrap().active_parameter;
+    assert_eq!(active(1), Some(1));
+    assert_eq!(active(2), Some(2));
+    // 省略したオプション引数は飛ばして、オプションでない引数を指す。
+    assert_eq!(active(3), Some(2));
+    assert_eq!(active(4), Some(2));
+    assert_eq!(active(5), Some(0));
+}
+
+#[test]
+fn test_nested_command() {
+    let doc = TestDocument::new(&format!("{}'<\n  +p{{ \\opt{{\\plain{{^1x}}}} \\undefined{{^2x}} }}\n>\n", PREAMBLE));
+    let help = signature_help(&doc, 1).unwrap();
+    assert_eq!(help.signatures[0].label, "\\plain (...)");
+    assert_eq!(help.active_parameter, Some(0));
+    assert!(signature_help(&doc, 2).is_none());
+}
+
+#[test]
+fn test_parameter_text() {
+    assert_eq!(parameter_text(CmdKind::Block, Some(&ParamKind::BlockText)), "<...>");
+    assert_eq!(parameter_text(CmdKind::Inline, Some(&ParamKind::InlineText)), "{...}");
+    assert_eq!(parameter_text(CmdKind::Math, None), "{...}");
+    assert_eq!(parameter_text(CmdKind::Inline, Some(&ParamKind::Unknown)), "(...)");
+}
@@ -0,0 +1,115 @@
+//! 式が評価されるステージの解析。
+//!
+//! ファイルの式は `@stage:` ヘッダで指定したステージで評価される。
+//! ステージ 0 のファイルでは `&` の後ろの式がステージ 1 のコードとなり、
+//! その中で `~` を使うとステージ 0 の式に戻る。
+//! `lift-int` などのプリミティブは、ステージ 0 の値をステージ 1 のコードに持ち上げる。
+
+use crate::{parser::Rule, position::Position, resolve::Stage, BufferCst, Cst, CstRange};
+
+/// ステージ 0 の値をステージ 1 のコードにするプリミティブ。
+pub const LIFT_PRIMITIVES: &[&str] = &["lift-int", "lift-float", "lift-length", "lift-string"];
+
+/// ステージの使い方の誤り。
+#[derive(Debug, Clone)]
+pub struct StageError {
+    /// 誤りのある式の範囲。
+    pub range: CstRange,
+    /// 誤りの内容。
+    pub message: String,
+}
+
+/// 与えられた位置の式が評価されるステージを返す。
+pub fn stage_at(buf_cst: &BufferCst, pos: &Position) -> Stage {
+    let file_stage = buf_cst.stage();
+    let cst = match &buf_cst.cst {
+        Some(cst) => cst,
+        None => return file_stage,
+    };
+    // dig は内側から順に返すので、外側から順にステージを移していく。
+    cst.dig(pos)
+        .into_iter()
+        .rev()
+        .filter(|c| c.rule == Rule::staged_expr)
+        .fold(file_stage, |stage, staged| {
+            shift(file_stage, stage, operator(buf_cst, staged)).unwrap_or(stage)
+        })
+}
+
+/// `&` や `~`、`lift-*` の使い方の誤りを列挙する。
+pub fn stage_errors(buf_cst: &BufferCst) -> Vec<StageError> {
+    let mut errors = vec![];
+    if let Some(cst) = &buf_cst.cst {
+        let file_stage = buf_cst.stage();
+        collect_stage_errors(buf_cst, cst, file_stage, file_stage, &mut errors);
+    }
+    errors
+}
+
+/// `stage` で評価される `cst` の中にある誤りを `errors` に加える。
+fn collect_stage_errors(
+    buf_cst: &BufferCst,
+    cst: &Cst,
+    file_stage: Stage,
+    stage: Stage,
+    errors: &mut Vec<StageError>,
+) {
+    let mut inner_stage = stage;
+    match cst.rule {
+        Rule::staged_expr => {
+            let op = operator(buf_cst, cst);
+            match shift(file_stage, stage, op) {
+                Some(next) => inner_stage = next,
+                None => errors.push(StageError {
+                    range: cst.range.clone(),
+                    message: match op {
+                        "&" => format!(
+                            "`&` can only be used at stage 0, but here is stage {}",
+                            stage.as_str()
+                        ),
+                        _ => "`~` can only be used inside `&` in a stage-0 file".to_owned(),
+                    },
+                }),
+            }
+        }
+        Rule::var if stage != Stage::Zero => {
+            let name = buf_cst.as_str(cst);
+            if LIFT_PRIMITIVES.contains(&name) {
+                errors.push(StageError {
+                    range: cst.range.clone(),
+                    message: format!(
+                        "`{}` can only be used at stage 0, but here is stage {}",
+                        name,
+                        stage.as_str()
+                    ),
+                });
+            }
+        }
+        _ => (),
+    }
+    for inner in cst.inner.iter() {
+        collect_stage_errors(buf_cst, inner, file_stage, inner_stage, errors);
+    }
+}
+
+/// staged_expr の演算子 (`&` または `~`) を返す。
+fn operator<'a>(buf_cst: &'a BufferCst, staged: &Cst) -> &'a str {
+    staged
+        .inner
+        .iter()
+        .find(|c| c.rule == Rule::stage_operator)
+        .map_or("", |op| buf_cst.as_str(op))
+}
+
+/// `&` や `~` で移った先のステージを返す。移れない場合は None を返す。
+/// `&` はステージ 0 から 1 へ移り、`~` は `&` で移ったステージ 1 から 0 へ戻る。
+fn shift(file_stage: Stage, stage: Stage, operator: &str) -> Option<Stage> {
+    match (operator, stage) {
+        ("&", Stage::Zero) => Some(Stage::One),
+        ("~", Stage::One) if file_stage == Stage::Zero => Some(Stage::Zero),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,63 @@
+//! test module for stage analysis.
+
+use super::*;
+use crate::test_utils::TestDocument;
+use crate::Buffer;
+
+fn buffer(text: &str) -> Buffer {
+    let buf = Buffer::new(text.to_owned());
+    assert!(buf.error.is_empty(), "parse failed: {:?}", buf.error);
+    buf
+}
+
+fn messages(text: &str) -> Vec<String> {
+    stage_errors(&buffer(text).buf_cst)
+        .into_iter()
+        .map(|e| e.message)
+        .collect()
+}
+
+#[test]
+fn test_stage_at() {
+    // マーカーは数字の直前に置けないため、引数には変数を用いる。
+    let doc = TestDocument::new("@stage: 0\nlet x^1 = &(^2f ~(^3g y) ^4z)\nin\nx\n");
+    let buf = buffer(doc.text());
+    let stage = |marker| stage_at(&buf.buf_cst, &doc.position(marker));
+    assert_eq!(stage(1), Stage::Zero);
+    assert_eq!(stage(2), Stage::One);
+    assert_eq!(stage(3), Stage::Zero);
+    assert_eq!(stage(4), Stage::One);
+
+    let doc = TestDocument::new("let x = ^1y in x\n");
+    let buf = buffer(doc.text());
+    assert_eq!(stage_at(&buf.buf_cst, &doc.position(1)), Stage::One);
+}
+
+#[test]
+fn test_valid_staging() {
+    assert!(messages("@stage: 0\nlet x = &(f ~(lift-int 1))\nin\nx\n").is_empty());
+    assert!(messages("let x = a && b in x\n").is_empty());
+}
+
+#[test]
+fn test_stage_errors() {
+    assert_eq!(
+        messages("let x = &(f 1) in x\n"),
+        vec!["`&` can only be used at stage 0, but here is stage 1"]
+    );
+    assert_eq!(
+        messages("@stage: 0\nlet x = ~y in &(&z)\n"),
+        vec![
+            "`~` can only be used inside `&` in a stage-0 file",
+            "`&` can only be used at stage 0, but here is stage 1",
+        ]
+    );
+    assert_eq!(
+        messages("@stage: persistent\nlet x = lift-int 1 in x\n"),
+        vec!["`lift-int` can only be used at stage 0, but here is stage persistent"]
+    );
+    assert_eq!(
+        messages("@stage: 0\nlet x = &(lift-string `a`) in x\n"),
+        vec!["`lift-string` can only be used at stage 0, but here is stage 1"]
+    );
+}
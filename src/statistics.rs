@@ -0,0 +1,116 @@
+//! 文書の分量を数える `satysfi/statistics` リクエストと `stats` サブコマンド。
+//!
+//! 段落は、最後の引数が `{...}` であるブロックコマンド（`+p{...}` など）とみなす。
+//! 語数と文字数は、プリアンブルの外にある水平モードの地の文だけを数え、コマンドや数式は含めない。
+//! 語は空白で区切られたものとし、文字数には空白を含めない。
+
+use lsp_types::{request::Request, TextDocumentIdentifier};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    parser::{relation::CompareRange, Rule},
+    position::Range,
+    Buffer, CmdKind, Cst,
+};
+
+/// 文書の分量を返すカスタムリクエスト。
+pub enum Statistics {}
+
+impl Request for Statistics {
+    type Params = StatisticsParams;
+    type Result = Option<DocumentStatistics>;
+    const METHOD: &'static str = "satysfi/statistics";
+}
+
+/// `satysfi/statistics` のパラメータ。
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StatisticsParams {
+    /// 対象のドキュメント。
+    pub text_document: TextDocumentIdentifier,
+}
+
+/// 文書の分量。
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DocumentStatistics {
+    /// 段落の数。
+    pub paragraphs: usize,
+    /// インラインコマンドを使っている箇所の数。
+    pub inline_commands: usize,
+    /// ブロックコマンドを使っている箇所の数。
+    pub block_commands: usize,
+    /// 数式コマンドを使っている箇所の数。
+    pub math_commands: usize,
+    /// 文書で定義されているコマンドの数。
+    pub defined_commands: usize,
+    /// 地の文の語数。
+    pub words: usize,
+    /// 地の文の文字数。
+    pub characters: usize,
+}
+
+impl DocumentStatistics {
+    /// 項目名と値の組を、出力する順に返す。
+    pub fn fields(&self) -> [(&'static str, usize); 7] {
+        [
+            ("paragraphs", self.paragraphs),
+            ("inline commands", self.inline_commands),
+            ("block commands", self.block_commands),
+            ("math commands", self.math_commands),
+            ("defined commands", self.defined_commands),
+            ("words", self.words),
+            ("characters", self.characters),
+        ]
+    }
+}
+
+/// statistics リクエストへの response を返す。
+pub fn get_statistics_response(buf: &Buffer) -> Option<DocumentStatistics> {
+    document_statistics(buf)
+}
+
+/// 文書の分量を数える。パースに失敗していれば None を返す。
+pub fn document_statistics(buf: &Buffer) -> Option<DocumentStatistics> {
+    let cst = buf.buf_cst.cst.as_ref()?;
+    let preamble: Option<Range> = cst.pickup(Rule::preamble).first().map(|p| p.range());
+    let in_body = |cst: &Cst| preamble.is_none_or(|preamble| !preamble.includes(&cst.range()));
+
+    let paragraphs = cst
+        .pickup(Rule::block_cmd)
+        .into_iter()
+        .filter(|cmd| in_body(cmd) && is_paragraph(cmd))
+        .count();
+    let (mut words, mut characters) = (0, 0);
+    for text in cst.pickup(Rule::regular_text).into_iter().filter(|text| in_body(text)) {
+        let text = buf.buf_cst.as_str(text);
+        words += text.split_whitespace().count();
+        characters += text.chars().filter(|c| !c.is_whitespace()).count();
+    }
+    let defined_commands = [CmdKind::Inline, CmdKind::Block, CmdKind::Math]
+        .iter()
+        .map(|kind| buf.env.commands(*kind).count())
+        .sum();
+
+    Some(DocumentStatistics {
+        paragraphs,
+        inline_commands: cst.pickup(Rule::inline_cmd).len(),
+        block_commands: cst.pickup(Rule::block_cmd).len(),
+        math_commands: cst.pickup(Rule::math_cmd).len(),
+        defined_commands,
+        words,
+        characters,
+    })
+}
+
+/// 最後の引数が水平モードのテキストであるブロックコマンドか。
+fn is_paragraph(cmd: &Cst) -> bool {
+    let last = match cmd.children().last() {
+        Some(last) if last.rule() == Rule::cmd_text_arg => last,
+        _ => return false,
+    };
+    matches!(last.children().first(), Some(arg) if arg.rule() == Rule::horizontal_mode)
+}
+
+#[cfg(test)]
+mod tests;
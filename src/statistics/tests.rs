@@ -0,0 +1,39 @@
+//! test module for statistics.
+
+use super::*;
+
+#[test]
+fn test_document_statistics() {
+    let text = concat!(
+        "let-inline ctx \\name = {Taro Yamada}\n",
+        "let-math \\id = ${x}\n",
+        "in\n",
+        "'<\n",
+        "  +section{Intro}<\n",
+        "    +p{ Hello, \\emph{brave} new world. ${x + \\alpha} }\n",
+        "    +p{ 吾輩は猫である。 }\n",
+        "    +math(${\\id});\n",
+        "  >\n",
+        ">\n",
+    );
+    let buf = Buffer::new(text.to_owned());
+    // プリアンブルの `{Taro Yamada}` は地の文に含めない。
+    assert_eq!(
+        document_statistics(&buf),
+        Some(DocumentStatistics {
+            paragraphs: 2,
+            inline_commands: 1,
+            block_commands: 4,
+            math_commands: 2,
+            defined_commands: 2,
+            words: 6,
+            characters: 33,
+        })
+    );
+}
+
+#[test]
+fn test_unparsable_document() {
+    let buf = Buffer::new("'<\n  +p{ \n".to_owned());
+    assert_eq!(document_statistics(&buf), None);
+}
@@ -0,0 +1,113 @@
+//! サーバの状態を返す `satysfi/serverStatus` リクエスト。
+//!
+//! エディタのプラグインが状態を表示したり、不具合の報告に添えたりするために用いる。
+
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+    time::Duration,
+};
+
+use log::error;
+use lsp_types::{request::Request, Url};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::Config,
+    parse_cache::{ParseCache, ParseCacheStats},
+    workspace::WorkspaceIndex,
+    Buffer, BufferCst,
+};
+
+/// サーバの状態を返すカスタムリクエスト。
+pub enum ServerStatus {}
+
+impl Request for ServerStatus {
+    type Params = Option<ServerStatusParams>;
+    type Result = ServerStatusResult;
+    const METHOD: &'static str = "satysfi/serverStatus";
+}
+
+/// `satysfi/serverStatus` のパラメータ。現時点では何も受け取らない。
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct ServerStatusParams {}
+
+/// `satysfi/serverStatus` の結果。
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ServerStatusResult {
+    /// 索引化されているファイルの数。
+    pub indexed_files: usize,
+    /// 開かれているバッファの数。
+    pub open_buffers: usize,
+    /// 起動してからパースに費やした時間の合計（ミリ秒）。
+    pub parse_time_ms: u64,
+    /// 開かれているバッファとパース結果のキャッシュの Cst が占めるおおよそのバイト数。
+    /// バッファとキャッシュで共有しているものは一度だけ数える。
+    pub cst_memory_bytes: usize,
+    /// パース結果を使い回すためのキャッシュの状態。
+    pub parse_cache: ParseCacheStats,
+    /// 最後に起きたエラー。
+    pub last_error: Option<String>,
+    /// 有効な設定。
+    pub config: Config,
+}
+
+/// サーバが動いている間に集める統計。
+#[derive(Debug, Default)]
+pub struct ServerStats {
+    /// パースに費やした時間の合計。
+    parse_time: Duration,
+    /// 最後に起きたエラー。
+    last_error: Option<String>,
+}
+
+impl ServerStats {
+    /// パースに費やした時間を加える。
+    pub fn record_parse(&mut self, elapsed: Duration) {
+        self.parse_time += elapsed;
+    }
+
+    /// パースに費やした時間の合計。
+    pub fn parse_time(&self) -> Duration {
+        self.parse_time
+    }
+
+    /// エラーをログに出力し、最後のエラーとして記録する。
+    pub fn record_error(&mut self, message: String) {
+        error!("{}", message);
+        self.last_error = Some(message);
+    }
+}
+
+/// serverStatus リクエストへの response を返す。
+pub fn get_server_status_response(
+    stats: &ServerStats,
+    index: &WorkspaceIndex,
+    buffers: &HashMap<Url, Buffer>,
+    parse_cache: &ParseCache,
+    config: &Config,
+) -> ServerStatusResult {
+    ServerStatusResult {
+        indexed_files: index.len(),
+        open_buffers: buffers.len(),
+        parse_time_ms: stats.parse_time.as_millis() as u64,
+        cst_memory_bytes: cst_memory_bytes(
+            buffers.values().map(|buf| &buf.buf_cst).chain(parse_cache.entries()),
+        ),
+        parse_cache: parse_cache.stats(),
+        last_error: stats.last_error.clone(),
+        config: config.clone(),
+    }
+}
+
+/// Cst が占めるおおよそのバイト数の合計。同じものを指す Arc は一度だけ数える。
+fn cst_memory_bytes<'a>(csts: impl Iterator<Item = &'a Arc<BufferCst>>) -> usize {
+    let mut seen = HashSet::new();
+    csts.filter(|buf_cst| seen.insert(Arc::as_ptr(buf_cst)))
+        .map(|buf_cst| buf_cst.memory_estimate())
+        .sum()
+}
+
+#[cfg(test)]
+mod tests;
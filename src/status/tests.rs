@@ -0,0 +1,26 @@
+//! test module for server status.
+
+use super::*;
+use crate::config::LanguageVersion;
+
+#[test]
+fn test_cst_memory_bytes_counts_shared_once() {
+    let mut cache = ParseCache::new(4);
+    let text = "let x = 1 in x\n";
+    let (buf_cst, _) = cache.parse(text.to_owned(), LanguageVersion::Stable);
+    let bytes = buf_cst.memory_estimate();
+    assert!(bytes > 0);
+
+    // バッファとキャッシュで共有しているものは一度だけ数える。
+    let (shared, _) = cache.parse(text.to_owned(), LanguageVersion::Stable);
+    let csts = vec![&buf_cst, &shared];
+    assert_eq!(cst_memory_bytes(csts.into_iter().chain(cache.entries())), bytes);
+
+    // キャッシュにだけ残っているものも数える。
+    assert_eq!(cst_memory_bytes(cache.entries()), bytes);
+    let other = Arc::new(BufferCst::parse_with("let y = 2 in y\n".to_owned(), LanguageVersion::Stable).0);
+    assert_eq!(
+        cst_memory_bytes(std::iter::once(&other).chain(cache.entries())),
+        bytes + other.memory_estimate()
+    );
+}
@@ -0,0 +1,108 @@
+//! 再パース前後の Environment の差分と、それを通知する `satysfi/symbolsChanged`.
+
+use std::collections::BTreeSet;
+
+use lsp_types::{notification::Notification, Url};
+use serde::{Deserialize, Serialize};
+
+use crate::Environment;
+
+/// 定義済みのコマンドや変数の増減を知らせるカスタム通知。
+pub enum SymbolsChanged {}
+
+impl Notification for SymbolsChanged {
+    type Params = SymbolsChangedParams;
+    const METHOD: &'static str = "satysfi/symbolsChanged";
+}
+
+/// `satysfi/symbolsChanged` のパラメータ。
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SymbolsChangedParams {
+    /// 変更のあったドキュメント。
+    pub uri: Url,
+    /// 新たに定義されたもの。
+    pub added: Vec<Symbol>,
+    /// 定義が消えたもの。
+    pub removed: Vec<Symbol>,
+}
+
+/// 定義の種類。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SymbolKind {
+    /// インラインコマンド。
+    InlineCmd,
+    /// ブロックコマンド。
+    BlockCmd,
+    /// 数式コマンド。
+    MathCmd,
+    /// 変数。
+    Variable,
+}
+
+/// 定義の種類と名前。
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize)]
+pub struct Symbol {
+    /// 定義の種類。
+    pub kind: SymbolKind,
+    /// 名前。
+    pub name: String,
+}
+
+/// 2 つの Environment の差分。
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct EnvironmentDiff {
+    /// 新たに定義されたもの。
+    pub added: Vec<Symbol>,
+    /// 定義が消えたもの。
+    pub removed: Vec<Symbol>,
+}
+
+impl EnvironmentDiff {
+    /// 差分がないかどうか。
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty()
+    }
+
+    /// 通知のパラメータに変換する。
+    pub fn into_params(self, uri: Url) -> SymbolsChangedParams {
+        SymbolsChangedParams {
+            uri,
+            added: self.added,
+            removed: self.removed,
+        }
+    }
+}
+
+impl Environment {
+    /// 古い environment と比べ、定義の増減を求める。定義の位置の変化は考慮しない。
+    pub fn diff(old: &Environment, new: &Environment) -> EnvironmentDiff {
+        let old = old.symbols();
+        let new = new.symbols();
+        EnvironmentDiff {
+            added: new.difference(&old).cloned().collect(),
+            removed: old.difference(&new).cloned().collect(),
+        }
+    }
+
+    /// 定義を種類と名前の集合にする。
+    fn symbols(&self) -> BTreeSet<Symbol> {
+        let symbol = |kind, name: &String| Symbol {
+            kind,
+            name: name.clone(),
+        };
+        let inline_cmds = self.inline_cmds.iter().map(|c| symbol(SymbolKind::InlineCmd, &c.name));
+        let block_cmds = self.block_cmds.iter().map(|c| symbol(SymbolKind::BlockCmd, &c.name));
+        let math_cmds = self.math_cmds.iter().map(|c| symbol(SymbolKind::MathCmd, &c.name));
+        let variables = self.variables.iter().map(|v| symbol(SymbolKind::Variable, &v.name));
+        inline_cmds
+            .chain(block_cmds)
+            .chain(math_cmds)
+            .chain(variables)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,32 @@
+//! test module for environment diff.
+
+use super::*;
+use crate::Buffer;
+
+fn env(text: &str) -> Environment {
+    let buf = Buffer::new(text.to_owned());
+    assert!(buf.error.is_empty(), "parse failed: {:?}", buf.error);
+    buf.env
+}
+
+#[test]
+fn test_diff() {
+    let old = env("let-inline \\foo = {}\nlet x = 1\nin {}");
+    let new = env("let-inline \\foo = {}\nlet-block +bar = '<>\nin {}");
+    let diff = Environment::diff(&old, &new);
+    assert_eq!(
+        diff.added,
+        vec![Symbol { kind: SymbolKind::BlockCmd, name: "+bar".to_owned() }]
+    );
+    assert_eq!(
+        diff.removed,
+        vec![Symbol { kind: SymbolKind::Variable, name: "x".to_owned() }]
+    );
+}
+
+#[test]
+fn test_diff_ignores_moves() {
+    let old = env("let-inline \\foo = {}\nin {}");
+    let new = env("\n\nlet-inline \\foo = {abc}\nin {}");
+    assert!(Environment::diff(&old, &new).is_empty());
+}
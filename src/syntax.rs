@@ -0,0 +1,59 @@
+//! パーサのバックエンドを抽象化した構文木の trait.
+//!
+//! デフォルトのバックエンドは pest による [`BufferCst`] である。
+//! `tree-sitter` feature を有効にすると、差分パースやエラー回復の挙動を比べるために
+//! tree-sitter による実験的なバックエンドも利用できる。
+
+use crate::{position::Position, BufferCst};
+
+#[cfg(feature = "tree-sitter")]
+pub mod tree_sitter;
+
+#[cfg(test)]
+mod tests;
+
+/// バックエンドによらない構文木の操作。
+pub trait SyntaxTree {
+    /// 構文木の元となった文字列。
+    fn text(&self) -> &str;
+
+    /// 与えられた位置を含むノードの種類を、内側から順に根まで列挙する。
+    fn kinds_at(&self, pos: &Position) -> Vec<String>;
+
+    /// 構文エラーを含むかどうか。
+    fn has_error(&self) -> bool;
+
+    /// 新しい文字列で構文木を作り直す。
+    /// バックエンドによっては前回の構文木を再利用して差分だけをパースする。
+    fn reparse(&mut self, text: String);
+}
+
+impl SyntaxTree for BufferCst {
+    fn text(&self) -> &str {
+        &self.buffer
+    }
+
+    fn kinds_at(&self, pos: &Position) -> Vec<String> {
+        let cst = match &self.cst {
+            Some(cst) => cst,
+            None => return vec![],
+        };
+        let mut kinds: Vec<String> = cst
+            .dig(pos)
+            .into_iter()
+            .map(|cst| format!("{:?}", cst.rule))
+            .collect();
+        kinds.push(format!("{:?}", cst.rule));
+        kinds
+    }
+
+    fn has_error(&self) -> bool {
+        // 修復してパースできた場合も、元の文字列は構文エラーである。
+        self.cst.is_none() || !self.recoveries.is_empty()
+    }
+
+    fn reparse(&mut self, text: String) {
+        let (buf_cst, _) = BufferCst::parse_into(text);
+        *self = buf_cst;
+    }
+}
@@ -0,0 +1,67 @@
+//! test module for syntax.
+
+use super::*;
+
+#[cfg(feature = "tree-sitter")]
+#[allow(unsafe_code)]
+mod words;
+
+/// どのバックエンドにも同じ問い合わせをするための入力と期待値。
+struct Case {
+    /// 構文エラーのない文字列。
+    valid: &'static str,
+    /// 構文エラーを含む文字列。
+    broken: &'static str,
+    /// `kinds_at` で問い合わせる位置。
+    pos: Position,
+    /// `pos` を含む最も内側のノードの種類。
+    innermost: &'static str,
+    /// 根のノードの種類。
+    root: &'static str,
+}
+
+fn check_queries<T: SyntaxTree>(tree: &mut T, case: &Case) {
+    assert_eq!(tree.text(), case.valid);
+    assert!(!tree.has_error());
+    let kinds = tree.kinds_at(&case.pos);
+    assert_eq!(kinds.first().map(String::as_str), Some(case.innermost));
+    assert_eq!(kinds.last().map(String::as_str), Some(case.root));
+
+    tree.reparse(case.broken.to_owned());
+    assert_eq!(tree.text(), case.broken);
+    assert!(tree.has_error());
+
+    // 元の文字列に戻せば、最初と同じ結果になる。
+    tree.reparse(case.valid.to_owned());
+    assert!(!tree.has_error());
+    assert_eq!(tree.kinds_at(&case.pos), kinds);
+}
+
+#[test]
+fn test_queries_on_pest() {
+    let case = Case {
+        valid: "let x = 1\n",
+        broken: "let x = \n",
+        pos: Position { line: 0, character: 4 },
+        innermost: "var",
+        root: "program",
+    };
+    let (mut tree, _) = BufferCst::parse_into(case.valid.to_owned());
+    check_queries(&mut tree, &case);
+}
+
+#[cfg(feature = "tree-sitter")]
+#[test]
+fn test_queries_on_tree_sitter() {
+    use super::tree_sitter::TreeSitterTree;
+
+    let case = Case {
+        valid: "foo bar\nbaz\n",
+        broken: "foo ? bar\nbaz\n",
+        pos: Position { line: 1, character: 1 },
+        innermost: "word",
+        root: "source_file",
+    };
+    let mut tree = TreeSitterTree::parse(words::language(), case.valid.to_owned()).unwrap();
+    check_queries(&mut tree, &case);
+}
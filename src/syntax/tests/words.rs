@@ -0,0 +1,283 @@
+//! テスト用の小さな tree-sitter 文法。
+//!
+//! tree-sitter-satysfi の文法はクレートに同梱していないため、
+//! tree-sitter CLI が生成する parser.c に相当する表を手で書いて [`Language`] を作る。
+//! 文法は次のとおりで、空白区切りの英小文字の単語の並びだけを受け付ける。
+//!
+//! ```text
+//! source_file: $ => repeat($.word),
+//! word: $ => /[a-z]+/,
+//! ```
+
+use std::{os::raw::c_char, ptr};
+
+use tree_sitter::Language;
+
+const SYM_END: u16 = 0;
+const SYM_WORD: u16 = 1;
+const SYM_SOURCE_FILE: u16 = 2;
+const SYM_WORDS: u16 = 3;
+
+const SYMBOL_COUNT: usize = 4;
+const TOKEN_COUNT: u32 = 2;
+const STATE_COUNT: usize = 6;
+
+#[repr(C)]
+struct Lexer {
+    lookahead: i32,
+    result_symbol: u16,
+    advance: unsafe extern "C" fn(*mut Lexer, bool),
+    mark_end: unsafe extern "C" fn(*mut Lexer),
+    get_column: unsafe extern "C" fn(*mut Lexer) -> u32,
+    is_at_included_range_start: unsafe extern "C" fn(*const Lexer) -> bool,
+    eof: unsafe extern "C" fn(*const Lexer) -> bool,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct Shift {
+    ty: u8,
+    state: u16,
+    extra: bool,
+    repetition: bool,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct Reduce {
+    ty: u8,
+    child_count: u8,
+    symbol: u16,
+    dynamic_precedence: i16,
+    production_id: u16,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct EntryHeader {
+    count: u8,
+    reusable: bool,
+}
+
+/// `TSParseActionEntry` に相当する共用体。
+#[repr(C)]
+#[derive(Clone, Copy)]
+union ActionEntry {
+    shift: Shift,
+    reduce: Reduce,
+    header: EntryHeader,
+}
+
+#[repr(C)]
+struct SymbolMetadata {
+    visible: bool,
+    named: bool,
+    supertype: bool,
+}
+
+#[repr(C)]
+struct LexMode {
+    lex_state: u16,
+    external_lex_state: u16,
+}
+
+#[repr(C)]
+struct ExternalScanner {
+    states: *const bool,
+    symbol_map: *const u16,
+    create: *const (),
+    destroy: *const (),
+    scan: *const (),
+    serialize: *const (),
+    deserialize: *const (),
+}
+
+/// `TSLanguage` (言語 ABI 14) に相当する構造体。
+#[repr(C)]
+struct RawLanguage {
+    version: u32,
+    symbol_count: u32,
+    alias_count: u32,
+    token_count: u32,
+    external_token_count: u32,
+    state_count: u32,
+    large_state_count: u32,
+    production_id_count: u32,
+    field_count: u32,
+    max_alias_sequence_length: u16,
+    parse_table: *const u16,
+    small_parse_table: *const u16,
+    small_parse_table_map: *const u32,
+    parse_actions: *const ActionEntry,
+    symbol_names: *const *const c_char,
+    field_names: *const *const c_char,
+    field_map_slices: *const u32,
+    field_map_entries: *const u32,
+    symbol_metadata: *const SymbolMetadata,
+    public_symbol_map: *const u16,
+    alias_map: *const u16,
+    alias_sequences: *const u16,
+    lex_modes: *const LexMode,
+    lex_fn: unsafe extern "C" fn(*mut Lexer, u16) -> bool,
+    keyword_lex_fn: Option<unsafe extern "C" fn(*mut Lexer, u16) -> bool>,
+    keyword_capture_token: u16,
+    external_scanner: ExternalScanner,
+    primary_state_ids: *const u16,
+}
+
+const fn header(count: u8, reusable: bool) -> ActionEntry {
+    ActionEntry { header: EntryHeader { count, reusable } }
+}
+
+const fn shift(state: u16) -> ActionEntry {
+    ActionEntry { shift: Shift { ty: 0, state, extra: false, repetition: false } }
+}
+
+const fn reduce(symbol: u16, child_count: u8) -> ActionEntry {
+    ActionEntry {
+        reduce: Reduce { ty: 1, child_count, symbol, dynamic_precedence: 0, production_id: 0 },
+    }
+}
+
+/// Accept (2) と Recover (3) は種類だけを持つ。
+const fn bare(ty: u8) -> ActionEntry {
+    ActionEntry {
+        reduce: Reduce { ty, child_count: 0, symbol: 0, dynamic_precedence: 0, production_id: 0 },
+    }
+}
+
+/// 構文解析の動作の表。各動作の前に、動作の数と再利用できるかどうかを置く。
+static PARSE_ACTIONS: [ActionEntry; 17] = [
+    header(0, false),
+    header(1, false),
+    bare(3),
+    header(1, true),
+    reduce(SYM_SOURCE_FILE, 0),
+    header(1, true),
+    shift(3),
+    header(1, true),
+    reduce(SYM_SOURCE_FILE, 1),
+    header(1, true),
+    shift(5),
+    header(1, true),
+    reduce(SYM_WORDS, 1),
+    header(1, true),
+    bare(2),
+    header(1, true),
+    reduce(SYM_WORDS, 2),
+];
+
+/// 状態と記号から、終端記号なら動作の位置、非終端記号なら遷移先の状態を引く表。
+/// `_words` は `_words word` と `word` からなる隠れた非終端記号である。
+/// 状態 0 はエラー回復用の状態、状態 1 が開始状態である。
+static PARSE_TABLE: [[u16; SYMBOL_COUNT]; STATE_COUNT] = [
+    [1, 1, 0, 0],
+    [3, 5, 4, 2],
+    [7, 9, 0, 0],
+    [11, 11, 0, 0],
+    [13, 0, 0, 0],
+    [15, 15, 0, 0],
+];
+
+static SYMBOL_METADATA: [SymbolMetadata; SYMBOL_COUNT] = [
+    SymbolMetadata { visible: false, named: true, supertype: false },
+    SymbolMetadata { visible: true, named: true, supertype: false },
+    SymbolMetadata { visible: true, named: true, supertype: false },
+    SymbolMetadata { visible: false, named: false, supertype: false },
+];
+
+static PUBLIC_SYMBOL_MAP: [u16; SYMBOL_COUNT] = [SYM_END, SYM_WORD, SYM_SOURCE_FILE, SYM_WORDS];
+
+static LEX_MODES: [LexMode; STATE_COUNT] = [
+    LexMode { lex_state: 0, external_lex_state: 0 },
+    LexMode { lex_state: 0, external_lex_state: 0 },
+    LexMode { lex_state: 0, external_lex_state: 0 },
+    LexMode { lex_state: 0, external_lex_state: 0 },
+    LexMode { lex_state: 0, external_lex_state: 0 },
+    LexMode { lex_state: 0, external_lex_state: 0 },
+];
+
+static PRIMARY_STATE_IDS: [u16; STATE_COUNT] = [0, 1, 2, 3, 4, 5];
+
+static EMPTY: [u16; 1] = [0];
+
+static EMPTY_MAP: [u32; 1] = [0];
+
+fn is_lowercase(c: i32) -> bool {
+    (b'a' as i32..=b'z' as i32).contains(&c)
+}
+
+fn is_whitespace(c: i32) -> bool {
+    [b' ', b'\t', b'\n', b'\r'].iter().any(|&w| w as i32 == c)
+}
+
+/// 空白を読み飛ばし、入力の終わりか単語を 1 つ読む。
+unsafe extern "C" fn lex(lexer: *mut Lexer, _state: u16) -> bool {
+    while is_whitespace((*lexer).lookahead) {
+        ((*lexer).advance)(lexer, true);
+    }
+    if ((*lexer).eof)(lexer) {
+        (*lexer).result_symbol = SYM_END;
+        ((*lexer).mark_end)(lexer);
+        return true;
+    }
+    if !is_lowercase((*lexer).lookahead) {
+        return false;
+    }
+    while is_lowercase((*lexer).lookahead) {
+        ((*lexer).advance)(lexer, false);
+    }
+    (*lexer).result_symbol = SYM_WORD;
+    ((*lexer).mark_end)(lexer);
+    true
+}
+
+/// 単語の並びを受け付ける文法を返す。
+pub fn language() -> Language {
+    let symbol_names: &'static [*const c_char; SYMBOL_COUNT] = Box::leak(Box::new([
+        b"end\0".as_ptr() as *const c_char,
+        b"word\0".as_ptr() as *const c_char,
+        b"source_file\0".as_ptr() as *const c_char,
+        b"_words\0".as_ptr() as *const c_char,
+    ]));
+    let raw: &'static RawLanguage = Box::leak(Box::new(RawLanguage {
+        version: 14,
+        symbol_count: SYMBOL_COUNT as u32,
+        alias_count: 0,
+        token_count: TOKEN_COUNT,
+        external_token_count: 0,
+        state_count: STATE_COUNT as u32,
+        large_state_count: STATE_COUNT as u32,
+        production_id_count: 1,
+        field_count: 0,
+        max_alias_sequence_length: 0,
+        parse_table: PARSE_TABLE.as_ptr() as *const u16,
+        small_parse_table: EMPTY.as_ptr(),
+        small_parse_table_map: EMPTY_MAP.as_ptr(),
+        parse_actions: PARSE_ACTIONS.as_ptr(),
+        symbol_names: symbol_names.as_ptr(),
+        field_names: ptr::null(),
+        field_map_slices: ptr::null(),
+        field_map_entries: ptr::null(),
+        symbol_metadata: SYMBOL_METADATA.as_ptr(),
+        public_symbol_map: PUBLIC_SYMBOL_MAP.as_ptr(),
+        alias_map: EMPTY.as_ptr(),
+        alias_sequences: EMPTY.as_ptr(),
+        lex_modes: LEX_MODES.as_ptr(),
+        lex_fn: lex,
+        keyword_lex_fn: None,
+        keyword_capture_token: 0,
+        external_scanner: ExternalScanner {
+            states: ptr::null(),
+            symbol_map: ptr::null(),
+            create: ptr::null(),
+            destroy: ptr::null(),
+            scan: ptr::null(),
+            serialize: ptr::null(),
+            deserialize: ptr::null(),
+        },
+        primary_state_ids: PRIMARY_STATE_IDS.as_ptr(),
+    }));
+    // Language は TSLanguage へのポインタを包んだだけの型である。
+    unsafe { std::mem::transmute::<*const RawLanguage, Language>(raw) }
+}
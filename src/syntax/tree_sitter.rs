@@ -0,0 +1,90 @@
+//! tree-sitter による実験的なバックエンド。
+//!
+//! tree-sitter-satysfi の文法はこのクレートに同梱しないため、
+//! 利用する側で [`Language`] を用意して渡す。
+
+use tree_sitter::{InputEdit, Language, LanguageError, Parser, Point, Tree};
+
+use crate::{
+    edit::{common_prefix_len, common_suffix_len},
+    position::{LineIndex, Position},
+};
+
+use super::SyntaxTree;
+
+/// tree-sitter でパースした構文木。
+pub struct TreeSitterTree {
+    /// パーサ本体。差分パースのために保持しておく。
+    parser: Parser,
+    /// 構文木。パースが中断された場合は None.
+    tree: Option<Tree>,
+    /// 構文木の元となった文字列。
+    text: String,
+}
+
+impl TreeSitterTree {
+    /// 与えられた文法で文字列をパースする。
+    pub fn parse(language: Language, text: String) -> Result<Self, LanguageError> {
+        let mut parser = Parser::new();
+        parser.set_language(language)?;
+        let tree = parser.parse(&text, None);
+        Ok(Self { parser, tree, text })
+    }
+}
+
+impl SyntaxTree for TreeSitterTree {
+    fn text(&self) -> &str {
+        &self.text
+    }
+
+    fn kinds_at(&self, pos: &Position) -> Vec<String> {
+        let tree = match &self.tree {
+            Some(tree) => tree,
+            None => return vec![],
+        };
+        let point = point_of(&self.text, LineIndex::new(&self.text).offset(*pos));
+        let mut kinds = vec![];
+        let mut node = tree.root_node().descendant_for_point_range(point, point);
+        while let Some(n) = node {
+            kinds.push(n.kind().to_owned());
+            node = n.parent();
+        }
+        kinds
+    }
+
+    fn has_error(&self) -> bool {
+        self.tree
+            .as_ref()
+            .map(|tree| tree.root_node().has_error())
+            .unwrap_or(true)
+    }
+
+    /// 前回の構文木を再利用して差分だけをパースする。
+    fn reparse(&mut self, text: String) {
+        let old = &self.text;
+        // 前後の共通部分を除いた範囲を 1 つの編集とみなす。
+        let prefix = common_prefix_len(old, &text);
+        let suffix = common_suffix_len(&old[prefix..], &text[prefix..]);
+        let edit = InputEdit {
+            start_byte: prefix,
+            old_end_byte: old.len() - suffix,
+            new_end_byte: text.len() - suffix,
+            start_position: point_of(old, prefix),
+            old_end_position: point_of(old, old.len() - suffix),
+            new_end_position: point_of(&text, text.len() - suffix),
+        };
+        if let Some(tree) = &mut self.tree {
+            tree.edit(&edit);
+        }
+        self.tree = self.parser.parse(&text, self.tree.as_ref());
+        self.text = text;
+    }
+}
+
+/// バイトオフセットを tree-sitter の Point (行とバイト単位の列) に変換する。
+fn point_of(text: &str, offset: usize) -> Point {
+    let before = &text[..offset];
+    let row = before.matches('\n').count();
+    let column = before.len() - before.rfind('\n').map(|i| i + 1).unwrap_or(0);
+    Point { row, column }
+}
@@ -0,0 +1,95 @@
+//! メッセージの処理にかかった時間の計測と、それを知らせる `$/logTrace`.
+//!
+//! 計測はクライアントが `initialize` の `trace` か `$/setTrace` で `messages` 以上を指定した場合に限り、
+//! `$/logTrace` で送る。`verbose` では段階ごとの時間を `parse_ms=...` のような
+//! 構造化された形式で添える。同じ内容はログにも debug レベルで出力する。
+
+use std::time::Duration;
+
+use lsp_types::{notification::Notification, TraceOption};
+use serde::{Deserialize, Serialize};
+
+/// サーバの動作の記録をクライアントに送る通知。
+pub enum LogTrace {}
+
+impl Notification for LogTrace {
+    type Params = LogTraceParams;
+    const METHOD: &'static str = "$/logTrace";
+}
+
+/// `$/logTrace` のパラメータ。
+#[derive(Debug, Deserialize, Serialize)]
+pub struct LogTraceParams {
+    /// 記録の内容。
+    pub message: String,
+    /// trace が `verbose` のときに添える詳しい内容。
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub verbose: Option<String>,
+}
+
+/// クライアントが trace の設定を変えたことを知らせる通知。
+pub enum SetTrace {}
+
+impl Notification for SetTrace {
+    type Params = SetTraceParams;
+    const METHOD: &'static str = "$/setTrace";
+}
+
+/// `$/setTrace` のパラメータ。
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SetTraceParams {
+    /// 新しい trace の設定。
+    pub value: TraceOption,
+}
+
+/// 1 つのメッセージの処理にかかった時間。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Timing {
+    /// メッセージのメソッド名。
+    pub method: String,
+    /// バッファのパースにかかった時間。
+    pub parse: Duration,
+    /// パース以外の処理にかかった時間。
+    pub analysis: Duration,
+    /// response の送信にかかった時間。通知では None となる。
+    pub respond: Option<Duration>,
+}
+
+impl Timing {
+    /// 処理全体にかかった時間。
+    pub fn total(&self) -> Duration {
+        self.parse + self.analysis + self.respond.unwrap_or_default()
+    }
+
+    /// 段階ごとの時間を `key=value` の形で空白区切りに並べる。
+    pub fn fields(&self) -> String {
+        let ms = |d: Duration| format!("{:.3}", d.as_secs_f64() * 1000.0);
+        let mut fields = vec![
+            format!("method={}", self.method),
+            format!("parse_ms={}", ms(self.parse)),
+            format!("analysis_ms={}", ms(self.analysis)),
+        ];
+        if let Some(respond) = self.respond {
+            fields.push(format!("respond_ms={}", ms(respond)));
+        }
+        fields.push(format!("total_ms={}", ms(self.total())));
+        fields.join(" ")
+    }
+
+    /// trace の設定に応じた `$/logTrace` のパラメータを返す。trace が `off` であれば None を返す。
+    pub fn to_log_trace(&self, trace: TraceOption) -> Option<LogTraceParams> {
+        let message = format!(
+            "{} took {:.3}ms",
+            self.method,
+            self.total().as_secs_f64() * 1000.0
+        );
+        match trace {
+            TraceOption::Off => None,
+            TraceOption::Messages => Some(LogTraceParams { message, verbose: None }),
+            TraceOption::Verbose => Some(LogTraceParams { message, verbose: Some(self.fields()) }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests;
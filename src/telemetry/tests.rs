@@ -0,0 +1,37 @@
+//! test module for telemetry.
+
+use super::*;
+
+fn timing(respond: Option<Duration>) -> Timing {
+    Timing {
+        method: "textDocument/hover".to_owned(),
+        parse: Duration::from_micros(1500),
+        analysis: Duration::from_micros(250),
+        respond,
+    }
+}
+
+#[test]
+fn test_fields() {
+    assert_eq!(
+        timing(Some(Duration::from_micros(10))).fields(),
+        "method=textDocument/hover parse_ms=1.500 analysis_ms=0.250 respond_ms=0.010 total_ms=1.760"
+    );
+    assert_eq!(
+        timing(None).fields(),
+        "method=textDocument/hover parse_ms=1.500 analysis_ms=0.250 total_ms=1.750"
+    );
+}
+
+#[test]
+fn test_to_log_trace() {
+    let timing = timing(None);
+    assert!(timing.to_log_trace(TraceOption::Off).is_none());
+
+    let params = timing.to_log_trace(TraceOption::Messages).unwrap();
+    assert_eq!(params.message, "textDocument/hover took 1.750ms");
+    assert_eq!(params.verbose, None);
+
+    let params = timing.to_log_trace(TraceOption::Verbose).unwrap();
+    assert_eq!(params.verbose.as_deref(), Some(timing.fields().as_str()));
+}
@@ -0,0 +1,146 @@
+//! テストで用いる文書とカーソル位置を組み立てるための関数群。
+//!
+//! `test-utils` feature を有効にすると、エディタのプラグインなどのテストからも使える。
+//! 文書の中に `^1` や `^2` のようなマーカーを書いておけば、それを取り除いた文書と
+//! マーカーのあった位置が得られる。`^` そのものは `^^` と書く。
+//! マーカーの番号に続く数字も番号とみなすため、数字の直前にはマーカーを置けない。
+//!
+//! 次の例は `test-utils` feature がないと doctest として走らないため、
+//! 同じ内容を `tests.rs` の `test_module_example` で確かめている。
+//!
+//! ```ignore
+//! use maquette_satysfi_language_server::test_utils::TestDocument;
+//!
+//! let doc = TestDocument::new("let x = ^1y in\n'<>\n");
+//! assert_eq!(doc.text(), "let x = y in\n'<>\n");
+//! assert_eq!(doc.position(1).character, 8);
+//! ```
+
+use std::{collections::BTreeMap, fs, path::PathBuf};
+
+use lsp_types::{TextDocumentIdentifier, TextDocumentPositionParams, Url};
+
+use crate::{
+    config::Config,
+    position::{LineIndex, Position},
+    Buffer,
+};
+
+/// [`TestDocument`] の URI を指定しなかったときに用いる URI.
+pub const DEFAULT_URI: &str = "file:///test/main.saty";
+
+/// マーカーを取り除いた文書と、マーカーの位置。
+#[derive(Debug, Clone)]
+pub struct TestDocument {
+    /// マーカーを取り除いた文書。
+    text: String,
+    /// マーカーの番号と、そのバイトオフセット。
+    markers: BTreeMap<u32, usize>,
+    /// 文書の URI.
+    uri: Url,
+}
+
+impl TestDocument {
+    /// `^1` のようなマーカーを含む文字列から文書を作る。
+    ///
+    /// 同じ番号のマーカーが二つ以上あるか、`^` の後に番号も `^` もない場合は panic する。
+    pub fn new(source: &str) -> Self {
+        let mut text = String::with_capacity(source.len());
+        let mut markers = BTreeMap::new();
+        let mut rest = source;
+        while let Some(i) = rest.find('^') {
+            text.push_str(&rest[..i]);
+            rest = &rest[i + 1..];
+            if let Some(after) = rest.strip_prefix('^') {
+                text.push('^');
+                rest = after;
+                continue;
+            }
+            let digits = rest.len() - rest.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+            let number: u32 = rest[..digits]
+                .parse()
+                .unwrap_or_else(|_| panic!("`^` must be followed by a marker number or `^`: {:?}", source));
+            if markers.insert(number, text.len()).is_some() {
+                panic!("marker ^{} appears more than once: {:?}", number, source);
+            }
+            rest = &rest[digits..];
+        }
+        text.push_str(rest);
+        Self {
+            text,
+            markers,
+            uri: Url::parse(DEFAULT_URI).unwrap(),
+        }
+    }
+
+    /// 文書の URI を変える。
+    pub fn with_uri(mut self, uri: Url) -> Self {
+        self.uri = uri;
+        self
+    }
+
+    /// マーカーを取り除いた文書。
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// 文書の URI.
+    pub fn uri(&self) -> &Url {
+        &self.uri
+    }
+
+    /// 番号が `marker` であるマーカーの位置。なければ None を返す。
+    pub fn try_position(&self, marker: u32) -> Option<Position> {
+        let offset = *self.markers.get(&marker)?;
+        Some(LineIndex::new(&self.text).position(offset))
+    }
+
+    /// 番号が `marker` であるマーカーの位置。なければ panic する。
+    pub fn position(&self, marker: u32) -> Position {
+        self.try_position(marker)
+            .unwrap_or_else(|| panic!("no marker ^{} in the document", marker))
+    }
+
+    /// すべてのマーカーの番号と位置を、番号の順に返す。
+    pub fn positions(&self) -> Vec<(u32, Position)> {
+        let index = LineIndex::new(&self.text);
+        self.markers
+            .iter()
+            .map(|(marker, offset)| (*marker, index.position(*offset)))
+            .collect()
+    }
+
+    /// 番号が `marker` であるマーカーの位置を指す、リクエストのパラメータ。
+    pub fn text_document_position(&self, marker: u32) -> TextDocumentPositionParams {
+        TextDocumentPositionParams {
+            text_document: TextDocumentIdentifier { uri: self.uri.clone() },
+            position: self.position(marker).into(),
+        }
+    }
+
+    /// 文書をデフォルトの設定でパースしたバッファ。
+    pub fn buffer(&self) -> Buffer {
+        self.buffer_with(&Config::default())
+    }
+
+    /// 文書を `config` の SATySFi の版でパースし、定義の書き方の設定を適用したバッファ。
+    pub fn buffer_with(&self, config: &Config) -> Buffer {
+        let mut buf = Buffer::with_language_version(self.text.clone(), config.language_version);
+        buf.apply_definition_patterns(&config.definition_patterns);
+        buf
+    }
+}
+
+/// テストごとに空の一時ディレクトリを作る。
+///
+/// 並行して走る他のテストや、同時に走る別のテストプロセスと衝突しないよう、
+/// `name` とプロセス ID からディレクトリ名を決める。`name` はテストごとに異なるものを与える。
+pub fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("satysfi-ls-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,53 @@
+//! test module for test_utils.
+
+use super::*;
+
+#[test]
+fn test_module_example() {
+    // モジュールのドキュメントの例と同じもの。
+    let doc = TestDocument::new("let x = ^1y in\n'<>\n");
+    assert_eq!(doc.text(), "let x = y in\n'<>\n");
+    assert_eq!(doc.position(1).character, 8);
+}
+
+#[test]
+fn test_markers() {
+    let doc = TestDocument::new("^1let x = ^2`あ` in\n'<\n  +p{^10}\n>^3");
+    assert_eq!(doc.text(), "let x = `あ` in\n'<\n  +p{}\n>");
+    assert_eq!(doc.position(1), Position { line: 0, character: 0 });
+    assert_eq!(doc.position(2), Position { line: 0, character: 8 });
+    assert_eq!(doc.position(10), Position { line: 2, character: 5 });
+    assert_eq!(doc.try_position(4), None);
+    let markers = doc.positions().into_iter().map(|(marker, _)| marker).collect::<Vec<_>>();
+    assert_eq!(markers, vec![1, 2, 3, 10]);
+    assert_eq!(doc.positions()[2].1, Position { line: 3, character: 1 });
+}
+
+#[test]
+fn test_escaped_caret() {
+    let doc = TestDocument::new("let s = a ^^ ^1b");
+    assert_eq!(doc.text(), "let s = a ^ b");
+    assert_eq!(doc.position(1).character, 12);
+}
+
+#[test]
+#[should_panic(expected = "marker ^1 appears more than once")]
+fn test_duplicate_marker() {
+    TestDocument::new("^1 ^1");
+}
+
+#[test]
+#[should_panic(expected = "must be followed by a marker number")]
+fn test_bare_caret() {
+    TestDocument::new("x ^ y");
+}
+
+#[test]
+fn test_request_params() {
+    let uri = Url::parse("file:///other/doc.saty").unwrap();
+    let doc = TestDocument::new("let x = 1\nin\n'<^1>\n").with_uri(uri.clone());
+    let params = doc.text_document_position(1);
+    assert_eq!(params.text_document.uri, uri);
+    assert_eq!(params.position, lsp_types::Position::new(2, 2));
+    assert!(doc.buffer().buf_cst.cst().is_some());
+}
@@ -0,0 +1,631 @@
+//! test module for Buffer and Environment.
+
+use crate::{
+    config::{DefinitionPattern, LanguageVersion},
+    parser::{Mode, ModeRegion, Rule},
+    position::{Position, Range},
+    test_utils::TestDocument,
+    Buffer, CmdKind, SliceError,
+};
+
+fn buffer(text: &str) -> Buffer {
+    let buf = Buffer::new(text.to_owned());
+    assert!(buf.error.is_empty(), "parse failed: {:?}", buf.error);
+    buf
+}
+
+fn pos(line: u32, character: u32) -> Position {
+    Position { line, character }
+}
+
+fn range(sl: u32, sc: u32, el: u32, ec: u32) -> Range {
+    Range {
+        start: pos(sl, sc),
+        end: pos(el, ec),
+    }
+}
+
+mod mode {
+
+    use super::*;
+
+    const DOCUMENT: &str = r#"@re^1quire: stdjabook
+
+doc^2ument (|title = {^3Title}|) '<
+  ^4+p{ a^5bc ${x^6^^2} `l^7it` }
+  % ^8comment
+>
+"#;
+
+    /// [`DOCUMENT`] の `marker` の位置のモード。
+    fn region(marker: u32) -> ModeRegion {
+        let doc = TestDocument::new(DOCUMENT);
+        buffer(doc.text()).mode_at(&doc.position(marker)).unwrap()
+    }
+
+    #[test]
+    fn test_mode_header() {
+        let region = region(1);
+        assert_eq!(region.mode, Mode::Header);
+    }
+
+    #[test]
+    fn test_mode_program() {
+        let region = region(2);
+        assert_eq!(region.mode, Mode::Program);
+    }
+
+    #[test]
+    fn test_mode_horizontal_in_record() {
+        let region = region(3);
+        assert_eq!(region.mode, Mode::Horizontal);
+        assert_eq!(region.range, range(2, 20, 2, 25));
+    }
+
+    #[test]
+    fn test_mode_vertical() {
+        let region = region(4);
+        assert_eq!(region.mode, Mode::Vertical);
+        assert_eq!(region.range, range(3, 2, 5, 0));
+    }
+
+    #[test]
+    fn test_mode_horizontal() {
+        let region = region(5);
+        assert_eq!(region.mode, Mode::Horizontal);
+    }
+
+    #[test]
+    fn test_mode_math() {
+        let region = region(6);
+        assert_eq!(region.mode, Mode::Math);
+        assert_eq!(region.range, range(3, 12, 3, 15));
+    }
+
+    #[test]
+    fn test_mode_literal() {
+        let region = region(7);
+        assert_eq!(region.mode, Mode::Literal);
+        assert_eq!(region.range, range(3, 18, 3, 21));
+    }
+
+    #[test]
+    fn test_mode_string_interpolation() {
+        let doc = TestDocument::new("let x = 1\nlet s = `n^2 = #^1{x}`\n");
+        let buf = buffer(doc.text());
+        let region = buf.mode_at(&doc.position(1)).unwrap();
+        assert_eq!(region.mode, Mode::Program);
+        assert_eq!(region.range, range(1, 13, 1, 17));
+        let region = buf.mode_at(&doc.position(2)).unwrap();
+        assert_eq!(region.mode, Mode::Literal);
+    }
+
+    #[test]
+    fn test_mode_math_cmd_arg() {
+        let doc = TestDocument::new("'<\n  +p{ ${\\text!{^2 a^1 } + \\frac{^3 x }{y}} }\n>\n");
+        let buf = buffer(doc.text());
+        let region = buf.mode_at(&doc.position(1)).unwrap();
+        assert_eq!(region.mode, Mode::Horizontal);
+        assert_eq!(region.range, range(1, 16, 1, 18));
+        let region = buf.mode_at(&doc.position(2)).unwrap();
+        assert_eq!(region.mode, Mode::Horizontal);
+        assert_eq!(region.range, range(1, 13, 1, 19));
+        let region = buf.mode_at(&doc.position(3)).unwrap();
+        assert_eq!(region.mode, Mode::Math);
+    }
+
+    #[test]
+    fn test_mode_language_version() {
+        let doc = TestDocument::new("use package open Stdlib\nuse F^1oo of `./foo`\n\ndocument (||) '<^2>\n");
+        assert!(!Buffer::new(doc.text().to_owned()).error.is_empty());
+        let buf = Buffer::with_language_version(doc.text().to_owned(), LanguageVersion::Next);
+        assert!(buf.error.is_empty(), "parse failed: {:?}", buf.error);
+        let region = buf.mode_at(&doc.position(1)).unwrap();
+        assert_eq!(region.mode, Mode::Header);
+        assert!(buf.buf_cst.headers().is_empty());
+        let region = buf.mode_at(&doc.position(2)).unwrap();
+        assert_eq!(region.mode, Mode::Vertical);
+    }
+
+    #[test]
+    fn test_mode_comment() {
+        let region = region(8);
+        assert_eq!(region.mode, Mode::Comment);
+    }
+
+    #[test]
+    fn test_mode_unparsable() {
+        let buf = Buffer::new("let x = ".to_owned());
+        assert_eq!(buf.mode_at(&pos(0, 0)), None);
+    }
+}
+
+mod variable {
+
+    use super::*;
+
+    fn value_of(buf: &Buffer, name: &str) -> Option<String> {
+        let var = buf.env.variables.iter().find(|v| v.name == name).unwrap();
+        var.value.clone()
+    }
+
+    #[test]
+    fn test_simple_literal_value() {
+        let buf = buffer("let x = 1cm\nlet y = -2\nlet s = `abc`\nlet f a = 1\nlet z = x\n");
+        assert_eq!(value_of(&buf, "x").as_deref(), Some("1cm"));
+        assert_eq!(value_of(&buf, "y").as_deref(), Some("-2"));
+        assert_eq!(value_of(&buf, "s").as_deref(), Some("`abc`"));
+        assert_eq!(value_of(&buf, "f"), None);
+        assert_eq!(value_of(&buf, "z"), None);
+    }
+
+    #[test]
+    fn test_exported_types() {
+        let buf = buffer(concat!(
+            "type t = int\n",
+            "module M : sig\n  type u\nend = struct\n",
+            "  type u = int\n  type v = int\nend\n",
+        ));
+        let names = buf.env.exported().types.into_iter().map(|ty| ty.name).collect::<Vec<_>>();
+        assert_eq!(names, vec!["t", "M.u"]);
+    }
+}
+
+mod environment {
+
+    use super::*;
+
+    #[test]
+    fn test_commands_by_kind_and_prefix() {
+        let buf = buffer(concat!(
+            "let-inline ctx \\foo x = {}\n",
+            "let-inline ctx \\foobar = {}\n",
+            "let-block ctx +foo = '<>\n",
+            "let-math \\fa = ${}\n",
+            "let-inline ctx \\foo ?:o x y = {}\n",
+        ));
+        let env = &buf.env;
+        let inline = env.commands(CmdKind::Inline).map(|c| c.name).collect::<Vec<_>>();
+        assert_eq!(inline, vec!["\\foo", "\\foobar", "\\foo"]);
+        let prefixed = env
+            .commands_with_prefix(CmdKind::Inline, "\\foob")
+            .map(|c| c.name)
+            .collect::<Vec<_>>();
+        assert_eq!(prefixed, vec!["\\foobar"]);
+
+        // 同じ名前の定義は最後のものを取る。
+        let foo = env.lookup(CmdKind::Inline, "\\foo").unwrap();
+        assert_eq!((foo.arity, foo.optional_arity), (2, 1));
+        assert_eq!(env.lookup(CmdKind::Block, "+foo").unwrap().kind, CmdKind::Block);
+        assert!(env.lookup(CmdKind::Math, "\\foo").is_none());
+        assert!(env.lookup(CmdKind::Math, "\\fa").unwrap().param_kinds.is_empty());
+    }
+
+    #[test]
+    fn test_variables_visible_at() {
+        let doc = TestDocument::new("let x = 1\nlet y = ^1x\nlet z = y\n^2");
+        let buf = buffer(doc.text());
+        let visible = |marker| {
+            buf.env.visible_at(&doc.position(marker)).map(|v| v.name()).collect::<Vec<_>>()
+        };
+        assert_eq!(visible(1), vec!["x"]);
+        assert_eq!(visible(2), vec!["x", "y", "z"]);
+        assert_eq!(buf.env.variable("y").unwrap().def_range(), range(1, 4, 1, 5));
+        assert!(buf.env.variable("w").is_none());
+    }
+
+    #[test]
+    fn test_definition_patterns() {
+        let mut buf = buffer(concat!(
+            "module M : sig\n",
+            "  direct \\foo : [inline-text] inline-cmd\n",
+            "  direct +bar : [] block-cmd\n",
+            "end = struct\n",
+            "  let x = 1\n",
+            "end\n",
+        ));
+        assert!(buf.env.lookup(CmdKind::Inline, "\\foo").is_none());
+        let patterns = [
+            DefinitionPattern { rule: Rule::sig_direct_stmt, child: 0, kind: CmdKind::Inline },
+            DefinitionPattern { rule: Rule::sig_direct_stmt, child: 0, kind: CmdKind::Block },
+            DefinitionPattern { rule: Rule::sig_direct_stmt, child: 5, kind: CmdKind::Block },
+        ];
+        buf.apply_definition_patterns(&patterns);
+        let foo = buf.env.lookup(CmdKind::Inline, "\\foo").unwrap();
+        assert_eq!(foo.def_range, range(1, 9, 1, 13));
+        // 名前がコマンドの種類に合わないものや、子がないものは無視する。
+        assert_eq!(buf.env.commands(CmdKind::Block).map(|c| c.name).collect::<Vec<_>>(), vec!["+bar"]);
+        assert!(buf.env.lookup(CmdKind::Inline, "+bar").is_none());
+    }
+}
+
+mod extraction {
+
+    use super::*;
+    use crate::{ParamKind, Visibility};
+
+    fn variables(buf: &Buffer) -> Vec<(&str, Range, Range)> {
+        buf.env
+            .variables
+            .iter()
+            .map(|v| (v.name.as_str(), v.def_range, v.stmt_range))
+            .collect()
+    }
+
+    #[test]
+    fn test_inline_cmd_forms() {
+        let buf = buffer("let-inline ctx \\foo ?:opt x y = {}\nlet-inline \\bar x = {}\n");
+        let foo = buf.env.lookup(CmdKind::Inline, "\\foo").unwrap();
+        assert_eq!(foo.def_range, range(0, 15, 0, 19));
+        assert_eq!(foo.stmt_range, range(0, 0, 0, 34));
+        assert_eq!((foo.arity, foo.optional_arity), (2, 1));
+        assert_eq!(foo.param_kinds, vec![ParamKind::Unknown, ParamKind::Unknown]);
+
+        // コンテキストを受け取らない形では、名前の直後から引数が並ぶ。
+        let bar = buf.env.lookup(CmdKind::Inline, "\\bar").unwrap();
+        assert_eq!(bar.def_range, range(1, 11, 1, 15));
+        assert_eq!((bar.arity, bar.optional_arity), (1, 0));
+    }
+
+    #[test]
+    fn test_block_and_math_cmd_forms() {
+        let buf = buffer("let-block ctx +foo x = '<>\nlet-block +bar = '<>\nlet-math \\baz x y = ${}\n");
+        let foo = buf.env.lookup(CmdKind::Block, "+foo").unwrap();
+        assert_eq!(foo.def_range, range(0, 14, 0, 18));
+        assert_eq!(foo.arity, 1);
+        let bar = buf.env.lookup(CmdKind::Block, "+bar").unwrap();
+        assert_eq!(bar.def_range, range(1, 10, 1, 14));
+        assert_eq!(bar.arity, 0);
+        let baz = buf.env.lookup(CmdKind::Math, "\\baz").unwrap();
+        assert_eq!(baz.def_range, range(2, 9, 2, 13));
+        assert_eq!(baz.stmt_range, range(2, 0, 2, 23));
+        assert_eq!(baz.arity, 2);
+    }
+
+    #[test]
+    fn test_tuple_patterns() {
+        let buf = buffer(concat!(
+            "let (a, b) = (1, 2)\n",
+            "let ((c, d), _) = ((1, 2), 3)\n",
+            "let f (x, y) = x\n",
+            "let g = let h = 1 in h\n",
+        ));
+        // 関数の引数や、式の中の let ... in で束縛される変数は含めない。
+        assert_eq!(
+            variables(&buf),
+            vec![
+                ("a", range(0, 5, 0, 6), range(0, 0, 0, 19)),
+                ("b", range(0, 8, 0, 9), range(0, 0, 0, 19)),
+                ("c", range(1, 6, 1, 7), range(1, 0, 1, 29)),
+                ("d", range(1, 9, 1, 10), range(1, 0, 1, 29)),
+                ("f", range(2, 4, 2, 5), range(2, 0, 2, 16)),
+                ("g", range(3, 4, 3, 5), range(3, 0, 3, 22)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_nested_modules() {
+        let buf = buffer(concat!(
+            "module M = struct\n",
+            "  module N : sig\n    val x : int\n  end = struct\n",
+            "    let x = 1\n    let z = 3\n",
+            "  end\n",
+            "  let y = 2\n",
+            "end\n",
+        ));
+        let visibility = |name: &str| {
+            let var = buf.env.variables.iter().find(|v| v.name == name).unwrap();
+            var.visibility.clone()
+        };
+        assert_eq!(visibility("x"), Visibility::Qualified("M.N".to_owned()));
+        assert_eq!(visibility("y"), Visibility::Qualified("M".to_owned()));
+        assert_eq!(visibility("z"), Visibility::Private);
+        let exported = buf.env.exported();
+        let names = exported.variables.iter().map(|v| v.name.as_str()).collect::<Vec<_>>();
+        assert_eq!(names, vec!["M.N.x", "M.y"]);
+    }
+
+    fn exported_commands(buf: &Buffer, kind: CmdKind) -> Vec<String> {
+        buf.env.exported().commands(kind).map(|c| c.name.to_owned()).collect()
+    }
+
+    #[test]
+    fn test_signature_hides_commands() {
+        let buf = buffer(concat!(
+            "module M : sig\n",
+            "  direct \\shown : [inline-text] inline-cmd\n",
+            "  direct +shown : [] block-cmd\n",
+            "  val \\mshown : [math] math-cmd\n",
+            "end = struct\n",
+            "  let-inline ctx \\shown it = {}\n",
+            "  let-inline ctx \\hidden it = {}\n",
+            "  let-block ctx +shown = '<>\n",
+            "  let-block ctx +hidden = '<>\n",
+            "  let-math \\mshown m = ${}\n",
+            "  let-math \\mhidden m = ${}\n",
+            "end\n",
+        ));
+        assert!(buf.error.is_empty());
+        assert_eq!(exported_commands(&buf, CmdKind::Inline), vec!["\\shown"]);
+        assert_eq!(exported_commands(&buf, CmdKind::Block), vec!["+shown"]);
+        assert_eq!(exported_commands(&buf, CmdKind::Math), vec!["\\M.mshown"]);
+    }
+
+    #[test]
+    fn test_signature_matches_command_kind() {
+        // インラインコマンドとして公開された名前で、同名の数式コマンドは公開されない。
+        let buf = buffer(concat!(
+            "module M : sig\n",
+            "  direct \\foo : [inline-text] inline-cmd\n",
+            "end = struct\n",
+            "  let-inline ctx \\foo it = {}\n",
+            "  let-math \\foo m = ${}\n",
+            "end\n",
+        ));
+        assert_eq!(exported_commands(&buf, CmdKind::Inline), vec!["\\foo"]);
+        assert!(exported_commands(&buf, CmdKind::Math).is_empty());
+    }
+
+    #[test]
+    fn test_commands_without_signature() {
+        // signature がなければ、すべてのコマンドがモジュール名で修飾して公開される。
+        let buf = buffer(concat!(
+            "module M = struct\n",
+            "  let-inline ctx \\foo = {}\n",
+            "  let-block ctx +bar = '<>\n",
+            "  let-math \\baz = ${}\n",
+            "end\n",
+            "let-inline ctx \\top = {}\n",
+        ));
+        assert_eq!(exported_commands(&buf, CmdKind::Inline), vec!["\\M.foo", "\\top"]);
+        assert_eq!(exported_commands(&buf, CmdKind::Block), vec!["+M.bar"]);
+        assert_eq!(exported_commands(&buf, CmdKind::Math), vec!["\\M.baz"]);
+    }
+
+    #[test]
+    fn test_unsupported_let_rec() {
+        // let-rec は文法が対応していないため、パースに失敗し、何も取り出さない。
+        let buf = Buffer::new("let-rec f x = g x\nand g x = f x\n".to_owned());
+        assert!(!buf.error.is_empty());
+        assert!(buf.env.variables.is_empty());
+        assert_eq!(buf.env.commands(CmdKind::Inline).count(), 0);
+    }
+}
+
+mod recovery {
+
+    use super::*;
+    use crate::parser::recovery::Recovered;
+
+    #[test]
+    fn test_env_after_incomplete_let() {
+        let buf = Buffer::new("let x =\nlet-inline ctx \\foo = {}\nlet y = 1\nin\n'<>\n".to_owned());
+        assert_eq!(buf.buf_cst.recoveries().len(), 1);
+        // 修復してパースしても、元の構文エラーは残す。
+        assert_eq!(buf.error.len(), 1);
+        assert_eq!(buf.env.inline_cmds[0].name, "\\foo");
+        assert_eq!(buf.env.inline_cmds[0].def_range, range(1, 15, 1, 19));
+        assert_eq!(buf.env.variables[0].name, "y");
+    }
+
+    #[test]
+    fn test_unparsable_recovery_falls_back() {
+        let recovered = Recovered {
+            text: "let x = (\n".to_owned(),
+            insertion: None,
+            recoveries: vec![],
+        };
+        assert!(crate::recovered_cst(recovered, Rule::program).is_none());
+    }
+
+    #[test]
+    fn test_positions_after_missing_in() {
+        let doc = TestDocument::new("let x = 1\n'<\n  +p{ ^1a }\n>\n");
+        let buf = doc.buffer();
+        assert!(buf.buf_cst.cst().is_some());
+        let region = buf.mode_at(&doc.position(1)).unwrap();
+        assert_eq!(region.mode, Mode::Horizontal);
+        assert_eq!(region.range, range(2, 6, 2, 8));
+    }
+}
+
+mod json {
+
+    use super::*;
+
+    #[test]
+    fn test_cst_to_json() {
+        let buf = buffer("let x = 1 in '<>\n");
+        let json = buf.buf_cst.to_json().unwrap();
+        assert_eq!(json.rule, "program");
+        assert!(json.text.is_none());
+
+        let value = serde_json::to_value(&json).unwrap();
+        assert_eq!(value["byteRange"], serde_json::json!([0, 17]));
+        assert!(value.get("text").is_none());
+
+        // 葉は必ず元の文字列の一部を持つ。
+        fn leaves(json: &crate::CstJson) -> Vec<&crate::CstJson> {
+            if json.children.is_empty() {
+                vec![json]
+            } else {
+                json.children.iter().flat_map(leaves).collect()
+            }
+        }
+        let var = leaves(&json).into_iter().find(|l| l.rule == "var").unwrap();
+        assert_eq!(var.text.as_deref(), Some("x"));
+        assert_eq!(var.range, range(0, 4, 0, 5));
+    }
+
+    #[test]
+    fn test_cst_to_json_parse_error() {
+        let buf = crate::Buffer::new("let x = (\n".to_owned());
+        assert!(buf.buf_cst.to_json().is_none());
+    }
+}
+
+mod deferred {
+
+    use lsp_types::Url;
+
+    use super::*;
+    use crate::config::{Config, LanguageVersion};
+
+    #[test]
+    fn test_size_guard() {
+        let text = "let x = 1 in '<>\n".to_owned();
+        let uri = Url::parse("file:///tmp/large.saty").unwrap();
+
+        let buf = Buffer::with_size_guard(text.clone(), text.len(), LanguageVersion::Stable);
+        assert!(!buf.is_deferred());
+        assert!(buf.buf_cst.to_json().is_some());
+
+        let mut buf = Buffer::with_size_guard(text.clone(), text.len() - 1, LanguageVersion::Stable);
+        assert!(buf.is_deferred());
+        assert!(buf.buf_cst.to_json().is_none());
+        assert!(buf.env.variables.is_empty());
+
+        assert!(buf.ensure_parsed(&uri, &Config::default()));
+        assert!(!buf.is_deferred());
+        assert_eq!(buf.buf_cst.buffer, text);
+        assert_eq!(buf.env.variables[0].name, "x");
+        assert!(!buf.ensure_parsed(&uri, &Config::default()));
+    }
+
+    #[test]
+    fn test_deferred_inherits_last_parsed() {
+        let parsed = Buffer::new("let x = 1\n".to_owned());
+        let text = "let x = 1\nlet y = 2\n".to_owned();
+        let mut deferred = Buffer::with_size_guard(text, 1, LanguageVersion::Stable);
+        deferred.inherit_last_parsed(parsed);
+        assert_eq!(deferred.latest_parsed().unwrap().env.variables[0].name, "x");
+        // パースすれば、引き継いだ版ではなく自身の定義を用いる。
+        let uri = Url::parse("file:///tmp/large.saty").unwrap();
+        assert!(deferred.ensure_parsed(&uri, &Config::default()));
+        assert_eq!(deferred.latest_parsed().unwrap().env.variables.len(), 2);
+    }
+
+    #[test]
+    fn test_reconfigure() {
+        let uri = Url::parse("file:///tmp/main.saty").unwrap();
+        let text = "module M : sig\n  direct \\foo : [inline-text] inline-cmd\nend = struct\n  let x = 1\nend\n";
+        let mut buf = buffer(text);
+        buf.version = Some(3);
+        let config = Config {
+            definition_patterns: vec![DefinitionPattern {
+                rule: Rule::sig_direct_stmt,
+                child: 0,
+                kind: CmdKind::Inline,
+            }],
+            ..Default::default()
+        };
+        buf.reconfigure(&uri, &config);
+        assert!(buf.env.lookup(CmdKind::Inline, "\\foo").is_some());
+        // 設定から取り除いた構文による定義は残さない。
+        buf.reconfigure(&uri, &Config::default());
+        assert!(buf.env.lookup(CmdKind::Inline, "\\foo").is_none());
+
+        // 文法の版が変われば、その版の文法でパースし直す。
+        let text = "use package open Stdlib\n\ndocument (||) '<>\n";
+        let mut buf = Buffer::new(text.to_owned());
+        buf.version = Some(3);
+        assert!(buf.buf_cst.cst().is_none());
+        let config = Config { language_version: LanguageVersion::Next, ..Default::default() };
+        buf.reconfigure(&uri, &config);
+        assert!(buf.buf_cst.cst().is_some());
+        assert_eq!(buf.buf_cst.buffer, text);
+        assert_eq!(buf.version, Some(3));
+    }
+}
+
+mod last_parsed {
+
+    use super::*;
+
+    fn versioned(text: &str, version: i32) -> Buffer {
+        let mut buf = Buffer::new(text.to_owned());
+        buf.version = Some(version);
+        buf
+    }
+
+    #[test]
+    fn test_inherit_last_parsed() {
+        let parsed = versioned("let x = 1\n", 1);
+        let mut broken = versioned("let x = 1 in\nlet y = (", 2);
+        assert!(broken.latest_parsed().is_none());
+        broken.inherit_last_parsed(parsed);
+        assert_eq!(broken.latest_parsed().unwrap().version, Some(1));
+
+        // パースに失敗し続ける間は、同じ版を引き継ぐ。
+        let mut still_broken = versioned("let x = 1 in\nlet y = (1", 3);
+        still_broken.inherit_last_parsed(broken);
+        let latest = still_broken.latest_parsed().unwrap();
+        assert_eq!(latest.version, Some(1));
+        assert_eq!(latest.env.variables[0].name, "x");
+
+        // パースに成功すれば、古い版は捨てる。
+        let mut fixed = versioned("let x = 1\nlet y = (1)\n", 4);
+        fixed.inherit_last_parsed(still_broken);
+        assert_eq!(fixed.latest_parsed().unwrap().version, Some(4));
+        assert!(fixed.last_parsed.is_none());
+    }
+}
+
+mod layout {
+
+    use crate::Cst;
+
+    #[test]
+    fn test_cst_node_size() {
+        // Cst は開いているファイルの節点の数だけ保持されるので、大きくしない。
+        assert!(std::mem::size_of::<Cst>() <= 48);
+    }
+}
+
+#[test]
+fn test_as_str_boundaries() {
+    let buf = buffer("let s = `あい`\nlet t = 1\n");
+    let cst = buf.buf_cst.cst.as_ref().unwrap();
+    let literal = cst.pickup(Rule::string_interior)[0];
+    assert_eq!(literal.try_as_str(&buf.buf_cst.buffer), Ok("あい"));
+
+    // 編集の後などで元の文字列と食い違っていても panic せず、文字の境界に丸める。
+    let edited = "let s = `aあい";
+    assert_eq!(
+        literal.try_as_str(edited),
+        Err(SliceError { start: 9, end: 15, len: edited.len() })
+    );
+    assert_eq!(literal.as_str(edited), "aあ");
+    assert_eq!(literal.as_str("let"), "");
+
+    // どの Cst をどんな文字列で切り出しても panic しない。
+    let texts = ["", "let s = `", "let s = `😀😀😀`\n", "ééééééééééééééééé", "let s = `あい`\nlet t = 1\n"];
+    for node in cst.pickup(Rule::var).into_iter().chain(cst.pickup(Rule::string_interior)) {
+        for text in texts.iter() {
+            let sliced = node.as_str(text);
+            assert!(text.contains(sliced));
+            if let Ok(exact) = node.try_as_str(text) {
+                assert_eq!(sliced, exact);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_prelude_cst_queries() {
+    use crate::prelude::*;
+
+    let buf = buffer("let-inline ctx \\hello = read-inline ctx {hello}\nin\n'<>\n");
+    let cst = buf.buf_cst.cst().unwrap();
+    let names = cst.pickup(Rule::inline_cmd_name);
+    assert_eq!(names.len(), 1);
+    assert_eq!(names[0].as_str(&buf.buf_cst.buffer), "\\hello");
+    assert_eq!(names[0].range(), Range::new(pos(0, 15), pos(0, 21)));
+
+    // dig は内側の Cst から順に返す。
+    let innermost = cst.dig(&pos(0, 17))[0];
+    assert_eq!(innermost.rule(), Rule::inline_cmd_name);
+    assert!(innermost.children().is_empty());
+    assert!(!VERSION.is_empty());
+}
@@ -0,0 +1,183 @@
+//! 与えられた位置のまわりで、どの文法規則が合致し、どれが失敗したかを調べる。
+//!
+//! 「パースできない」という報告を、文法のどこで何が期待されていたかという形にするために用いる。
+//! コマンドラインの `parse --trace-parse` と、カスタムリクエスト `satysfi/traceParse` から使う。
+
+use std::fmt;
+
+use lsp_types::{request::Request, TextDocumentIdentifier};
+use pest::{
+    error::{ErrorVariant, InputLocation},
+    Parser,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    parser::{Rule, SatysfiParser},
+    position::{LineIndex, Position},
+    Buffer, Cst, MAX_BUFFER_SIZE,
+};
+
+/// 位置のまわりの文法規則の合否を返すカスタムリクエスト。
+pub enum TraceParse {}
+
+impl Request for TraceParse {
+    type Params = TraceParseParams;
+    type Result = Option<TraceParseResult>;
+    const METHOD: &'static str = "satysfi/traceParse";
+}
+
+/// `satysfi/traceParse` のパラメータ。
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TraceParseParams {
+    /// 対象のドキュメント。
+    pub text_document: TextDocumentIdentifier,
+    /// 調べる位置。
+    pub position: Position,
+}
+
+/// `satysfi/traceParse` の結果。ドキュメントが開かれていなければ null となる。
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TraceParseResult {
+    /// 試した規則を、試した順に並べたもの。
+    pub steps: Vec<TraceStep>,
+}
+
+/// 一つの文法規則を試した結果。
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TraceStep {
+    /// 規則の名前。
+    pub rule: String,
+    /// 規則を試し始めた位置。
+    pub start: Position,
+    /// 合致した場合は合致した範囲の終わり、失敗した場合は失敗した位置。
+    pub end: Position,
+    /// 合致したか。
+    pub matched: bool,
+    /// 失敗した場合に、失敗した位置で期待されていた規則。
+    pub expected: Vec<String>,
+}
+
+impl fmt::Display for TraceStep {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // 人が読むための出力なので、行と列は 1 始まりにする。
+        let show = |pos: &Position| format!("{}:{}", pos.line + 1, pos.character + 1);
+        if self.matched {
+            write!(f, "matched {} at {}-{}", self.rule, show(&self.start), show(&self.end))
+        } else {
+            let (start, end) = (show(&self.start), show(&self.end));
+            write!(f, "failed  {} at {}: stopped at {}", self.rule, start, end)?;
+            if !self.expected.is_empty() {
+                write!(f, ", expected {}", self.expected.join(" | "))?;
+            }
+            Ok(())
+        }
+    }
+}
+
+/// 位置から単独で試す規則。
+const TRIAL_RULES: &[Rule] = &[
+    Rule::header,
+    Rule::statement,
+    Rule::expr,
+    Rule::type_expr,
+    Rule::pattern,
+    Rule::horizontal_single,
+    Rule::vertical_element,
+    Rule::math_single,
+];
+
+/// traceParse リクエストへの response を返す。
+pub fn get_trace_parse_response(buf: &Buffer, params: TraceParseParams) -> TraceParseResult {
+    TraceParseResult {
+        steps: trace_parse(&buf.buf_cst.buffer, params.position),
+    }
+}
+
+/// `pos` のまわりで文法規則を試した結果を返す。
+///
+/// 1. 文書全体を `program` としてパースし、その合否を記録する。
+/// 2. 合致した場合は、`pos` を含む規則を外側から順に記録する。
+/// 3. `pos` のある行の字下げの直後と `pos` 自身から、[`TRIAL_RULES`] の各規則を単独で試す。
+pub fn trace_parse(text: &str, pos: Position) -> Vec<TraceStep> {
+    let index = LineIndex::new(text);
+    let mut steps = vec![];
+    if text.len() > MAX_BUFFER_SIZE {
+        return steps;
+    }
+
+    match SatysfiParser::parse(Rule::program, text) {
+        Ok(mut pairs) => {
+            let cst = Cst::new(pairs.next().unwrap(), text);
+            steps.push(matched_step(&index, Rule::program, 0, text.len()));
+            for node in cst.dig(&pos).into_iter().rev() {
+                let start = node.range.start.byte as usize;
+                let end = node.range.end.byte as usize;
+                steps.push(matched_step(&index, node.rule, start, end));
+            }
+        }
+        Err(e) => steps.push(failed_step(&index, Rule::program, 0, &e)),
+    }
+
+    let offset = index.offset(pos);
+    let line_start = text[..offset].rfind('\n').map_or(0, |i| i + 1);
+    let indent = text[line_start..].len() - text[line_start..].trim_start_matches([' ', '\t']).len();
+    let mut anchors = vec![(line_start + indent).min(offset), offset];
+    anchors.dedup();
+    for anchor in anchors {
+        for &rule in TRIAL_RULES {
+            let step = match SatysfiParser::parse(rule, &text[anchor..]) {
+                Ok(mut pairs) => {
+                    let end = pairs.next().map_or(0, |pair| pair.as_span().end());
+                    matched_step(&index, rule, anchor, anchor + end)
+                }
+                Err(e) => failed_step(&index, rule, anchor, &e),
+            };
+            steps.push(step);
+        }
+    }
+    steps
+}
+
+/// 合致した規則の記録を作る。
+fn matched_step(index: &LineIndex<'_>, rule: Rule, start: usize, end: usize) -> TraceStep {
+    TraceStep {
+        rule: format!("{:?}", rule),
+        start: index.position(start),
+        end: index.position(end),
+        matched: true,
+        expected: vec![],
+    }
+}
+
+/// 失敗した規則の記録を作る。`e` の位置は `start` からの相対位置である。
+fn failed_step(
+    index: &LineIndex<'_>,
+    rule: Rule,
+    start: usize,
+    e: &pest::error::Error<Rule>,
+) -> TraceStep {
+    let failed_at = match e.location {
+        InputLocation::Pos(pos) => pos,
+        InputLocation::Span((pos, _)) => pos,
+    };
+    let expected = match &e.variant {
+        ErrorVariant::ParsingError { positives, .. } => {
+            positives.iter().map(|rule| format!("{:?}", rule)).collect()
+        }
+        ErrorVariant::CustomError { message } => vec![message.clone()],
+    };
+    TraceStep {
+        rule: format!("{:?}", rule),
+        start: index.position(start),
+        end: index.position(start + failed_at),
+        matched: false,
+        expected,
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,41 @@
+//! test module for parse tracing.
+
+use super::*;
+
+fn pos(line: u32, character: u32) -> Position {
+    Position { line, character }
+}
+
+#[test]
+fn test_trace_parsable() {
+    let text = "let x = 1\nin\n'<\n  +p{ hello }\n>\n";
+    let steps = trace_parse(text, pos(0, 8));
+    assert_eq!(steps[0].rule, "program");
+    assert!(steps[0].matched);
+    // 位置を含む規則が外側から順に並ぶ。
+    let enclosing = steps.iter().take_while(|s| s.matched).map(|s| s.rule.as_str()).collect::<Vec<_>>();
+    assert!(enclosing.contains(&"let_stmt"));
+    assert_eq!(enclosing.last(), Some(&"int_decimal_const"));
+
+    let statement = steps.iter().find(|s| s.rule == "statement" && s.start == pos(0, 0)).unwrap();
+    assert!(statement.matched);
+    assert_eq!(statement.end, pos(0, 9));
+}
+
+#[test]
+fn test_trace_unparsable() {
+    let text = "let x = \nin\n'<>\n";
+    let steps = trace_parse(text, pos(0, 8));
+    assert_eq!(steps[0].rule, "program");
+    assert!(!steps[0].matched);
+    assert_eq!(steps[0].end.line, 1);
+
+    let statement = steps.iter().find(|s| s.rule == "statement" && s.start == pos(0, 0)).unwrap();
+    assert!(!statement.matched);
+    assert!(!statement.expected.is_empty());
+    let line = statement.to_string();
+    assert!(line.starts_with("failed  statement at 1:1: stopped at "), "{}", line);
+
+    // カーソル位置からも試す。
+    assert!(steps.iter().any(|s| s.rule == "expr" && s.start == pos(0, 8)));
+}
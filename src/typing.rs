@@ -0,0 +1,148 @@
+//! 補完の順位づけに用いる、型注釈とリテラルにもとづく簡易な型の推論。
+//!
+//! 型検査器は持たないため、let に書かれた型注釈、単純なリテラルの値、
+//! 補完候補のカタログに書かれたプリミティブの型だけを手がかりにする。
+//! 型はテキストのまま扱い、空白を正規化して比較する。
+
+/// 関数適用の引数として完結した式とみなさない予約語。`true` と `false` はリテラルなので含めない。
+const STOP_WORDS: &[&str] = &[
+    "let", "let-rec", "let-mutable", "in", "if", "then", "else", "match", "with", "when", "fun",
+    "and", "mod", "not", "before", "while", "do", "open", "module", "struct", "sig", "end", "val",
+    "type", "of", "as", "direct", "constraint", "controls", "cycle",
+];
+
+/// リテラルのテキストから、その型を返す。リテラルでなければ None を返す。
+pub fn literal_type(text: &str) -> Option<&'static str> {
+    let text = text.trim();
+    let numeric_end = text
+        .trim_start_matches('-')
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .map_or(text.len(), |i| i + text.len() - text.trim_start_matches('-').len());
+    let (number, unit) = text.split_at(numeric_end);
+    let digits = number.trim_start_matches('-');
+    if !digits.is_empty() && digits.chars().any(|c| c.is_ascii_digit()) {
+        return match unit {
+            "" if digits.contains('.') => Some("float"),
+            "" => Some("int"),
+            _ if unit.starts_with(|c: char| c.is_ascii_lowercase()) => Some("length"),
+            _ => None,
+        };
+    }
+    match text {
+        "true" | "false" => Some("bool"),
+        "()" => Some("unit"),
+        _ if text.starts_with('`') || text.starts_with("#`") => Some("string"),
+        _ => None,
+    }
+}
+
+/// 型を、最も外側の `->` で区切った引数と返り値の型の列にする。
+pub fn arrow_types(ty: &str) -> Vec<&str> {
+    let mut types = vec![];
+    let mut depth = 0;
+    let mut start = 0;
+    let bytes = ty.as_bytes();
+    for (i, &b) in bytes.iter().enumerate() {
+        match b {
+            b'(' | b'[' => depth += 1,
+            b')' | b']' => depth -= 1,
+            b'-' if depth == 0 && bytes.get(i + 1) == Some(&b'>') => {
+                types.push(ty[start..i].trim());
+                start = i + 2;
+            }
+            _ => (),
+        }
+    }
+    types.push(ty[start..].trim());
+    types
+}
+
+/// 型のテキストの空白を正規化する。
+pub fn normalize_type(ty: &str) -> String {
+    ty.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// カーソルの直前までのテキストが関数適用の途中であれば、関数名と、
+/// カーソル位置の引数が何番目 (0 始まり) かを返す。
+/// カーソル位置で入力中の語は引数に数えない。
+pub fn application_at(text: &str) -> Option<(&str, usize)> {
+    let rest = text.trim_end_matches(is_word_char);
+    // 入力中の語が前の語と続いていれば、それは引数ではない。
+    if rest.len() != text.len() && !rest.ends_with(char::is_whitespace) && !rest.is_empty() {
+        return None;
+    }
+    let (mut atoms, _) = trailing_atoms(rest)?;
+    let head = atoms.pop()?;
+    let is_function = head.starts_with(|c: char| c.is_ascii_lowercase())
+        || head.contains('.') && head.starts_with(|c: char| c.is_ascii_uppercase());
+    (is_function && literal_type(head).is_none()).then_some((head, atoms.len()))
+}
+
+/// カーソルの直前までのテキストがパイプライン `x |> f` の関数の位置で終わっていれば、
+/// 左辺の最後の段 `x` を関数適用とみなし、その先頭の語と、与えられた引数の数を返す。
+/// `x` 自身もパイプの右辺であれば、パイプで渡される値も引数に数える。
+/// カーソル位置で入力中の語は関数に含めない。
+pub fn pipeline_operand(text: &str) -> Option<(&str, usize)> {
+    let rest = text.trim_end_matches(is_word_char).trim_end().strip_suffix("|>")?;
+    let (mut atoms, before) = trailing_atoms(rest)?;
+    let head = atoms.pop()?;
+    let piped = before.trim_end().ends_with("|>");
+    Some((head, atoms.len() + piped as usize))
+}
+
+/// 変数名などの語を構成する文字か。
+fn is_word_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || matches!(c, '-' | '.' | '_')
+}
+
+/// テキストの末尾に並ぶ、語や括弧でくくられた式などの項を、後ろから順に返す。
+/// 予約語や演算子に出会ったところで止め、項とそれより前のテキストを返す。
+/// 対応する開き括弧のない閉じ括弧があれば None を返す。
+fn trailing_atoms(text: &str) -> Option<(Vec<&str>, &str)> {
+    let mut rest = text;
+    let mut atoms = vec![];
+    loop {
+        rest = rest.trim_end();
+        let atom_start = match rest.chars().last() {
+            Some(')' | ']') => matching_open(rest)?,
+            Some('`') => rest[..rest.len() - 1].rfind('`')?,
+            Some(c) if is_word_char(c) => rest.trim_end_matches(is_word_char).len(),
+            _ => break,
+        };
+        let atom = &rest[atom_start..];
+        if STOP_WORDS.contains(&atom) {
+            break;
+        }
+        atoms.push(atom);
+        rest = &rest[..atom_start];
+    }
+    Some((atoms, rest))
+}
+
+/// 末尾の閉じ括弧に対応する開き括弧の位置を返す。
+fn matching_open(text: &str) -> Option<usize> {
+    let mut depth = 0;
+    for (i, c) in text.char_indices().rev() {
+        match c {
+            ')' | ']' => depth += 1,
+            '(' | '[' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => (),
+        }
+    }
+    None
+}
+
+/// 関数の型 `signature` と引数の位置から、その引数に期待される型を返す。
+/// 引数の数を超えていれば None を返す。
+pub fn expected_argument_type(signature: &str, index: usize) -> Option<&str> {
+    let types = arrow_types(signature);
+    (index + 1 < types.len()).then(|| types[index])
+}
+
+#[cfg(test)]
+mod tests;
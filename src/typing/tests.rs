@@ -0,0 +1,50 @@
+//! test module for the type skeleton.
+
+use super::*;
+
+#[test]
+fn test_literal_type() {
+    assert_eq!(literal_type("12pt"), Some("length"));
+    assert_eq!(literal_type("-1.5cm"), Some("length"));
+    assert_eq!(literal_type("3"), Some("int"));
+    assert_eq!(literal_type("0.5"), Some("float"));
+    assert_eq!(literal_type("`abc`"), Some("string"));
+    assert_eq!(literal_type("true"), Some("bool"));
+    assert_eq!(literal_type("x"), None);
+    assert_eq!(literal_type("f 1"), None);
+}
+
+#[test]
+fn test_arrow_types() {
+    assert_eq!(
+        arrow_types("context -> (length -> length) -> int list -> unit"),
+        vec!["context", "(length -> length)", "int list", "unit"]
+    );
+    assert_eq!(arrow_types("length"), vec!["length"]);
+    assert_eq!(expected_argument_type("length -> context -> context", 0), Some("length"));
+    assert_eq!(expected_argument_type("length -> context -> context", 1), Some("context"));
+    assert_eq!(expected_argument_type("length -> context -> context", 2), None);
+}
+
+#[test]
+fn test_application_at() {
+    assert_eq!(application_at("let c = set-font-size "), Some(("set-font-size", 0)));
+    assert_eq!(application_at("let c = set-font-size 12pt "), Some(("set-font-size", 1)));
+    assert_eq!(application_at("let c = set-font-size w"), Some(("set-font-size", 0)));
+    assert_eq!(application_at("  f (g x) `s` "), Some(("f", 2)));
+    assert_eq!(application_at("M.f x "), Some(("M.f", 1)));
+    assert_eq!(application_at("let x = "), None);
+    assert_eq!(application_at("let x = f"), None);
+    assert_eq!(application_at("if cond then "), None);
+    assert_eq!(application_at("1 + "), None);
+}
+
+#[test]
+fn test_pipeline_operand() {
+    assert_eq!(pipeline_operand("read-inline (ctx |> "), Some(("ctx", 0)));
+    assert_eq!(pipeline_operand("let c = ctx |> set-f"), Some(("ctx", 0)));
+    assert_eq!(pipeline_operand("ctx |> set-font-size 12pt |> "), Some(("set-font-size", 2)));
+    assert_eq!(pipeline_operand("get-initial-context 400pt cmd |> "), Some(("get-initial-context", 2)));
+    assert_eq!(pipeline_operand("let c = ctx "), None);
+    assert_eq!(pipeline_operand("x) |> "), None);
+}
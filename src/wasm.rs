@@ -0,0 +1,70 @@
+//! ブラウザ上のエディタから使うための wasm-bindgen のラッパー。
+//!
+//! `wasm` feature を有効にし、`server` feature を無効にして `wasm32-unknown-unknown` 向けに作る。
+//! ファイルを持たないため、パッケージは読み込めず、バッファ自身の定義とプリミティブだけを扱う。
+//! 結果は LSP の型を JSON の文字列にして返す。
+
+use lsp_types::{
+    CompletionContext, CompletionParams, CompletionTriggerKind, TextDocumentIdentifier,
+    TextDocumentPositionParams, Url,
+};
+use wasm_bindgen::prelude::*;
+
+use crate::{
+    completion::get_completion_response, config::Config, diagnostic::syntax_errors,
+    position::Position, workspace::WorkspaceIndex, Buffer,
+};
+
+/// プレイグラウンドのバッファを指す URI.
+const PLAYGROUND_URI: &str = "untitled:playground.saty";
+
+/// 補完を起動する文字。
+const TRIGGER_CHARACTERS: &[char] = &['\\', '+', '#'];
+
+/// `text` の `line` 行 `col` 文字目 (いずれも 0 始まり) での補完候補を、
+/// LSP の `CompletionResponse` の JSON として返す。
+/// 直前の文字が `\` などであれば、その文字で補完が起動されたものとして扱う。
+#[wasm_bindgen]
+pub fn complete(text: &str, line: u32, col: u32) -> String {
+    let buf = Buffer::new(text.to_owned());
+    let position = Position { line, character: col };
+    let offset = buf.buf_cst.line_index().offset(position);
+    let trigger_character = buf.buf_cst.buffer[..offset]
+        .chars()
+        .next_back()
+        .filter(|c| TRIGGER_CHARACTERS.contains(c))
+        .map(String::from);
+    let params = CompletionParams {
+        text_document_position: TextDocumentPositionParams {
+            text_document: TextDocumentIdentifier { uri: playground_uri() },
+            position: position.into(),
+        },
+        work_done_progress_params: Default::default(),
+        partial_result_params: Default::default(),
+        context: Some(CompletionContext {
+            trigger_kind: if trigger_character.is_some() {
+                CompletionTriggerKind::TriggerCharacter
+            } else {
+                CompletionTriggerKind::Invoked
+            },
+            trigger_character,
+        }),
+    };
+    let response = get_completion_response(&buf, params, &Config::default(), &WorkspaceIndex::default());
+    serde_json::to_string(&response).unwrap_or_else(|_| "null".to_owned())
+}
+
+/// `text` の構文エラーを、LSP の `Diagnostic` の配列の JSON として返す。
+#[wasm_bindgen]
+pub fn parse_errors(text: &str) -> String {
+    let buf = Buffer::new(text.to_owned());
+    serde_json::to_string(&syntax_errors(&buf)).unwrap_or_else(|_| "[]".to_owned())
+}
+
+/// プレイグラウンドのバッファを指す URI.
+fn playground_uri() -> Url {
+    Url::parse(PLAYGROUND_URI).unwrap()
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,28 @@
+//! test module for the wasm wrapper.
+
+use serde_json::Value;
+
+use super::*;
+
+#[test]
+fn test_complete() {
+    let text = "let-inline ctx \\emph x = x\nin\n'<\n  +p{ \\ }\n>\n";
+    let response: Value = serde_json::from_str(&complete(text, 3, 7)).unwrap();
+    let labels: Vec<_> = response["items"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|item| item["label"].as_str().unwrap())
+        .collect();
+    assert_eq!(labels, vec!["\\emph"]);
+}
+
+#[test]
+fn test_parse_errors() {
+    assert_eq!(parse_errors("'<\n  +p{ hello }\n>\n"), "[]");
+
+    let errors: Value = serde_json::from_str(&parse_errors("'<\n  +p{ hello \n")).unwrap();
+    let errors = errors.as_array().unwrap();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0]["code"], "syntax-error");
+}
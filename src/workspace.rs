@@ -0,0 +1,239 @@
+//! ワークスペース内のパッケージファイルを走査し、定義とコマンドの使用箇所を索引化する関数群。
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    time::Instant,
+};
+
+use anyhow::Result;
+use log::{info, warn};
+use itertools::Itertools;
+use lsp_types::Url;
+use rayon::prelude::*;
+
+use crate::{
+    config::Config, ignore::IgnoreFilter, parser::Rule, position::Range, resolve::{is_file_uri, path_to_uri}, Buffer, Environment,
+};
+
+/// 索引化の対象とするファイルの拡張子。
+const INDEXED_EXTENSIONS: &[&str] = &["satyh", "satyg", "saty"];
+
+/// 索引化に用いるスレッド数の既定値。
+const DEFAULT_INDEX_THREADS: usize = 4;
+
+/// 使用例として示すテキストの最大文字数。
+const MAX_EXAMPLE_CHARS: usize = 80;
+
+/// ワークスペース内の各ファイルで定義されているものと、使われているコマンド。
+#[derive(Debug, Default)]
+pub struct WorkspaceIndex {
+    files: HashMap<Url, Environment>,
+    usages: HashMap<Url, Vec<CommandUsage>>,
+}
+
+/// コマンドが使われている箇所。
+#[derive(Debug, Clone)]
+pub struct CommandUsage {
+    /// コマンド名。
+    pub name: String,
+    /// 使われている範囲。
+    pub range: Range,
+    /// 使われている箇所の最初の行。長いものは切り詰める。
+    pub text: String,
+}
+
+impl WorkspaceIndex {
+    /// `root` 以下のファイルを走査し、並列にパースして索引を作る。
+    pub fn build(root: &Path, config: &Config) -> Result<Self> {
+        let threads = config.index_threads.unwrap_or(DEFAULT_INDEX_THREADS);
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build()?;
+
+        let start = Instant::now();
+        let filter = IgnoreFilter::new(root, &config.ignore);
+        let paths = scan_files(root, &filter);
+        let scanned = start.elapsed();
+
+        let indexed: Vec<_> = pool.install(|| {
+            paths
+                .par_iter()
+                .filter_map(|path| index_file(path, config))
+                .collect()
+        });
+        let mut files = HashMap::new();
+        let mut usages = HashMap::new();
+        for (uri, env, file_usages) in indexed {
+            usages.insert(uri.clone(), file_usages);
+            files.insert(uri, env);
+        }
+        info!(
+            "indexed {} files with {} threads: scan {:?}, total {:?}",
+            files.len(),
+            threads,
+            scanned,
+            start.elapsed()
+        );
+        Ok(Self { files, usages })
+    }
+
+    /// 与えられたファイルの定義を返す。
+    pub fn get(&self, uri: &Url) -> Option<&Environment> {
+        self.files.get(uri)
+    }
+
+    /// 索引化されたファイルとその定義を列挙する。
+    pub fn iter(&self) -> impl Iterator<Item = (&Url, &Environment)> {
+        self.files.iter()
+    }
+
+    /// 索引化されたファイルの数。
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    /// 索引化されたファイルが1つもないか。
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    /// 開いているバッファの内容で索引を更新する。パースに失敗していれば最後にパースに成功した版を用いる。
+    /// `untitled:` のようにファイルを指さない URI のバッファはワークスペースに含めない。
+    pub fn update(&mut self, uri: Url, buf: &Buffer) {
+        if !is_file_uri(&uri) {
+            return;
+        }
+        let buf = buf.latest_parsed().unwrap_or(buf);
+        self.usages.insert(uri.clone(), command_usages(buf));
+        self.files.insert(uri, buf.env.clone());
+    }
+
+    /// ファイルを索引から取り除く。
+    pub fn remove(&mut self, uri: &Url) {
+        self.usages.remove(uri);
+        self.files.remove(uri);
+    }
+
+    /// `exclude` 以外のファイルでコマンド `name` が使われている回数。
+    pub fn usage_count(&self, name: &str, exclude: &Url) -> usize {
+        self.usages
+            .iter()
+            .filter(|(uri, _)| *uri != exclude)
+            .flat_map(|(_, usages)| usages)
+            .filter(|usage| usage.name == name)
+            .count()
+    }
+
+    /// `exclude` 以外のファイルでコマンド `name` が使われている箇所を、
+    /// ファイルの URI と位置の順に最大 `limit` 個返す。
+    pub fn usage_examples(&self, name: &str, exclude: &Url, limit: usize) -> Vec<(&Url, &CommandUsage)> {
+        self.usages
+            .iter()
+            .filter(|(uri, _)| *uri != exclude)
+            .sorted_by_key(|(uri, _)| uri.as_str())
+            .flat_map(|(uri, usages)| usages.iter().map(move |usage| (uri, usage)))
+            .filter(|(_, usage)| usage.name == name)
+            .take(limit)
+            .collect()
+    }
+}
+
+/// バッファの中でコマンドが使われている箇所を列挙する。
+pub fn command_usages(buf: &Buffer) -> Vec<CommandUsage> {
+    let cst = match &buf.buf_cst.cst {
+        Some(cst) => cst,
+        None => return vec![],
+    };
+    [Rule::inline_cmd, Rule::block_cmd, Rule::math_cmd]
+        .iter()
+        .flat_map(|rule| cst.pickup(*rule))
+        .filter_map(|usage| {
+            let name = usage.inner.first()?;
+            let line = buf.buf_cst.as_str(usage).lines().next()?.trim_end();
+            let text = match line.char_indices().nth(MAX_EXAMPLE_CHARS) {
+                Some((i, _)) => format!("{}…", &line[..i]),
+                None => line.to_owned(),
+            };
+            Some(CommandUsage {
+                name: buf.buf_cst.as_str(name).to_owned(),
+                range: usage.range(),
+                text,
+            })
+        })
+        .sorted_by_key(|usage| (usage.range.start.line, usage.range.start.character))
+        .collect()
+}
+
+/// コマンドの使用例を、hover や補完の説明に載せる Markdown にする。使用例がなければ None を返す。
+pub fn describe_usage_examples(examples: &[(&Url, &CommandUsage)]) -> Option<String> {
+    if examples.is_empty() {
+        return None;
+    }
+    let lines = examples
+        .iter()
+        .map(|(uri, usage)| {
+            let file = uri
+                .path_segments()
+                .and_then(|mut segments| segments.next_back())
+                .unwrap_or_else(|| uri.as_str());
+            format!("{}  % {}:{}", usage.text, file, usage.range.start.line + 1)
+        })
+        .join("\n");
+    Some(format!("usage in workspace:\n```satysfi\n{}\n```", lines))
+}
+
+/// `dir` 以下にある索引化の対象ファイルを再帰的に集める。
+/// 隠しディレクトリと、`filter` や途中の `.gitignore` で除かれたパスは飛ばす。
+/// 循環して止まらなくならないよう、ディレクトリへのシンボリックリンクはたどらない。
+pub fn scan_files(dir: &Path, filter: &IgnoreFilter) -> Vec<PathBuf> {
+    let filter = filter.with_gitignore(dir);
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            warn!("failed to read {}: {}", dir.display(), e);
+            return vec![];
+        }
+    };
+    let mut paths = vec![];
+    for entry in entries.filter_map(|entry| entry.ok()) {
+        let path = entry.path();
+        let hidden = entry.file_name().to_string_lossy().starts_with('.');
+        let is_dir = match entry.file_type() {
+            Ok(file_type) => file_type.is_dir(),
+            Err(e) => {
+                warn!("failed to read {}: {}", path.display(), e);
+                continue;
+            }
+        };
+        if filter.is_ignored(&path, is_dir) {
+            continue;
+        }
+        if is_dir {
+            if !hidden {
+                paths.extend(scan_files(&path, &filter));
+            }
+        } else if path
+            .extension()
+            .is_some_and(|ext| INDEXED_EXTENSIONS.iter().any(|e| ext == *e))
+        {
+            paths.push(path);
+        }
+    }
+    paths
+}
+
+/// 1つのファイルを読み込んでパースし、その定義とコマンドの使用箇所を返す。
+fn index_file(path: &Path, config: &Config) -> Option<(Url, Environment, Vec<CommandUsage>)> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| warn!("failed to read {}: {}", path.display(), e))
+        .ok()?;
+    let uri = path_to_uri(path)?;
+    let mut buf = Buffer::with_language_version(text, config.language_version);
+    buf.apply_definition_patterns(&config.definition_patterns);
+    let usages = command_usages(&buf);
+    Some((uri, buf.env, usages))
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,99 @@
+//! test module for workspace indexing.
+
+use std::fs;
+
+use super::*;
+use crate::test_utils::temp_dir;
+
+#[test]
+fn test_build_index() {
+    let root = temp_dir("index");
+    fs::create_dir_all(root.join("lib")).unwrap();
+    fs::create_dir_all(root.join(".git")).unwrap();
+    fs::write(root.join("lib/a.satyh"), "let-inline ctx \\foo = {}\n").unwrap();
+    fs::write(root.join("lib/b.satyh"), "let-block ctx +bar = '<>\n").unwrap();
+    fs::write(root.join(".git/c.satyh"), "let-inline ctx \\baz = {}\n").unwrap();
+    fs::write(root.join("note.txt"), "let x = 1\n").unwrap();
+
+    let index = WorkspaceIndex::build(&root, &Config::default()).unwrap();
+    assert_eq!(index.len(), 2);
+
+    let uri = Url::from_file_path(root.join("lib/a.satyh")).unwrap();
+    let env = index.get(&uri).unwrap();
+    assert_eq!(env.inline_cmds[0].name, "\\foo");
+
+    fs::remove_dir_all(&root).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn test_scan_files_symlink_cycle() {
+    let root = temp_dir("symlink");
+    fs::create_dir_all(root.join("lib")).unwrap();
+    fs::write(root.join("lib/a.satyh"), "let-inline ctx \\foo = {}\n").unwrap();
+    std::os::unix::fs::symlink("..", root.join("lib/parent")).unwrap();
+    std::os::unix::fs::symlink(&root, root.join("self")).unwrap();
+
+    let files = scan_files(&root, &IgnoreFilter::new(&root, &[]));
+    assert_eq!(files, vec![root.join("lib/a.satyh")]);
+
+    fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn test_build_index_with_ignore() {
+    let root = temp_dir("index-ignore");
+    fs::create_dir_all(root.join("build")).unwrap();
+    fs::create_dir_all(root.join("lib/gen")).unwrap();
+    fs::write(root.join(".gitignore"), "build/\n").unwrap();
+    fs::write(root.join("lib/.gitignore"), "gen/*.satyh\n").unwrap();
+    fs::write(root.join("build/a.satyh"), "let-inline ctx \\a = {}\n").unwrap();
+    fs::write(root.join("lib/gen/b.satyh"), "let-inline ctx \\b = {}\n").unwrap();
+    fs::write(root.join("lib/gen/keep.satyh"), "let-inline ctx \\keep = {}\n").unwrap();
+    fs::write(root.join("lib/c.satyh"), "let-inline ctx \\c = {}\n").unwrap();
+    fs::write(root.join("lib/d.satyh"), "let-inline ctx \\d = {}\n").unwrap();
+
+    let config = Config {
+        ignore: vec!["lib/d.satyh".to_owned(), "!keep.satyh".to_owned()],
+        ..Config::default()
+    };
+    let index = WorkspaceIndex::build(&root, &config).unwrap();
+    let mut names: Vec<_> = ["lib/gen/keep.satyh", "lib/c.satyh"]
+        .iter()
+        .map(|p| Url::from_file_path(root.join(p)).unwrap())
+        .collect();
+    names.sort();
+    let mut indexed: Vec<_> = index.files.keys().cloned().collect();
+    indexed.sort();
+    assert_eq!(indexed, names);
+
+    fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn test_usage_examples() {
+    let a = Url::parse("file:///a.saty").unwrap();
+    let b = Url::parse("file:///b.saty").unwrap();
+    let mut index = WorkspaceIndex::default();
+    let long = "x".repeat(100);
+    index.update(a.clone(), &Buffer::new(format!("'<\n  +sec{{{}}}<>\n  +p{{ \\emph{{a}} }}\n>\n", long)));
+    index.update(b.clone(), &Buffer::new("'<\n  +p{ \\emph{b} }\n>\n".to_owned()));
+
+    let examples = index.usage_examples("\\emph", &b, 3);
+    assert_eq!(examples.len(), 1);
+    assert_eq!(examples[0].0, &a);
+    assert_eq!(examples[0].1.text, "\\emph{a}");
+    assert_eq!(examples[0].1.range.start.line, 2);
+
+    let examples = index.usage_examples("+sec", &b, 3);
+    assert_eq!(examples[0].1.text.chars().count(), MAX_EXAMPLE_CHARS + 1);
+    assert!(examples[0].1.text.ends_with('…'));
+
+    let examples = index.usage_examples("+p", &Url::parse("file:///c.saty").unwrap(), 1);
+    assert_eq!(examples.len(), 1);
+    assert_eq!(examples[0].0, &a);
+
+    let untitled = Url::parse("untitled:Untitled-1").unwrap();
+    index.update(untitled, &Buffer::new("'<\n  +p{ \\emph{c} }\n>\n".to_owned()));
+    assert_eq!(index.usage_examples("\\emph", &b, 3).len(), 1);
+}
@@ -0,0 +1,38 @@
+//! ワークスペースのファイルを、開かれていなくても検査するための関数群。
+//!
+//! `workspace/executeCommand` の [`LINT_WORKSPACE_COMMAND`] で呼び出される。
+//! ディスク上の内容を読んでパースし、開かれているファイルと同じ diagnostics を計算する。
+
+use log::warn;
+use lsp_types::{Diagnostic, Url};
+
+use crate::{config::Config, diagnostic::get_diagnostics, Buffer};
+
+/// ワークスペース全体を検査するコマンドの名前。
+pub const LINT_WORKSPACE_COMMAND: &str = "satysfi.lintWorkspace";
+
+/// ディスク上のファイルを読み込んでパースし、その diagnostics を返す。
+/// ファイルを指さない URI であったり、読み込めなかったりすれば None を返す。
+pub fn lint_file(uri: &Url, config: &Config) -> Option<Vec<Diagnostic>> {
+    let path = uri.to_file_path().ok()?;
+    let text = std::fs::read_to_string(&path)
+        .map_err(|e| warn!("failed to read {}: {}", path.display(), e))
+        .ok()?;
+    let mut buf = Buffer::with_language_version(text, config.language_version);
+    buf.apply_definition_patterns(&config.definition_patterns);
+    buf.load_packages(uri, config);
+    Some(get_diagnostics(&buf, uri, config))
+}
+
+/// 検査の進み具合を示すメッセージ。
+pub fn progress_message(done: usize, total: usize) -> String {
+    format!("{}/{} files", done, total)
+}
+
+/// 検査を終えたときのメッセージ。
+pub fn summary_message(problems: usize, files: usize) -> String {
+    format!("{} problem(s) in {} file(s)", problems, files)
+}
+
+#[cfg(test)]
+mod tests;
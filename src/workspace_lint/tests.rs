@@ -0,0 +1,31 @@
+//! test module for workspace lint.
+
+use lsp_types::NumberOrString;
+
+use super::*;
+use crate::test_utils::temp_dir;
+
+#[test]
+fn test_lint_file() {
+    let dir = temp_dir("workspace-lint");
+    let path = dir.join("main.saty");
+    std::fs::write(&path, "'<\n  +sec{A}\n>\n").unwrap();
+    let uri = Url::from_file_path(&path).unwrap();
+
+    let diagnostics = lint_file(&uri, &Config::default()).unwrap();
+    let codes = diagnostics.iter().map(|d| d.code.clone()).collect::<Vec<_>>();
+    assert_eq!(codes, vec![Some(NumberOrString::String("undefined-command".to_owned()))]);
+
+    // 読み込めないファイルや、ファイルを指さない URI は検査しない。
+    let missing = Url::from_file_path(dir.join("missing.saty")).unwrap();
+    assert!(lint_file(&missing, &Config::default()).is_none());
+    assert!(lint_file(&Url::parse("untitled:Untitled-1").unwrap(), &Config::default()).is_none());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_messages() {
+    assert_eq!(progress_message(3, 25), "3/25 files");
+    assert_eq!(summary_message(2, 25), "2 problem(s) in 25 file(s)");
+}
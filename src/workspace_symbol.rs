@@ -0,0 +1,94 @@
+//! workspace/symbol に関する関数群。
+//!
+//! ワークスペースの索引にある各ファイルの定義を、名前で絞り込んで返す。
+//! 型はそのコンストラクタやフィールドとともに返し、それらの container_name には型名を入れる。
+
+use itertools::Itertools;
+use lsp_types::{Location, SymbolInformation, SymbolKind, Url, WorkspaceSymbolParams};
+
+use crate::{
+    fuzzy::normalize_name, position::Range, workspace::WorkspaceIndex, CmdKind, Environment,
+    TypeDefKind,
+};
+
+/// 一度に返す symbol の最大の数。
+pub const MAX_WORKSPACE_SYMBOLS: usize = 1000;
+
+/// workspace/symbol リクエストへの response を返す。
+/// 大文字と小文字、`-` と `_` の有無を無視して、名前に query を含むものを返す。
+pub fn get_workspace_symbol_response(
+    index: &WorkspaceIndex,
+    params: WorkspaceSymbolParams,
+) -> Option<Vec<SymbolInformation>> {
+    let query = normalize_name(&params.query);
+    let symbols = index
+        .iter()
+        .sorted_by_key(|(uri, _)| uri.as_str())
+        .flat_map(|(uri, env)| environment_symbols(uri, env))
+        .filter(|symbol| normalize_name(&symbol.name).contains(&query))
+        .take(MAX_WORKSPACE_SYMBOLS)
+        .collect();
+    Some(symbols)
+}
+
+/// 1 つのファイルで定義されているコマンドと変数、型とそのコンストラクタ、フィールドの symbol を作る。
+fn environment_symbols(uri: &Url, env: &Environment) -> Vec<SymbolInformation> {
+    let commands = [CmdKind::Inline, CmdKind::Block, CmdKind::Math]
+        .iter()
+        .flat_map(|kind| env.commands(*kind))
+        .map(|cmd| symbol(cmd.name, SymbolKind::Function, uri, cmd.def_range, None))
+        .collect_vec();
+    let variables = env
+        .variables()
+        .iter()
+        .map(|var| symbol(var.name(), SymbolKind::Variable, uri, var.def_range(), None));
+    let types = env.types().iter().flat_map(|ty| {
+        let kind = match ty.kind() {
+            TypeDefKind::Variant => SymbolKind::Enum,
+            TypeDefKind::Record => SymbolKind::Struct,
+            _ => SymbolKind::TypeParameter,
+        };
+        let container = Some(ty.name());
+        let constructors = ty.constructors().iter().map(move |c| {
+            symbol(c.name(), SymbolKind::EnumMember, uri, c.def_range(), container)
+        });
+        let fields = ty
+            .fields()
+            .iter()
+            .map(move |f| symbol(f.name(), SymbolKind::Field, uri, f.def_range(), container));
+        std::iter::once(symbol(ty.name(), kind, uri, ty.def_range(), None))
+            .chain(constructors)
+            .chain(fields)
+    });
+    commands
+        .into_iter()
+        .chain(variables)
+        .chain(types)
+        .sorted_by_key(|symbol| {
+            let start = symbol.location.range.start;
+            (start.line, start.character)
+        })
+        .collect()
+}
+
+/// 定義の場所から symbol を作る。
+fn symbol(
+    name: &str,
+    kind: SymbolKind,
+    uri: &Url,
+    range: Range,
+    container: Option<&str>,
+) -> SymbolInformation {
+    #[allow(deprecated)]
+    SymbolInformation {
+        name: name.to_owned(),
+        kind,
+        tags: None,
+        deprecated: None,
+        location: Location { uri: uri.clone(), range: range.into() },
+        container_name: container.map(str::to_owned),
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,49 @@
+//! test module for workspace_symbol.
+
+use super::*;
+use crate::Buffer;
+
+fn query(index: &WorkspaceIndex, query: &str) -> Vec<(String, SymbolKind, Option<String>)> {
+    let params = WorkspaceSymbolParams {
+        partial_result_params: Default::default(),
+        work_done_progress_params: Default::default(),
+        query: query.to_owned(),
+    };
+    get_workspace_symbol_response(index, params)
+        .unwrap()
+        .into_iter()
+        .map(|symbol| (symbol.name, symbol.kind, symbol.container_name))
+        .collect()
+}
+
+#[test]
+fn test_workspace_symbols() {
+    let mut index = WorkspaceIndex::default();
+    let shapes = Url::parse("file:///ws/shapes.satyh").unwrap();
+    index.update(
+        shapes.clone(),
+        &Buffer::new("type shape = | Circle of length | Square\ntype point = (| x : length; y-pos : length |)\nlet-inline ctx \\draw-shape s = {}\n".to_owned()),
+    );
+    index.update(
+        Url::parse("file:///ws/main.saty").unwrap(),
+        &Buffer::new("let shape-count = 2\nin\n'<>\n".to_owned()),
+    );
+
+    let sym = |name: &str, kind, container: Option<&str>| {
+        (name.to_owned(), kind, container.map(str::to_owned))
+    };
+    assert_eq!(
+        query(&index, "shape"),
+        vec![
+            sym("shape-count", SymbolKind::Variable, None),
+            sym("shape", SymbolKind::Enum, None),
+            sym("\\draw-shape", SymbolKind::Function, None),
+        ]
+    );
+    assert_eq!(
+        query(&index, "circle"),
+        vec![sym("Circle", SymbolKind::EnumMember, Some("shape"))]
+    );
+    assert_eq!(query(&index, "ypos"), vec![sym("y-pos", SymbolKind::Field, Some("point"))]);
+    assert_eq!(query(&index, "").len(), 8);
+}